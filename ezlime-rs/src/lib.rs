@@ -26,19 +26,206 @@
 //! ```
 //!
 
-use reqwest::Url;
+use futures_util::{Stream, StreamExt, TryStreamExt, stream};
+use quick_cache::sync::Cache;
+use reqwest::{StatusCode, Url, header::HeaderMap};
 use serde::{Deserialize, Serialize};
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use thiserror::Error;
 
 /// Request payload for creating a shortened URL.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct CreateLinkRequest {
     /// The original URL to be shortened.
     pub url: String,
+    /// Redirect permanently (HTTP 301) instead of temporarily (HTTP 307).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub permanent: bool,
+    /// Passphrase visitors must enter before being redirected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// Seconds after which the link expires and is deleted. Expiring links
+    /// are never shared with other requests for the same URL.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl_seconds: Option<u64>,
+    /// Clicks after which the link stops redirecting, e.g. `1` for a
+    /// single-use link. Servers count clicks in batches, so a link may take
+    /// a few more before it is refused. Limited links are never shared with
+    /// other requests for the same URL.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_clicks: Option<u32>,
+    /// List the link on the server's public trending page.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub public: bool,
+    /// Always mint a new id, even if the URL was shortened before, e.g. to
+    /// track several campaigns to the same destination separately.
+    ///
+    /// Shortening the URL again without this flag still returns the first
+    /// link created for it. Each forced link costs the server one more lookup
+    /// when creating the next one for the same URL.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub force_new: bool,
+    /// Short human readable title shown in link lists, e.g. the one of the
+    /// destination page. Servers may fill it in themselves if missing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Free text note, e.g. the campaign the link is for. Only shown to the
+    /// owner and admins, never on public views of the link.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// Character (a lowercase letter or digit) prepended to the generated
+    /// id, e.g. `b` for links of a blog and `d` for those of the docs, so
+    /// products sharing one server get ids attributable to them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id_prefix: Option<char>,
+    /// Id to create the link under instead of a generated one, see
+    /// [`EzlimeApi::create_with_alias`]. `id_prefix` is not applied to it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+    /// Preview shown when the link is shared on social media. Servers
+    /// configured for it answer social crawlers with these tags instead of
+    /// the redirect.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub open_graph: Option<OpenGraph>,
+}
+
+impl CreateLinkRequest {
+    /// Whether the server hands out the existing link when this request is
+    /// made again, so a failed attempt can be retried without a duplicate.
+    fn is_deduplicated(&self) -> bool {
+        !self.force_new
+            && self.ttl_seconds.is_none()
+            && self.max_clicks.is_none()
+            && self.id_prefix.is_none()
+    }
+}
+
+/// Open Graph tags of a link, see [`CreateLinkRequest::open_graph`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OpenGraph {
+    /// `og:title`, the headline of the preview.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// `og:description`, a sentence or two below the title.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// `og:image`, the absolute http(s) URL of the preview image.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+}
+
+/// Request payload for creating several links at once.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CreateLinksRequest {
+    /// The links to create, in order.
+    pub links: Vec<CreateLinkRequest>,
+}
+
+/// Outcome of creating one link of a [`CreateLinksRequest`], either `link`
+/// or `error` is set.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CreateLinkOutcome {
+    /// The URL the link was requested for.
+    pub url: String,
+    /// The created link.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link: Option<CreatedLinkResponse>,
+    /// Machine readable reason the link was not created, e.g.
+    /// `host_blocked` or `validation_failed`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Human readable description of `error`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// Response to a [`CreateLinksRequest`], one result per requested link.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CreateLinksResponse {
+    /// Results in the order of the requested links.
+    pub results: Vec<CreateLinkOutcome>,
+}
+
+/// Result of [`EzlimeApi::create_links`], so callers can retry just the
+/// failed URLs.
+#[derive(Debug, Default)]
+pub struct BatchCreateResult {
+    /// The links created, in the order of their URLs.
+    pub successes: Vec<CreatedLinkResponse>,
+    /// The URLs no link was created for and why.
+    pub failures: Vec<(String, EzlimeApiError)>,
+}
+
+/// Request payload for changing the destination of an existing link.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UpdateLinkRequest {
+    /// The new URL the link redirects to.
+    pub url: String,
+}
+
+/// Request payload for deleting several links at once.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DeleteLinksRequest {
+    /// Ids of the links to delete.
+    pub ids: Vec<String>,
+}
+
+/// Outcome of deleting one link of a [`DeleteLinksRequest`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeletedLink {
+    /// The id of the link.
+    pub id: String,
+    /// Whether the link was deleted, `false` if it does not exist or is
+    /// owned by another key.
+    pub deleted: bool,
+}
+
+/// Response to a [`DeleteLinksRequest`], one result per requested id.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DeleteLinksResponse {
+    /// Results in the order of the requested ids.
+    pub results: Vec<DeletedLink>,
+}
+
+/// Request payload for looking up the links of several URLs at once.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct FindLinksRequest {
+    /// The URLs to look up.
+    pub urls: Vec<String>,
+}
+
+/// A URL of a [`FindLinksRequest`] and the id of its link, if it has one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FoundLink {
+    /// The URL as requested.
+    pub url: String,
+    /// The id of the link to the URL, `None` if there is none.
+    pub id: Option<String>,
+}
+
+/// Request payload for resolving several links at once.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ResolveLinksRequest {
+    /// Ids of the links to resolve.
+    pub ids: Vec<String>,
+}
+
+/// A link of a [`ResolveLinksRequest`] and where it redirects to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResolvedLink {
+    /// The id of the link.
+    pub id: String,
+    /// The URL the link redirects to.
+    pub original_url: String,
 }
 
 /// Response from the ezli.me API after creating a shortened URL.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CreatedLinkResponse {
     /// The unique identifier for the shortened link.
     pub id: String,
@@ -57,7 +244,7 @@ impl CreatedLinkResponse {
     /// * `prefix` - The URL prefix (e.g., `https://ezli.me`)
     /// * `original_url` - The original URL that was shortened
     pub fn new(id: String, prefix: &str, original_url: String) -> Self {
-        let shortened_url = format!("{}/{}", prefix, id);
+        let shortened_url = short_url(prefix, &id);
         Self {
             id,
             shortened_url,
@@ -66,6 +253,125 @@ impl CreatedLinkResponse {
     }
 }
 
+fn short_url(prefix: &str, id: &str) -> String {
+    format!("{}/{}", prefix, id)
+}
+
+/// Rules the server validates URLs against before shortening them.
+///
+/// Fetch them with [`EzlimeApi::rules`] to check URLs client-side with exactly
+/// the rules of the server in use.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValidationRules {
+    /// URL schemes that can be shortened, e.g. `https`.
+    pub allowed_schemes: Vec<String>,
+    /// Maximum length of a URL in bytes.
+    pub max_url_length: usize,
+    /// Whether URLs pointing at loopback or private network hosts are rejected.
+    pub block_private_hosts: bool,
+}
+
+/// What the server knows about an API key, see [`EzlimeApi::check_key`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyInfo {
+    /// Whether the key is accepted by the server.
+    pub valid: bool,
+    /// Scopes granted to the key, empty if the server does not scope keys.
+    #[serde(default)]
+    pub scopes: Vec<String>,
+}
+
+/// Public stats of a link, see [`EzlimeApi::create_with_stats`].
+///
+/// The server may be configured to hide some of the fields, which are `None`
+/// then. Timestamps are passed on as sent by the server.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinkStats {
+    /// The id of the link.
+    pub id: String,
+    /// How often the link was followed.
+    #[serde(default)]
+    pub click_count: Option<i64>,
+    /// When the link was last followed.
+    #[serde(default)]
+    pub last_used: Option<String>,
+    /// When the link was created.
+    #[serde(default)]
+    pub created_at: Option<String>,
+    /// The title of the link, if it has one.
+    #[serde(default)]
+    pub title: Option<String>,
+    /// When the link was first followed, `None` before that.
+    #[serde(default)]
+    pub first_used: Option<String>,
+}
+
+/// Clicks of a link on one day, see [`EzlimeApi::timeseries`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DailyClicks {
+    /// The day (UTC) as `YYYY-MM-DD`.
+    pub date: String,
+    /// How often the link was followed that day.
+    pub clicks: u64,
+}
+
+/// A link created with the client's API key, see [`EzlimeApi::links_stream`].
+///
+/// Timestamps are passed on as sent by the server.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OwnedLink {
+    /// The id of the link.
+    pub id: String,
+    /// The URL the link redirects to.
+    pub url: String,
+    /// When the link was created.
+    pub created_at: String,
+    /// How often the link was followed.
+    pub click_count: i64,
+    /// When the link was last followed.
+    pub last_used: String,
+    /// How the link was created, e.g. `api` or `import`.
+    pub source: String,
+    /// The title given when creating the link, or fetched by the server.
+    #[serde(default)]
+    pub title: Option<String>,
+    /// The note given when creating the link.
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+/// A page of `GET /link/list`.
+#[derive(Deserialize)]
+struct LinksPage {
+    links: Vec<OwnedLink>,
+    /// Cursor of the next page, missing on the last one.
+    next: Option<String>,
+}
+
+/// Links requested per page by [`EzlimeApi::links_stream`].
+const LIST_PAGE_SIZE: u32 = 100;
+
+/// Most redirects [`EzlimeApi::inspect`] follows from a link's destination.
+pub const INSPECT_MAX_HOPS: usize = 10;
+
+/// Longest alias [`EzlimeApi::create_with_alias`] sends, the default limit
+/// of the server.
+pub const MAX_ALIAS_LENGTH: usize = 32;
+
+/// Where a short link leads, see [`EzlimeApi::inspect`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinkInspection {
+    /// The id of the link.
+    pub id: String,
+    /// The destination the link redirects to, followed by every URL the
+    /// destination redirected to in turn.
+    pub chain: Vec<String>,
+    /// The last URL of `chain`, where a visitor ends up.
+    pub final_url: String,
+    /// Whether the final URL still redirected when the hop limit was reached.
+    pub truncated: bool,
+}
+
 /// A client for interacting with the ezli.me API.
 ///
 /// This struct provides a convenient interface for creating shortened URLs
@@ -88,7 +394,36 @@ impl CreatedLinkResponse {
 pub struct EzlimeApi {
     url: String,
     key: String,
+    auth_header: String,
     client: reqwest::Client,
+    /// Settings `client` was built with, see [`EzlimeApi::client_builder`].
+    default_headers: HeaderMap,
+    accept_invalid_certs: bool,
+    pool_max_idle_per_host: Option<usize>,
+    pool_idle_timeout: Option<Duration>,
+    cache: Option<Arc<Cache<String, CreatedLinkResponse>>>,
+    retries: u32,
+    retry_deadline: Option<Duration>,
+    retry_jitter: bool,
+}
+
+/// Wait before the first retry, doubled for every further one.
+const INITIAL_RETRY_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Longest wait between two retries, however many were made.
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(10);
+
+/// Times [`EzlimeApi::create_all`] waits out a `429` for one URL before
+/// yielding the error.
+const MAX_RATE_LIMITED_RETRIES: u32 = 5;
+
+/// A random wait between zero and `backoff`, so clients failing together
+/// don't retry together.
+fn full_jitter(backoff: Duration) -> Duration {
+    // every `RandomState` is keyed differently, sparing a dependency on rand
+    let random = RandomState::new().build_hasher().finish();
+
+    backoff.mul_f64(random as f64 / u64::MAX as f64)
 }
 
 /// Errors that can occur when interacting with the ezli.me API.
@@ -98,11 +433,126 @@ pub enum EzlimeApiError {
     #[error("Configuration error: {0}")]
     ConfigurationError(String),
     /// An error occurred while sending the HTTP request or receiving the response.
+    ///
+    /// The underlying [`reqwest::Error`] is exposed as the error source, see
+    /// also [`EzlimeApiError::is_timeout`] and [`EzlimeApiError::is_connect`].
     #[error("Request error: {0}")]
-    RequestError(String),
+    RequestError(#[source] reqwest::Error),
     /// An error occurred while deserializing the API response.
     #[error("Deserialization error: {0}")]
-    DeserializationError(String),
+    DeserializationError(#[source] reqwest::Error),
+    /// The server found no free id for the link, its id space is used up.
+    /// Retrying does not help until the operator lengthens the ids.
+    #[error("Id space exhausted")]
+    IdSpaceExhausted,
+    /// The server rate limits the API key, the request may be repeated
+    /// after `retry_after`.
+    #[error("Rate limited, retry after {}s", retry_after.as_secs())]
+    RateLimited { retry_after: Duration },
+    /// The link redirects to a host not on the allow-list passed to
+    /// [`EzlimeApi::resolve_checked`].
+    #[error("Destination host '{host}' is not allowed")]
+    DisallowedDestination { host: String },
+    /// The alias passed to [`EzlimeApi::create_with_alias`] does not follow
+    /// the rules of ids, it was not sent.
+    #[error("Invalid alias: {0}")]
+    InvalidAlias(String),
+    /// The alias passed to [`EzlimeApi::create_with_alias`] is the id of
+    /// another link already.
+    #[error("Alias is already taken")]
+    AliasTaken,
+    /// The server refused to create one link of a batch, see
+    /// [`EzlimeApi::create_links`]. `code` tags the reason, e.g.
+    /// `host_blocked`.
+    #[error("Rejected ({code}): {message}")]
+    Rejected { code: String, message: String },
+    /// The server rejected the link for breaking the listed rules, e.g.
+    /// `scheme_not_allowed` and `url_too_long`, all of them at once.
+    #[error("Validation failed: {}", .0.join(", "))]
+    Validation(Vec<String>),
+}
+
+/// Error body of tagged server errors, e.g. `{"error": "id_space_exhausted"}`.
+#[derive(Deserialize)]
+struct ErrorBody {
+    error: String,
+    #[serde(default)]
+    retry_after_secs: Option<u64>,
+    #[serde(default)]
+    violations: Vec<String>,
+}
+
+/// Turns a `429` response into [`EzlimeApiError::RateLimited`], taking the
+/// wait from the JSON body or else the `Retry-After` header.
+async fn check_rate_limit(resp: reqwest::Response) -> Result<reqwest::Response, EzlimeApiError> {
+    if resp.status() != StatusCode::TOO_MANY_REQUESTS {
+        return Ok(resp);
+    }
+
+    let header = resp
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok());
+    let body = resp
+        .json::<ErrorBody>()
+        .await
+        .ok()
+        .filter(|body| body.error == "rate_limited")
+        .and_then(|body| body.retry_after_secs);
+
+    Err(EzlimeApiError::RateLimited {
+        retry_after: Duration::from_secs(body.or(header).unwrap_or_default()),
+    })
+}
+
+impl EzlimeApiError {
+    /// A stable, machine readable code of the error kind, for matching or
+    /// alerting without parsing the [`Display`](std::fmt::Display) output.
+    ///
+    /// | Variant | Code |
+    /// |---|---|
+    /// | [`EzlimeApiError::ConfigurationError`] | `config` |
+    /// | [`EzlimeApiError::RequestError`] | `request` |
+    /// | [`EzlimeApiError::DeserializationError`] | `deserialize` |
+    /// | [`EzlimeApiError::IdSpaceExhausted`] | `id_space_exhausted` |
+    /// | [`EzlimeApiError::RateLimited`] | `rate_limited` |
+    /// | [`EzlimeApiError::DisallowedDestination`] | `disallowed_destination` |
+    /// | [`EzlimeApiError::InvalidAlias`] | `invalid_alias` |
+    /// | [`EzlimeApiError::AliasTaken`] | `alias_taken` |
+    /// | [`EzlimeApiError::Rejected`] | `rejected` |
+    /// | [`EzlimeApiError::Validation`] | `validation_failed` |
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::ConfigurationError(_) => "config",
+            Self::RequestError(_) => "request",
+            Self::DeserializationError(_) => "deserialize",
+            Self::IdSpaceExhausted => "id_space_exhausted",
+            Self::RateLimited { .. } => "rate_limited",
+            Self::DisallowedDestination { .. } => "disallowed_destination",
+            Self::InvalidAlias(_) => "invalid_alias",
+            Self::AliasTaken => "alias_taken",
+            Self::Rejected { .. } => "rejected",
+            Self::Validation(_) => "validation_failed",
+        }
+    }
+
+    /// Whether the request timed out, which is usually worth a retry.
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, Self::RequestError(e) if e.is_timeout())
+    }
+
+    /// Whether connecting to the server failed, e.g. on DNS or TCP errors.
+    pub fn is_connect(&self) -> bool {
+        matches!(self, Self::RequestError(e) if e.is_connect())
+    }
+
+    /// Whether a retry might succeed, i.e. on timeouts, connect errors and
+    /// `5xx` responses.
+    fn is_transient(&self) -> bool {
+        matches!(self, Self::RequestError(e)
+            if e.is_timeout() || e.is_connect() || e.status().is_some_and(|s| s.is_server_error()))
+    }
 }
 
 impl EzlimeApi {
@@ -123,10 +573,38 @@ impl EzlimeApi {
         Self {
             url: String::from("https://ezli.me"),
             key,
+            auth_header: String::from("Authorization"),
             client: reqwest::Client::new(),
+            default_headers: HeaderMap::new(),
+            accept_invalid_certs: false,
+            pool_max_idle_per_host: None,
+            pool_idle_timeout: None,
+            cache: None,
+            retries: 0,
+            retry_deadline: None,
+            retry_jitter: true,
         }
     }
 
+    /// Sets the header the API key is sent in.
+    ///
+    /// By default, the key is sent in the `Authorization` header. Use this for
+    /// servers behind a gateway that consumes `Authorization` itself and are
+    /// configured to read the key from another header.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use ezlime_rs::EzlimeApi;
+    ///
+    /// let api = EzlimeApi::new("your-api-key".to_string())
+    ///     .with_auth_header("X-Api-Key");
+    /// ```
+    pub fn with_auth_header(mut self, name: &str) -> Self {
+        self.auth_header = name.into();
+        self
+    }
+
     /// Sets a custom API endpoint URL.
     ///
     /// By default, the client uses `https://ezli.me`. Use this method to
@@ -149,10 +627,241 @@ impl EzlimeApi {
         self
     }
 
+    /// Enables an in-memory cache of created links.
+    ///
+    /// Links are cached by their original URL, so shortening the same URL again
+    /// returns the cached result without another request to the API. This is safe
+    /// because the server returns the same link when shortening an identical URL.
+    /// The cache is shared between clones of this client.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - The maximum number of links kept in the cache
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use ezlime_rs::EzlimeApi;
+    ///
+    /// let api = EzlimeApi::new("your-api-key".to_string())
+    ///     .with_cache(1000);
+    /// ```
+    pub fn with_cache(mut self, capacity: usize) -> Self {
+        self.cache = Some(Arc::new(Cache::new(capacity)));
+        self
+    }
+
+    /// Retries creating links up to `retries` times on transient failures.
+    ///
+    /// Timeouts, connection errors and `5xx` responses are retried with an
+    /// exponential backoff starting at 100ms, waiting a random part of it
+    /// unless disabled via [`EzlimeApi::with_retry_jitter`], doubling up to
+    /// 10s. Retrying is safe as the server returns the existing link when a
+    /// URL is shortened again. Creates the server never deduplicates (forced,
+    /// expiring, click limited or prefixed links) are not retried, as an
+    /// attempt failing after the link was stored would leave a duplicate.
+    /// By default no retries are made. See also
+    /// [`EzlimeApi::with_retry_deadline`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use ezlime_rs::EzlimeApi;
+    ///
+    /// let api = EzlimeApi::new("your-api-key".to_string())
+    ///     .with_retries(3);
+    /// ```
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Caps the total time a create call spends, including all retries and
+    /// backoffs.
+    ///
+    /// Every attempt is limited to the time left, and no retry is started
+    /// if its backoff would exceed the deadline. The last error is returned
+    /// then, so a create never takes much longer than `deadline`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use ezlime_rs::EzlimeApi;
+    /// use std::time::Duration;
+    ///
+    /// let api = EzlimeApi::new("your-api-key".to_string())
+    ///     .with_retries(10)
+    ///     .with_retry_deadline(Duration::from_secs(5));
+    /// ```
+    pub fn with_retry_deadline(mut self, deadline: Duration) -> Self {
+        self.retry_deadline = Some(deadline);
+        self
+    }
+
+    /// Waits a random time between zero and the backoff before a retry
+    /// ("full jitter"), which is the default. Many clients retrying after
+    /// the same outage are spread out this way instead of hitting the
+    /// server at once. Disabled, every retry waits the full backoff.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use ezlime_rs::EzlimeApi;
+    ///
+    /// let api = EzlimeApi::new("your-api-key".to_string())
+    ///     .with_retries(3)
+    ///     .with_retry_jitter(false);
+    /// ```
+    pub fn with_retry_jitter(mut self, jitter: bool) -> Self {
+        self.retry_jitter = jitter;
+        self
+    }
+
+    /// How long to wait before a retry with the given `backoff`.
+    fn retry_delay(&self, backoff: Duration) -> Duration {
+        if self.retry_jitter {
+            full_jitter(backoff)
+        } else {
+            backoff
+        }
+    }
+
+    /// Accepts any TLS certificate of the server, e.g. a self-signed one of
+    /// a self-hosted instance during development.
+    ///
+    /// **Only use this for local testing.** Without verification anyone on
+    /// the network path can impersonate the server and read the API key, so
+    /// never enable it in production. Passing `false` restores verification.
+    ///
+    /// # Panics
+    ///
+    /// Like [`reqwest::Client::new`], if the TLS backend can't be initialized.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use ezlime_rs::EzlimeApi;
+    ///
+    /// let api = EzlimeApi::new("your-api-key".to_string())
+    ///     .with_url("https://localhost:8443")
+    ///     .with_danger_accept_invalid_certs(true);
+    /// ```
+    pub fn with_danger_accept_invalid_certs(mut self, accept: bool) -> Self {
+        self.accept_invalid_certs = accept;
+        self.client = self
+            .client_builder()
+            .build()
+            .expect("TLS backend cannot be initialized");
+        self
+    }
+
+    /// Sends `headers` with every request, e.g. the credentials of a
+    /// corporate proxy.
+    ///
+    /// The headers are merged into the ones set before, replacing those of
+    /// the same name. The API key is still sent per request in the header of
+    /// [`EzlimeApi::with_auth_header`], taking precedence over a default
+    /// header of that name.
+    ///
+    /// # Panics
+    ///
+    /// Like [`reqwest::Client::new`], if the TLS backend can't be initialized.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use ezlime_rs::EzlimeApi;
+    /// use reqwest::header::{HeaderMap, HeaderValue};
+    ///
+    /// let mut headers = HeaderMap::new();
+    /// headers.insert("X-Proxy-Auth", HeaderValue::from_static("secret"));
+    ///
+    /// let api = EzlimeApi::new("your-api-key".to_string())
+    ///     .with_default_headers(headers);
+    /// ```
+    pub fn with_default_headers(mut self, headers: HeaderMap) -> Self {
+        self.default_headers.extend(headers);
+        self.client = self
+            .client_builder()
+            .build()
+            .expect("TLS backend cannot be initialized");
+        self
+    }
+
+    /// Keeps at most `max` idle connections to the server open for reuse.
+    ///
+    /// By default reqwest keeps every connection a burst of concurrent
+    /// requests opened, until it idled for 90 seconds. Integrations sending
+    /// many requests at once can cap that at their usual concurrency to hold
+    /// fewer sockets, `0` opens a new connection for every request.
+    ///
+    /// # Panics
+    ///
+    /// Like [`reqwest::Client::new`], if the TLS backend can't be initialized.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use ezlime_rs::EzlimeApi;
+    ///
+    /// let api = EzlimeApi::new("your-api-key".to_string())
+    ///     .with_pool_max_idle_per_host(16);
+    /// ```
+    pub fn with_pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.pool_max_idle_per_host = Some(max);
+        self.client = self
+            .client_builder()
+            .build()
+            .expect("TLS backend cannot be initialized");
+        self
+    }
+
+    /// Closes connections that idled for `timeout`, 90 seconds by default.
+    /// Keep it below the keep-alive timeout of proxies in between, which
+    /// otherwise close connections the client still tries to reuse.
+    ///
+    /// # Panics
+    ///
+    /// Like [`reqwest::Client::new`], if the TLS backend can't be initialized.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use ezlime_rs::EzlimeApi;
+    /// use std::time::Duration;
+    ///
+    /// let api = EzlimeApi::new("your-api-key".to_string())
+    ///     .with_pool_idle_timeout(Duration::from_secs(30));
+    /// ```
+    pub fn with_pool_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.pool_idle_timeout = Some(timeout);
+        self.client = self
+            .client_builder()
+            .build()
+            .expect("TLS backend cannot be initialized");
+        self
+    }
+
+    /// A builder for HTTP clients with the settings of this one, for requests
+    /// needing their own client.
+    fn client_builder(&self) -> reqwest::ClientBuilder {
+        let mut builder = reqwest::Client::builder()
+            .default_headers(self.default_headers.clone())
+            .danger_accept_invalid_certs(self.accept_invalid_certs);
+        if let Some(max) = self.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max);
+        }
+        if let Some(timeout) = self.pool_idle_timeout {
+            builder = builder.pool_idle_timeout(timeout);
+        }
+        builder
+    }
+
     /// Creates a shortened URL using the ezli.me API.
     ///
     /// This method sends a request to the ezli.me API to create a shortened version
-    /// of the provided URL.
+    /// of the provided URL. If a cache is enabled via [`EzlimeApi::with_cache`] and
+    /// the URL was shortened before, the cached result is returned instead.
     ///
     /// # Arguments
     ///
@@ -169,6 +878,7 @@ impl EzlimeApi {
     /// - The API endpoint URL is invalid (`ConfigurationError`)
     /// - The HTTP request fails (`RequestError`)
     /// - The response cannot be deserialized (`DeserializationError`)
+    /// - The server has no free ids left (`IdSpaceExhausted`)
     ///
     /// # Example
     ///
@@ -183,23 +893,2328 @@ impl EzlimeApi {
     /// # }
     /// ```
     pub async fn create_short_url(&self, original_link: &str) -> Result<String, EzlimeApiError> {
-        let url: Url = Url::parse(&format!("{}/link/create", self.url))
-            .map_err(|e| EzlimeApiError::ConfigurationError(e.to_string()))?;
+        let (resp, _) = self.ensure(original_link).await?;
 
-        let resp = self
-            .client
-            .post(url)
-            .header("Authorization", self.key.clone())
-            .json(&CreateLinkRequest {
+        Ok(resp.shortened_url)
+    }
+
+    /// Ensures a shortened URL exists for the given URL.
+    ///
+    /// The server returns the existing link when the same URL is shortened again, so
+    /// this either creates a new link or returns the one that already exists. The
+    /// returned flag tells which of the two happened.
+    ///
+    /// Newness is derived from the response status: `201 Created` for a new link and
+    /// `200 OK` for an existing one. Servers that do not distinguish the two yet answer
+    /// every create with `200 OK`, in which case the flag is always `false`. Results
+    /// served from the cache (see [`EzlimeApi::with_cache`]) are reported as existing.
+    ///
+    /// # Arguments
+    ///
+    /// * `original_link` - The URL to be shortened
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`EzlimeApi::create_short_url`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # async fn example() -> Result<(), ezlime_rs::EzlimeApiError> {
+    /// use ezlime_rs::EzlimeApi;
+    ///
+    /// let api = EzlimeApi::new("your-api-key".to_string());
+    /// let (link, created) = api.ensure("https://example.com/long/url").await?;
+    /// println!("{} (new: {})", link.shortened_url, created);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn ensure(
+        &self,
+        original_link: &str,
+    ) -> Result<(CreatedLinkResponse, bool), EzlimeApiError> {
+        if let Some(cached) = self.cache.as_ref().and_then(|c| c.get(original_link)) {
+            return Ok((cached, false));
+        }
+
+        let (resp, status) = self
+            .create(&CreateLinkRequest {
                 url: original_link.to_string(),
+                ..Default::default()
             })
-            .send()
-            .await
-            .map_err(|e| EzlimeApiError::RequestError(e.to_string()))?
-            .json::<CreatedLinkResponse>()
-            .await
-            .map_err(|e| EzlimeApiError::DeserializationError(e.to_string()))?;
+            .await?;
 
-        Ok(resp.shortened_url)
+        if let Some(cache) = &self.cache {
+            cache.insert(original_link.to_string(), resp.clone());
+        }
+
+        Ok((resp, status == StatusCode::CREATED))
+    }
+
+    /// Shortens a URL like [`EzlimeApi::ensure`], returning the raw HTTP
+    /// status of the response instead of the derived flag, e.g. for metrics.
+    ///
+    /// Servers answer `201 Created` for a new link and `200 OK` for an
+    /// existing one, older servers `200 OK` for both. The cache is bypassed,
+    /// as cached links have no status.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`EzlimeApi::create_short_url`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # async fn example() -> Result<(), ezlime_rs::EzlimeApiError> {
+    /// use ezlime_rs::EzlimeApi;
+    ///
+    /// let api = EzlimeApi::new("your-api-key".to_string());
+    /// let (link, status) = api.create_detailed("https://example.com/long/url").await?;
+    /// println!("{} ({status})", link.shortened_url);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn create_detailed(
+        &self,
+        url: &str,
+    ) -> Result<(CreatedLinkResponse, StatusCode), EzlimeApiError> {
+        self.create(&CreateLinkRequest {
+            url: url.to_string(),
+            ..Default::default()
+        })
+        .await
+    }
+
+    /// Shortens a URL into a link that expires after `ttl`.
+    ///
+    /// The TTL is sent in whole seconds and the cache is bypassed, as the
+    /// link stops working once it expires. The server may clamp or reject
+    /// TTLs longer than it allows.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`EzlimeApi::create_short_url`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # async fn example() -> Result<(), ezlime_rs::EzlimeApiError> {
+    /// use ezlime_rs::EzlimeApi;
+    /// use std::time::Duration;
+    ///
+    /// let api = EzlimeApi::new("your-api-key".to_string());
+    /// let link = api
+    ///     .create_with_ttl("https://example.com/long/url", Duration::from_secs(3600))
+    ///     .await?;
+    /// println!("Shortened URL: {}", link.shortened_url);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn create_with_ttl(
+        &self,
+        url: &str,
+        ttl: Duration,
+    ) -> Result<CreatedLinkResponse, EzlimeApiError> {
+        let (resp, _) = self
+            .create(&CreateLinkRequest {
+                url: url.to_string(),
+                ttl_seconds: Some(ttl.as_secs()),
+                ..Default::default()
+            })
+            .await?;
+
+        Ok(resp)
+    }
+
+    /// Shortens a URL under the id `alias` of the caller's choice, e.g. a
+    /// vanity slug like `launch`.
+    ///
+    /// The alias must consist of 1 to [`MAX_ALIAS_LENGTH`] lowercase letters
+    /// and digits, which is checked before sending. Servers may allow fewer
+    /// characters and reserve ids of their own routes like `admin`, which
+    /// they answer with an error. The cache is bypassed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidAlias` error for aliases breaking the rules above,
+    /// an `AliasTaken` error if another link has the id already, and
+    /// otherwise the same errors as [`EzlimeApi::create_short_url`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # async fn example() -> Result<(), ezlime_rs::EzlimeApiError> {
+    /// use ezlime_rs::{EzlimeApi, EzlimeApiError};
+    ///
+    /// let api = EzlimeApi::new("your-api-key".to_string());
+    /// match api.create_with_alias("https://example.com/launch", "launch").await {
+    ///     Ok(link) => println!("Shortened URL: {}", link.shortened_url),
+    ///     Err(EzlimeApiError::AliasTaken) => println!("pick another alias"),
+    ///     Err(e) => return Err(e),
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn create_with_alias(
+        &self,
+        url: &str,
+        alias: &str,
+    ) -> Result<CreatedLinkResponse, EzlimeApiError> {
+        if alias.is_empty() || alias.len() > MAX_ALIAS_LENGTH {
+            return Err(EzlimeApiError::InvalidAlias(format!(
+                "must be 1 to {MAX_ALIAS_LENGTH} characters long"
+            )));
+        }
+        if !alias
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit())
+        {
+            return Err(EzlimeApiError::InvalidAlias(String::from(
+                "may only contain lowercase letters and digits",
+            )));
+        }
+
+        let (resp, _) = self
+            .create(&CreateLinkRequest {
+                url: url.to_string(),
+                alias: Some(alias.to_string()),
+                ..Default::default()
+            })
+            .await?;
+
+        Ok(resp)
+    }
+
+    /// Shortens a URL and fetches the stats of the resulting link.
+    ///
+    /// Convenient for showing a link together with its stats right away. If
+    /// the stats are not visible yet, e.g. when served by a lagging replica,
+    /// zeroed stats are returned instead of an error.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`EzlimeApi::create_short_url`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # async fn example() -> Result<(), ezlime_rs::EzlimeApiError> {
+    /// use ezlime_rs::EzlimeApi;
+    ///
+    /// let api = EzlimeApi::new("your-api-key".to_string());
+    /// let (link, stats) = api.create_with_stats("https://example.com/long/url").await?;
+    /// println!("{}: {:?} clicks", link.shortened_url, stats.click_count);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn create_with_stats(
+        &self,
+        url: &str,
+    ) -> Result<(CreatedLinkResponse, LinkStats), EzlimeApiError> {
+        let (link, _) = self.ensure(url).await?;
+
+        let stats_url: Url = Url::parse(&format!("{}/stats/{}", self.url, link.id))
+            .map_err(|e| EzlimeApiError::ConfigurationError(e.to_string()))?;
+
+        let resp = self
+            .client
+            .get(stats_url)
+            .send()
+            .await
+            .map_err(EzlimeApiError::RequestError)?;
+
+        if resp.status() == StatusCode::NOT_FOUND {
+            let stats = LinkStats {
+                id: link.id.clone(),
+                click_count: Some(0),
+                ..Default::default()
+            };
+            return Ok((link, stats));
+        }
+
+        let stats = resp
+            .json::<LinkStats>()
+            .await
+            .map_err(EzlimeApiError::DeserializationError)?;
+
+        Ok((link, stats))
+    }
+
+    async fn create(
+        &self,
+        request: &CreateLinkRequest,
+    ) -> Result<(CreatedLinkResponse, StatusCode), EzlimeApiError> {
+        let deadline = self
+            .retry_deadline
+            .map(|deadline| Instant::now() + deadline);
+        let mut backoff = INITIAL_RETRY_BACKOFF;
+        let mut retries = 0;
+        // a retry could store a second link
+        let max_retries = if request.is_deduplicated() {
+            self.retries
+        } else {
+            0
+        };
+
+        loop {
+            let timeout =
+                deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+
+            match self.try_create(request, timeout).await {
+                Err(e) if e.is_transient() && retries < max_retries => {
+                    let delay = self.retry_delay(backoff);
+                    if deadline.is_some_and(|deadline| Instant::now() + delay >= deadline) {
+                        return Err(e);
+                    }
+
+                    tokio::time::sleep(delay).await;
+                    backoff = (backoff * 2).min(MAX_RETRY_BACKOFF);
+                    retries += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn try_create(
+        &self,
+        request: &CreateLinkRequest,
+        timeout: Option<Duration>,
+    ) -> Result<(CreatedLinkResponse, StatusCode), EzlimeApiError> {
+        let url: Url = Url::parse(&format!("{}/link/create", self.url))
+            .map_err(|e| EzlimeApiError::ConfigurationError(e.to_string()))?;
+
+        let mut builder = self
+            .client
+            .post(url)
+            .header(&self.auth_header, self.key.clone())
+            .json(request);
+        if let Some(timeout) = timeout {
+            builder = builder.timeout(timeout);
+        }
+
+        let resp = builder.send().await.map_err(EzlimeApiError::RequestError)?;
+        let resp = check_rate_limit(resp).await?;
+
+        if resp.status() == StatusCode::CONFLICT {
+            return Err(EzlimeApiError::AliasTaken);
+        }
+
+        if resp.status() == StatusCode::BAD_REQUEST {
+            let err = resp.error_for_status_ref().unwrap_err();
+
+            return Err(match resp.json::<ErrorBody>().await {
+                Ok(body) if body.error == "validation_failed" => {
+                    EzlimeApiError::Validation(body.violations)
+                }
+                _ => EzlimeApiError::RequestError(err),
+            });
+        }
+
+        if resp.status().is_server_error() {
+            let err = resp.error_for_status_ref().unwrap_err();
+
+            if resp.status() == StatusCode::INSUFFICIENT_STORAGE
+                && let Ok(body) = resp.json::<ErrorBody>().await
+                && body.error == "id_space_exhausted"
+            {
+                return Err(EzlimeApiError::IdSpaceExhausted);
+            }
+
+            return Err(EzlimeApiError::RequestError(err));
+        }
+
+        let status = resp.status();
+
+        let resp = resp
+            .json::<CreatedLinkResponse>()
+            .await
+            .map_err(EzlimeApiError::DeserializationError)?;
+
+        Ok((resp, status))
+    }
+
+    /// Deletes several links at once, returning per id whether it was deleted.
+    ///
+    /// Only links created with this client's API key can be deleted. Ids of
+    /// unknown links or links of other keys are reported as not deleted
+    /// rather than failing the whole call. Deleted links are also dropped
+    /// from the cache (see [`EzlimeApi::with_cache`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`EzlimeApi::create_short_url`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # async fn example() -> Result<(), ezlime_rs::EzlimeApiError> {
+    /// use ezlime_rs::EzlimeApi;
+    ///
+    /// let api = EzlimeApi::new("your-api-key".to_string());
+    /// for (id, deleted) in api.delete_links(&["as9sud", "b8x2kq"]).await? {
+    ///     println!("{id}: {deleted}");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn delete_links(&self, ids: &[&str]) -> Result<Vec<(String, bool)>, EzlimeApiError> {
+        let url: Url = Url::parse(&format!("{}/link/delete", self.url))
+            .map_err(|e| EzlimeApiError::ConfigurationError(e.to_string()))?;
+
+        let resp = self
+            .client
+            .post(url)
+            .header(&self.auth_header, self.key.clone())
+            .json(&DeleteLinksRequest {
+                ids: ids.iter().map(|id| id.to_string()).collect(),
+            })
+            .send()
+            .await
+            .map_err(EzlimeApiError::RequestError)?;
+        let resp = check_rate_limit(resp)
+            .await?
+            .json::<DeleteLinksResponse>()
+            .await
+            .map_err(EzlimeApiError::DeserializationError)?;
+
+        if let Some(cache) = &self.cache {
+            let deleted: Vec<&str> = resp
+                .results
+                .iter()
+                .filter(|link| link.deleted)
+                .map(|link| link.id.as_str())
+                .collect();
+            cache.retain(|_, link| !deleted.contains(&link.id.as_str()));
+        }
+
+        Ok(resp
+            .results
+            .into_iter()
+            .map(|link| (link.id, link.deleted))
+            .collect())
+    }
+
+    /// Shortens several URLs in one request.
+    ///
+    /// URLs the server refuses, e.g. invalid ones or those of blocked hosts,
+    /// end up in [`BatchCreateResult::failures`] instead of failing the
+    /// others, so callers can retry just those. The cache is bypassed.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `RequestError` if the server refuses the whole batch, e.g.
+    /// for too many URLs, and a `RateLimited` error if the API key is rate
+    /// limited.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # async fn example() -> Result<(), ezlime_rs::EzlimeApiError> {
+    /// use ezlime_rs::EzlimeApi;
+    ///
+    /// let api = EzlimeApi::new("your-api-key".to_string());
+    /// let result = api
+    ///     .create_links(&["https://example.com/a", "https://example.com/b"])
+    ///     .await?;
+    /// for (url, e) in &result.failures {
+    ///     println!("{url} failed: {e}");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn create_links(&self, urls: &[&str]) -> Result<BatchCreateResult, EzlimeApiError> {
+        let url: Url = Url::parse(&format!("{}/link/create/batch", self.url))
+            .map_err(|e| EzlimeApiError::ConfigurationError(e.to_string()))?;
+
+        let resp = self
+            .client
+            .post(url)
+            .header(&self.auth_header, self.key.clone())
+            .json(&CreateLinksRequest {
+                links: urls
+                    .iter()
+                    .map(|url| CreateLinkRequest {
+                        url: url.to_string(),
+                        ..Default::default()
+                    })
+                    .collect(),
+            })
+            .send()
+            .await
+            .map_err(EzlimeApiError::RequestError)?;
+        let resp = check_rate_limit(resp)
+            .await?
+            .error_for_status()
+            .map_err(EzlimeApiError::RequestError)?
+            .json::<CreateLinksResponse>()
+            .await
+            .map_err(EzlimeApiError::DeserializationError)?;
+
+        let mut result = BatchCreateResult::default();
+        for outcome in resp.results {
+            match outcome.link {
+                Some(link) => result.successes.push(link),
+                None => {
+                    let code = outcome.error.unwrap_or_default();
+                    let error = match code.as_str() {
+                        "id_space_exhausted" => EzlimeApiError::IdSpaceExhausted,
+                        _ => EzlimeApiError::Rejected {
+                            code,
+                            message: outcome.message.unwrap_or_default(),
+                        },
+                    };
+                    result.failures.push((outcome.url, error));
+                }
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Shortens every URL of `urls` like [`EzlimeApi::ensure`], with up to
+    /// `concurrency` creates in flight, e.g. to import a large list.
+    ///
+    /// Results are yielded as the creates finish, not in the order of
+    /// `urls`, and carry their URL in
+    /// [`CreatedLinkResponse::original_url`]. A create answered with `429`
+    /// waits the time the server asks for and is tried again, up to 5 times,
+    /// so the import slows down instead of failing. A `concurrency` of `0`
+    /// is taken as `1`.
+    ///
+    /// # Errors
+    ///
+    /// Yields the same errors as [`EzlimeApi::create_short_url`] for the
+    /// URLs that failed, the others are created regardless.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # async fn example() -> Result<(), ezlime_rs::EzlimeApiError> {
+    /// use ezlime_rs::EzlimeApi;
+    /// use futures_util::StreamExt;
+    ///
+    /// let api = EzlimeApi::new("your-api-key".to_string());
+    /// let urls = (0..1000).map(|i| format!("https://example.com/{i}"));
+    /// let mut links = std::pin::pin!(api.create_all(urls, 8));
+    /// while let Some(link) = links.next().await {
+    ///     let link = link?;
+    ///     println!("{} -> {}", link.original_url, link.shortened_url);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn create_all<'a, I>(
+        &'a self,
+        urls: I,
+        concurrency: usize,
+    ) -> impl Stream<Item = Result<CreatedLinkResponse, EzlimeApiError>> + 'a
+    where
+        I: IntoIterator<Item = String>,
+        I::IntoIter: 'a,
+    {
+        stream::iter(urls)
+            .map(move |url| async move {
+                let mut rate_limited = 0;
+                loop {
+                    match self.ensure(&url).await {
+                        Err(EzlimeApiError::RateLimited { retry_after })
+                            if rate_limited < MAX_RATE_LIMITED_RETRIES =>
+                        {
+                            rate_limited += 1;
+                            tokio::time::sleep(retry_after.max(INITIAL_RETRY_BACKOFF)).await;
+                        }
+                        result => return result.map(|(link, _)| link),
+                    }
+                }
+            })
+            .buffer_unordered(concurrency.max(1))
+    }
+
+    /// Looks up which of `urls` have a link of this client's API key
+    /// already, returning `(url, id)` pairs in the order of `urls`, e.g. to
+    /// skip those in a bulk import.
+    ///
+    /// The server compares the URLs after normalizing them like it does on
+    /// creates. Invalid URLs and those only shortened by other keys have no
+    /// id.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `RequestError` if the server rejects the request, e.g. for
+    /// too many URLs.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # async fn example() -> Result<(), ezlime_rs::EzlimeApiError> {
+    /// use ezlime_rs::EzlimeApi;
+    ///
+    /// let api = EzlimeApi::new("your-api-key".to_string());
+    /// let urls = ["https://example.com/a", "https://example.com/b"];
+    /// for (url, id) in api.find_many(&urls).await? {
+    ///     if id.is_none() {
+    ///         api.create_short_url(&url).await?;
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn find_many(
+        &self,
+        urls: &[&str],
+    ) -> Result<Vec<(String, Option<String>)>, EzlimeApiError> {
+        let url: Url = Url::parse(&format!("{}/link/exists/batch", self.url))
+            .map_err(|e| EzlimeApiError::ConfigurationError(e.to_string()))?;
+
+        let resp = self
+            .client
+            .post(url)
+            .header(&self.auth_header, self.key.clone())
+            .json(&FindLinksRequest {
+                urls: urls.iter().map(|url| url.to_string()).collect(),
+            })
+            .send()
+            .await
+            .map_err(EzlimeApiError::RequestError)?;
+
+        Ok(check_rate_limit(resp)
+            .await?
+            .error_for_status()
+            .map_err(EzlimeApiError::RequestError)?
+            .json::<Vec<FoundLink>>()
+            .await
+            .map_err(EzlimeApiError::DeserializationError)?
+            .into_iter()
+            .map(|link| (link.url, link.id))
+            .collect())
+    }
+
+    /// Resolves several links at once, returning `(id, original_url)` pairs
+    /// in the order of `ids`.
+    ///
+    /// Unlike following the links, this does not count clicks. Links that
+    /// would not redirect, e.g. unknown, disabled or password protected ones,
+    /// are left out of the results rather than failing the whole call.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `RequestError` if the server rejects the request, e.g. for
+    /// too many ids.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # async fn example() -> Result<(), ezlime_rs::EzlimeApiError> {
+    /// use ezlime_rs::EzlimeApi;
+    ///
+    /// let api = EzlimeApi::new("your-api-key".to_string());
+    /// for (id, url) in api.resolve_many(&["as9sud", "b8x2kq"]).await? {
+    ///     println!("{id} -> {url}");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn resolve_many(
+        &self,
+        ids: &[&str],
+    ) -> Result<Vec<(String, String)>, EzlimeApiError> {
+        let url: Url = Url::parse(&format!("{}/link/resolve/batch", self.url))
+            .map_err(|e| EzlimeApiError::ConfigurationError(e.to_string()))?;
+
+        let resp = self
+            .client
+            .post(url)
+            .header(&self.auth_header, self.key.clone())
+            .json(&ResolveLinksRequest {
+                ids: ids.iter().map(|id| id.to_string()).collect(),
+            })
+            .send()
+            .await
+            .map_err(EzlimeApiError::RequestError)?;
+
+        Ok(check_rate_limit(resp)
+            .await?
+            .error_for_status()
+            .map_err(EzlimeApiError::RequestError)?
+            .json::<Vec<ResolvedLink>>()
+            .await
+            .map_err(EzlimeApiError::DeserializationError)?
+            .into_iter()
+            .map(|link| (link.id, link.original_url))
+            .collect())
+    }
+
+    /// Lists every link created with this client's API key, ordered by id.
+    ///
+    /// Links are requested a page at a time as the stream is polled. A failed
+    /// page ends the stream with its error, after the links of the previous
+    /// pages were yielded.
+    ///
+    /// # Errors
+    ///
+    /// Yields the same errors as [`EzlimeApi::create_short_url`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # async fn example() -> Result<(), ezlime_rs::EzlimeApiError> {
+    /// use ezlime_rs::EzlimeApi;
+    /// use futures_util::TryStreamExt;
+    ///
+    /// let api = EzlimeApi::new("your-api-key".to_string());
+    /// let mut links = std::pin::pin!(api.links_stream());
+    /// while let Some(link) = links.try_next().await? {
+    ///     println!("{} -> {}", link.id, link.url);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn links_stream(&self) -> impl Stream<Item = Result<OwnedLink, EzlimeApiError>> + '_ {
+        // `None` once the last page was fetched
+        stream::try_unfold(Some(String::new()), move |after| async move {
+            let Some(after) = after else {
+                return Ok(None);
+            };
+
+            let page = self.links_page(&after).await?;
+
+            Ok(Some((
+                stream::iter(page.links.into_iter().map(Ok)),
+                page.next,
+            )))
+        })
+        .try_flatten()
+    }
+
+    async fn links_page(&self, after: &str) -> Result<LinksPage, EzlimeApiError> {
+        let mut url: Url = Url::parse(&format!("{}/link/list", self.url))
+            .map_err(|e| EzlimeApiError::ConfigurationError(e.to_string()))?;
+        url.query_pairs_mut()
+            .append_pair("limit", &LIST_PAGE_SIZE.to_string());
+        if !after.is_empty() {
+            url.query_pairs_mut().append_pair("after", after);
+        }
+
+        let resp = self
+            .client
+            .get(url)
+            .header(&self.auth_header, self.key.clone())
+            .send()
+            .await
+            .map_err(EzlimeApiError::RequestError)?;
+
+        check_rate_limit(resp)
+            .await?
+            .error_for_status()
+            .map_err(EzlimeApiError::RequestError)?
+            .json::<LinksPage>()
+            .await
+            .map_err(EzlimeApiError::DeserializationError)
+    }
+
+    /// Fetches the daily clicks of a link created with the client's API key.
+    ///
+    /// `from` and `to` are days as `YYYY-MM-DD` (UTC), both included. Every
+    /// day of the range is returned in order, days without clicks as `0`.
+    /// The server caps how many days can be fetched at once.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `RequestError` if the link does not exist, belongs to
+    /// another key or the range is rejected.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # async fn example() -> Result<(), ezlime_rs::EzlimeApiError> {
+    /// use ezlime_rs::EzlimeApi;
+    ///
+    /// let api = EzlimeApi::new("your-api-key".to_string());
+    /// for day in api.timeseries("as9sud", "2025-01-01", "2025-01-31").await? {
+    ///     println!("{}: {}", day.date, day.clicks);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn timeseries(
+        &self,
+        id: &str,
+        from: &str,
+        to: &str,
+    ) -> Result<Vec<DailyClicks>, EzlimeApiError> {
+        let mut url: Url = Url::parse(&format!("{}/link/{id}/timeseries", self.url))
+            .map_err(|e| EzlimeApiError::ConfigurationError(e.to_string()))?;
+        url.query_pairs_mut()
+            .append_pair("from", from)
+            .append_pair("to", to);
+
+        let resp = self
+            .client
+            .get(url)
+            .header(&self.auth_header, self.key.clone())
+            .send()
+            .await
+            .map_err(EzlimeApiError::RequestError)?;
+
+        check_rate_limit(resp)
+            .await?
+            .error_for_status()
+            .map_err(EzlimeApiError::RequestError)?
+            .json::<Vec<DailyClicks>>()
+            .await
+            .map_err(EzlimeApiError::DeserializationError)
+    }
+
+    /// Builds the short URL of a link id without a request to the server.
+    ///
+    /// The URL is formatted like the server does, appending the id to the
+    /// configured endpoint URL (including any base path), so this only matches
+    /// deployments serving their short links from the API endpoint.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use ezlime_rs::EzlimeApi;
+    ///
+    /// let api = EzlimeApi::new("your-api-key".to_string());
+    /// assert_eq!(api.short_url_for("as9sud"), "https://ezli.me/as9sud");
+    /// ```
+    pub fn short_url_for(&self, id: &str) -> String {
+        short_url(&self.url, id)
+    }
+
+    /// Checks the configured API key without creating a link.
+    ///
+    /// A key the server rejects is reported as `valid: false` rather than as
+    /// an error.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`EzlimeApi::create_short_url`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # async fn example() -> Result<(), ezlime_rs::EzlimeApiError> {
+    /// use ezlime_rs::EzlimeApi;
+    ///
+    /// let api = EzlimeApi::new("your-api-key".to_string());
+    /// if !api.check_key().await?.valid {
+    ///     println!("invalid API key");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn check_key(&self) -> Result<KeyInfo, EzlimeApiError> {
+        let url: Url = Url::parse(&format!("{}/auth/check", self.url))
+            .map_err(|e| EzlimeApiError::ConfigurationError(e.to_string()))?;
+
+        let resp = self
+            .client
+            .get(url)
+            .header(&self.auth_header, self.key.clone())
+            .send()
+            .await
+            .map_err(EzlimeApiError::RequestError)?;
+        let resp = check_rate_limit(resp).await?;
+
+        if resp.status() == StatusCode::UNAUTHORIZED {
+            return Ok(KeyInfo::default());
+        }
+
+        resp.json::<KeyInfo>()
+            .await
+            .map_err(EzlimeApiError::DeserializationError)
+    }
+
+    /// Resolves a short link and follows the redirects of its destination.
+    ///
+    /// The link is resolved with a `HEAD` request, which the server does not
+    /// count as a click. At most [`INSPECT_MAX_HOPS`] redirects of the
+    /// destination are followed, each with a `GET` request whose body is not
+    /// read. A chain still redirecting after that is reported as `truncated`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `RequestError` if the link does not exist or a hop fails,
+    /// and a `ConfigurationError` if the endpoint does not redirect at all.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # async fn example() -> Result<(), ezlime_rs::EzlimeApiError> {
+    /// use ezlime_rs::EzlimeApi;
+    ///
+    /// let api = EzlimeApi::new("your-api-key".to_string());
+    /// let inspection = api.inspect("as9sud").await?;
+    /// println!("as9sud ends up at {}", inspection.final_url);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn inspect(&self, id: &str) -> Result<LinkInspection, EzlimeApiError> {
+        let client = self
+            .client_builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .map_err(EzlimeApiError::RequestError)?;
+
+        let destination = self.resolve_with(&client, id).await?;
+
+        let mut chain = vec![destination.to_string()];
+        let mut current = destination;
+        let mut truncated = false;
+
+        for hop in 0..=INSPECT_MAX_HOPS {
+            let resp = client
+                .get(current.clone())
+                .send()
+                .await
+                .map_err(EzlimeApiError::RequestError)?;
+
+            let Some(next) = redirect_target(&current, &resp) else {
+                break;
+            };
+
+            if hop == INSPECT_MAX_HOPS {
+                truncated = true;
+                break;
+            }
+
+            chain.push(next.to_string());
+            current = next;
+        }
+
+        Ok(LinkInspection {
+            id: id.to_string(),
+            final_url: current.to_string(),
+            chain,
+            truncated,
+        })
+    }
+
+    /// Resolves a short link like [`EzlimeApi::inspect`] and returns its
+    /// destination if the host is one of `allowed_hosts`, e.g. before
+    /// security tooling fetches it.
+    ///
+    /// Hosts are compared exactly and case-insensitively, subdomains have to
+    /// be listed themselves. Redirects of the destination are not followed,
+    /// use [`EzlimeApi::inspect`] to check where they lead.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `DisallowedDestination` error if the host is not allowed,
+    /// otherwise the same errors as [`EzlimeApi::inspect`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # async fn example() -> Result<(), ezlime_rs::EzlimeApiError> {
+    /// use ezlime_rs::EzlimeApi;
+    ///
+    /// let api = EzlimeApi::new("your-api-key".to_string());
+    /// let url = api.resolve_checked("as9sud", &["rustunit.com"]).await?;
+    /// println!("as9sud is safe to open: {url}");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn resolve_checked(
+        &self,
+        id: &str,
+        allowed_hosts: &[&str],
+    ) -> Result<String, EzlimeApiError> {
+        let client = self
+            .client_builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .map_err(EzlimeApiError::RequestError)?;
+
+        let destination = self.resolve_with(&client, id).await?;
+        let host = destination.host_str().unwrap_or_default();
+
+        if !allowed_hosts
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(host))
+        {
+            return Err(EzlimeApiError::DisallowedDestination {
+                host: host.to_string(),
+            });
+        }
+
+        Ok(destination.to_string())
+    }
+
+    /// The destination of the link `id`, asked for with a `HEAD` request of
+    /// `client`, which must not follow redirects.
+    async fn resolve_with(
+        &self,
+        client: &reqwest::Client,
+        id: &str,
+    ) -> Result<Url, EzlimeApiError> {
+        let url: Url = Url::parse(&self.short_url_for(id))
+            .map_err(|e| EzlimeApiError::ConfigurationError(e.to_string()))?;
+
+        let resp = client
+            .head(url.clone())
+            .send()
+            .await
+            .map_err(EzlimeApiError::RequestError)?
+            .error_for_status()
+            .map_err(EzlimeApiError::RequestError)?;
+
+        redirect_target(&url, &resp)
+            .ok_or_else(|| EzlimeApiError::ConfigurationError(format!("{url} did not redirect")))
+    }
+
+    /// Opens a connection to the server ahead of the first real request, so
+    /// a latency sensitive first create does not wait for DNS and the TLS
+    /// handshake.
+    ///
+    /// This is optional, every request connects on its own if needed. It
+    /// sends a `HEAD /livez`, which the server answers without touching its
+    /// database, and succeeds on any response. The connection stays in the
+    /// pool until it idled for the timeout of
+    /// [`EzlimeApi::with_pool_idle_timeout`].
+    ///
+    /// # Errors
+    ///
+    /// Returns a `RequestError` if the server can't be reached.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # async fn example() -> Result<(), ezlime_rs::EzlimeApiError> {
+    /// use ezlime_rs::EzlimeApi;
+    ///
+    /// let api = EzlimeApi::new("your-api-key".to_string());
+    /// api.preconnect().await?;
+    /// // reuses the connection
+    /// api.create_short_url("https://example.com/long/url").await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn preconnect(&self) -> Result<(), EzlimeApiError> {
+        let url: Url = Url::parse(&format!("{}/livez", self.url))
+            .map_err(|e| EzlimeApiError::ConfigurationError(e.to_string()))?;
+
+        self.client
+            .head(url)
+            .send()
+            .await
+            .map_err(EzlimeApiError::RequestError)?;
+
+        Ok(())
+    }
+
+    /// Fetches the URL validation rules of the server.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`EzlimeApi::create_short_url`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # async fn example() -> Result<(), ezlime_rs::EzlimeApiError> {
+    /// use ezlime_rs::EzlimeApi;
+    ///
+    /// let api = EzlimeApi::new("your-api-key".to_string());
+    /// let rules = api.rules().await?;
+    /// assert!(rules.allowed_schemes.iter().any(|s| s == "https"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn rules(&self) -> Result<ValidationRules, EzlimeApiError> {
+        let url: Url = Url::parse(&format!("{}/rules", self.url))
+            .map_err(|e| EzlimeApiError::ConfigurationError(e.to_string()))?;
+
+        self.client
+            .get(url)
+            .send()
+            .await
+            .map_err(EzlimeApiError::RequestError)?
+            .json::<ValidationRules>()
+            .await
+            .map_err(EzlimeApiError::DeserializationError)
+    }
+}
+
+/// The `Location` of a redirect response to a request for `url`.
+fn redirect_target(url: &Url, resp: &reqwest::Response) -> Option<Url> {
+    if !resp.status().is_redirection() {
+        return None;
+    }
+
+    let location = resp
+        .headers()
+        .get(reqwest::header::LOCATION)?
+        .to_str()
+        .ok()?;
+
+    url.join(location).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{
+            body_json, body_partial_json, header, method, path, query_param, query_param_is_missing,
+        },
+    };
+
+    fn created(server: &MockServer, id: &str, original_url: &str) -> ResponseTemplate {
+        link_response(200, server, id, original_url)
+    }
+
+    fn link_response(
+        status: u16,
+        server: &MockServer,
+        id: &str,
+        original_url: &str,
+    ) -> ResponseTemplate {
+        ResponseTemplate::new(status).set_body_json(CreatedLinkResponse::new(
+            id.to_string(),
+            &server.uri(),
+            original_url.to_string(),
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_cached_create_skips_request() {
+        let server = MockServer::start().await;
+        let original_url = "https://www.rustunit.com";
+
+        Mock::given(method("POST"))
+            .and(path("/link/create"))
+            .and(header("Authorization", "key"))
+            .respond_with(created(&server, "as9sud", original_url))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let api = EzlimeApi::new("key".to_string())
+            .with_url(&server.uri())
+            .with_cache(10);
+
+        let first = api.create_short_url(original_url).await.unwrap();
+        let second = api.create_short_url(original_url).await.unwrap();
+
+        assert_eq!(first, format!("{}/as9sud", server.uri()));
+        assert_eq!(first, second);
+    }
+
+    #[tokio::test]
+    async fn test_uncached_create_requests_every_time() {
+        let server = MockServer::start().await;
+        let original_url = "https://www.rustunit.com";
+
+        Mock::given(method("POST"))
+            .and(path("/link/create"))
+            .respond_with(created(&server, "as9sud", original_url))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let api = EzlimeApi::new("key".to_string()).with_url(&server.uri());
+
+        api.create_short_url(original_url).await.unwrap();
+        api.create_short_url(original_url).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_ensure_new_link() {
+        let server = MockServer::start().await;
+        let original_url = "https://www.rustunit.com";
+
+        Mock::given(method("POST"))
+            .and(path("/link/create"))
+            .respond_with(link_response(201, &server, "as9sud", original_url))
+            .mount(&server)
+            .await;
+
+        let api = EzlimeApi::new("key".to_string()).with_url(&server.uri());
+
+        let (link, created) = api.ensure(original_url).await.unwrap();
+
+        assert!(created);
+        assert_eq!(link.id, "as9sud");
+        assert_eq!(link.original_url, original_url);
+    }
+
+    #[tokio::test]
+    async fn test_ensure_existing_link() {
+        let server = MockServer::start().await;
+        let original_url = "https://www.rustunit.com";
+
+        Mock::given(method("POST"))
+            .and(path("/link/create"))
+            .respond_with(link_response(200, &server, "as9sud", original_url))
+            .mount(&server)
+            .await;
+
+        let api = EzlimeApi::new("key".to_string()).with_url(&server.uri());
+
+        let (link, created) = api.ensure(original_url).await.unwrap();
+
+        assert!(!created);
+        assert_eq!(link.id, "as9sud");
+    }
+
+    #[tokio::test]
+    async fn test_create_validation_failed() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/link/create"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+                "error": "validation_failed",
+                "violations": ["scheme_not_allowed", "url_too_long"],
+            })))
+            .mount(&server)
+            .await;
+
+        let api = EzlimeApi::new("key".to_string()).with_url(&server.uri());
+
+        let err = api
+            .create_short_url("ftp://example.com/far/too/long")
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            &err,
+            EzlimeApiError::Validation(violations)
+                if violations == &["scheme_not_allowed", "url_too_long"]
+        ));
+        assert_eq!(err.code(), "validation_failed");
+    }
+
+    #[tokio::test]
+    async fn test_create_detailed() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/link/create"))
+            .and(body_json(
+                serde_json::json!({"url": "https://www.rustunit.com"}),
+            ))
+            .respond_with(link_response(
+                201,
+                &server,
+                "as9sud",
+                "https://www.rustunit.com",
+            ))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/link/create"))
+            .and(body_json(
+                serde_json::json!({"url": "https://www.rustunit.com/blog"}),
+            ))
+            .respond_with(link_response(
+                200,
+                &server,
+                "b8x2kq",
+                "https://www.rustunit.com/blog",
+            ))
+            .mount(&server)
+            .await;
+
+        let api = EzlimeApi::new("key".to_string()).with_url(&server.uri());
+
+        let (link, status) = api
+            .create_detailed("https://www.rustunit.com")
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(link.id, "as9sud");
+
+        let (link, status) = api
+            .create_detailed("https://www.rustunit.com/blog")
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(link.id, "b8x2kq");
+    }
+
+    #[tokio::test]
+    async fn test_create_with_ttl() {
+        let server = MockServer::start().await;
+        let original_url = "https://www.rustunit.com";
+
+        Mock::given(method("POST"))
+            .and(path("/link/create"))
+            .and(body_json(serde_json::json!({
+                "url": original_url,
+                "ttl_seconds": 3600,
+            })))
+            .respond_with(created(&server, "as9sud", original_url))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let api = EzlimeApi::new("key".to_string()).with_url(&server.uri());
+
+        let link = api
+            .create_with_ttl(original_url, Duration::from_secs(3600))
+            .await
+            .unwrap();
+
+        assert_eq!(link.id, "as9sud");
+    }
+
+    #[tokio::test]
+    async fn test_create_with_stats() {
+        let server = MockServer::start().await;
+        let original_url = "https://www.rustunit.com";
+
+        Mock::given(method("POST"))
+            .and(path("/link/create"))
+            .respond_with(created(&server, "as9sud", original_url))
+            .mount(&server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/stats/as9sud"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "id": "as9sud",
+                "click_count": 3,
+                "created_at": "2025-01-01T00:00:00Z",
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let api = EzlimeApi::new("key".to_string()).with_url(&server.uri());
+
+        let (link, stats) = api.create_with_stats(original_url).await.unwrap();
+
+        assert_eq!(link.id, "as9sud");
+        assert_eq!(
+            stats,
+            LinkStats {
+                id: "as9sud".to_string(),
+                click_count: Some(3),
+                last_used: None,
+                created_at: Some("2025-01-01T00:00:00Z".to_string()),
+                title: None,
+                first_used: None,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_with_stats_not_visible_yet() {
+        let server = MockServer::start().await;
+        let original_url = "https://www.rustunit.com";
+
+        Mock::given(method("POST"))
+            .and(path("/link/create"))
+            .respond_with(created(&server, "as9sud", original_url))
+            .mount(&server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/stats/as9sud"))
+            .respond_with(ResponseTemplate::new(404))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let api = EzlimeApi::new("key".to_string()).with_url(&server.uri());
+
+        let (_, stats) = api.create_with_stats(original_url).await.unwrap();
+
+        assert_eq!(stats.id, "as9sud");
+        assert_eq!(stats.click_count, Some(0));
+    }
+
+    #[tokio::test]
+    async fn test_delete_links() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/link/delete"))
+            .and(header("Authorization", "key"))
+            .and(body_json(serde_json::json!({"ids": ["as9sud", "b8x2kq"]})))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "results": [
+                    {"id": "as9sud", "deleted": true},
+                    {"id": "b8x2kq", "deleted": false},
+                ]
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let api = EzlimeApi::new("key".to_string()).with_url(&server.uri());
+
+        let results = api.delete_links(&["as9sud", "b8x2kq"]).await.unwrap();
+
+        assert_eq!(
+            results,
+            [("as9sud".to_string(), true), ("b8x2kq".to_string(), false)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_links() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/link/create/batch"))
+            .and(header("Authorization", "key"))
+            .and(body_json(serde_json::json!({"links": [
+                {"url": "https://www.rustunit.com"},
+                {"url": "https://evil.com"},
+                {"url": "https://www.rustunit.com/blog"},
+            ]})))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "results": [
+                    {
+                        "url": "https://www.rustunit.com",
+                        "link": {
+                            "id": "as9sud",
+                            "shortened_url": format!("{}/as9sud", server.uri()),
+                            "original_url": "https://www.rustunit.com",
+                        },
+                    },
+                    {
+                        "url": "https://evil.com",
+                        "error": "host_blocked",
+                        "message": "destination host is blocked",
+                    },
+                    {
+                        "url": "https://www.rustunit.com/blog",
+                        "error": "id_space_exhausted",
+                        "message": "id space exhausted",
+                    },
+                ]
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let api = EzlimeApi::new("key".to_string()).with_url(&server.uri());
+
+        let result = api
+            .create_links(&[
+                "https://www.rustunit.com",
+                "https://evil.com",
+                "https://www.rustunit.com/blog",
+            ])
+            .await
+            .unwrap();
+
+        assert_eq!(result.successes.len(), 1);
+        assert_eq!(result.successes[0].id, "as9sud");
+
+        assert_eq!(result.failures.len(), 2);
+        let (url, error) = &result.failures[0];
+        assert_eq!(url, "https://evil.com");
+        assert!(matches!(
+            error,
+            EzlimeApiError::Rejected { code, message }
+                if code == "host_blocked" && message == "destination host is blocked"
+        ));
+        let (url, error) = &result.failures[1];
+        assert_eq!(url, "https://www.rustunit.com/blog");
+        assert!(matches!(error, EzlimeApiError::IdSpaceExhausted));
+    }
+
+    #[tokio::test]
+    async fn test_create_all() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/link/create"))
+            .respond_with(ResponseTemplate::new(429).set_body_json(
+                serde_json::json!({ "error": "rate_limited", "retry_after_secs": 0 }),
+            ))
+            .up_to_n_times(3)
+            .mount(&server)
+            .await;
+        let prefix = server.uri();
+        Mock::given(method("POST"))
+            .and(path("/link/create"))
+            .respond_with(move |request: &wiremock::Request| {
+                let request: CreateLinkRequest = request.body_json().unwrap();
+                let id = request.url.rsplit('/').next().unwrap().to_string();
+                ResponseTemplate::new(201).set_body_json(CreatedLinkResponse::new(
+                    id,
+                    &prefix,
+                    request.url,
+                ))
+            })
+            .expect(50)
+            .mount(&server)
+            .await;
+
+        let api = EzlimeApi::new("key".to_string()).with_url(&server.uri());
+
+        let urls = (0..50).map(|i| format!("https://www.rustunit.com/{i}"));
+        let links: Vec<_> = api.create_all(urls, 8).collect().await;
+
+        assert_eq!(links.len(), 50);
+        let mut urls: Vec<_> = links
+            .into_iter()
+            .map(|link| link.unwrap().original_url)
+            .collect();
+        urls.sort();
+        let mut expected: Vec<_> = (0..50)
+            .map(|i| format!("https://www.rustunit.com/{i}"))
+            .collect();
+        expected.sort();
+        assert_eq!(urls, expected);
+    }
+
+    #[tokio::test]
+    async fn test_find_many() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/link/exists/batch"))
+            .and(header("Authorization", "key"))
+            .and(body_json(serde_json::json!({"urls": [
+                "https://www.rustunit.com",
+                "https://www.rustunit.com/new",
+            ]})))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                {"url": "https://www.rustunit.com", "id": "as9sud"},
+                {"url": "https://www.rustunit.com/new", "id": null},
+            ])))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let api = EzlimeApi::new("key".to_string()).with_url(&server.uri());
+
+        let found = api
+            .find_many(&["https://www.rustunit.com", "https://www.rustunit.com/new"])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            found,
+            [
+                (
+                    "https://www.rustunit.com".to_string(),
+                    Some("as9sud".to_string())
+                ),
+                ("https://www.rustunit.com/new".to_string(), None),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resolve_many() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/link/resolve/batch"))
+            .and(header("Authorization", "key"))
+            .and(body_json(
+                serde_json::json!({"ids": ["as9sud", "missing", "b8x2kq"]}),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                {"id": "as9sud", "original_url": "https://www.rustunit.com"},
+                {"id": "b8x2kq", "original_url": "https://www.rustunit.com/blog"},
+            ])))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let api = EzlimeApi::new("key".to_string()).with_url(&server.uri());
+
+        let results = api
+            .resolve_many(&["as9sud", "missing", "b8x2kq"])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            results,
+            [
+                ("as9sud".to_string(), "https://www.rustunit.com".to_string()),
+                (
+                    "b8x2kq".to_string(),
+                    "https://www.rustunit.com/blog".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_custom_auth_header() {
+        let server = MockServer::start().await;
+        let original_url = "https://www.rustunit.com";
+
+        Mock::given(method("POST"))
+            .and(path("/link/create"))
+            .and(header("X-Api-Key", "key"))
+            .respond_with(created(&server, "as9sud", original_url))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let api = EzlimeApi::new("key".to_string())
+            .with_url(&server.uri())
+            .with_auth_header("X-Api-Key");
+
+        api.create_short_url(original_url).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rules() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/rules"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "allowed_schemes": ["http", "https"],
+                "max_url_length": 2048,
+                "block_private_hosts": true,
+            })))
+            .mount(&server)
+            .await;
+
+        let api = EzlimeApi::new("key".to_string()).with_url(&server.uri());
+
+        let rules = api.rules().await.unwrap();
+
+        assert_eq!(
+            rules,
+            ValidationRules {
+                allowed_schemes: vec!["http".to_string(), "https".to_string()],
+                max_url_length: 2048,
+                block_private_hosts: true,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_check_key() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/auth/check"))
+            .and(header("Authorization", "valid"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "valid": true,
+                "scopes": [],
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/auth/check"))
+            .respond_with(ResponseTemplate::new(401))
+            .mount(&server)
+            .await;
+
+        let valid = EzlimeApi::new("valid".to_string()).with_url(&server.uri());
+        assert_eq!(
+            valid.check_key().await.unwrap(),
+            KeyInfo {
+                valid: true,
+                scopes: Vec::new(),
+            }
+        );
+
+        let invalid = EzlimeApi::new("invalid".to_string()).with_url(&server.uri());
+        assert!(!invalid.check_key().await.unwrap().valid);
+    }
+
+    #[tokio::test]
+    async fn test_timeseries() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/link/as9sud/timeseries"))
+            .and(header("Authorization", "key"))
+            .and(query_param("from", "2025-01-01"))
+            .and(query_param("to", "2025-01-02"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                { "date": "2025-01-01", "clicks": 3 },
+                { "date": "2025-01-02", "clicks": 0 },
+            ])))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/link/other/timeseries"))
+            .respond_with(ResponseTemplate::new(403))
+            .mount(&server)
+            .await;
+
+        let api = EzlimeApi::new("key".to_string()).with_url(&server.uri());
+        assert_eq!(
+            api.timeseries("as9sud", "2025-01-01", "2025-01-02")
+                .await
+                .unwrap(),
+            [("2025-01-01", 3), ("2025-01-02", 0)].map(|(date, clicks)| DailyClicks {
+                date: date.to_string(),
+                clicks,
+            })
+        );
+
+        let err = api
+            .timeseries("other", "2025-01-01", "2025-01-02")
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), "request");
+    }
+
+    #[tokio::test]
+    async fn test_timeout_error_source() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/rules"))
+            .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(5)))
+            .mount(&server)
+            .await;
+
+        let mut api = EzlimeApi::new("key".to_string()).with_url(&server.uri());
+        api.client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_millis(50))
+            .build()
+            .unwrap();
+
+        let err = api.rules().await.unwrap_err();
+
+        assert!(err.is_timeout());
+        assert!(!err.is_connect());
+
+        let source = std::error::Error::source(&err).expect("source is exposed");
+        let source = source.downcast_ref::<reqwest::Error>().unwrap();
+        assert!(source.is_timeout());
+        assert!(err.to_string().starts_with("Request error: "));
+    }
+
+    #[tokio::test]
+    async fn test_retry_transient_errors() {
+        let server = MockServer::start().await;
+        let original_url = "https://www.rustunit.com";
+
+        Mock::given(method("POST"))
+            .and(path("/link/create"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(2)
+            .expect(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/link/create"))
+            .respond_with(created(&server, "as9sud", original_url))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let api = EzlimeApi::new("key".to_string())
+            .with_url(&server.uri())
+            .with_retries(2);
+
+        let (link, _) = api.ensure(original_url).await.unwrap();
+        assert_eq!(link.id, "as9sud");
+    }
+
+    #[tokio::test]
+    async fn test_no_retry_without_dedup() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/link/create"))
+            .respond_with(ResponseTemplate::new(503))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let api = EzlimeApi::new("key".to_string())
+            .with_url(&server.uri())
+            .with_retries(3);
+
+        // the link may have been stored before the server failed
+        let err = api
+            .create_with_ttl("https://www.rustunit.com", Duration::from_secs(60))
+            .await
+            .unwrap_err();
+        assert!(err.is_transient());
+    }
+
+    #[test]
+    fn test_retry_jitter() {
+        let api = EzlimeApi::new("key".to_string());
+
+        for backoff in [INITIAL_RETRY_BACKOFF, INITIAL_RETRY_BACKOFF * 8] {
+            let delays: Vec<_> = (0..100).map(|_| api.retry_delay(backoff)).collect();
+
+            assert!(delays.iter().all(|delay| *delay <= backoff));
+            // spread out, not just a shorter fixed delay
+            assert!(delays.iter().any(|delay| *delay < backoff / 2));
+            assert!(delays.iter().any(|delay| *delay > backoff / 2));
+        }
+
+        let api = api.with_retry_jitter(false);
+        assert_eq!(
+            api.retry_delay(INITIAL_RETRY_BACKOFF),
+            INITIAL_RETRY_BACKOFF
+        );
+    }
+
+    #[tokio::test]
+    async fn test_retry_deadline() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/link/create"))
+            .respond_with(ResponseTemplate::new(503).set_delay(Duration::from_millis(300)))
+            .mount(&server)
+            .await;
+
+        let api = EzlimeApi::new("key".to_string())
+            .with_url(&server.uri())
+            .with_retries(100)
+            .with_retry_deadline(Duration::from_secs(1));
+
+        let started = Instant::now();
+        let err = api
+            .create_short_url("https://www.rustunit.com")
+            .await
+            .unwrap_err();
+
+        assert!(started.elapsed() < Duration::from_millis(1200));
+        assert!(err.is_timeout() || err.is_transient());
+    }
+
+    fn owned_link(id: &str) -> serde_json::Value {
+        serde_json::json!({
+            "id": id,
+            "url": format!("https://www.rustunit.com/{id}"),
+            "created_at": "2025-01-01T00:00:00Z",
+            "click_count": 0,
+            "last_used": "2025-01-01T00:00:00Z",
+            "source": "api",
+        })
+    }
+
+    #[tokio::test]
+    async fn test_links_stream() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/link/list"))
+            .and(header("Authorization", "key"))
+            .and(query_param_is_missing("after"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "links": [owned_link("a1"), owned_link("b2")],
+                "next": "b2",
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/link/list"))
+            .and(query_param("after", "b2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "links": [owned_link("c3")],
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let api = EzlimeApi::new("key".to_string()).with_url(&server.uri());
+
+        let links: Vec<OwnedLink> = api.links_stream().try_collect().await.unwrap();
+        let ids: Vec<&str> = links.iter().map(|link| link.id.as_str()).collect();
+        assert_eq!(ids, ["a1", "b2", "c3"]);
+        assert_eq!(links[2].url, "https://www.rustunit.com/c3");
+    }
+
+    #[tokio::test]
+    async fn test_links_stream_error() {
+        use futures_util::StreamExt;
+
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/link/list"))
+            .and(query_param_is_missing("after"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "links": [owned_link("a1")],
+                "next": "a1",
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/link/list"))
+            .and(query_param("after", "a1"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&server)
+            .await;
+
+        let api = EzlimeApi::new("key".to_string()).with_url(&server.uri());
+        let links: Vec<_> = api.links_stream().collect().await;
+
+        assert_eq!(links.len(), 2);
+        assert_eq!(links[0].as_ref().unwrap().id, "a1");
+        assert_eq!(links[1].as_ref().unwrap_err().code(), "request");
+    }
+
+    #[tokio::test]
+    async fn test_id_space_exhausted() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/link/create"))
+            .respond_with(
+                ResponseTemplate::new(507)
+                    .set_body_json(serde_json::json!({ "error": "id_space_exhausted" })),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let api = EzlimeApi::new("key".to_string())
+            .with_url(&server.uri())
+            .with_retries(3);
+
+        let err = api
+            .create_short_url("https://www.rustunit.com")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, EzlimeApiError::IdSpaceExhausted));
+        assert!(!err.is_transient());
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/link/create"))
+            .respond_with(
+                ResponseTemplate::new(429)
+                    .insert_header("Retry-After", "7")
+                    .set_body_json(
+                        serde_json::json!({ "error": "rate_limited", "retry_after_secs": 7 }),
+                    ),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let api = EzlimeApi::new("key".to_string())
+            .with_url(&server.uri())
+            .with_retries(3);
+
+        let err = api
+            .create_short_url("https://www.rustunit.com")
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            EzlimeApiError::RateLimited { retry_after } if retry_after == Duration::from_secs(7)
+        ));
+        assert_eq!(err.code(), "rate_limited");
+    }
+
+    /// Answers `/link/create` over TLS with a self-signed certificate for
+    /// `localhost`, returning the port.
+    async fn self_signed_server() -> u16 {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio_rustls::rustls::{self, pki_types::PrivatePkcs8KeyDer};
+
+        let cert = rcgen::generate_simple_self_signed(vec![String::from("localhost")]).unwrap();
+        let config = rustls::ServerConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![cert.cert.der().clone()],
+            PrivatePkcs8KeyDer::from(cert.key_pair.serialize_der()).into(),
+        )
+        .unwrap();
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let acceptor = acceptor.clone();
+
+                tokio::spawn(async move {
+                    // the handshake of a verifying client fails
+                    let Ok(mut stream) = acceptor.accept(stream).await else {
+                        return;
+                    };
+
+                    // read the headers and as much body as they announce
+                    let mut request = Vec::new();
+                    let mut buf = [0; 4096];
+                    loop {
+                        let read = stream.read(&mut buf).await.unwrap();
+                        request.extend_from_slice(&buf[..read]);
+
+                        let text = String::from_utf8_lossy(&request).to_ascii_lowercase();
+                        if let Some(end) = text.find("\r\n\r\n") {
+                            let length: usize = text
+                                .lines()
+                                .find_map(|line| line.strip_prefix("content-length:"))
+                                .map_or(0, |length| length.trim().parse().unwrap());
+                            if request.len() >= end + 4 + length {
+                                break;
+                            }
+                        }
+                        if read == 0 {
+                            return;
+                        }
+                    }
+
+                    let body = serde_json::to_string(&CreatedLinkResponse::new(
+                        String::from("as9sud"),
+                        &format!("https://localhost:{port}"),
+                        String::from("https://www.rustunit.com"),
+                    ))
+                    .unwrap();
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                        body.len()
+                    );
+                    stream.write_all(response.as_bytes()).await.unwrap();
+                    stream.shutdown().await.ok();
+                });
+            }
+        });
+
+        port
+    }
+
+    /// Dev-only option, tested against a local server with a self-signed
+    /// certificate.
+    #[tokio::test]
+    async fn test_default_headers() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/link/create"))
+            .and(header("X-Proxy-Auth", "proxy-secret"))
+            .and(header("Authorization", "key"))
+            .respond_with(created(&server, "as9sud", "https://www.rustunit.com"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "X-Proxy-Auth",
+            reqwest::header::HeaderValue::from_static("proxy-secret"),
+        );
+        headers.insert(
+            "Authorization",
+            reqwest::header::HeaderValue::from_static("not-the-key"),
+        );
+
+        let api = EzlimeApi::new("key".to_string())
+            .with_url(&server.uri())
+            .with_default_headers(headers)
+            .with_danger_accept_invalid_certs(false);
+
+        api.create_short_url("https://www.rustunit.com")
+            .await
+            .unwrap();
+    }
+
+    /// Forwards connections to `target`, counting them.
+    async fn counting_proxy(target: std::net::SocketAddr) -> (String, Arc<AtomicUsize>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let connections = Arc::new(AtomicUsize::new(0));
+
+        let counted = connections.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut inbound, _) = listener.accept().await.unwrap();
+                counted.fetch_add(1, Ordering::SeqCst);
+
+                tokio::spawn(async move {
+                    let mut outbound = tokio::net::TcpStream::connect(target).await.unwrap();
+                    let _ = tokio::io::copy_bidirectional(&mut inbound, &mut outbound).await;
+                });
+            }
+        });
+
+        (url, connections)
+    }
+
+    #[tokio::test]
+    async fn test_pool_max_idle_per_host() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/link/create"))
+            .respond_with(created(&server, "as9sud", "https://www.rustunit.com"))
+            .mount(&server)
+            .await;
+
+        let (url, connections) = counting_proxy(*server.address()).await;
+
+        // two concurrent rounds of four requests each
+        let rounds = |api: EzlimeApi| async move {
+            for _ in 0..2 {
+                futures_util::future::try_join_all(
+                    (0..4).map(|_| api.create_short_url("https://www.rustunit.com")),
+                )
+                .await
+                .unwrap();
+            }
+        };
+
+        rounds(EzlimeApi::new("key".to_string()).with_url(&url)).await;
+        let pooled = connections.swap(0, Ordering::SeqCst);
+        assert!(pooled <= 4, "{pooled}");
+
+        rounds(
+            EzlimeApi::new("key".to_string())
+                .with_url(&url)
+                .with_pool_max_idle_per_host(0),
+        )
+        .await;
+        assert_eq!(connections.load(Ordering::SeqCst), 8);
+    }
+
+    #[tokio::test]
+    async fn test_preconnect() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("HEAD"))
+            .and(path("/livez"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/link/create"))
+            .respond_with(created(&server, "as9sud", "https://www.rustunit.com"))
+            .mount(&server)
+            .await;
+
+        let (url, connections) = counting_proxy(*server.address()).await;
+        let api = EzlimeApi::new("key".to_string()).with_url(&url);
+
+        api.preconnect().await.unwrap();
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+
+        api.create_short_url("https://www.rustunit.com")
+            .await
+            .unwrap();
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_danger_accept_invalid_certs() {
+        let url = format!("https://localhost:{}", self_signed_server().await);
+        let api = EzlimeApi::new("key".to_string()).with_url(&url);
+
+        let err = api
+            .create_short_url("https://www.rustunit.com")
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), "request");
+
+        let api = api.with_danger_accept_invalid_certs(true);
+        assert_eq!(
+            api.create_short_url("https://www.rustunit.com")
+                .await
+                .unwrap(),
+            format!("{url}/as9sud")
+        );
+
+        let api = api.with_danger_accept_invalid_certs(false);
+        assert!(
+            api.create_short_url("https://www.rustunit.com")
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_error_codes() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/rules"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("not json"))
+            .mount(&server)
+            .await;
+
+        let api = EzlimeApi::new("key".to_string()).with_url(&server.uri());
+        assert_eq!(api.rules().await.unwrap_err().code(), "deserialize");
+
+        let api = EzlimeApi::new("key".to_string()).with_url("not a url");
+        assert_eq!(api.rules().await.unwrap_err().code(), "config");
+
+        // nothing listens on port 1
+        let api = EzlimeApi::new("key".to_string()).with_url("http://127.0.0.1:1");
+        let err = api.rules().await.unwrap_err();
+        assert_eq!(err.code(), "request");
+        assert!(err.to_string().starts_with("Request error: "));
+    }
+
+    #[tokio::test]
+    async fn test_inspect() {
+        let server = MockServer::start().await;
+        let hop = |status: u16, location: String| {
+            ResponseTemplate::new(status).insert_header("Location", location.as_str())
+        };
+
+        Mock::given(method("HEAD"))
+            .and(path("/as9sud"))
+            .respond_with(hop(307, format!("{}/first", server.uri())))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/first"))
+            .respond_with(hop(301, String::from("/second")))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/second"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+
+        let api = EzlimeApi::new("key".to_string()).with_url(&server.uri());
+        let inspection = api.inspect("as9sud").await.unwrap();
+
+        assert_eq!(
+            inspection.chain,
+            vec![
+                format!("{}/first", server.uri()),
+                format!("{}/second", server.uri())
+            ]
+        );
+        assert_eq!(inspection.final_url, format!("{}/second", server.uri()));
+        assert!(!inspection.truncated);
+    }
+
+    #[tokio::test]
+    async fn test_create_with_alias() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/link/create"))
+            .and(body_partial_json(serde_json::json!({ "alias": "launch" })))
+            .respond_with(
+                ResponseTemplate::new(201).set_body_json(CreatedLinkResponse::new(
+                    String::from("launch"),
+                    &server.uri(),
+                    String::from("https://example.com/launch"),
+                )),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/link/create"))
+            .and(body_partial_json(serde_json::json!({ "alias": "taken" })))
+            .respond_with(ResponseTemplate::new(409).set_body_string("id is already taken"))
+            .mount(&server)
+            .await;
+
+        let api = EzlimeApi::new("key".to_string()).with_url(&server.uri());
+
+        let link = api
+            .create_with_alias("https://example.com/launch", "launch")
+            .await
+            .unwrap();
+        assert_eq!(link.id, "launch");
+        assert_eq!(link.shortened_url, format!("{}/launch", server.uri()));
+
+        let err = api
+            .create_with_alias("https://example.com/launch", "taken")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, EzlimeApiError::AliasTaken));
+        assert_eq!(err.code(), "alias_taken");
+
+        for alias in ["", "Launch", "la-unch", &"a".repeat(MAX_ALIAS_LENGTH + 1)] {
+            let err = api
+                .create_with_alias("https://example.com/launch", alias)
+                .await
+                .unwrap_err();
+            assert!(matches!(err, EzlimeApiError::InvalidAlias(_)), "{alias}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_checked() {
+        let server = MockServer::start().await;
+
+        for (id, destination) in [
+            ("allowed", "https://www.Rustunit.com/blog"),
+            ("denied", "https://evil.example/login"),
+        ] {
+            Mock::given(method("HEAD"))
+                .and(path(format!("/{id}")))
+                .respond_with(ResponseTemplate::new(307).insert_header("Location", destination))
+                .mount(&server)
+                .await;
+        }
+
+        let api = EzlimeApi::new("key".to_string()).with_url(&server.uri());
+        let allowed = ["rustunit.com", "www.rustunit.com"];
+
+        assert_eq!(
+            api.resolve_checked("allowed", &allowed).await.unwrap(),
+            "https://www.rustunit.com/blog"
+        );
+
+        let err = api.resolve_checked("denied", &allowed).await.unwrap_err();
+        assert!(matches!(
+            &err,
+            EzlimeApiError::DisallowedDestination { host } if host == "evil.example"
+        ));
+        assert_eq!(err.code(), "disallowed_destination");
+    }
+
+    #[tokio::test]
+    async fn test_inspect_hop_limit() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("HEAD"))
+            .and(path("/as9sud"))
+            .respond_with(ResponseTemplate::new(307).insert_header("Location", "/loop"))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/loop"))
+            .respond_with(ResponseTemplate::new(302).insert_header("Location", "/loop"))
+            .mount(&server)
+            .await;
+
+        let api = EzlimeApi::new("key".to_string()).with_url(&server.uri());
+        let inspection = api.inspect("as9sud").await.unwrap();
+
+        assert!(inspection.truncated);
+        assert_eq!(inspection.chain.len(), INSPECT_MAX_HOPS + 1);
+    }
+
+    #[test]
+    fn test_short_url_for_matches_server() {
+        for prefix in ["https://ezli.me", "https://example.com/links"] {
+            let api = EzlimeApi::new(String::from("key")).with_url(prefix);
+            let created = CreatedLinkResponse::new(String::from("as9sud"), prefix, String::new());
+
+            assert_eq!(api.short_url_for("as9sud"), created.shortened_url);
+        }
+
+        let api = EzlimeApi::new(String::from("key")).with_url("https://example.com/links");
+        assert_eq!(
+            api.short_url_for("as9sud"),
+            "https://example.com/links/as9sud"
+        );
     }
 }
@@ -1,4 +1,4 @@
-CREATE TABLE "public"."links" (
+CREATE TABLE "links" (
     "id" VARCHAR PRIMARY KEY,
     "url" TEXT NOT NULL,
     "created_at" TIMESTAMP NOT NULL DEFAULT NOW()
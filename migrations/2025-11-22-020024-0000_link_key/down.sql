@@ -1 +1 @@
-ALTER TABLE "public"."links" DROP COLUMN key;
+ALTER TABLE "links" DROP COLUMN key;
@@ -1 +1 @@
-ALTER TABLE "public"."links" ADD COLUMN key VARCHAR(32);
+ALTER TABLE "links" ADD COLUMN key VARCHAR(32);
@@ -1,8 +1,8 @@
 -- Drop view
-DROP VIEW "public"."links_with_x402";
+DROP VIEW "links_with_x402";
 
 -- Drop index
-DROP INDEX "public"."idx_x402_id";
+DROP INDEX "idx_x402_id";
 
 -- Drop x402 table
-DROP TABLE "public"."x402";
+DROP TABLE "x402";
@@ -0,0 +1,4 @@
+ALTER TABLE "links" ADD COLUMN redirect_status SMALLINT NOT NULL DEFAULT 307;
+
+COMMENT ON COLUMN links.redirect_status IS
+'HTTP status code used when redirecting this link (e.g. 301 permanent, 307 temporary)';
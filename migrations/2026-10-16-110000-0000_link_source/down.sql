@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS idx_links_source;
+
+ALTER TABLE "links" DROP COLUMN source;
@@ -0,0 +1,9 @@
+ALTER TABLE "links" ADD COLUMN source VARCHAR NOT NULL DEFAULT 'api';
+
+-- Backfill from the key links were created with before the column existed
+UPDATE "links" SET source = key WHERE key IN ('public', 'x402');
+
+CREATE INDEX idx_links_source ON links(source);
+
+COMMENT ON COLUMN links.source IS
+'Channel the link was created through: api, public or x402';
@@ -0,0 +1,8 @@
+ALTER TABLE "x402"
+    DROP CONSTRAINT x402_link_id_fkey,
+    ADD CONSTRAINT x402_link_id_fkey
+        FOREIGN KEY ("link_id") REFERENCES "links"("id") ON DELETE RESTRICT;
+
+DROP INDEX IF EXISTS idx_links_expires_at;
+
+ALTER TABLE "links" DROP COLUMN expires_at;
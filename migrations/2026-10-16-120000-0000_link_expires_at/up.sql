@@ -0,0 +1,12 @@
+ALTER TABLE "links" ADD COLUMN expires_at TIMESTAMPTZ;
+
+CREATE INDEX idx_links_expires_at ON links(expires_at) WHERE expires_at IS NOT NULL;
+
+-- Purging expired links removes their x402 transactions as well
+ALTER TABLE "x402"
+    DROP CONSTRAINT x402_link_id_fkey,
+    ADD CONSTRAINT x402_link_id_fkey
+        FOREIGN KEY ("link_id") REFERENCES "links"("id") ON DELETE CASCADE;
+
+COMMENT ON COLUMN links.expires_at IS
+'Point in time after which the link is purged, NULL for links that never expire';
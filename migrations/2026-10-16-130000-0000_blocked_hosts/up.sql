@@ -0,0 +1,7 @@
+CREATE TABLE "blocked_hosts" (
+    "host" VARCHAR PRIMARY KEY,
+    "created_at" TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+COMMENT ON TABLE blocked_hosts IS
+'Destination hosts (including their subdomains) links must not be created for or redirect to';
@@ -0,0 +1,38 @@
+CREATE OR REPLACE FUNCTION batch_update_clicks(
+    link_ids TEXT[],
+    increments INTEGER[],
+    timestamps TIMESTAMPTZ[]
+) RETURNS INTEGER AS $$
+DECLARE
+    rows_updated INTEGER;
+BEGIN
+    -- Validate array lengths match
+    IF array_length(link_ids, 1) != array_length(increments, 1) OR
+       array_length(link_ids, 1) != array_length(timestamps, 1) THEN
+        RAISE EXCEPTION 'Array length mismatch';
+    END IF;
+
+    -- Validate no negative increments
+    IF EXISTS (SELECT 1 FROM unnest(increments) AS i WHERE i < 0) THEN
+        RAISE EXCEPTION 'Negative increments not allowed';
+    END IF;
+
+    -- Perform the batch update
+    UPDATE links AS l
+    SET
+        click_count = l.click_count + v.inc,
+        last_used = GREATEST(l.last_used, v.ts)
+    FROM (
+        SELECT
+            unnest(link_ids) AS link_id,
+            unnest(increments) AS inc,
+            unnest(timestamps) AS ts
+    ) AS v
+    WHERE l.id = v.link_id;
+
+    GET DIAGNOSTICS rows_updated = ROW_COUNT;
+    RETURN rows_updated;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TABLE "link_daily_clicks";
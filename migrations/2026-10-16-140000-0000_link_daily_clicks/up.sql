@@ -0,0 +1,61 @@
+CREATE TABLE "link_daily_clicks" (
+    "link_id" VARCHAR NOT NULL REFERENCES links(id) ON DELETE CASCADE,
+    "day" DATE NOT NULL,
+    "clicks" INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY ("link_id", "day")
+);
+
+-- Same as before but also rolls the increments up into daily buckets
+CREATE OR REPLACE FUNCTION batch_update_clicks(
+    link_ids TEXT[],
+    increments INTEGER[],
+    timestamps TIMESTAMPTZ[]
+) RETURNS INTEGER AS $$
+DECLARE
+    rows_updated INTEGER;
+BEGIN
+    -- Validate array lengths match
+    IF array_length(link_ids, 1) != array_length(increments, 1) OR
+       array_length(link_ids, 1) != array_length(timestamps, 1) THEN
+        RAISE EXCEPTION 'Array length mismatch';
+    END IF;
+
+    -- Validate no negative increments
+    IF EXISTS (SELECT 1 FROM unnest(increments) AS i WHERE i < 0) THEN
+        RAISE EXCEPTION 'Negative increments not allowed';
+    END IF;
+
+    -- Perform the batch update
+    UPDATE links AS l
+    SET
+        click_count = l.click_count + v.inc,
+        last_used = GREATEST(l.last_used, v.ts)
+    FROM (
+        SELECT
+            unnest(link_ids) AS link_id,
+            unnest(increments) AS inc,
+            unnest(timestamps) AS ts
+    ) AS v
+    WHERE l.id = v.link_id;
+
+    GET DIAGNOSTICS rows_updated = ROW_COUNT;
+
+    -- Increments are attributed to the (UTC) day of their latest click
+    INSERT INTO link_daily_clicks AS d (link_id, day, clicks)
+    SELECT v.link_id, (v.ts AT TIME ZONE 'UTC')::date, SUM(v.inc)
+    FROM (
+        SELECT
+            unnest(link_ids) AS link_id,
+            unnest(increments) AS inc,
+            unnest(timestamps) AS ts
+    ) AS v
+    JOIN links AS l ON l.id = v.link_id
+    GROUP BY 1, 2
+    ON CONFLICT (link_id, day) DO UPDATE SET clicks = d.clicks + EXCLUDED.clicks;
+
+    RETURN rows_updated;
+END;
+$$ LANGUAGE plpgsql;
+
+COMMENT ON TABLE link_daily_clicks IS
+'Clicks per link and UTC day, written alongside click_count by batch_update_clicks';
@@ -0,0 +1,3 @@
+ALTER TABLE "x402"
+    DROP COLUMN "amount",
+    DROP COLUMN "payer";
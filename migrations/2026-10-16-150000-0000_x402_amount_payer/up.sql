@@ -0,0 +1,10 @@
+-- Unknown for transactions stored before these were recorded
+ALTER TABLE "x402"
+    ADD COLUMN "amount" VARCHAR,
+    ADD COLUMN "payer" VARCHAR;
+
+COMMENT ON COLUMN x402.amount IS
+'Paid amount in the base units of the token, e.g. 1000000 for 1 USDC';
+
+COMMENT ON COLUMN x402.payer IS
+'Address the payment was made from';
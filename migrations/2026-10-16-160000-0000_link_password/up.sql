@@ -0,0 +1,5 @@
+ALTER TABLE "links"
+    ADD COLUMN "password_hash" VARCHAR;
+
+COMMENT ON COLUMN links.password_hash IS
+'Salted hash (sha256:<salt>:<hex digest>) of the passphrase required to follow the link, NULL for public links';
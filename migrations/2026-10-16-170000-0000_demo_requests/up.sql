@@ -0,0 +1,9 @@
+CREATE TABLE "demo_requests" (
+    "id" BIGSERIAL PRIMARY KEY,
+    "url" TEXT NOT NULL,
+    "source_ip" VARCHAR,
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+COMMENT ON TABLE demo_requests IS
+'URLs requested while only the demo response was given, no link is created for them';
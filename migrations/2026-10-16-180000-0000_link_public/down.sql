@@ -0,0 +1,4 @@
+DROP INDEX links_public_click_count_idx;
+
+ALTER TABLE "links"
+    DROP COLUMN "public";
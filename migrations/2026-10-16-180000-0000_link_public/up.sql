@@ -0,0 +1,5 @@
+-- Links only show up on /trending if their creator opted in
+ALTER TABLE "links"
+    ADD COLUMN "public" BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE INDEX links_public_click_count_idx ON links (click_count DESC) WHERE "public";
@@ -0,0 +1,3 @@
+-- Disabled links stay stored but are not redirected until enabled again
+ALTER TABLE "links"
+    ADD COLUMN "disabled" BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,17 @@
+DROP VIEW "links_with_x402";
+
+ALTER TABLE "links"
+    ALTER COLUMN "created_at" TYPE TIMESTAMP USING "created_at" AT TIME ZONE 'UTC';
+
+CREATE VIEW "links_with_x402" AS
+SELECT
+    l.id,
+    l.url,
+    l.created_at,
+    l.key,
+    l.click_count,
+    l.last_used,
+    x.network,
+    x.tx_hash
+FROM "links" l
+LEFT JOIN "x402" x ON l.id = x.link_id;
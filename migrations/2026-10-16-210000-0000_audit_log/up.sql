@@ -0,0 +1,24 @@
+CREATE TABLE "audit_log" (
+    "id" BIGSERIAL PRIMARY KEY,
+    "action" VARCHAR NOT NULL,
+    -- no foreign key, entries outlive the links they are about
+    "link_id" VARCHAR NOT NULL,
+    "url" TEXT NOT NULL,
+    "key" VARCHAR(32),
+    "source" VARCHAR NOT NULL,
+    "source_ip" VARCHAR,
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+COMMENT ON TABLE audit_log IS
+'Append only trail of link creations, written with --audit-log';
+
+CREATE FUNCTION audit_log_append_only() RETURNS TRIGGER AS $$
+BEGIN
+    RAISE EXCEPTION 'audit_log is append only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_log_append_only
+    BEFORE UPDATE OR DELETE ON "audit_log"
+    FOR EACH ROW EXECUTE FUNCTION audit_log_append_only();
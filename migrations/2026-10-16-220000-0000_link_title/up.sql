@@ -0,0 +1,3 @@
+-- Human readable title of the destination, provided or fetched for link lists
+ALTER TABLE "links"
+    ADD COLUMN "title" VARCHAR;
@@ -0,0 +1,72 @@
+ALTER TABLE "links"
+    ADD COLUMN "first_used" TIMESTAMPTZ;
+
+-- Updates the running total, first_used (once), last_used and the daily
+-- buckets of a batch in one call, so they are never seen out of step
+CREATE FUNCTION batch_update_clicks(
+    link_ids TEXT[],
+    increments INTEGER[],
+    first_timestamps TIMESTAMPTZ[],
+    last_timestamps TIMESTAMPTZ[]
+) RETURNS INTEGER AS $$
+DECLARE
+    rows_updated INTEGER;
+BEGIN
+    -- Validate array lengths match
+    IF array_length(link_ids, 1) != array_length(increments, 1) OR
+       array_length(link_ids, 1) != array_length(first_timestamps, 1) OR
+       array_length(link_ids, 1) != array_length(last_timestamps, 1) THEN
+        RAISE EXCEPTION 'Array length mismatch';
+    END IF;
+
+    -- Validate no negative increments
+    IF EXISTS (SELECT 1 FROM unnest(increments) AS i WHERE i < 0) THEN
+        RAISE EXCEPTION 'Negative increments not allowed';
+    END IF;
+
+    -- Perform the batch update
+    UPDATE links AS l
+    SET
+        click_count = l.click_count + v.inc,
+        first_used = COALESCE(l.first_used, v.first_ts),
+        last_used = GREATEST(l.last_used, v.last_ts)
+    FROM (
+        SELECT
+            unnest(link_ids) AS link_id,
+            unnest(increments) AS inc,
+            unnest(first_timestamps) AS first_ts,
+            unnest(last_timestamps) AS last_ts
+    ) AS v
+    WHERE l.id = v.link_id;
+
+    GET DIAGNOSTICS rows_updated = ROW_COUNT;
+
+    -- Increments are attributed to the (UTC) day of their latest click
+    INSERT INTO link_daily_clicks AS d (link_id, day, clicks)
+    SELECT v.link_id, (v.ts AT TIME ZONE 'UTC')::date, SUM(v.inc)
+    FROM (
+        SELECT
+            unnest(link_ids) AS link_id,
+            unnest(increments) AS inc,
+            unnest(last_timestamps) AS ts
+    ) AS v
+    JOIN links AS l ON l.id = v.link_id
+    GROUP BY 1, 2
+    ON CONFLICT (link_id, day) DO UPDATE SET clicks = d.clicks + EXCLUDED.clicks;
+
+    RETURN rows_updated;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Replicas not updated yet keep flushing through the old signature during a
+-- rolling deploy. Their clicks count as first used at the latest click. To be
+-- dropped by a later migration once no replica calls it anymore.
+CREATE OR REPLACE FUNCTION batch_update_clicks(
+    link_ids TEXT[],
+    increments INTEGER[],
+    timestamps TIMESTAMPTZ[]
+) RETURNS INTEGER AS $$
+BEGIN
+    RETURN batch_update_clicks(link_ids, increments, timestamps, timestamps);
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,4 @@
+-- Reserved links hold an id for a destination set later, they are not
+-- redirected until then
+ALTER TABLE "links"
+    ADD COLUMN "reserved" BOOLEAN NOT NULL DEFAULT FALSE;
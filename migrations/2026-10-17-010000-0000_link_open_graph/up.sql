@@ -0,0 +1,6 @@
+-- Open Graph tags served to social crawlers on a preview page instead of
+-- the redirect
+ALTER TABLE "links"
+    ADD COLUMN "og_title" VARCHAR,
+    ADD COLUMN "og_description" VARCHAR,
+    ADD COLUMN "og_image" TEXT;
@@ -0,0 +1,3 @@
+-- Client IP creating the link, only stored if configured
+ALTER TABLE "links"
+    ADD COLUMN "creator_ip" VARCHAR;
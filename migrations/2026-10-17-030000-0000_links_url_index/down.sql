@@ -0,0 +1 @@
+DROP INDEX "links_url_idx";
@@ -0,0 +1,2 @@
+-- Looking up links by destination, see `POST /link/exists/batch`
+CREATE INDEX "links_url_idx" ON "links" USING HASH ("url");
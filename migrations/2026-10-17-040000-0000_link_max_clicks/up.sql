@@ -0,0 +1,3 @@
+-- Clicks after which the link stops redirecting, unlimited if NULL
+ALTER TABLE "links"
+    ADD COLUMN "max_clicks" INT4;
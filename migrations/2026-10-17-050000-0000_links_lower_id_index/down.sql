@@ -0,0 +1 @@
+DROP INDEX "links_lower_id_idx";
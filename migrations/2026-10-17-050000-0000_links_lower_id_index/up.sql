@@ -0,0 +1,2 @@
+-- Lookups of case-insensitive ids, see --case-insensitive-ids
+CREATE INDEX "links_lower_id_idx" ON "links" (lower("id"));
@@ -0,0 +1 @@
+DROP SEQUENCE "links_id_seq";
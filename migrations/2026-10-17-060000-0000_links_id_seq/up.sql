@@ -0,0 +1,2 @@
+-- Numbers generated ids, see --id-strategy
+CREATE SEQUENCE "links_id_seq";
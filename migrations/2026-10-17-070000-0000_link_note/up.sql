@@ -0,0 +1,3 @@
+-- Free text of the owner, never shown on public views
+ALTER TABLE "links"
+    ADD COLUMN "note" VARCHAR;
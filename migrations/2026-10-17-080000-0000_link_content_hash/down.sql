@@ -0,0 +1,3 @@
+DROP INDEX "links_content_hash_idx";
+ALTER TABLE "links"
+    DROP COLUMN "content_hash";
@@ -0,0 +1,4 @@
+-- Hash of the destination's body, see --dedupe-by-content
+ALTER TABLE "links"
+    ADD COLUMN "content_hash" VARCHAR;
+CREATE INDEX "links_content_hash_idx" ON "links" ("content_hash");
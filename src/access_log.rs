@@ -0,0 +1,145 @@
+use axum::{extract::Request, middleware::Next, response::Response};
+use chrono::{SecondsFormat, Utc};
+use serde_json::{Map, Value};
+use std::{fs::OpenOptions, io::Write, path::Path, sync::Mutex, time::Instant};
+use tracing::{
+    Event, Metadata, Subscriber,
+    field::{Field, Visit},
+};
+use tracing_subscriber::{Layer, layer::Context};
+
+/// Target of the access log events, which only [`AccessLogLayer`] writes.
+pub const TARGET: &str = "access_log";
+
+/// Whether `metadata` belongs to an access log event.
+pub fn is_access_log(metadata: &Metadata<'_>) -> bool {
+    metadata.target() == TARGET
+}
+
+/// Middleware emitting one access log event per request, once it was
+/// answered.
+pub async fn record(request: Request, next: Next) -> Response {
+    let start = Instant::now();
+    let method = request.method().clone();
+    let uri = request.uri().clone();
+
+    let response = next.run(request).await;
+
+    tracing::info!(
+        target: TARGET,
+        method = %method,
+        uri = %uri,
+        status = response.status().as_u16(),
+        latency_ms = start.elapsed().as_millis() as u64,
+    );
+
+    response
+}
+
+/// Writes access log events as JSON lines, apart from the app log and
+/// regardless of its level.
+pub struct AccessLogLayer {
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+
+impl AccessLogLayer {
+    /// Appends to the file at `path`, creating it if missing.
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        Ok(Self {
+            writer: Mutex::new(Box::new(file)),
+        })
+    }
+}
+
+impl<S: Subscriber> Layer<S> for AccessLogLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut fields = JsonFields(Map::new());
+        fields.0.insert(
+            String::from("timestamp"),
+            Value::from(Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)),
+        );
+        event.record(&mut fields);
+
+        let mut line = Value::Object(fields.0).to_string();
+        line.push('\n');
+
+        // a lost line must not take the request down with it
+        let _ = self
+            .writer
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .write_all(line.as_bytes());
+    }
+}
+
+struct JsonFields(Map<String, Value>);
+
+impl Visit for JsonFields {
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), Value::from(format!("{value:?}")));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, middleware, routing::get};
+    use tower::ServiceExt;
+    use tracing_subscriber::{filter::filter_fn, layer::SubscriberExt};
+
+    #[tokio::test]
+    async fn test_access_log_file() {
+        let path = std::env::temp_dir().join(format!("ezlime-access-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let layer = AccessLogLayer::open(&path).unwrap();
+        let _guard = tracing::subscriber::set_default(
+            tracing_subscriber::registry().with(layer.with_filter(filter_fn(is_access_log))),
+        );
+
+        let router = Router::new()
+            .route("/health", get(|| async { "ok" }))
+            .layer(middleware::from_fn(record));
+
+        for uri in ["/health", "/missing?q=1"] {
+            router
+                .clone()
+                .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+        }
+        // app events are not access log lines
+        tracing::info!("not an access");
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let lines: Vec<Value> = log
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["method"], "GET");
+        assert_eq!(lines[0]["uri"], "/health");
+        assert_eq!(lines[0]["status"], 200);
+        assert_eq!(lines[1]["uri"], "/missing?q=1");
+        assert_eq!(lines[1]["status"], 404);
+        assert!(lines[1]["latency_ms"].is_u64());
+        assert!(lines[1]["timestamp"].is_string());
+    }
+}
@@ -1,16 +1,47 @@
 use crate::{
-    counter::ClickCounter,
+    auth::{hash_password, verify_password},
+    clock::{Clock, SystemClock},
+    counter::{self, ClickStore, PendingClicks},
     db::{DbError, LinksDB},
-    models::{CreateLink, CreateTransaction},
+    invalidation::{Invalidation, Invalidations},
+    limiter::Limiter,
+    metrics::Metrics,
+    models::{
+        CreateAuditEntry, CreateDemoRequest, CreateLink, CreateTransaction, FetchLink, LinkDetails,
+        LinkSource, OpenGraphTags, OwnedLink, TopLink, Transaction,
+    },
 };
-use ezlime_rs::{CreateLinkRequest, CreatedLinkResponse};
-use quick_cache::sync::Cache;
-use reqwest::Url;
+use chrono::{DateTime, NaiveDate, Utc};
+use ezlime_rs::{
+    CreateLinkOutcome, CreateLinkRequest, CreatedLinkResponse, DeletedLink, FoundLink, OpenGraph,
+    ResolvedLink, UpdateLinkRequest, ValidationRules,
+};
+use futures_util::{
+    FutureExt,
+    future::{BoxFuture, Shared},
+    stream::BoxStream,
+};
+use publicsuffix::Psl;
+use quick_cache::{Weighter, sync::Cache};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
     hash::{DefaultHasher, Hash, Hasher},
-    sync::Arc,
+    net::IpAddr,
+    ops::RangeInclusive,
+    sync::{
+        Arc, Mutex, Weak,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
 };
-use tracing::{error, info, instrument, warn};
+use thiserror::Error;
+use tokio::sync::broadcast;
+use tracing::{debug, error, info, instrument, warn};
+use unicode_normalization::UnicodeNormalization;
+use url::{Host, Position, Url};
 
 fn hash_string(s: &str) -> u64 {
     let mut hasher = DefaultHasher::new();
@@ -18,385 +49,7220 @@ fn hash_string(s: &str) -> u64 {
     hasher.finish() // Returns u64
 }
 
-fn link_hash(url: &str, hash_length: usize, hash_offset: u64) -> String {
-    let mut hash = hash_string(url);
+fn link_hash(url: &str, salt: &str, hash_length: usize, hash_offset: u64) -> String {
+    // an empty salt keeps the ids of unsalted deployments
+    let salted = format!("{}{}", salt, url);
+
+    let mut hash = hash_string(&salted);
 
     if hash_offset > 0 {
-        hash = hash_string(&format!("{}_{}", url, hash_offset));
+        hash = hash_string(&format!("{}_{}", salted, hash_offset));
     }
 
-    let mut hash = base62::encode(hash);
+    // small hashes encode to fewer digits, padding keeps all ids equally long
+    let mut hash = format!("{:0>hash_length$}", base62::encode(hash));
     hash.truncate(hash_length);
     hash.make_ascii_lowercase();
 
     hash
 }
 
-#[derive(Clone)]
-pub struct App {
-    db: Arc<dyn LinksDB>,
-    click_counter: Arc<ClickCounter>,
-    prefix: String,
-    hash_length: usize,
-    cache: Arc<Cache<String, String>>,
+/// Characters of sequential ids, in an order derived from `salt`, so ids
+/// differ between deployments.
+fn sequence_alphabet(salt: &str) -> Vec<u8> {
+    let mut alphabet = b"abcdefghijklmnopqrstuvwxyz0123456789".to_vec();
+
+    // xorshift seeded from the salt, unlike `DefaultHasher` stable across
+    // Rust releases, which would otherwise change all ids
+    let digest = Sha256::digest(salt.as_bytes());
+    let mut state = u64::from_le_bytes(digest[..8].try_into().unwrap()) | 1;
+    for i in (1..alphabet.len()).rev() {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        alphabet.swap(i, (state % (i as u64 + 1)) as usize);
+    }
+
+    alphabet
 }
 
-fn validate_url(url: &str) -> Result<(), anyhow::Error> {
-    let parsed = Url::parse(url)?;
-    if !["http", "https"].contains(&parsed.scheme()) {
-        anyhow::bail!("Only HTTP(S) URLs are allowed");
+/// Encodes `n` to an id of at least `min_length` characters of `alphabet`.
+/// The least significant digit comes first and shifts all following ones,
+/// so consecutive numbers give unrelated looking ids.
+fn encode_sequence(mut n: u64, alphabet: &[u8], min_length: usize) -> String {
+    let base = alphabet.len() as u64;
+
+    let mut id = String::with_capacity(min_length);
+    let mut offset = 0;
+    while n > 0 || id.len() < min_length {
+        let digit = n % base;
+        id.push(alphabet[((digit + offset) % base) as usize] as char);
+        offset = (offset + digit + 1) % base;
+        n /= base;
     }
-    // Optional: check against blacklist of domains
-    Ok(())
+
+    id
 }
 
-impl App {
-    pub fn new(
-        prefix: String,
-        hash_length: usize,
-        db: Arc<dyn LinksDB>,
-        click_counter: Arc<ClickCounter>,
-        cache_size: usize,
-    ) -> Arc<Self> {
-        Arc::new(Self {
-            db,
-            prefix,
-            hash_length,
-            cache: Arc::new(Cache::new(cache_size)),
-            click_counter,
-        })
+/// Reverses [`encode_sequence`], `None` if `id` is no encoded number.
+#[cfg(test)]
+fn decode_sequence(id: &str, alphabet: &[u8]) -> Option<u64> {
+    let base = alphabet.len() as u64;
+
+    let mut n: u64 = 0;
+    let mut scale: u64 = 1;
+    let mut offset = 0;
+    for (i, c) in id.bytes().enumerate() {
+        let position = alphabet.iter().position(|&a| a == c)? as u64;
+        let digit = (position + base - offset) % base;
+        offset = (offset + digit + 1) % base;
+        if i > 0 {
+            scale = scale.checked_mul(base)?;
+        }
+        n = n.checked_add(digit.checked_mul(scale)?)?;
     }
 
-    #[instrument(skip(self), err)]
-    pub async fn store_transaction(
-        &self,
-        link_id: String,
-        tx_hash: String,
-        network: String,
-    ) -> Result<(), anyhow::Error> {
-        let tx = CreateTransaction {
-            link_id,
-            tx_hash,
-            network,
-        };
+    Some(n)
+}
 
-        self.db.create_transaction(&tx).await?;
+/// When a link created at `now` with a TTL of `ttl_seconds` expires.
+fn expires_after(now: DateTime<Utc>, ttl_seconds: u64) -> Result<DateTime<Utc>, anyhow::Error> {
+    i64::try_from(ttl_seconds)
+        .ok()
+        .and_then(chrono::TimeDelta::try_seconds)
+        .and_then(|ttl| now.checked_add_signed(ttl))
+        .ok_or_else(|| anyhow::anyhow!("ttl of {ttl_seconds}s is too long"))
+}
 
-        Ok(())
+/// `max_clicks` of a create request as stored in `links.max_clicks`.
+fn max_clicks(max_clicks: u32) -> Result<i32, anyhow::Error> {
+    match i32::try_from(max_clicks) {
+        Ok(0) => anyhow::bail!("max_clicks must be at least 1"),
+        Ok(max_clicks) => Ok(max_clicks),
+        Err(_) => anyhow::bail!("max_clicks of {max_clicks} is too large"),
     }
+}
 
-    #[instrument(skip(self), err)]
-    pub async fn create_link(
-        &self,
-        api_key: String,
-        payload: CreateLinkRequest,
-        demo_mode: bool,
-    ) -> Result<CreatedLinkResponse, anyhow::Error> {
-        let url = payload.url.as_str();
+/// Whether `link` is protected by exactly `password`, so dedup never hands
+/// out a link with a different (or no) password.
+fn same_password(link: &FetchLink, password: Option<&str>) -> bool {
+    match (&link.password_hash, password) {
+        (None, None) => true,
+        (Some(hash), Some(password)) => verify_password(hash, password),
+        _ => false,
+    }
+}
 
-        validate_url(url)?;
+/// Whether a stored `link` of the same url can be handed out for a create
+/// asking for `redirect_status` and `password`. Limited and expiring links
+/// may be used up already and disabled ones would not redirect, so they are
+/// never shared.
+fn reusable(link: &FetchLink, redirect_status: i16, password: Option<&str>) -> bool {
+    !link.disabled
+        && link.max_clicks.is_none()
+        && link.expires_at.is_none()
+        && link.redirect_status == redirect_status
+        && same_password(link, password)
+}
 
-        // If demo mode is enabled, return a demo response without creating a real link
-        if demo_mode {
-            info!("demo request");
-            return Ok(CreatedLinkResponse::new(
-                "rustunit".to_string(),
-                &self.prefix,
-                url.to_string(),
-            ));
+/// Whether urls `a` and `b` lead to the same site: the same registrable
+/// domain if `suffixes` are known, otherwise the same host.
+fn same_site(a: &str, b: &str, suffixes: Option<&publicsuffix::List>) -> bool {
+    let (Ok(a), Ok(b)) = (Url::parse(a), Url::parse(b)) else {
+        return false;
+    };
+
+    match (a.host(), b.host()) {
+        (Some(Host::Domain(a)), Some(Host::Domain(b))) => {
+            let a = a.trim_end_matches('.');
+            let b = b.trim_end_matches('.');
+            match suffixes
+                .and_then(|list| Some((list.domain(a.as_bytes())?, list.domain(b.as_bytes())?)))
+            {
+                Some((a, b)) => a.as_bytes() == b.as_bytes(),
+                None => a == b,
+            }
         }
+        (Some(a), Some(b)) => a == b,
+        _ => false,
+    }
+}
 
-        let mut hash_offset: u64 = 0;
+#[derive(Clone, Debug)]
+pub struct AppConfig {
+    pub prefix: String,
+    pub hash_length: usize,
+    /// Shortest id clients may choose for a link themselves (an alias), e.g.
+    /// on import. Generated ids are `hash_length` long, plus the `id_prefix`
+    /// a create may ask for.
+    pub alias_min_length: usize,
+    /// Longest id clients may choose, see [`AppConfig::alias_min_length`].
+    pub alias_max_length: usize,
+    /// Folded into the url hash, so ids differ between deployments. Changes
+    /// all generated ids when set.
+    pub hash_salt: String,
+    /// How ids of links without an alias are generated.
+    pub id_strategy: IdStrategy,
+    pub cache_size: usize,
+    /// Bound the link cache by the bytes of its ids and urls instead of
+    /// [`AppConfig::cache_size`] entries, which then only serves as an
+    /// estimate of how many links fit.
+    pub cache_max_bytes: Option<u64>,
+    /// Shares cache invalidations with the other replicas, see
+    /// [`crate::invalidation::Invalidations`].
+    pub invalidations: Option<Arc<dyn Invalidations>>,
+    /// Current time of link expiry, e.g. a [`crate::clock::MockClock`] in
+    /// tests.
+    pub clock: Arc<dyn Clock>,
+    /// Persist links paid for on a testnet (e.g. Base Sepolia) instead of
+    /// answering them with the demo response. Off by default, so testnet
+    /// payments never create real links unless explicitly configured.
+    pub x402_persist_testnet: bool,
+    /// How x402 payments on Solana are turned down, which are not supported
+    /// yet.
+    pub x402_solana: SolanaPayments,
+    /// Prices (in USDC base units) of links to these hosts and their
+    /// subdomains, overriding the flat price. Checked against the amount the
+    /// payer authorized before it is settled, see [`App::x402_price_for`].
+    pub x402_host_prices: HashMap<String, u64>,
+    /// Skip the Turnstile check on the public create endpoint. Only honored
+    /// in debug builds, meant for automated tests of `/shorten`.
+    pub turnstile_disabled: bool,
+    /// Refuse to create new links once this many are stored. Existing links
+    /// are still returned for URLs that were shortened before.
+    pub max_total_links: Option<u64>,
+    /// Answer the public create endpoint with just the short url.
+    pub public_minimal_response: bool,
+    pub idn_policy: IdnPolicy,
+    /// Published at `/rules` so clients can validate upfront.
+    pub validation_rules: ValidationRules,
+    /// Body of the `451` answered for links to hosts blocked after creation.
+    pub blocked_redirect_message: String,
+    /// Mirror the `Location` of redirects in an `X-Original-Url` header.
+    pub expose_original_url_header: bool,
+    /// Add an `X-Link-Id` header with the id to create responses.
+    pub link_id_header: bool,
+    /// Let browsers and proxies cache permanent redirects for this many
+    /// seconds. Cached redirects skip the service, so their clicks go
+    /// uncounted and updates or disabling reach them only once expired.
+    pub redirect_cache_max_age: Option<u64>,
+    /// Append the query of redirect requests to the destination, e.g. for
+    /// campaign parameters added to a shared link.
+    pub forward_query: bool,
+    /// Most parameters appended by `forward_query`, further ones are dropped.
+    pub forward_query_max_params: usize,
+    /// Most bytes of parameters appended by `forward_query`, bounding the
+    /// length of the `Location` header.
+    pub forward_query_max_bytes: usize,
+    /// Drop `#fragment`s before hashing, so fragment-only variations of a
+    /// URL share one link. Off by default as some SPAs route on fragments.
+    pub strip_fragments: bool,
+    /// Sort query parameters before hashing, so reordered variations of a
+    /// URL share one link. Off by default as some endpoints are sensitive
+    /// to the order.
+    pub canonicalize_query: bool,
+    /// Query parameters dropped by [`AppConfig::canonicalize_query`], e.g.
+    /// `fbclid`. Lowercase, a trailing `*` matches any suffix as in `utm_*`.
+    pub strip_query_params: Vec<String>,
+    pub health_format: HealthFormat,
+    /// Body of the html health page, see [`HealthFormat::Html`].
+    pub health_page: String,
+    /// Fields returned by the public stats endpoint, e.g. to hide `last_used`
+    /// which reveals recent activity. The admin view returns all of them.
+    pub public_stats_fields: Vec<PublicStatsField>,
+    /// Use the scheme of `X-Forwarded-Proto` for returned short urls. Only
+    /// safe if the server is exclusively reached through a proxy setting it.
+    pub trust_forwarded_proto: bool,
+    /// Take the client IP of public creates, which their rate limit, cooldown
+    /// and tarpit count by, from the last `X-Forwarded-For` entry instead of
+    /// the peer address. Behind a proxy all clients share the proxy's address
+    /// otherwise. Only safe if the server is exclusively reached through a
+    /// proxy appending it.
+    pub trust_forwarded_for: bool,
+    /// Where `GET /` redirects to, a default page is shown if unset.
+    pub root_redirect: Option<String>,
+    /// Hash collisions of one create logged at warn, further ones are logged
+    /// at debug and summarized once the link is created.
+    pub collision_warn_limit: u64,
+    /// Retries with the next hash offset a create makes before it gives up
+    /// with [`LinkError::IdSpaceExhausted`], e.g. once `hash_length` is too
+    /// short for the number of stored links.
+    pub max_collision_retries: u64,
+    /// Public creates per client IP and minute answered right away, further
+    /// ones are held back by `public_tarpit_delay_ms` instead of refused.
+    /// Unset disables the tarpit.
+    pub public_tarpit_threshold: Option<u32>,
+    /// Delay of public creates past `public_tarpit_threshold`.
+    pub public_tarpit_delay_ms: u64,
+    /// Client IPs the tarpit, the public rate limit and the cooldown each
+    /// keep track of. IPs beyond are held back or refused until the windows
+    /// of others ran out, rather than letting tracked IPs start over.
+    pub public_limiter_capacity: usize,
+    /// Public creates per client IP and minute answered before further ones
+    /// fail with [`LinkError::PublicRateLimited`], separate from the rate
+    /// limit of API keys. Unset leaves them unlimited.
+    pub public_rate_limit: Option<u32>,
+    /// Seconds a client IP has to wait after a public create before the
+    /// next one, which fails with [`LinkError::PublicCooldown`] until then.
+    /// Unset disables the cooldown.
+    pub public_cooldown_secs: Option<u64>,
+    /// Hash the API key along with the URL, so keys shortening the same URL
+    /// get separate links instead of sharing one. Changes all generated ids.
+    pub namespace_by_key: bool,
+    /// Resolve ids regardless of their case. Generated ids and aliases are
+    /// lowercase already, older mixed-case ids are found through the
+    /// `lower(id)` index.
+    pub case_insensitive_ids: bool,
+    /// Accept aliases in any case, storing them lowercase. A new alias
+    /// matching an existing id regardless of case is refused with
+    /// [`LinkError::IdTaken`], unless it is a link to the same url.
+    pub case_insensitive_aliases: bool,
+    /// Seconds sent as `Retry-After` with the `503` for redirects of uncached
+    /// links while the DB is down.
+    pub unavailable_retry_after: u64,
+    /// Count every click in the DB during the redirect instead of batching
+    /// them in the click store. Accurate right away, but costs a write per
+    /// redirect.
+    pub sync_click_counts: bool,
+    /// Log and store the urls of requests answered with the demo response,
+    /// to see what a demo instance is used for. Still creates no links.
+    pub record_demo_requests: bool,
+    /// Store `http://` urls as `https://`. Happens at creation, as a redirect
+    /// can't fall back to http if the host turns out not to serve https, so
+    /// only upgrade hosts known to support it. See
+    /// [`AppConfig::upgrade_insecure_hosts`].
+    pub upgrade_insecure: UpgradeInsecure,
+    /// Hosts upgraded by [`UpgradeInsecure::Listed`], including subdomains.
+    pub upgrade_insecure_hosts: HashSet<String>,
+    /// Lowercase terms generated ids must not contain, so a hash never
+    /// spells something offensive. Ids chosen by clients are not checked.
+    pub blocked_id_terms: Vec<String>,
+    /// Answer redirects to short links of this service with `508` instead of
+    /// bouncing visitors through several hops, e.g. for imported links.
+    pub redirect_loop_guard: bool,
+    /// Further hosts serving this service besides the one of
+    /// [`AppConfig::prefix`], checked by [`AppConfig::redirect_loop_guard`].
+    pub redirect_loop_hosts: HashSet<String>,
+    /// Append every created link to the `audit_log` table, with the key,
+    /// source and client IP creating it. Costs a write per create, failures
+    /// are only logged.
+    pub audit_log: bool,
+    /// Store the client IP creating a link with it, shown in the admin view
+    /// of the link for abuse investigations. Off by default, as the IP is
+    /// personal data kept for as long as the link.
+    pub store_creator_ip: bool,
+    /// Reject URLs whose host has no suffix of this list, like
+    /// `http://localhost` or `http://intranet`, which are mistakes on a
+    /// public shortener. IP hosts are left to
+    /// [`ValidationRules::block_private_hosts`]. Unset accepts any host.
+    pub public_suffixes: Option<Arc<publicsuffix::List>>,
+    /// Hosts accepted despite [`AppConfig::public_suffixes`], e.g. intranet
+    /// hosts.
+    pub tld_exempt_hosts: HashSet<String>,
+    /// Let concurrent requests creating a link to the same URL share one
+    /// insert and its response, instead of all racing for the id.
+    pub dedup_concurrent_creates: bool,
+    /// Case insensitive parts of the `User-Agent` of social crawlers, which
+    /// get a preview page with the Open Graph tags of links having any
+    /// instead of the redirect. Empty disables the previews.
+    pub crawler_user_agents: Vec<String>,
+    /// Fetch the `<title>` of new links created without a title in the
+    /// background, see [`crate::destination::fetch_title`] for what is refused.
+    pub fetch_titles: bool,
+    /// Reject URLs whose destination is unreachable or answers a `HEAD`
+    /// request with `4xx`/`5xx`, at the cost of latency and of rejecting
+    /// destinations that mishandle `HEAD`. Private addresses are only
+    /// requested if [`ValidationRules::block_private_hosts`] is off.
+    pub verify_destination: bool,
+    /// Fetch the destination of a new link and hand out the existing link
+    /// of another url serving the same body, e.g. of urls with tracking
+    /// parameters. Only links to the same site (registrable domain if
+    /// [`AppConfig::public_suffixes`] is set, otherwise host) are shared, so
+    /// a mirror elsewhere never captures the links to a page, and with
+    /// [`AppConfig::namespace_by_key`] only links of the same key. Adds the
+    /// fetch (up to 5s) to every create, and pages changing over time
+    /// (timestamps, ads) never match while pages that once matched keep
+    /// sharing a link. Bodies over 1 MiB and failed fetches are not
+    /// deduplicated. Private addresses are only requested
+    /// if [`ValidationRules::block_private_hosts`] is off.
+    pub dedupe_by_content: bool,
+    /// NFC normalize the path and query of URLs before hashing, so variants
+    /// of the same text in other Unicode normal forms share one link instead
+    /// of looking identical while leading elsewhere. Non-ASCII text ends up
+    /// percent-encoded. Alters the URLs of the rare servers telling normal
+    /// forms apart.
+    pub normalize_unicode: bool,
+    /// Resolves of a single id per minute (over a sliding window) answered
+    /// before further ones get a `429`, so one hammered link can't flood the
+    /// backend. Other ids are unaffected. Unset disables the limit.
+    pub redirect_rate_limit: Option<u32>,
+    /// Ids `redirect_rate_limit` keeps track of. Ids beyond are redirected
+    /// unlimited until the windows of others ran out, a full limiter must
+    /// not take links offline. Wrong passphrases are counted for as many
+    /// ids, see `password_attempts`.
+    pub redirect_limiter_capacity: usize,
+    /// Wrong passphrases of a protected link within 15 minutes before
+    /// further attempts fail with [`LinkError::PasswordAttempts`], so a
+    /// short passphrase can't be guessed online. Counted per link for all
+    /// clients, once more ids than `redirect_limiter_capacity` see wrong
+    /// attempts, further ones are refused too.
+    pub password_attempts: u32,
+    /// Namespaces of API keys named `<tenant>_<secret>`, labelling the
+    /// create and redirect metrics with their `tenant`. Keys of unlisted
+    /// namespaces are counted as [`OTHER_TENANT`], bounding the cardinality.
+    pub metrics_tenants: HashSet<String>,
+}
 
-        loop {
-            let hash = link_hash(url, self.hash_length, hash_offset);
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            prefix: String::from("http://localhost:8080"),
+            hash_length: 6,
+            alias_min_length: 1,
+            alias_max_length: 32,
+            hash_salt: String::new(),
+            id_strategy: IdStrategy::Hash,
+            cache_size: 100,
+            cache_max_bytes: None,
+            invalidations: None,
+            clock: Arc::new(SystemClock),
+            x402_persist_testnet: false,
+            x402_solana: SolanaPayments::default(),
+            x402_host_prices: HashMap::new(),
+            turnstile_disabled: false,
+            max_total_links: None,
+            public_minimal_response: false,
+            idn_policy: IdnPolicy::Allow,
+            validation_rules: ValidationRules {
+                allowed_schemes: vec![String::from("http"), String::from("https")],
+                max_url_length: 4096,
+                block_private_hosts: false,
+            },
+            blocked_redirect_message: String::from(
+                "This link has been disabled because its destination is blocked.",
+            ),
+            expose_original_url_header: false,
+            link_id_header: false,
+            redirect_cache_max_age: None,
+            forward_query: false,
+            forward_query_max_params: 20,
+            forward_query_max_bytes: 1024,
+            strip_fragments: false,
+            canonicalize_query: false,
+            strip_query_params: Vec::new(),
+            health_format: HealthFormat::Html,
+            health_page: String::from("<h1>Hello, World!</h1>"),
+            public_stats_fields: PublicStatsField::DEFAULT.to_vec(),
+            trust_forwarded_proto: false,
+            trust_forwarded_for: false,
+            root_redirect: None,
+            collision_warn_limit: 1,
+            max_collision_retries: 1000,
+            public_tarpit_threshold: None,
+            public_tarpit_delay_ms: 2000,
+            public_limiter_capacity: 10_000,
+            public_rate_limit: None,
+            public_cooldown_secs: None,
+            namespace_by_key: false,
+            case_insensitive_ids: false,
+            case_insensitive_aliases: false,
+            unavailable_retry_after: 5,
+            sync_click_counts: false,
+            record_demo_requests: false,
+            upgrade_insecure: UpgradeInsecure::Off,
+            upgrade_insecure_hosts: HashSet::new(),
+            blocked_id_terms: Vec::new(),
+            redirect_loop_guard: false,
+            redirect_loop_hosts: HashSet::new(),
+            audit_log: false,
+            store_creator_ip: false,
+            public_suffixes: None,
+            tld_exempt_hosts: HashSet::new(),
+            dedup_concurrent_creates: false,
+            crawler_user_agents: DEFAULT_CRAWLER_USER_AGENTS.map(String::from).to_vec(),
+            fetch_titles: false,
+            verify_destination: false,
+            dedupe_by_content: false,
+            normalize_unicode: false,
+            redirect_rate_limit: None,
+            redirect_limiter_capacity: 10_000,
+            password_attempts: 5,
+            metrics_tenants: HashSet::new(),
+        }
+    }
+}
 
-            info!(hash, "creating link");
+/// Clicks of a link on one day (UTC), see [`App::link_timeseries`].
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct DailyClicks {
+    pub date: NaiveDate,
+    pub clicks: u64,
+}
 
-            let new_link = CreateLink {
-                id: hash.clone(),
-                url: url.to_string(),
-                key: api_key.clone(),
-            };
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct Summary {
+    pub total_links: i64,
+    pub by_source: BTreeMap<&'static str, i64>,
+}
 
-            let res = self.db.create(&new_link).await;
+/// Something that happened to a link, streamed to `/admin/events`.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LinkEvent {
+    Created {
+        id: String,
+        url: String,
+        source: &'static str,
+    },
+    Redirect {
+        id: String,
+    },
+}
 
-            match res {
-                Ok(_) => {
-                    return Ok(CreatedLinkResponse::new(
-                        new_link.id.clone(),
-                        &self.prefix,
-                        new_link.url.clone(),
-                    ));
-                }
-                Err(DbError::DuplicateId) => {
-                    info!(id = new_link.id, "id already exists");
+/// Events buffered per subscriber, slower ones skip what they missed.
+const EVENTS_CAPACITY: usize = 1024;
 
-                    if let Some(link) = self.db.get(&new_link.id).await?
-                        && link.url == payload.url
-                    {
-                        info!(hash, "id found");
+/// Field of the public link stats, see [`AppConfig::public_stats_fields`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PublicStatsField {
+    ClickCount,
+    LastUsed,
+    CreatedAt,
+    Title,
+    FirstUsed,
+}
 
-                        return Ok(CreatedLinkResponse::new(
-                            new_link.id.clone(),
-                            &self.prefix,
-                            new_link.url.clone(),
-                        ));
-                    }
+impl PublicStatsField {
+    pub const ALL: [PublicStatsField; 5] = [
+        PublicStatsField::ClickCount,
+        PublicStatsField::LastUsed,
+        PublicStatsField::CreatedAt,
+        PublicStatsField::Title,
+        PublicStatsField::FirstUsed,
+    ];
 
-                    hash_offset += 1;
+    /// Public unless configured otherwise, titles hint at the destination.
+    pub const DEFAULT: [PublicStatsField; 4] = [
+        PublicStatsField::ClickCount,
+        PublicStatsField::LastUsed,
+        PublicStatsField::CreatedAt,
+        PublicStatsField::FirstUsed,
+    ];
+}
 
-                    warn!(hash, hash_offset, "hash collision");
-                }
-                Err(e) => {
-                    error!("db error: {e}");
-                    anyhow::bail!("unexpected error");
-                }
-            };
-        }
-    }
+/// Shape of the `/readyz` (and `/health`) response.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HealthFormat {
+    /// Static html page, only the status code reflects the checks.
+    #[default]
+    Html,
+    /// `application/health+json` as described by the IETF health check draft.
+    Json,
+}
 
-    pub async fn redirect(&self, id: &str) -> Result<String, anyhow::Error> {
-        if let Some(link) = self.cache.get(id) {
-            self.click_counter.increment(id).await;
-            info!(id, "redirect from cache");
-            return Ok(link.clone());
-        }
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Pass,
+    Fail,
+}
 
-        let Some(link) = self.db.get(id).await? else {
-            anyhow::bail!("unknown link")
-        };
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthCheck {
+    pub component_type: &'static str,
+    pub status: HealthStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub observed_value: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub observed_unit: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Health {
+    pub status: HealthStatus,
+    /// Keyed by `component:measurement`.
+    pub checks: BTreeMap<&'static str, Vec<HealthCheck>>,
+}
+
+#[derive(Error, Debug, Clone)]
+pub enum LinkError {
+    #[error("maximum number of links reached")]
+    LimitReached,
+    #[error("unknown link")]
+    NotFound,
+    #[error("link belongs to another key")]
+    NotOwner,
+    #[error("destination host is blocked")]
+    HostBlocked,
+    /// The link points to a host blocked after it was created.
+    #[error("{0}")]
+    RedirectBlocked(String),
+    #[error("id is already taken")]
+    IdTaken,
+    #[error("link is password protected")]
+    PasswordRequired,
+    #[error("wrong password")]
+    WrongPassword,
+    #[error("link is disabled")]
+    Disabled,
+    /// The payment does not cover the price of the destination host.
+    #[error("links to this host cost {required} (USDC base units)")]
+    InsufficientPayment { required: u64 },
+    /// The DB could not be reached to look up an uncached link.
+    #[error("temporarily unavailable, retry in {retry_after}s")]
+    Unavailable { retry_after: u64 },
+    /// The request breaks the rules listed by their codes, e.g.
+    /// `url_too_long`.
+    #[error("validation failed: {}", .0.join(", "))]
+    Validation(Vec<&'static str>),
+    /// The payment was made on a chain not supported, e.g. Solana.
+    #[error("{0} payments are not supported")]
+    UnsupportedPayment(&'static str),
+    /// The link reached its `max_clicks`.
+    #[error("link is used up")]
+    Exhausted,
+    /// The link points to another short link of this service.
+    #[error("redirect loop detected")]
+    RedirectLoop,
+    /// The stored url of the link does not parse, e.g. after an import
+    /// straight into the DB.
+    #[error("the destination of this link is malformed")]
+    MalformedUrl,
+    /// No free id was found within `max_collision_retries`.
+    #[error("id space exhausted")]
+    IdSpaceExhausted,
+    /// The link was resolved more than [`AppConfig::redirect_rate_limit`]
+    /// times within a minute.
+    #[error("too many requests for this link, retry in {retry_after}s")]
+    RedirectRateLimited { retry_after: u64 },
+    /// The client IP created more than [`AppConfig::public_rate_limit`]
+    /// public links within a minute.
+    #[error("too many links created, retry in {retry_after}s")]
+    PublicRateLimited { retry_after: u64 },
+    /// The client IP created a public link less than
+    /// [`AppConfig::public_cooldown_secs`] ago.
+    #[error("one link per {cooldown}s, retry in {retry_after}s")]
+    PublicCooldown { cooldown: u64, retry_after: u64 },
+    /// The server is shutting down and refuses new links, another replica
+    /// may take them.
+    #[error("shutting down, retry in {retry_after}s")]
+    ShuttingDown { retry_after: u64 },
+    /// The link was tried with more than [`AppConfig::password_attempts`]
+    /// wrong passphrases recently.
+    #[error("too many wrong passwords, retry in {retry_after}s")]
+    PasswordAttempts { retry_after: u64 },
+}
+
+impl LinkError {
+    /// Tag of the error in per-item results, e.g. of [`App::create_links`].
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::LimitReached => "limit_reached",
+            Self::NotFound => "not_found",
+            Self::NotOwner => "not_owner",
+            Self::HostBlocked => "host_blocked",
+            Self::RedirectBlocked(_) => "redirect_blocked",
+            Self::IdTaken => "id_taken",
+            Self::PasswordRequired => "password_required",
+            Self::WrongPassword => "wrong_password",
+            Self::Disabled => "disabled",
+            Self::Exhausted => "exhausted",
+            Self::UnsupportedPayment(_) => "unsupported_payment",
+            Self::Validation(_) => "validation_failed",
+            Self::InsufficientPayment { .. } => "insufficient_payment",
+            Self::Unavailable { .. } => "unavailable",
+            Self::RedirectLoop => "redirect_loop",
+            Self::MalformedUrl => "malformed_url",
+            Self::IdSpaceExhausted => "id_space_exhausted",
+            Self::RedirectRateLimited { .. }
+            | Self::PublicRateLimited { .. }
+            | Self::PublicCooldown { .. }
+            | Self::PasswordAttempts { .. } => "rate_limited",
+            Self::ShuttingDown { .. } => "shutting_down",
+        }
+    }
+}
 
-        info!(id, "redirect from db");
+#[derive(Clone)]
+pub struct App {
+    db: Arc<dyn LinksDB>,
+    click_store: Arc<dyn ClickStore>,
+    pending_clicks: PendingClicks,
+    config: AppConfig,
+    cache: Arc<Cache<String, FetchLink, LinkWeighter>>,
+    metrics: Arc<Metrics>,
+    link_count: Fetched<i64>,
+    blocked_hosts: Fetched<Arc<HashSet<String>>>,
+    /// When links were last seen disabled, kept out of `cache`.
+    disabled_links: Arc<Cache<String, Instant>>,
+    /// Whether a destination url loops back, see [`App::is_redirect_loop`].
+    redirect_loops: Arc<Cache<String, bool>>,
+    /// Public creates per client IP in the current window, see
+    /// [`App::public_tarpit`].
+    public_requests: Arc<Limiter<IpAddr, (Instant, u32)>>,
+    /// Public creates per client IP in the current window, see
+    /// [`App::limit_public_rate`].
+    public_rates: Arc<Limiter<IpAddr, (Instant, u32)>>,
+    /// Last public create per client IP, see [`App::public_cooldown`].
+    public_creates: Arc<Limiter<IpAddr, Option<Instant>>>,
+    /// Resolves per id, see [`App::limit_redirect_rate`].
+    redirect_rates: Arc<Limiter<String, RedirectWindow>>,
+    /// Passphrase attempts per id, see [`App::check_password`].
+    password_attempts: Arc<Limiter<String, (Instant, u32)>>,
+    /// Creates in progress, see [`AppConfig::dedup_concurrent_creates`].
+    inflight_creates: Arc<Mutex<HashMap<String, InflightCreate>>>,
+    events: broadcast::Sender<LinkEvent>,
+    /// Set once the server began to shut down, see [`App::begin_shutdown`].
+    shutting_down: Arc<AtomicBool>,
+}
 
-        self.cache.insert(id.to_string(), link.url.clone());
+/// Resolves of an id in the current fixed window and the one before, which
+/// approximate a sliding window.
+#[derive(Clone, Copy)]
+struct RedirectWindow {
+    start: Instant,
+    count: u32,
+    previous: u32,
+}
 
-        self.click_counter.increment(id).await;
+/// Weight of a cached link, see [`AppConfig::cache_max_bytes`].
+#[derive(Clone, Copy)]
+enum LinkWeighter {
+    Entries,
+    Bytes,
+}
 
-        Ok(link.url)
+impl Weighter<String, FetchLink> for LinkWeighter {
+    fn weight(&self, id: &String, link: &FetchLink) -> u64 {
+        match self {
+            LinkWeighter::Entries => 1,
+            LinkWeighter::Bytes => {
+                let password_hash = link.password_hash.as_ref().map_or(0, String::len);
+                (id.len() + link.url.len() + password_hash) as u64
+            }
+        }
     }
 }
 
-#[cfg(test)]
-mod e2e_tests {
-    use super::*;
-    use crate::{
-        db::PostgresDb,
-        db_pool::{DbPool, init_crypto_provider},
-        migrations::run_migrations,
-    };
-    use testcontainers::{ContainerAsync, runners::AsyncRunner};
-    use testcontainers_modules::postgres::Postgres;
+/// A create shared by concurrent identical requests.
+type InflightCreate = Shared<BoxFuture<'static, Result<CreatedLinkResponse, Arc<anyhow::Error>>>>;
 
-    async fn get_postgres_testcontainer() -> (ContainerAsync<Postgres>, String) {
-        let c = Postgres::default().start().await.unwrap();
+/// A value fetched from the DB together with when it was fetched.
+type Fetched<T> = Arc<Mutex<Option<(Instant, T)>>>;
 
-        let host_port = c.get_host_port_ipv4(5432).await.unwrap();
-        let host = c.get_host().await.unwrap();
+const TEMPORARY_REDIRECT: i16 = 307;
+const PERMANENT_REDIRECT: i16 = 301;
 
-        let db_url = format!("postgres://postgres:postgres@{host}:{host_port}/postgres",);
+/// Rows fetched per DB round trip when exporting links.
+const EXPORT_PAGE_SIZE: i64 = 1000;
 
-        (c, db_url)
-    }
+/// Links per page of `GET /link/list` if the caller does not ask otherwise.
+const DEFAULT_LIST_PAGE_SIZE: u32 = 100;
 
-    #[tokio::test]
-    async fn test_app_smoke_test() {
-        init_crypto_provider();
+/// Most links per page of `GET /link/list`.
+const MAX_LIST_PAGE_SIZE: u32 = 1000;
 
-        let (_db_container, dburl) = get_postgres_testcontainer().await;
+/// How long the total link count is trusted before asking the DB again.
+const LINK_COUNT_TTL: Duration = Duration::from_secs(10);
 
-        run_migrations(&dburl).unwrap();
+/// How long a link seen disabled is answered without asking the DB, bounding
+/// how long other replicas keep refusing a link enabled again.
+const DISABLED_LINK_TTL: Duration = Duration::from_secs(10);
 
-        let pool = DbPool::build(&dburl, 1).await.unwrap();
+/// How long the blocklist is trusted before asking the DB again, bounding how
+/// long other replicas keep redirecting to a newly blocked host.
+const BLOCKED_HOSTS_TTL: Duration = Duration::from_secs(30);
 
-        let original_url = String::from("https://www.rustunit.com");
-        let key = String::from("key");
+/// Window public creates are counted in for [`AppConfig::public_tarpit_threshold`].
+const PUBLIC_TARPIT_WINDOW: Duration = Duration::from_secs(60);
 
-        let app = App::new(
-            "http://localhost".to_string(),
-            6,
-            Arc::new(PostgresDb::new(pool)),
-            Arc::new(ClickCounter::new()),
-            10,
-        );
-        let res = app
-            .create_link(
-                key.clone(),
-                CreateLinkRequest {
-                    url: original_url.clone(),
-                },
-                false,
-            )
-            .await
-            .unwrap();
+/// Window public creates are counted in for [`AppConfig::public_rate_limit`].
+const PUBLIC_RATE_WINDOW: Duration = Duration::from_secs(60);
 
-        assert_eq!(&res.id, "as9sud");
-        assert_eq!(&res.original_url, &original_url);
-        assert_eq!(&res.shortened_url, "http://localhost/as9sud");
+/// Window of [`AppConfig::redirect_rate_limit`].
+const REDIRECT_RATE_WINDOW: Duration = Duration::from_secs(60);
 
-        let res = app
-            .create_link(
-                key,
-                CreateLinkRequest {
-                    url: original_url.clone(),
-                },
-                false,
-            )
-            .await
-            .unwrap();
+/// Window wrong passphrases are counted in for [`AppConfig::password_attempts`].
+const PASSWORD_ATTEMPT_WINDOW: Duration = Duration::from_secs(15 * 60);
 
-        assert_eq!(&res.id, "as9sud");
-        assert_eq!(&res.original_url, &original_url);
-        assert_eq!(&res.shortened_url, "http://localhost/as9sud");
+/// Whether `host` or any domain it is a subdomain of is in `list`.
+fn is_host_listed(list: &HashSet<String>, host: &str) -> bool {
+    let host = host.trim_end_matches('.');
+
+    std::iter::successors(Some(host), |host| {
+        host.split_once('.').map(|(_, parent)| parent)
+    })
+    .any(|host| list.contains(host))
+}
+
+/// Blocklist entries are compared against the ascii form of url hosts.
+fn normalize_host(host: &str) -> Result<String, anyhow::Error> {
+    let host = idna::domain_to_ascii(host.trim().trim_end_matches('.'))?;
+    if host.is_empty() {
+        anyhow::bail!("empty host");
     }
+    Ok(host)
+}
 
-    #[tokio::test]
-    async fn test_invalid_url() {
-        init_crypto_provider();
+/// How to treat internationalized (non-ASCII) hosts, a common vector for
+/// homograph phishing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IdnPolicy {
+    /// Store the url as submitted.
+    #[default]
+    Allow,
+    /// Store the url with its host in punycode form.
+    Normalize,
+    /// Refuse urls with internationalized hosts.
+    Reject,
+}
 
-        let (_db_container, dburl) = get_postgres_testcontainer().await;
+/// `tenant` label of metrics for keys outside [`AppConfig::metrics_tenants`].
+pub const OTHER_TENANT: &str = "other";
 
-        run_migrations(&dburl).unwrap();
+/// What [`App::import_link`] does with an id that exists already.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnConflict {
+    /// Fail with [`LinkError::IdTaken`].
+    #[default]
+    Fail,
+    /// Leave the stored link as it is.
+    Skip,
+    /// Point the stored link to the imported url, if the same API key owns
+    /// it. Its stats are kept.
+    Overwrite,
+}
 
-        let pool = DbPool::build(&dburl, 1).await.unwrap();
+/// What [`App::import_link`] did with a link.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImportOutcome {
+    Imported,
+    /// The id existed already and was left alone.
+    Skipped,
+    /// The id existed already and now points to the imported url.
+    Overwritten,
+}
 
-        let app = App::new(
-            "http://localhost".to_string(),
-            6,
-            Arc::new(PostgresDb::new(pool)),
-            Arc::new(ClickCounter::new()),
-            10,
-        );
-        let res = app
-            .create_link(
-                String::from("key"),
-                CreateLinkRequest {
-                    url: String::from("abcde.com"),
-                },
-                false,
-            )
-            .await;
+/// How ids of new links are generated.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IdStrategy {
+    /// Hash the url, which can collide with stored links and is retried
+    /// with the next hash offset then.
+    #[default]
+    Hash,
+    /// Encode the next number of a DB sequence, which never collides with
+    /// other generated ids. Links of the same url are shared per API key
+    /// only.
+    Sequential,
+}
 
-        assert!(res.is_err());
-    }
+/// Handling of x402 payments on Solana, until they are supported.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SolanaPayments {
+    /// Log an error and answer `500`, to debug clients paying on Solana.
+    #[default]
+    RejectLoud,
+    /// Log at debug level and answer `400`, for deployments that never
+    /// expect Solana payments.
+    RejectQuiet,
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::{db::MockLinksDB, models::FetchLink};
+/// Which `http://` urls to store as `https://`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UpgradeInsecure {
+    /// Store the url as submitted.
+    #[default]
+    Off,
+    /// Upgrade urls of the configured hosts only.
+    Listed,
+    /// Upgrade all urls, breaking links to hosts without https.
+    All,
+}
 
-    #[tokio::test]
-    async fn test_caching() {
-        let link = CreateLink {
-            id: String::from("id"),
-            url: String::from("url"),
-            key: String::from("key"),
-        };
+/// Switches `url` to https according to `mode`. Urls with an explicit port
+/// are left alone, as the port most likely speaks plain http.
+fn upgrade_insecure<'a>(
+    url: &'a str,
+    mode: UpgradeInsecure,
+    hosts: &HashSet<String>,
+) -> Cow<'a, str> {
+    let Ok(mut parsed) = Url::parse(url) else {
+        return Cow::Borrowed(url);
+    };
 
-        let mut db = MockLinksDB::new();
-        db.expect_get().times(1).returning(move |_| {
-            Ok(Some(FetchLink {
-                id: link.id.clone(),
-                url: link.url.clone(),
-            }))
-        });
+    if parsed.scheme() != "http" || parsed.port().is_some() {
+        return Cow::Borrowed(url);
+    }
 
-        let app = App::new(
-            "http://localhost".to_string(),
-            6,
-            Arc::new(db),
-            Arc::new(ClickCounter::new()),
-            10,
-        );
+    let upgrade = match mode {
+        UpgradeInsecure::Off => false,
+        UpgradeInsecure::Listed => parsed
+            .host_str()
+            .is_some_and(|host| is_host_listed(hosts, host)),
+        UpgradeInsecure::All => true,
+    };
+
+    // rebuilt from the parsed url, the input may spell the scheme oddly,
+    // e.g. `http:example.com`
+    if upgrade && parsed.set_scheme("https").is_ok() {
+        Cow::Owned(parsed.to_string())
+    } else {
+        Cow::Borrowed(url)
+    }
+}
 
-        let res = app.redirect("foo").await.unwrap();
-        assert_eq!(&res, "url");
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Script {
+    Latin,
+    Greek,
+    Cyrillic,
+    Other,
+}
 
-        let res = app.redirect("foo").await.unwrap();
-        assert_eq!(&res, "url");
+fn script(c: char) -> Option<Script> {
+    match c {
+        'a'..='z' | 'A'..='Z' | '\u{00C0}'..='\u{024F}' => Some(Script::Latin),
+        '\u{0370}'..='\u{03FF}' => Some(Script::Greek),
+        '\u{0400}'..='\u{052F}' => Some(Script::Cyrillic),
+        // digits and hyphens are shared by all scripts
+        '0'..='9' | '-' => None,
+        _ => Some(Script::Other),
     }
 }
 
-#[cfg(test)]
-mod test_collisions {
-    use crate::models::FetchLink;
+/// Whether any label of the (unicode) host mixes letters of different
+/// scripts, like a latin domain with a single cyrillic lookalike.
+fn is_mixed_script(host: &str) -> bool {
+    host.split('.').any(|label| {
+        let mut scripts = label.chars().filter_map(script);
+        scripts
+            .next()
+            .is_some_and(|first| scripts.any(|s| s != first))
+    })
+}
 
-    use super::*;
-    use async_trait::async_trait;
-    use std::collections::HashMap;
-    use tokio::sync::Mutex;
+/// Loopback, private network and link-local hosts. IP hosts are parsed into
+/// their standard form already, so encodings like `http://0x7f.1/` or
+/// `http://2130706433/` are caught as `127.0.0.1`.
+pub fn is_private_host(host: &Host<&str>) -> bool {
+    match host {
+        Host::Domain(domain) => *domain == "localhost" || domain.ends_with(".localhost"),
+        Host::Ipv4(ip) => {
+            ip.is_loopback() || ip.is_private() || ip.is_link_local() || ip.is_unspecified()
+        }
+        Host::Ipv6(ip) => match ip.to_ipv4_mapped() {
+            // e.g. `[::ffff:127.0.0.1]`, which reaches the IPv4 address
+            Some(ip) => is_private_host(&Host::Ipv4(ip)),
+            None => {
+                ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local()
+            }
+        },
+    }
+}
+
+/// Schemes embedding or reaching local content, rejected even when a
+/// misconfigured allow-list lets them through.
+const DENIED_SCHEMES: [&str; 4] = ["data", "blob", "file", "javascript"];
+
+/// Violation of a URL without a public domain suffix, see
+/// [`AppConfig::public_suffixes`].
+const NO_PUBLIC_SUFFIX: &str = "no_public_suffix";
+
+/// Violation of a URL that does not parse at all, no further rules are
+/// checked then.
+const INVALID_URL: &str = "invalid_url";
 
-    #[derive(Debug, Default)]
-    struct MemDb {
-        data: Arc<Mutex<HashMap<String, CreateLink>>>,
+/// Validates `url` and returns the form of it to store, or the codes of all
+/// rules it violates, see [`LinkError::Validation`].
+fn validate_url(
+    url: &str,
+    rules: &ValidationRules,
+    idn_policy: IdnPolicy,
+) -> Result<String, Vec<&'static str>> {
+    let mut violations = Vec::new();
+
+    if url.len() > rules.max_url_length {
+        violations.push("url_too_long");
     }
 
-    #[async_trait]
-    impl LinksDB for MemDb {
-        async fn create_transaction(&self, _tx: &CreateTransaction) -> Result<(), DbError> {
-            panic!("should not be used in this test");
-        }
+    let Ok(parsed) = Url::parse(url) else {
+        violations.push(INVALID_URL);
+        return Err(violations);
+    };
+    if DENIED_SCHEMES.contains(&parsed.scheme())
+        || !rules.allowed_schemes.iter().any(|s| s == parsed.scheme())
+    {
+        violations.push("scheme_not_allowed");
+    }
 
-        async fn create(&self, link: &CreateLink) -> Result<CreateLink, DbError> {
-            let mut db = self.data.lock().await;
+    if rules.block_private_hosts && parsed.host().as_ref().is_some_and(is_private_host) {
+        violations.push("private_host");
+    }
 
-            if db.contains_key(&link.id) {
-                Err(DbError::DuplicateId)
-            } else {
-                db.insert(link.id.clone(), link.clone());
-                Ok(link.clone())
-            }
+    // the parser already converted internationalized hosts to punycode
+    let host = parsed.host_str().unwrap_or_default();
+    let is_idn = host.split('.').any(|label| label.starts_with("xn--"));
+
+    if is_idn {
+        let (unicode_host, _) = idna::domain_to_unicode(host);
+        if is_mixed_script(&unicode_host) {
+            warn!(host = unicode_host, "mixed-script host");
         }
 
-        async fn get(&self, id: &str) -> Result<Option<FetchLink>, DbError> {
-            let db = self.data.lock().await;
-            Ok(Some(FetchLink {
-                id: id.to_string(),
-                url: db.get(id).unwrap().url.clone(),
-            }))
+        if idn_policy == IdnPolicy::Reject {
+            violations.push("idn_not_allowed");
         }
     }
 
-    #[tokio::test]
-    async fn test_hash_collision() {
-        let link1 = "https://www.google.com/search?q=foobar";
-        let link2 = "https://www.google.com/search?q=foobar7";
-        assert_eq!(&link_hash(link1, 1, 0)[0..1], "d");
-        assert_eq!(&link_hash(link1, 1, 0)[0..1], &link_hash(link2, 1, 0)[0..1],);
+    if !violations.is_empty() {
+        return Err(violations);
+    }
 
-        let db = MemDb::default();
-        let key = String::from("key");
+    match idn_policy {
+        IdnPolicy::Normalize if is_idn => Ok(parsed.to_string()),
+        _ => Ok(url.to_string()),
+    }
+}
+
+/// Whether the domain of `url` ends in a suffix of `list` or is exempt.
+fn has_public_suffix(url: &str, list: &publicsuffix::List, exempt: &HashSet<String>) -> bool {
+    let Ok(url) = Url::parse(url) else {
+        return false;
+    };
+
+    match url.host() {
+        Some(Host::Domain(domain)) => {
+            let domain = domain.trim_end_matches('.');
+            exempt.contains(domain)
+                || list
+                    .suffix(domain.as_bytes())
+                    .is_some_and(|suffix| suffix.is_known())
+        }
+        Some(Host::Ipv4(_) | Host::Ipv6(_)) => true,
+        None => false,
+    }
+}
+
+/// Paths of the router itself, which would shadow links with these ids.
+const RESERVED_IDS: [&str; 12] = [
+    "admin", "auth", "health", "link", "livez", "metrics", "readyz", "rules", "shorten", "stats",
+    "trending", "x402",
+];
+
+/// Most ids [`App::delete_links`] accepts in one call.
+pub const MAX_DELETE_BATCH: usize = 1000;
+
+/// Most links [`App::create_links`] accepts in one call.
+pub const MAX_CREATE_BATCH: usize = 100;
+
+/// Most urls [`App::find_links`] accepts in one call.
+pub const MAX_FIND_BATCH: usize = 1000;
+
+/// Most ids [`App::resolve_many`] accepts in one call.
+pub const MAX_RESOLVE_BATCH: usize = 1000;
+
+/// Most ids [`App::warm_cache`] loads in one call.
+pub const MAX_WARM_BATCH: usize = 1000;
+
+/// Cap of the `limit` of [`App::top_links`].
+pub const MAX_TOP_LINKS: u32 = 100;
+
+/// Most days [`App::link_timeseries`] covers in one call.
+pub const MAX_TIMESERIES_DAYS: i64 = 366;
+
+/// Most characters of a link title, longer fetched titles are cut.
+pub const MAX_TITLE_LENGTH: usize = 200;
+
+/// Most characters of a link note.
+pub const MAX_NOTE_LENGTH: usize = 500;
+
+/// Most characters of an `og:description`.
+pub const MAX_OG_DESCRIPTION_LENGTH: usize = 500;
+
+/// `User-Agent` parts of the crawlers building link previews of the common
+/// social networks and messengers.
+pub const DEFAULT_CRAWLER_USER_AGENTS: [&str; 7] = [
+    "facebookexternalhit",
+    "twitterbot",
+    "linkedinbot",
+    "slackbot",
+    "discordbot",
+    "telegrambot",
+    "whatsapp",
+];
+
+/// Checks the Open Graph tags of a new link, the image must be an absolute
+/// http(s) URL for crawlers to fetch it.
+fn validate_open_graph(og: &OpenGraph) -> Result<(), anyhow::Error> {
+    if og
+        .title
+        .as_ref()
+        .is_some_and(|title| title.chars().count() > MAX_TITLE_LENGTH)
+    {
+        anyhow::bail!("og:title exceeds {MAX_TITLE_LENGTH} characters");
+    }
+    if og
+        .description
+        .as_ref()
+        .is_some_and(|description| description.chars().count() > MAX_OG_DESCRIPTION_LENGTH)
+    {
+        anyhow::bail!("og:description exceeds {MAX_OG_DESCRIPTION_LENGTH} characters");
+    }
+    if let Some(image) = &og.image
+        && !Url::parse(image).is_ok_and(|url| matches!(url.scheme(), "http" | "https"))
+    {
+        anyhow::bail!("og:image must be an http or https URL");
+    }
+
+    Ok(())
+}
+
+/// Whether `id` could name a link at all, so scanner requests for paths
+/// like `/wp-login.php` are answered without a lookup.
+pub fn is_plausible_id(id: &str) -> bool {
+    !id.is_empty() && id.bytes().all(|b| b.is_ascii_alphanumeric())
+}
+
+/// Checks an id given by the client follows the rules of generated ids:
+/// lowercase letters and digits only, within the configured `length`.
+fn validate_id(id: &str, length: RangeInclusive<usize>) -> Result<(), anyhow::Error> {
+    if !length.contains(&id.len()) {
+        anyhow::bail!(
+            "id must be {} to {} characters long",
+            length.start(),
+            length.end()
+        );
+    }
+
+    if !id
+        .bytes()
+        .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit())
+    {
+        anyhow::bail!("id may only contain lowercase letters and digits");
+    }
+
+    if RESERVED_IDS.contains(&id) {
+        anyhow::bail!("id '{id}' is reserved");
+    }
+
+    Ok(())
+}
+
+/// Parses a blocklist file with one term per line, skipping empty lines and
+/// `#` comments.
+pub fn parse_blocked_id_terms(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_ascii_lowercase)
+        .collect()
+}
+
+/// Whether `url` has the form of a short link below `prefix`, on its host or
+/// one of the `aliases`.
+fn points_to_short_link(url: &str, prefix: &str, aliases: &HashSet<String>) -> bool {
+    let (Ok(url), Ok(prefix)) = (Url::parse(url), Url::parse(prefix)) else {
+        return false;
+    };
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+
+    let own_host = prefix
+        .host_str()
+        .is_some_and(|own| own.eq_ignore_ascii_case(host) && url.port() == prefix.port());
+    if !own_host && !is_host_listed(aliases, host) {
+        return false;
+    }
+
+    url.path()
+        .strip_prefix(prefix.path().trim_end_matches('/'))
+        .and_then(|path| path.strip_prefix('/'))
+        .is_some_and(is_plausible_id)
+}
+
+/// Appends the parameters of the raw `query` to `url`, stopping at
+/// `max_params` of them or before exceeding `max_bytes`, so a huge query
+/// cannot bloat the redirect.
+pub fn forward_query(url: &str, query: &str, max_params: usize, max_bytes: usize) -> String {
+    let mut forwarded = String::new();
+    for param in query
+        .split('&')
+        .filter(|param| !param.is_empty())
+        .take(max_params)
+    {
+        let separator = usize::from(!forwarded.is_empty());
+        if forwarded.len() + separator + param.len() > max_bytes {
+            break;
+        }
+        if separator == 1 {
+            forwarded.push('&');
+        }
+        forwarded.push_str(param);
+    }
+
+    if forwarded.is_empty() {
+        return url.to_string();
+    }
+
+    let (url, fragment) = match url.split_once('#') {
+        Some((url, fragment)) => (url, Some(fragment)),
+        None => (url, None),
+    };
+    let separator = match url.find('?') {
+        None => "?",
+        Some(_) if url.ends_with(['?', '&']) => "",
+        Some(_) => "&",
+    };
+
+    match fragment {
+        Some(fragment) => format!("{url}{separator}{forwarded}#{fragment}"),
+        None => format!("{url}{separator}{forwarded}"),
+    }
+}
+
+fn strip_fragment(url: &str) -> &str {
+    url.split_once('#').map_or(url, |(url, _)| url)
+}
+
+/// NFC normalizes the non-ASCII text of the path and query of `url`, which
+/// comes out percent-encoded whether it was submitted so or not. Escaped
+/// ASCII like `%2F` is kept as is, as decoding it could change the meaning
+/// of the URL.
+fn normalize_unicode(url: &str) -> Cow<'_, str> {
+    let Ok(parsed) = Url::parse(url) else {
+        return Cow::Borrowed(url);
+    };
+    let path_and_query = &parsed[Position::BeforePath..Position::AfterQuery];
+
+    let mut bytes = Vec::with_capacity(path_and_query.len());
+    let mut rest = path_and_query.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = tail
+            .get(..2)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            .filter(|decoded| byte == b'%' && !decoded.is_ascii());
+
+        match escaped {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+
+    let Ok(decoded) = String::from_utf8(bytes) else {
+        return Cow::Borrowed(url);
+    };
+    if decoded.is_ascii() {
+        return Cow::Borrowed(url);
+    }
+
+    let mut normalized = parsed[..Position::BeforePath].to_string();
+    for c in decoded.nfc() {
+        if c.is_ascii() {
+            normalized.push(c);
+        } else {
+            for byte in c.encode_utf8(&mut [0; 4]).bytes() {
+                normalized.push_str(&format!("%{byte:02X}"));
+            }
+        }
+    }
+    normalized.push_str(&parsed[Position::AfterQuery..]);
+
+    if normalized == url {
+        Cow::Borrowed(url)
+    } else {
+        Cow::Owned(normalized)
+    }
+}
+
+/// Sorts the query parameters of `url` by name and drops those matching
+/// `strip`. Parameters sharing a name keep their order, as it may matter
+/// for lists, and the encoding is left as submitted.
+fn canonicalize_query<'a>(url: &'a str, strip: &[String]) -> Cow<'a, str> {
+    let (rest, fragment) = match url.split_once('#') {
+        Some((rest, fragment)) => (rest, Some(fragment)),
+        None => (url, None),
+    };
+    let Some((base, query)) = rest.split_once('?') else {
+        return Cow::Borrowed(url);
+    };
+
+    let mut params: Vec<&str> = query
+        .split('&')
+        .filter(|param| !param.is_empty())
+        .filter(|param| !is_param_listed(strip, param_name(param)))
+        .collect();
+    params.sort_by(|a, b| param_name(a).cmp(param_name(b)));
+
+    let mut canonical = base.to_string();
+    if !params.is_empty() {
+        canonical.push('?');
+        canonical.push_str(&params.join("&"));
+    }
+    if let Some(fragment) = fragment {
+        canonical.push('#');
+        canonical.push_str(fragment);
+    }
+
+    if canonical == url {
+        Cow::Borrowed(url)
+    } else {
+        Cow::Owned(canonical)
+    }
+}
+
+fn param_name(param: &str) -> &str {
+    param.split_once('=').map_or(param, |(name, _)| name)
+}
+
+fn is_param_listed(list: &[String], name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+
+    list.iter().any(|pattern| match pattern.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => name == *pattern,
+    })
+}
+
+/// Drops the links other replicas changed from the caches of `app`, until
+/// the app is gone.
+async fn apply_invalidations(app: Weak<App>, mut invalidations: broadcast::Receiver<Invalidation>) {
+    loop {
+        let invalidation = invalidations.recv().await;
+        let Some(app) = app.upgrade() else {
+            return;
+        };
+
+        match invalidation {
+            Ok(Invalidation::Link(id)) => {
+                app.cache.remove(&id);
+                app.disabled_links.remove(&id);
+            }
+            Ok(Invalidation::All) | Err(broadcast::error::RecvError::Lagged(_)) => {
+                warn!("dropping all cached links, invalidations may have been missed");
+                app.cache.clear();
+                app.disabled_links.clear();
+            }
+            Err(broadcast::error::RecvError::Closed) => return,
+        }
+    }
+}
+
+impl App {
+    pub fn new(
+        config: AppConfig,
+        db: Arc<dyn LinksDB>,
+        click_store: Arc<dyn ClickStore>,
+    ) -> Arc<Self> {
+        let cache = match config.cache_max_bytes {
+            Some(max_bytes) => {
+                Cache::with_weighter(config.cache_size, max_bytes, LinkWeighter::Bytes)
+            }
+            None => Cache::with_weighter(
+                config.cache_size,
+                config.cache_size as u64,
+                LinkWeighter::Entries,
+            ),
+        };
+
+        let app = Arc::new(Self {
+            db,
+            cache: Arc::new(cache),
+            disabled_links: Arc::new(Cache::new(config.cache_size)),
+            redirect_loops: Arc::new(Cache::new(config.cache_size)),
+            public_requests: Arc::new(Limiter::new(config.public_limiter_capacity)),
+            public_rates: Arc::new(Limiter::new(config.public_limiter_capacity)),
+            public_creates: Arc::new(Limiter::new(config.public_limiter_capacity)),
+            redirect_rates: Arc::new(Limiter::new(config.redirect_limiter_capacity)),
+            password_attempts: Arc::new(Limiter::new(config.redirect_limiter_capacity)),
+            inflight_creates: Arc::new(Mutex::new(HashMap::new())),
+            metrics: Arc::new(Metrics::new()),
+            config,
+            click_store,
+            pending_clicks: PendingClicks::default(),
+            link_count: Arc::new(Mutex::new(None)),
+            blocked_hosts: Arc::new(Mutex::new(None)),
+            events: broadcast::channel(EVENTS_CAPACITY).0,
+            shutting_down: Arc::new(AtomicBool::new(false)),
+        });
+
+        if let Some(invalidations) = &app.config.invalidations {
+            tokio::spawn(apply_invalidations(
+                Arc::downgrade(&app),
+                invalidations.subscribe(),
+            ));
+        }
+
+        app
+    }
+
+    /// Drops link `id` from the cache, and from the caches of the other
+    /// replicas if [`AppConfig::invalidations`] are shared.
+    async fn invalidate(&self, id: &str) {
+        self.cache.remove(id);
+
+        if let Some(invalidations) = &self.config.invalidations {
+            invalidations.publish(id).await;
+        }
+    }
+
+    /// Makes [`App::accept_creates`] refuse new links while requests in
+    /// flight finish, redirects keep being served.
+    pub fn begin_shutdown(&self) {
+        info!("refusing creates, shutting down");
+        self.shutting_down.store(true, Ordering::Relaxed);
+    }
+
+    /// Fails with [`LinkError::ShuttingDown`] once [`App::begin_shutdown`]
+    /// was called, so clients retry against another replica.
+    pub fn accept_creates(&self) -> Result<(), LinkError> {
+        if self.shutting_down.load(Ordering::Relaxed) {
+            return Err(LinkError::ShuttingDown {
+                retry_after: self.config.unavailable_retry_after,
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn config(&self) -> &AppConfig {
+        &self.config
+    }
+
+    /// The price of a paid link to `url` if its host (or a domain it is a
+    /// subdomain of) has its own price, the most specific one winning.
+    pub fn x402_price_for(&self, url: &str) -> Option<u64> {
+        let url = Url::parse(url).ok()?;
+        let host = url.host_str()?.trim_end_matches('.');
+
+        std::iter::successors(Some(host), |host| {
+            host.split_once('.').map(|(_, parent)| parent)
+        })
+        .find_map(|host| self.config.x402_host_prices.get(host).copied())
+    }
+
+    pub fn subscribe_events(&self) -> broadcast::Receiver<LinkEvent> {
+        self.events.subscribe()
+    }
+
+    fn publish(&self, event: LinkEvent) {
+        // only fails when nobody is subscribed
+        let _ = self.events.send(event);
+    }
+
+    /// Records a request answered with the demo response, if enabled.
+    /// Failures are only logged, as they must not fail the demo response.
+    pub async fn record_demo_request(&self, url: &str, source_ip: Option<String>) {
+        if !self.config.record_demo_requests {
+            return;
+        }
+
+        info!(url, source_ip, "demo request recorded");
+
+        let request = CreateDemoRequest {
+            url: url.to_string(),
+            source_ip,
+        };
+        if let Err(e) = self.db.create_demo_request(&request).await {
+            error!("failed to store demo request: {e}");
+        }
+    }
+
+    /// Appends the creation of `link` to the audit log, if enabled.
+    /// Failures are only logged, as the link exists already.
+    async fn audit_creation(&self, link: &CreateLink, source_ip: Option<IpAddr>) {
+        if !self.config.audit_log {
+            return;
+        }
+
+        let entry = CreateAuditEntry {
+            action: String::from("create"),
+            link_id: link.id.clone(),
+            url: link.url.clone(),
+            key: Some(link.key.clone()),
+            source: link.source.clone(),
+            source_ip: source_ip.map(|ip| ip.to_string()),
+        };
+        if let Err(e) = self.db.create_audit_entry(&entry).await {
+            error!(id = link.id, "failed to write audit log: {e}");
+        }
+    }
+
+    /// Fills the title of the new link `id` with the one of its destination,
+    /// without holding up the response.
+    fn spawn_title_fetch(&self, id: String, url: String) {
+        let db = self.db.clone();
+
+        tokio::spawn(async move {
+            match crate::destination::fetch_title(&url).await {
+                Ok(Some(title)) => {
+                    if let Err(e) = db.set_title(&id, &title).await {
+                        error!(id, "failed to store title: {e}");
+                    }
+                }
+                Ok(None) => debug!(id, "destination has no title"),
+                Err(e) => debug!(id, "failed to fetch title: {e:#}"),
+            }
+        });
+    }
+
+    #[instrument(skip(self), err)]
+    pub async fn store_transaction(&self, tx: CreateTransaction) -> Result<(), anyhow::Error> {
+        self.db.create_transaction(&tx).await?;
+
+        Ok(())
+    }
+
+    /// [`validate_url`] with the rules of the config, returning the form of
+    /// `url` to store.
+    fn validate_url(&self, url: &str) -> Result<String, anyhow::Error> {
+        self.check_url(url)
+            .map_err(|violations| LinkError::Validation(violations).into())
+    }
+
+    /// [`validate_url`] with the configured rules, plus the public suffix
+    /// check if enabled.
+    fn check_url(&self, url: &str) -> Result<String, Vec<&'static str>> {
+        let checked = validate_url(url, &self.config.validation_rules, self.config.idn_policy);
+
+        let Some(list) = &self.config.public_suffixes else {
+            return checked;
+        };
+        let suffixed = has_public_suffix(
+            checked.as_deref().unwrap_or(url),
+            list,
+            &self.config.tld_exempt_hosts,
+        );
+
+        match checked {
+            Ok(url) if suffixed => Ok(url),
+            Ok(_) => Err(vec![NO_PUBLIC_SUFFIX]),
+            Err(mut violations) => {
+                if !suffixed && !violations.contains(&INVALID_URL) {
+                    violations.push(NO_PUBLIC_SUFFIX);
+                }
+                Err(violations)
+            }
+        }
+    }
+
+    /// Validates `url` and normalizes it as configured, into the form links
+    /// are stored with.
+    fn normalize_url(&self, url: &str) -> Result<String, anyhow::Error> {
+        let url = self.validate_url(url)?;
+
+        Ok(self.normalize_checked_url(&url))
+    }
+
+    /// [`App::normalize_url`] of a `url` validated already.
+    fn normalize_checked_url(&self, url: &str) -> String {
+        let url = if self.config.strip_fragments {
+            strip_fragment(url)
+        } else {
+            url
+        };
+        let url = if self.config.normalize_unicode {
+            normalize_unicode(url)
+        } else {
+            Cow::Borrowed(url)
+        };
+        let url = if self.config.canonicalize_query {
+            canonicalize_query(&url, &self.config.strip_query_params)
+        } else {
+            url
+        };
+        let url = upgrade_insecure(
+            &url,
+            self.config.upgrade_insecure,
+            &self.config.upgrade_insecure_hosts,
+        );
+
+        url.into_owned()
+    }
+
+    /// x402 payments made for the link, failing if the link does not exist.
+    pub async fn link_transactions(&self, id: &str) -> Result<Vec<Transaction>, anyhow::Error> {
+        if self.db.get_details(id).await?.is_none() {
+            return Err(LinkError::NotFound.into());
+        }
+
+        Ok(self.db.transactions_for(id).await?)
+    }
+
+    /// Distinct networks of the x402 payments made for link `id`.
+    pub async fn link_networks(&self, id: &str) -> Result<Vec<String>, anyhow::Error> {
+        let mut networks: Vec<String> = self
+            .db
+            .transactions_for(id)
+            .await?
+            .into_iter()
+            .map(|tx| tx.network)
+            .collect();
+        // transactions come sorted by network
+        networks.dedup();
+
+        Ok(networks)
+    }
+
+    /// Creates a link for `api_key`, `source_ip` is kept in the audit log.
+    /// Only the url of `payload` is recorded, it may carry a password.
+    #[instrument(skip(self, payload), fields(url = %payload.url), err)]
+    pub async fn create_link(
+        &self,
+        api_key: String,
+        source: LinkSource,
+        mut payload: CreateLinkRequest,
+        demo_mode: bool,
+        source_ip: Option<IpAddr>,
+    ) -> Result<CreatedLinkResponse, anyhow::Error> {
+        if self.config.case_insensitive_aliases
+            && let Some(alias) = payload.alias.as_mut()
+        {
+            alias.make_ascii_lowercase();
+        }
+
+        // all violations are reported at once, so clients can fix them in one go
+        let (url, mut violations) = match self.check_url(&payload.url) {
+            Ok(url) => (Some(url), Vec::new()),
+            Err(violations) => (None, violations),
+        };
+        if payload
+            .title
+            .as_ref()
+            .is_some_and(|title| title.chars().count() > MAX_TITLE_LENGTH)
+        {
+            violations.push("title_too_long");
+        }
+        if payload
+            .note
+            .as_ref()
+            .is_some_and(|note| note.chars().count() > MAX_NOTE_LENGTH)
+        {
+            violations.push("note_too_long");
+        }
+        if let Some(og) = &payload.open_graph
+            && let Err(e) = validate_open_graph(og)
+        {
+            debug!("invalid open graph tags: {e}");
+            violations.push("invalid_open_graph");
+        }
+        if payload
+            .id_prefix
+            .is_some_and(|prefix| !prefix.is_ascii_lowercase() && !prefix.is_ascii_digit())
+        {
+            violations.push("invalid_id_prefix");
+        }
+        if let Some(alias) = &payload.alias
+            && let Err(e) = validate_id(
+                alias,
+                self.config.alias_min_length..=self.config.alias_max_length,
+            )
+        {
+            debug!(alias, "invalid alias: {e}");
+            violations.push("invalid_alias");
+        }
+        let url = match url {
+            Some(url) if violations.is_empty() => self.normalize_checked_url(&url),
+            _ => return Err(LinkError::Validation(violations).into()),
+        };
+        let url = url.as_str();
+
+        if self.is_destination_blocked(url).await? {
+            return Err(LinkError::HostBlocked.into());
+        }
+
+        // If demo mode is enabled, return a demo response without creating a real link
+        if demo_mode {
+            info!("demo request");
+            return Ok(CreatedLinkResponse::new(
+                "rustunit".to_string(),
+                &self.config.prefix,
+                url.to_string(),
+            ));
+        }
+
+        if self.config.verify_destination {
+            crate::destination::verify(url, !self.config.validation_rules.block_private_hosts)
+                .await?;
+        }
+
+        // expiring links are never shared, each caller gets its own
+        if !self.config.dedup_concurrent_creates
+            || payload.force_new
+            || payload.password.as_deref().is_some_and(|pw| !pw.is_empty())
+            || payload.ttl_seconds.is_some()
+            || payload.max_clicks.is_some()
+        {
+            return self
+                .insert_link(api_key, source, payload, url, source_ip)
+                .await;
+        }
+
+        self.insert_link_once(api_key, source, payload, url, source_ip)
+            .await
+    }
+
+    /// Runs [`App::insert_link`] once for concurrent identical requests,
+    /// which all get its response, so a trending URL costs one insert.
+    async fn insert_link_once(
+        &self,
+        api_key: String,
+        source: LinkSource,
+        payload: CreateLinkRequest,
+        url: &str,
+        source_ip: Option<IpAddr>,
+    ) -> Result<CreatedLinkResponse, anyhow::Error> {
+        let key = format!(
+            "{api_key}\n{}\n{}\n{}\n{:?}\n{:?}\n{:?}\n{:?}\n{:?}\n{url}",
+            source.as_str(),
+            payload.permanent,
+            payload.public,
+            payload.title,
+            payload.note,
+            payload.id_prefix,
+            payload.alias,
+            payload.open_graph
+        );
+
+        let flight = self
+            .inflight_creates
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_insert_with(|| {
+                let app = self.clone();
+                let url = url.to_string();
+                async move {
+                    let res = app
+                        .insert_link(api_key, source, payload, &url, source_ip)
+                        .await
+                        .map_err(Arc::new);
+                    app.inflight_creates.lock().unwrap().remove(&key);
+                    res
+                }
+                .boxed()
+                .shared()
+            })
+            .clone();
+
+        flight
+            .await
+            .map_err(|e| match e.downcast_ref::<LinkError>() {
+                Some(e) => e.clone().into(),
+                None => anyhow::anyhow!("{e:#}"),
+            })
+    }
+
+    /// Stores a link to the validated `url`, or finds the one existing
+    /// already.
+    async fn insert_link(
+        &self,
+        api_key: String,
+        source: LinkSource,
+        payload: CreateLinkRequest,
+        url: &str,
+        source_ip: Option<IpAddr>,
+    ) -> Result<CreatedLinkResponse, anyhow::Error> {
+        let redirect_status = if payload.permanent {
+            PERMANENT_REDIRECT
+        } else {
+            TEMPORARY_REDIRECT
+        };
+
+        let now = self.config.clock.now();
+        let expires_at = payload
+            .ttl_seconds
+            .map(|ttl| expires_after(now, ttl))
+            .transpose()?;
+        let max_clicks = payload.max_clicks.map(max_clicks).transpose()?;
+        // a limited or expiring link may be used up already, so it is never
+        // shared, and never shares a link living longer
+        let force_new = payload.force_new || max_clicks.is_some() || expires_at.is_some();
+
+        let password = payload.password.as_deref().filter(|pw| !pw.is_empty());
+        let password_hash = password.map(hash_password).transpose()?;
+
+        let creator_ip = source_ip
+            .filter(|_| self.config.store_creator_ip)
+            .map(|ip| ip.to_string());
+
+        let open_graph = payload
+            .open_graph
+            .clone()
+            .map(|og| OpenGraphTags {
+                og_title: og.title,
+                og_description: og.description,
+                og_image: og.image,
+            })
+            .unwrap_or_default();
+
+        let content_hash = if self.config.dedupe_by_content && payload.alias.is_none() {
+            crate::destination::content_hash(url, !self.config.validation_rules.block_private_hosts)
+                .await
+                .unwrap_or_else(|e| {
+                    debug!("destination not hashed: {e}");
+                    None
+                })
+        } else {
+            None
+        };
+
+        // another url of the same site serving the same body counts as the
+        // same url, a mirror elsewhere must not capture the links to it
+        if !force_new && let Some(content_hash) = &content_hash {
+            let suffixes = self.config.public_suffixes.as_deref();
+            let found = self
+                .db
+                .get_by_content_hash(content_hash)
+                .await?
+                .into_iter()
+                .find(|link| {
+                    self.shareable_with(link, &api_key)
+                        && same_site(&link.url, url, suffixes)
+                        && reusable(link, redirect_status, password)
+                });
+
+            if let Some(link) = found {
+                info!(id = link.id, "link with same content found");
+
+                return Ok(CreatedLinkResponse::new(
+                    link.id,
+                    &self.config.prefix,
+                    link.url,
+                ));
+            }
+        }
+
+        let limit_reached = self.link_limit_reached().await?;
+
+        // the link to store, once its id is known
+        let template = CreateLink {
+            id: String::new(),
+            url: url.to_string(),
+            key: api_key.clone(),
+            redirect_status,
+            source: source.as_str().to_string(),
+            expires_at,
+            password_hash,
+            public: payload.public,
+            title: payload.title.clone(),
+            reserved: false,
+            open_graph,
+            creator_ip,
+            max_clicks,
+            note: payload.note.clone(),
+            content_hash,
+        };
+
+        if let Some(alias) = &payload.alias {
+            if limit_reached {
+                return Err(LinkError::LimitReached.into());
+            }
+
+            // ids stored before may differ from the alias in case only
+            if self.config.case_insensitive_aliases
+                && let Some(link) = self.db.get_case_insensitive(alias).await?
+            {
+                if !force_new && link.url == url && reusable(&link, redirect_status, password) {
+                    info!(id = link.id, "alias found");
+
+                    return Ok(CreatedLinkResponse::new(
+                        link.id,
+                        &self.config.prefix,
+                        link.url,
+                    ));
+                }
+
+                return Err(LinkError::IdTaken.into());
+            }
+
+            let new_link = CreateLink {
+                id: alias.clone(),
+                ..template
+            };
+
+            return match self.db.create(&new_link).await {
+                Ok(_) => {
+                    info!(id = new_link.id, "created link with alias");
+                    self.link_created(&new_link, source, source_ip).await;
+
+                    Ok(CreatedLinkResponse::new(
+                        new_link.id,
+                        &self.config.prefix,
+                        new_link.url,
+                    ))
+                }
+                Err(DbError::DuplicateId) => Err(LinkError::IdTaken.into()),
+                Err(e) => {
+                    error!("db error: {e}");
+                    anyhow::bail!("unexpected error");
+                }
+            };
+        }
+
+        if self.config.id_strategy == IdStrategy::Sequential {
+            // without a hash to find it by, the url is looked up among the
+            // links of the key
+            if !force_new {
+                let found = self
+                    .db
+                    .find_owned_by_urls(&[url.to_string()], &api_key)
+                    .await?;
+                for (_, id) in found {
+                    if let Some(link) = self.db.get(&id).await?
+                        && reusable(&link, redirect_status, password)
+                    {
+                        info!(id, "id found");
+
+                        return Ok(CreatedLinkResponse::new(id, &self.config.prefix, link.url));
+                    }
+                }
+            }
+
+            if limit_reached {
+                warn!("link limit reached");
+                return Err(LinkError::LimitReached.into());
+            }
+
+            return self
+                .insert_sequential(template, payload.id_prefix, source, source_ip)
+                .await;
+        }
+
+        // folding the key into the salt gives every key its own ids
+        let salt = if self.config.namespace_by_key {
+            Cow::Owned(format!("{}{}:", self.config.hash_salt, api_key))
+        } else {
+            Cow::Borrowed(self.config.hash_salt.as_str())
+        };
+
+        let mut hash_offset: u64 = 0;
+
+        loop {
+            if hash_offset > self.config.max_collision_retries {
+                error!(hash_offset, "no free id left, id space exhausted");
+                return Err(LinkError::IdSpaceExhausted.into());
+            }
+
+            let mut hash = link_hash(url, &salt, self.config.hash_length, hash_offset);
+            if let Some(prefix) = payload.id_prefix {
+                hash.insert(0, prefix);
+            }
+
+            // a prefix can complete the hash to a path of the router
+            if RESERVED_IDS.contains(&hash.as_str()) {
+                debug!(hash, "generated id is reserved");
+                hash_offset += 1;
+                continue;
+            }
+
+            if let Some(term) = self
+                .config
+                .blocked_id_terms
+                .iter()
+                .find(|term| hash.contains(term.as_str()))
+            {
+                debug!(hash, term, "generated id contains blocked term");
+                hash_offset += 1;
+                continue;
+            }
+
+            // at the cap only links that already exist can be handed out
+            if limit_reached {
+                let existing = if self.config.case_insensitive_ids {
+                    self.db.get_case_insensitive(&hash).await?
+                } else {
+                    self.db.get(&hash).await?
+                };
+                match existing {
+                    Some(link)
+                        if !force_new
+                            && link.url == url
+                            && self.shareable_with(&link, &api_key)
+                            && reusable(&link, redirect_status, password) =>
+                    {
+                        info!(id = link.id, "id found");
+
+                        return Ok(CreatedLinkResponse::new(
+                            link.id,
+                            &self.config.prefix,
+                            link.url,
+                        ));
+                    }
+                    Some(_) => {
+                        hash_offset += 1;
+                        continue;
+                    }
+                    None => {
+                        warn!(hash, "link limit reached");
+                        return Err(LinkError::LimitReached.into());
+                    }
+                }
+            }
+
+            // an older id differing in case only is what redirects find
+            if self.config.case_insensitive_ids
+                && let Some(link) = self.db.get_case_insensitive(&hash).await?
+                && link.id != hash
+            {
+                if !force_new
+                    && link.url == url
+                    && self.shareable_with(&link, &api_key)
+                    && reusable(&link, redirect_status, password)
+                {
+                    info!(id = link.id, "id found in other case");
+
+                    return Ok(CreatedLinkResponse::new(
+                        link.id,
+                        &self.config.prefix,
+                        link.url,
+                    ));
+                }
+
+                debug!(hash, id = link.id, "generated id taken in other case");
+                hash_offset += 1;
+                continue;
+            }
+
+            info!(hash, "creating link");
+
+            let new_link = CreateLink {
+                id: hash.clone(),
+                ..template.clone()
+            };
+
+            let res = self.db.create(&new_link).await;
+
+            match res {
+                Ok(_) => {
+                    if hash_offset > self.config.collision_warn_limit {
+                        warn!(
+                            id = new_link.id,
+                            hash_offset, "created after hash collisions"
+                        );
+                    }
+
+                    self.link_created(&new_link, source, source_ip).await;
+
+                    return Ok(CreatedLinkResponse::new(
+                        new_link.id.clone(),
+                        &self.config.prefix,
+                        new_link.url.clone(),
+                    ));
+                }
+                Err(DbError::DuplicateId) => {
+                    info!(id = new_link.id, "id already exists");
+
+                    // a forced create skips the same url, as it asks for a new id
+                    if !force_new
+                        && let Some(link) = self.db.get(&new_link.id).await?
+                        && link.url == url
+                        && self.shareable_with(&link, &api_key)
+                        && reusable(&link, redirect_status, password)
+                    {
+                        info!(hash, "id found");
+
+                        return Ok(CreatedLinkResponse::new(
+                            new_link.id.clone(),
+                            &self.config.prefix,
+                            new_link.url.clone(),
+                        ));
+                    }
+
+                    hash_offset += 1;
+
+                    if hash_offset <= self.config.collision_warn_limit {
+                        warn!(hash, hash_offset, "hash collision");
+                    } else {
+                        debug!(hash, hash_offset, "hash collision");
+                    }
+                }
+                Err(e) => {
+                    error!("db error: {e}");
+                    anyhow::bail!("unexpected error");
+                }
+            };
+        }
+    }
+
+    /// Stores `template` under the id encoding the next sequence number,
+    /// see [`IdStrategy::Sequential`].
+    async fn insert_sequential(
+        &self,
+        template: CreateLink,
+        id_prefix: Option<char>,
+        source: LinkSource,
+        source_ip: Option<IpAddr>,
+    ) -> Result<CreatedLinkResponse, anyhow::Error> {
+        let alphabet = sequence_alphabet(&self.config.hash_salt);
+
+        // only ids stored by other means, e.g. aliases, are skipped
+        for _ in 0..=self.config.max_collision_retries {
+            let n = self.db.next_sequence().await?;
+            let mut id = encode_sequence(n as u64, &alphabet, self.config.hash_length);
+            if let Some(prefix) = id_prefix {
+                id.insert(0, prefix);
+            }
+
+            if RESERVED_IDS.contains(&id.as_str()) {
+                debug!(id, "generated id is reserved");
+                continue;
+            }
+
+            if let Some(term) = self
+                .config
+                .blocked_id_terms
+                .iter()
+                .find(|term| id.contains(term.as_str()))
+            {
+                debug!(id, term, "generated id contains blocked term");
+                continue;
+            }
+
+            let new_link = CreateLink {
+                id,
+                ..template.clone()
+            };
+
+            match self.db.create(&new_link).await {
+                Ok(_) => {
+                    info!(id = new_link.id, "created link");
+                    self.link_created(&new_link, source, source_ip).await;
+
+                    return Ok(CreatedLinkResponse::new(
+                        new_link.id,
+                        &self.config.prefix,
+                        new_link.url,
+                    ));
+                }
+                Err(DbError::DuplicateId) => {
+                    warn!(id = new_link.id, "sequential id taken already");
+                }
+                Err(e) => {
+                    error!("db error: {e}");
+                    anyhow::bail!("unexpected error");
+                }
+            }
+        }
+
+        error!("no free sequential id found");
+        Err(LinkError::IdSpaceExhausted.into())
+    }
+
+    /// Counts, announces and audits a link just stored.
+    async fn link_created(
+        &self,
+        new_link: &CreateLink,
+        source: LinkSource,
+        source_ip: Option<IpAddr>,
+    ) {
+        if let Some((_, count)) = self.link_count.lock().unwrap().as_mut() {
+            *count += 1;
+        }
+        self.metrics
+            .count_link_created(self.metrics_tenant(Some(&new_link.key)));
+
+        self.publish(LinkEvent::Created {
+            id: new_link.id.clone(),
+            url: new_link.url.clone(),
+            source: source.as_str(),
+        });
+        self.audit_creation(new_link, source_ip).await;
+        if new_link.title.is_none() && self.config.fetch_titles {
+            self.spawn_title_fetch(new_link.id.clone(), new_link.url.clone());
+        }
+    }
+
+    /// Stores `url` under the given `id`, e.g. when migrating from another
+    /// shortener. An existing id is handled as `on_conflict` says, failing
+    /// with [`LinkError::IdTaken`] if it cannot be overwritten.
+    #[instrument(skip(self), err)]
+    pub async fn import_link(
+        &self,
+        api_key: &str,
+        id: &str,
+        url: &str,
+        on_conflict: OnConflict,
+    ) -> Result<ImportOutcome, anyhow::Error> {
+        validate_id(
+            id,
+            self.config.alias_min_length..=self.config.alias_max_length,
+        )?;
+
+        let url = self.normalize_url(url)?;
+        let url = url.as_str();
+
+        if self.is_destination_blocked(url).await? {
+            return Err(LinkError::HostBlocked.into());
+        }
+
+        if self.link_limit_reached().await? {
+            return Err(LinkError::LimitReached.into());
+        }
+
+        let link = CreateLink {
+            id: id.to_string(),
+            url: url.to_string(),
+            key: api_key.to_string(),
+            redirect_status: TEMPORARY_REDIRECT,
+            source: LinkSource::Api.as_str().to_string(),
+            expires_at: None,
+            password_hash: None,
+            public: false,
+            title: None,
+            reserved: false,
+            open_graph: OpenGraphTags::default(),
+            creator_ip: None,
+            max_clicks: None,
+            note: None,
+            content_hash: None,
+        };
+
+        let outcome = match on_conflict {
+            OnConflict::Fail => match self.db.create(&link).await {
+                Ok(_) => ImportOutcome::Imported,
+                Err(DbError::DuplicateId) => return Err(LinkError::IdTaken.into()),
+                Err(e) => return Err(e.into()),
+            },
+            OnConflict::Skip => {
+                if self.db.create_or_skip(&link).await? {
+                    ImportOutcome::Imported
+                } else {
+                    ImportOutcome::Skipped
+                }
+            }
+            OnConflict::Overwrite => match self.db.create(&link).await {
+                Ok(_) => ImportOutcome::Imported,
+                Err(DbError::DuplicateId) => {
+                    if self.db.create_or_overwrite(&link).await? {
+                        self.invalidate(id).await;
+                        ImportOutcome::Overwritten
+                    } else {
+                        // either another key owns the id or it is unchanged
+                        let existing = self.db.get_details(id).await?;
+                        match existing {
+                            Some(existing)
+                                if existing.key.as_deref() == Some(api_key)
+                                    && existing.url == url =>
+                            {
+                                ImportOutcome::Skipped
+                            }
+                            _ => return Err(LinkError::IdTaken.into()),
+                        }
+                    }
+                }
+                Err(e) => return Err(e.into()),
+            },
+        };
+
+        if outcome == ImportOutcome::Imported
+            && let Some((_, count)) = self.link_count.lock().unwrap().as_mut()
+        {
+            *count += 1;
+        }
+
+        Ok(outcome)
+    }
+
+    /// Holds `id` for `api_key` without a destination, until
+    /// [`App::update_link`] sets one. Redirects answer 404 meanwhile. Fails
+    /// with [`LinkError::IdTaken`] if the id exists already.
+    #[instrument(skip(self), err)]
+    pub async fn reserve_link(&self, api_key: &str, id: &str) -> Result<(), anyhow::Error> {
+        validate_id(
+            id,
+            self.config.alias_min_length..=self.config.alias_max_length,
+        )?;
+
+        if self.link_limit_reached().await? {
+            return Err(LinkError::LimitReached.into());
+        }
+
+        let link = CreateLink {
+            id: id.to_string(),
+            url: String::new(),
+            key: api_key.to_string(),
+            redirect_status: TEMPORARY_REDIRECT,
+            source: LinkSource::Api.as_str().to_string(),
+            expires_at: None,
+            password_hash: None,
+            public: false,
+            title: None,
+            reserved: true,
+            open_graph: OpenGraphTags::default(),
+            creator_ip: None,
+            max_clicks: None,
+            note: None,
+            content_hash: None,
+        };
+
+        match self.db.create(&link).await {
+            Ok(_) => {
+                if let Some((_, count)) = self.link_count.lock().unwrap().as_mut() {
+                    *count += 1;
+                }
+
+                info!(id, "link reserved");
+
+                Ok(())
+            }
+            Err(DbError::DuplicateId) => Err(LinkError::IdTaken.into()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Whether `link` may be handed out for a create of `api_key`, with
+    /// [`AppConfig::namespace_by_key`] only links of the same key.
+    fn shareable_with(&self, link: &FetchLink, api_key: &str) -> bool {
+        !self.config.namespace_by_key || link.key.as_deref() == Some(api_key)
+    }
+
+    async fn link_limit_reached(&self) -> Result<bool, anyhow::Error> {
+        let Some(max) = self.config.max_total_links else {
+            return Ok(false);
+        };
+
+        let cached = *self.link_count.lock().unwrap();
+
+        let count = match cached {
+            Some((fetched, count)) if fetched.elapsed() < LINK_COUNT_TTL => count,
+            _ => {
+                let count = self.db.count().await?;
+                *self.link_count.lock().unwrap() = Some((Instant::now(), count));
+                count
+            }
+        };
+
+        Ok(u64::try_from(count).unwrap_or_default() >= max)
+    }
+
+    /// Points an existing link owned by `api_key` to a new url. The row is
+    /// updated in place, so click stats carry over to the new destination.
+    #[instrument(skip(self), err)]
+    pub async fn update_link(
+        &self,
+        api_key: &str,
+        id: &str,
+        payload: UpdateLinkRequest,
+    ) -> Result<CreatedLinkResponse, anyhow::Error> {
+        // normalized like a create, so the url dedups the same way
+        let url = self.normalize_url(&payload.url)?;
+
+        if self.is_destination_blocked(&url).await? {
+            return Err(LinkError::HostBlocked.into());
+        }
+
+        let Some(link) = self.db.get_details(id).await? else {
+            return Err(LinkError::NotFound.into());
+        };
+
+        if link.key.as_deref() != Some(api_key) {
+            return Err(LinkError::NotOwner.into());
+        }
+
+        if self.config.verify_destination {
+            crate::destination::verify(&url, !self.config.validation_rules.block_private_hosts)
+                .await?;
+        }
+
+        if !self.db.update(id, &url).await? {
+            return Err(LinkError::NotFound.into());
+        }
+
+        self.invalidate(id).await;
+
+        info!(id, "link updated");
+
+        Ok(CreatedLinkResponse::new(
+            id.to_string(),
+            &self.config.prefix,
+            url,
+        ))
+    }
+
+    /// Takes a link offline or brings it back, keeping it stored. Only its
+    /// owner may do so, unless `api_key` is `None` for admins.
+    #[instrument(skip(self), err)]
+    pub async fn set_disabled(
+        &self,
+        api_key: Option<&str>,
+        id: &str,
+        disabled: bool,
+    ) -> Result<(), anyhow::Error> {
+        if let Some(api_key) = api_key {
+            let Some(link) = self.db.get_details(id).await? else {
+                return Err(LinkError::NotFound.into());
+            };
+
+            if link.key.as_deref() != Some(api_key) {
+                return Err(LinkError::NotOwner.into());
+            }
+        }
+
+        if !self.db.set_disabled(id, disabled).await? {
+            return Err(LinkError::NotFound.into());
+        }
+
+        self.invalidate(id).await;
+        if disabled {
+            self.disabled_links.insert(id.to_string(), Instant::now());
+        } else {
+            self.disabled_links.remove(id);
+        }
+
+        info!(id, disabled, "link disabled state changed");
+
+        Ok(())
+    }
+
+    /// Creates `links` for `api_key` one after the other. A link failing,
+    /// e.g. for an invalid url, is reported in its result instead of failing
+    /// the others, only the backend failing fails the whole batch.
+    #[instrument(skip(self, links), fields(links = links.len()), err)]
+    pub async fn create_links(
+        &self,
+        api_key: String,
+        links: Vec<CreateLinkRequest>,
+        source_ip: Option<IpAddr>,
+    ) -> Result<Vec<CreateLinkOutcome>, anyhow::Error> {
+        if links.len() > MAX_CREATE_BATCH {
+            anyhow::bail!("at most {MAX_CREATE_BATCH} links can be created at once");
+        }
+
+        let mut outcomes = Vec::with_capacity(links.len());
+        for link in links {
+            let url = link.url.clone();
+            let created = self
+                .create_link(api_key.clone(), LinkSource::Api, link, false, source_ip)
+                .await;
+
+            outcomes.push(match created {
+                Ok(link) => CreateLinkOutcome {
+                    url,
+                    link: Some(link),
+                    ..Default::default()
+                },
+                Err(e)
+                    if e.downcast_ref::<DbError>().is_some()
+                        || matches!(
+                            e.downcast_ref::<LinkError>(),
+                            Some(LinkError::Unavailable { .. })
+                        ) =>
+                {
+                    return Err(e);
+                }
+                Err(e) => CreateLinkOutcome {
+                    url,
+                    error: Some(
+                        e.downcast_ref::<LinkError>()
+                            .map_or("invalid_link", LinkError::code)
+                            .to_string(),
+                    ),
+                    message: Some(e.to_string()),
+                    ..Default::default()
+                },
+            });
+        }
+
+        Ok(outcomes)
+    }
+
+    /// The id of a link of `api_key` to each of `urls`, compared after the
+    /// normalization creates apply, so a create of a url with an id would
+    /// not add a link. Invalid urls and those of other keys have none.
+    #[instrument(skip(self, urls), fields(urls = urls.len()), err)]
+    pub async fn find_links(
+        &self,
+        api_key: &str,
+        urls: Vec<String>,
+    ) -> Result<Vec<FoundLink>, anyhow::Error> {
+        if urls.len() > MAX_FIND_BATCH {
+            anyhow::bail!("at most {MAX_FIND_BATCH} urls can be looked up at once");
+        }
+
+        let normalized: Vec<Option<String>> = urls
+            .iter()
+            .map(|url| self.normalize_url(url).ok())
+            .collect();
+        let lookup: Vec<String> = normalized.iter().flatten().cloned().collect();
+
+        let mut ids = HashMap::new();
+        for (url, id) in self.db.find_owned_by_urls(&lookup, api_key).await? {
+            // forced creates leave several links to a url, the oldest counts
+            ids.entry(url).or_insert(id);
+        }
+
+        Ok(urls
+            .into_iter()
+            .zip(normalized)
+            .map(|(url, normalized)| FoundLink {
+                id: normalized.and_then(|normalized| ids.get(&normalized).cloned()),
+                url,
+            })
+            .collect())
+    }
+
+    /// Deletes the links among `ids` owned by `api_key`. Ids of unknown links
+    /// or links of other keys are reported as not deleted.
+    #[instrument(skip(self, ids), fields(ids = ids.len()), err)]
+    pub async fn delete_links(
+        &self,
+        api_key: &str,
+        ids: Vec<String>,
+    ) -> Result<Vec<DeletedLink>, anyhow::Error> {
+        if ids.len() > MAX_DELETE_BATCH {
+            anyhow::bail!("at most {MAX_DELETE_BATCH} links can be deleted at once");
+        }
+
+        let deleted: HashSet<String> = self
+            .db
+            .delete_owned(&ids, api_key)
+            .await?
+            .into_iter()
+            .collect();
+
+        for id in &deleted {
+            self.invalidate(id).await;
+        }
+
+        if let Some((_, count)) = self.link_count.lock().unwrap().as_mut() {
+            *count -= deleted.len() as i64;
+        }
+
+        info!(deleted = deleted.len(), "links deleted");
+
+        Ok(ids
+            .into_iter()
+            .map(|id| DeletedLink {
+                deleted: deleted.contains(&id),
+                id,
+            })
+            .collect())
+    }
+
+    /// Clicks on the link since the day of `since`, only for its owner.
+    pub async fn link_clicks_since(
+        &self,
+        api_key: &str,
+        id: &str,
+        since: DateTime<Utc>,
+    ) -> Result<u64, anyhow::Error> {
+        let Some(link) = self.db.get_details(id).await? else {
+            return Err(LinkError::NotFound.into());
+        };
+
+        if link.key.as_deref() != Some(api_key) {
+            return Err(LinkError::NotOwner.into());
+        }
+
+        Ok(self.db.clicks_in_window(id, since).await?)
+    }
+
+    /// Clicks on the link for every day from `from` to `to` (inclusive),
+    /// days without clicks included as `0`. Only for its owner.
+    pub async fn link_timeseries(
+        &self,
+        api_key: &str,
+        id: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<DailyClicks>, anyhow::Error> {
+        if from > to {
+            anyhow::bail!("from must not be after to");
+        }
+        if (to - from).num_days() >= MAX_TIMESERIES_DAYS {
+            anyhow::bail!("at most {MAX_TIMESERIES_DAYS} days can be fetched at once");
+        }
+
+        let Some(link) = self.db.get_details(id).await? else {
+            return Err(LinkError::NotFound.into());
+        };
+
+        if link.key.as_deref() != Some(api_key) {
+            return Err(LinkError::NotOwner.into());
+        }
+
+        let mut days = self
+            .db
+            .daily_clicks(id, from, to)
+            .await?
+            .into_iter()
+            .peekable();
+
+        Ok(from
+            .iter_days()
+            .take_while(|date| *date <= to)
+            .map(|date| DailyClicks {
+                date,
+                clicks: days
+                    .next_if(|(day, _)| *day == date)
+                    .map(|(_, clicks)| clicks)
+                    .unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    pub async fn summary(&self) -> Result<Summary, anyhow::Error> {
+        let mut by_source: BTreeMap<&'static str, i64> =
+            LinkSource::ALL.iter().map(|s| (s.as_str(), 0)).collect();
+
+        for (source, count) in self.db.count_by_source().await? {
+            match LinkSource::ALL.iter().find(|s| s.as_str() == source) {
+                Some(s) => *by_source.entry(s.as_str()).or_default() += count,
+                None => warn!(source, count, "unknown link source"),
+            }
+        }
+
+        Ok(Summary {
+            total_links: by_source.values().sum(),
+            by_source,
+        })
+    }
+
+    /// Checks the DB is reachable, failing overall if it is not.
+    pub async fn health(&self) -> Health {
+        let mut checks = BTreeMap::new();
+
+        let connectivity = match self.db.ping().await {
+            Ok(()) => HealthCheck {
+                component_type: "datastore",
+                status: HealthStatus::Pass,
+                observed_value: None,
+                observed_unit: None,
+                output: None,
+            },
+            Err(e) => {
+                error!("health check failed: {e}");
+                HealthCheck {
+                    component_type: "datastore",
+                    status: HealthStatus::Fail,
+                    observed_value: None,
+                    observed_unit: None,
+                    output: Some(e.to_string()),
+                }
+            }
+        };
+        let status = connectivity.status;
+        checks.insert("database:connectivity", vec![connectivity]);
+
+        if let Some(pool) = self.db.pool_status() {
+            checks.insert(
+                "database:connections",
+                vec![HealthCheck {
+                    component_type: "datastore",
+                    status: HealthStatus::Pass,
+                    observed_value: Some(pool.size.saturating_sub(pool.available)),
+                    observed_unit: Some("connections"),
+                    output: None,
+                }],
+            );
+        }
+
+        Health { status, checks }
+    }
+
+    pub fn count_x402_payment(&self, network: &str, result: &str) {
+        self.metrics.count_x402_payment(network, result);
+    }
+
+    /// `tenant` label of metrics about links of `key`.
+    fn metrics_tenant<'a>(&'a self, key: Option<&'a str>) -> &'a str {
+        key.and_then(|key| key.split_once('_'))
+            .map(|(tenant, _)| tenant)
+            .filter(|tenant| self.config.metrics_tenants.contains(*tenant))
+            .unwrap_or(OTHER_TENANT)
+    }
+
+    /// Metrics in the Prometheus text format, with the pool gauges refreshed.
+    pub fn render_metrics(&self) -> Result<String, anyhow::Error> {
+        self.refresh_pool_metrics();
+
+        self.metrics.render()
+    }
+
+    /// The metrics of [`App::render_metrics`] as JSON.
+    pub fn render_metrics_json(&self) -> serde_json::Value {
+        self.refresh_pool_metrics();
+
+        self.metrics.render_json()
+    }
+
+    fn refresh_pool_metrics(&self) {
+        if let Some(status) = self.db.pool_status() {
+            self.metrics.set_pool_status(status);
+        }
+    }
+
+    /// Writes pending click counts to the DB right away instead of waiting
+    /// for the next flusher tick, including increments still in flight.
+    pub async fn flush_counters(&self) -> Result<u64, anyhow::Error> {
+        self.pending_clicks.settle().await;
+
+        Ok(counter::flush(self.click_store.as_ref(), self.db.as_ref()).await?)
+    }
+
+    /// Loads the links of `ids` into the cache ahead of their redirects,
+    /// e.g. before a deployment takes traffic. Unknown, disabled and reserved
+    /// links are skipped, returns how many were cached.
+    #[instrument(skip(self, ids), fields(ids = ids.len()), err)]
+    pub async fn warm_cache(&self, ids: &[String]) -> Result<usize, anyhow::Error> {
+        if ids.len() > MAX_WARM_BATCH {
+            anyhow::bail!("at most {MAX_WARM_BATCH} links can be warmed at once");
+        }
+
+        let mut warmed = 0;
+        for id in ids {
+            if let Some(link) = self.db.get(id).await?
+                && !link.disabled
+                && !link.reserved
+            {
+                self.cache.insert(id.clone(), link);
+                warmed += 1;
+            }
+        }
+
+        info!(warmed, "cache warmed");
+
+        Ok(warmed)
+    }
+
+    /// Drops link `id` from the cache, e.g. after it was changed in the DB
+    /// directly, so the next redirect reads it again. Returns whether it was
+    /// cached here, other replicas drop it as well if
+    /// [`AppConfig::invalidations`] are shared.
+    #[instrument(skip(self))]
+    pub async fn evict_cached(&self, id: &str) -> bool {
+        let cached = self.cache.remove(id).is_some();
+        self.disabled_links.remove(id);
+        self.invalidate(id).await;
+
+        cached
+    }
+
+    pub fn export_links(&self) -> BoxStream<'static, Result<LinkDetails, DbError>> {
+        self.db.stream_all(EXPORT_PAGE_SIZE)
+    }
+
+    /// The links created with `api_key`, for their owner's data export.
+    pub fn export_owned_links(
+        &self,
+        api_key: &str,
+    ) -> BoxStream<'static, Result<OwnedLink, DbError>> {
+        self.db.stream_owned(api_key, EXPORT_PAGE_SIZE)
+    }
+
+    /// A page of the links created with `api_key` ordered by id, starting
+    /// after the id `after`. Returns the cursor of the next page along with
+    /// it, `None` once all links were listed.
+    pub async fn owned_links_page(
+        &self,
+        api_key: &str,
+        after: Option<&str>,
+        limit: Option<u32>,
+    ) -> Result<(Vec<OwnedLink>, Option<String>), anyhow::Error> {
+        let limit = limit
+            .unwrap_or(DEFAULT_LIST_PAGE_SIZE)
+            .clamp(1, MAX_LIST_PAGE_SIZE);
+
+        let links = self
+            .db
+            .owned_page(api_key, after.unwrap_or_default(), i64::from(limit))
+            .await?;
+
+        let next = match links.last() {
+            Some(last) if links.len() == limit as usize => Some(last.id.clone()),
+            _ => None,
+        };
+
+        Ok((links, next))
+    }
+
+    /// The most clicked public links, at most `limit` of them.
+    pub async fn top_links(&self, limit: u32) -> Result<Vec<TopLink>, anyhow::Error> {
+        let limit = limit.min(MAX_TOP_LINKS);
+
+        Ok(self
+            .db
+            .top_links(i64::from(limit), self.config.clock.now())
+            .await?)
+    }
+
+    pub async fn link_details(&self, id: &str) -> Result<Option<LinkDetails>, anyhow::Error> {
+        Ok(self.db.get_details(id).await?)
+    }
+
+    /// Resolves `id` for a redirect, `password` unlocks protected links.
+    pub async fn redirect(
+        &self,
+        id: &str,
+        password: Option<&str>,
+    ) -> Result<FetchLink, anyhow::Error> {
+        let link = self.resolve(id, password).await?;
+        // counted under the stored id, which `id` may differ from in case
+        let id = link.id.as_str();
+
+        if self.config.sync_click_counts {
+            // a failed count must not fail the redirect
+            if let Err(e) = self.db.increment_click(id).await {
+                error!(id, "failed to count click: {e}");
+            }
+        } else {
+            self.pending_clicks
+                .increment_detached(&self.click_store, id);
+        }
+
+        self.metrics
+            .count_redirect(self.metrics_tenant(link.key.as_deref()));
+        self.publish(LinkEvent::Redirect { id: id.to_string() });
+
+        Ok(link)
+    }
+
+    /// Resolves `id` like [`App::redirect`] without counting it as a visit,
+    /// e.g. for `HEAD` requests of link checkers.
+    pub async fn resolve(
+        &self,
+        id: &str,
+        password: Option<&str>,
+    ) -> Result<FetchLink, anyhow::Error> {
+        self.limit_redirect_rate(id)?;
+
+        self.lookup(id, password).await
+    }
+
+    /// The destinations of the links among `ids`, in their order, without
+    /// counting visits. Links that would not redirect, e.g. unknown, disabled
+    /// or password protected ones, are left out.
+    #[instrument(skip(self, ids), fields(ids = ids.len()), err)]
+    pub async fn resolve_many(&self, ids: &[String]) -> Result<Vec<ResolvedLink>, anyhow::Error> {
+        if ids.len() > MAX_RESOLVE_BATCH {
+            anyhow::bail!("at most {MAX_RESOLVE_BATCH} links can be resolved at once");
+        }
+
+        let mut resolved = Vec::with_capacity(ids.len());
+        for id in ids {
+            match self.lookup(id, None).await {
+                Ok(link) => resolved.push(ResolvedLink {
+                    id: link.id,
+                    original_url: link.url,
+                }),
+                // the backend failing is no property of the link
+                Err(e)
+                    if matches!(
+                        e.downcast_ref::<LinkError>(),
+                        None | Some(LinkError::Unavailable { .. })
+                    ) =>
+                {
+                    return Err(e);
+                }
+                Err(e) => debug!(id, "not resolved: {e}"),
+            }
+        }
+
+        Ok(resolved)
+    }
+
+    /// [`App::resolve`] without the redirect rate limit.
+    async fn lookup(&self, id: &str, password: Option<&str>) -> Result<FetchLink, anyhow::Error> {
+        let folded;
+        let id = if self.config.case_insensitive_ids {
+            folded = id.to_ascii_lowercase();
+            folded.as_str()
+        } else {
+            id
+        };
+
+        if self
+            .disabled_links
+            .get(id)
+            .is_some_and(|seen| seen.elapsed() < DISABLED_LINK_TTL)
+        {
+            return Err(LinkError::Disabled.into());
+        }
+
+        let now = self.config.clock.now();
+
+        let link = if let Some(link) = self.cache.get(id) {
+            // cached before it expired
+            if link.expires_at.is_some_and(|expires_at| expires_at <= now) {
+                self.cache.remove(id);
+                return Err(LinkError::NotFound.into());
+            }
+
+            info!(id, "redirect from cache");
+            link
+        } else {
+            let link = if self.config.case_insensitive_ids {
+                self.db.get_case_insensitive(id).await
+            } else {
+                self.db.get(id).await
+            };
+            let link = match link {
+                Ok(Some(link)) => link,
+                Ok(None) => return Err(LinkError::NotFound.into()),
+                Err(e) => {
+                    // cached links keep working, the rest can be retried
+                    error!(id, "redirect lookup failed: {e}");
+                    return Err(LinkError::Unavailable {
+                        retry_after: self.config.unavailable_retry_after,
+                    }
+                    .into());
+                }
+            };
+
+            info!(id, "redirect from db");
+
+            // not active until `update_link` gives it a destination
+            if link.reserved {
+                return Err(LinkError::NotFound.into());
+            }
+
+            if link.disabled {
+                self.disabled_links.insert(id.to_string(), Instant::now());
+                return Err(LinkError::Disabled.into());
+            }
+
+            // gone like a purged link, the purge may not have run yet
+            if link.expires_at.is_some_and(|expires_at| expires_at <= now) {
+                return Err(LinkError::NotFound.into());
+            }
+
+            // Counts are flushed in batches, so a limited link can take a few
+            // clicks more than allowed, unless `sync_click_counts` is set.
+            // Caching it would hide the count from all further clicks.
+            if let Some(max_clicks) = link.max_clicks {
+                if link.click_count >= max_clicks {
+                    return Err(LinkError::Exhausted.into());
+                }
+            } else {
+                self.cache.insert(id.to_string(), link.clone());
+            }
+
+            link
+        };
+
+        // the host might have been blocked after the link was created
+        if self.is_destination_blocked(&link.url).await? {
+            warn!(id, "redirect to blocked destination");
+            return Err(
+                LinkError::RedirectBlocked(self.config.blocked_redirect_message.clone()).into(),
+            );
+        }
+
+        if self.config.redirect_loop_guard && self.is_redirect_loop(&link.url) {
+            warn!(id, "redirect loop");
+            return Err(LinkError::RedirectLoop.into());
+        }
+
+        if let Some(hash) = &link.password_hash {
+            let Some(password) = password else {
+                return Err(LinkError::PasswordRequired.into());
+            };
+            self.check_password(&link.id, hash, password).await?;
+        }
+
+        Ok(link)
+    }
+
+    /// Verifies `password` of link `id`, failing with
+    /// [`LinkError::PasswordAttempts`] once [`AppConfig::password_attempts`]
+    /// wrong ones were tried in the current window.
+    async fn check_password(&self, id: &str, hash: &str, password: &str) -> Result<(), LinkError> {
+        let now = Instant::now();
+        let current = |since: Instant| now.duration_since(since) < PASSWORD_ATTEMPT_WINDOW;
+        let limit = self.config.password_attempts;
+
+        // the attempt is counted before verifying, so parallel guesses can't
+        // all get through, and given back if it was right
+        let refused = self.password_attempts.update(
+            id,
+            || (now, 0),
+            |&(since, _)| current(since),
+            |(since, count)| {
+                if !current(*since) {
+                    (*since, *count) = (now, 0);
+                }
+                if *count >= limit {
+                    return Some(PASSWORD_ATTEMPT_WINDOW - now.duration_since(*since));
+                }
+                *count += 1;
+                None
+            },
+        );
+        match refused {
+            Some(None) => {}
+            Some(Some(left)) => {
+                warn!(id, "too many wrong link passwords");
+                return Err(LinkError::PasswordAttempts {
+                    retry_after: left.as_secs_f64().ceil() as u64,
+                });
+            }
+            None => {
+                warn!(id, "password attempt limiter full");
+                return Err(LinkError::PasswordAttempts {
+                    retry_after: PASSWORD_ATTEMPT_WINDOW.as_secs(),
+                });
+            }
+        }
+
+        // hashing on purpose takes long enough to stall the runtime
+        let (hash, password) = (hash.to_string(), password.to_string());
+        let verified = tokio::task::spawn_blocking(move || verify_password(&hash, &password))
+            .await
+            .unwrap_or(false);
+
+        if !verified {
+            warn!(id, "wrong link password");
+            return Err(LinkError::WrongPassword);
+        }
+
+        self.password_attempts.update(
+            id,
+            || (now, 0),
+            |_| true,
+            |(_, count)| *count = count.saturating_sub(1),
+        );
+
+        Ok(())
+    }
+
+    /// Counts a public create from `ip` and returns how long to hold it back,
+    /// if `ip` exceeded [`AppConfig::public_tarpit_threshold`] in the current
+    /// window.
+    pub fn public_tarpit(&self, ip: IpAddr) -> Option<Duration> {
+        let threshold = self.config.public_tarpit_threshold?;
+        let delay = Duration::from_millis(self.config.public_tarpit_delay_ms);
+        let now = Instant::now();
+        let current = |since: Instant| now.duration_since(since) < PUBLIC_TARPIT_WINDOW;
+
+        let count = self.public_requests.update(
+            &ip,
+            || (now, 0),
+            |&(since, _)| current(since),
+            |(since, count)| {
+                if !current(*since) {
+                    (*since, *count) = (now, 0);
+                }
+                *count = count.saturating_add(1);
+                *count
+            },
+        );
+        let Some(count) = count else {
+            warn!(%ip, "public limiter full, tarpitting");
+            return Some(delay);
+        };
+
+        (count > threshold).then_some(delay)
+    }
+
+    /// Whether `user_agent` is one of [`AppConfig::crawler_user_agents`].
+    pub fn is_crawler(&self, user_agent: &str) -> bool {
+        let user_agent = user_agent.to_ascii_lowercase();
+
+        self.config
+            .crawler_user_agents
+            .iter()
+            // `CRAWLER_USER_AGENTS=` leaves one empty part, matching anything
+            .filter(|crawler| !crawler.is_empty())
+            .any(|crawler| user_agent.contains(&crawler.to_ascii_lowercase()))
+    }
+
+    /// The Open Graph tags of link `id`, `None` if it has none.
+    pub async fn open_graph(&self, id: &str) -> Result<Option<OpenGraphTags>, anyhow::Error> {
+        Ok(self
+            .db
+            .open_graph(id)
+            .await?
+            .filter(|tags| !tags.is_empty()))
+    }
+
+    /// Counts a public create from `ip`, failing once it exceeds
+    /// [`AppConfig::public_rate_limit`] in the current window.
+    pub fn limit_public_rate(&self, ip: IpAddr) -> Result<(), LinkError> {
+        let Some(limit) = self.config.public_rate_limit else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        let current = |since: Instant| now.duration_since(since) < PUBLIC_RATE_WINDOW;
+
+        // checked and counted under one lock, parallel creates can't overshoot
+        let limited = self.public_rates.update(
+            &ip,
+            || (now, 0),
+            |&(since, _)| current(since),
+            |(since, count)| {
+                if !current(*since) {
+                    (*since, *count) = (now, 0);
+                }
+                if *count >= limit {
+                    return Some(PUBLIC_RATE_WINDOW - now.duration_since(*since));
+                }
+                *count += 1;
+                None
+            },
+        );
+        let left = match limited {
+            Some(None) => return Ok(()),
+            Some(Some(left)) => left,
+            None => {
+                warn!(%ip, "public limiter full");
+                PUBLIC_RATE_WINDOW
+            }
+        };
+
+        warn!(%ip, "public create rate limited");
+        Err(LinkError::PublicRateLimited {
+            retry_after: left.as_secs_f64().ceil() as u64,
+        })
+    }
+
+    /// Fails while `ip` is within [`AppConfig::public_cooldown_secs`] of its
+    /// last public create, otherwise starts a new cooldown.
+    pub fn public_cooldown(&self, ip: IpAddr) -> Result<(), LinkError> {
+        let Some(cooldown) = self.config.public_cooldown_secs else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        let left = |last: Option<Instant>| {
+            last.map_or(Duration::ZERO, |last| {
+                Duration::from_secs(cooldown).saturating_sub(now.duration_since(last))
+            })
+        };
+
+        // checked and started under one lock, parallel creates can't slip through
+        let left = self.public_creates.update(
+            &ip,
+            || None,
+            |&last| !left(last).is_zero(),
+            |last| {
+                let left = left(*last);
+                if left.is_zero() {
+                    *last = Some(now);
+                }
+                left
+            },
+        );
+        let left = match left {
+            Some(left) if left.is_zero() => return Ok(()),
+            Some(left) => left,
+            None => {
+                warn!(%ip, "public limiter full");
+                Duration::from_secs(cooldown)
+            }
+        };
+
+        debug!(%ip, ?left, "public create in cooldown");
+        Err(LinkError::PublicCooldown {
+            cooldown,
+            retry_after: left.as_secs_f64().ceil() as u64,
+        })
+    }
+
+    /// Counts a resolve of `id`, failing once it exceeds
+    /// [`AppConfig::redirect_rate_limit`]. The resolves of the previous
+    /// window count for the part of it the sliding window still covers.
+    fn limit_redirect_rate(&self, id: &str) -> Result<(), LinkError> {
+        let Some(limit) = self.config.redirect_rate_limit else {
+            return Ok(());
+        };
+        let now = Instant::now();
+
+        // counted in place under the lock of the id's shard, so concurrent
+        // resolves of a hammered id all count
+        let limited = self.redirect_rates.update(
+            id,
+            || RedirectWindow {
+                start: now,
+                count: 0,
+                previous: 0,
+            },
+            |window| now.duration_since(window.start) < 2 * REDIRECT_RATE_WINDOW,
+            |window| {
+                if now.duration_since(window.start) >= 2 * REDIRECT_RATE_WINDOW {
+                    *window = RedirectWindow {
+                        start: now,
+                        count: 0,
+                        previous: 0,
+                    };
+                } else if now.duration_since(window.start) >= REDIRECT_RATE_WINDOW {
+                    *window = RedirectWindow {
+                        start: window.start + REDIRECT_RATE_WINDOW,
+                        count: 0,
+                        previous: window.count,
+                    };
+                }
+
+                let elapsed = now.duration_since(window.start);
+                let overlap = 1.0 - elapsed.as_secs_f64() / REDIRECT_RATE_WINDOW.as_secs_f64();
+                let estimate = f64::from(window.previous) * overlap + f64::from(window.count);
+
+                if estimate >= f64::from(limit) {
+                    return Some(REDIRECT_RATE_WINDOW - elapsed);
+                }
+                window.count += 1;
+                None
+            },
+        );
+
+        match limited {
+            Some(None) => Ok(()),
+            Some(Some(left)) => {
+                warn!(id, "redirect rate limited");
+                Err(LinkError::RedirectRateLimited {
+                    retry_after: left.as_secs_f64().ceil() as u64,
+                })
+            }
+            None => {
+                warn!(id, "redirect limiter full, not limiting");
+                Ok(())
+            }
+        }
+    }
+
+    /// Whether `url` is a short link of this service, served from the host
+    /// of the prefix or one of [`AppConfig::redirect_loop_hosts`].
+    fn is_redirect_loop(&self, url: &str) -> bool {
+        if let Some(looping) = self.redirect_loops.get(url) {
+            return looping;
+        }
+
+        let looping =
+            points_to_short_link(url, &self.config.prefix, &self.config.redirect_loop_hosts);
+        self.redirect_loops.insert(url.to_string(), looping);
+
+        looping
+    }
+
+    async fn is_destination_blocked(&self, url: &str) -> Result<bool, anyhow::Error> {
+        let Some(host) = Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+        else {
+            return Ok(false);
+        };
+
+        Ok(is_host_listed(&*self.blocked_hosts().await?, &host))
+    }
+
+    async fn blocked_hosts(&self) -> Result<Arc<HashSet<String>>, anyhow::Error> {
+        let cached = self.blocked_hosts.lock().unwrap().clone();
+
+        if let Some((fetched, hosts)) = &cached
+            && fetched.elapsed() < BLOCKED_HOSTS_TTL
+        {
+            return Ok(Arc::clone(hosts));
+        }
+
+        let hosts = match self.db.blocked_hosts().await {
+            Ok(hosts) => Arc::new(hosts.into_iter().collect()),
+            // keep redirecting cached links with the last list while the DB is down
+            Err(e) => match cached {
+                Some((_, hosts)) => {
+                    warn!("failed to refresh blocked hosts, keeping the last list: {e}");
+                    hosts
+                }
+                None => return Err(e.into()),
+            },
+        };
+        *self.blocked_hosts.lock().unwrap() = Some((Instant::now(), Arc::clone(&hosts)));
+
+        Ok(hosts)
+    }
+
+    /// Blocks `host` and all its subdomains, for new links as well as
+    /// redirects of existing ones.
+    pub async fn block_host(&self, host: &str) -> Result<(), anyhow::Error> {
+        let host = normalize_host(host)?;
+
+        self.db.block_host(&host).await?;
+        // make this replica pick up the change right away
+        *self.blocked_hosts.lock().unwrap() = None;
+
+        info!(host, "host blocked");
+
+        Ok(())
+    }
+
+    pub async fn unblock_host(&self, host: &str) -> Result<(), anyhow::Error> {
+        let host = normalize_host(host)?;
+
+        if !self.db.unblock_host(&host).await? {
+            return Err(LinkError::NotFound.into());
+        }
+        *self.blocked_hosts.lock().unwrap() = None;
+
+        info!(host, "host unblocked");
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod e2e_tests {
+    use super::*;
+    use crate::{
+        counter::ClickCounter,
+        db::PostgresDb,
+        db_pool::{DbPool, DbPoolOptions, init_crypto_provider},
+        migrations::run_migrations,
+    };
+    use testcontainers::{ContainerAsync, runners::AsyncRunner};
+    use testcontainers_modules::postgres::Postgres;
+
+    async fn get_postgres_testcontainer() -> (ContainerAsync<Postgres>, String) {
+        let c = Postgres::default().start().await.unwrap();
+
+        let host_port = c.get_host_port_ipv4(5432).await.unwrap();
+        let host = c.get_host().await.unwrap();
+
+        let db_url = format!("postgres://postgres:postgres@{host}:{host_port}/postgres",);
+
+        (c, db_url)
+    }
+
+    #[tokio::test]
+    async fn test_app_smoke_test() {
+        init_crypto_provider();
+
+        let (_db_container, dburl) = get_postgres_testcontainer().await;
+
+        run_migrations(&dburl, None).unwrap();
+
+        let pool = DbPool::build(&dburl, DbPoolOptions::default())
+            .await
+            .unwrap();
+
+        let original_url = String::from("https://www.rustunit.com");
+        let key = String::from("key");
+
+        let app = App::new(
+            AppConfig {
+                prefix: "http://localhost".to_string(),
+                hash_length: 6,
+                cache_size: 10,
+                ..Default::default()
+            },
+            Arc::new(PostgresDb::new(pool)),
+            Arc::new(ClickCounter::new()),
+        );
+        let res = app
+            .create_link(
+                key.clone(),
+                LinkSource::Api,
+                CreateLinkRequest {
+                    url: original_url.clone(),
+                    ..Default::default()
+                },
+                false,
+                None,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(&res.id, "as9sud");
+        assert_eq!(&res.original_url, &original_url);
+        assert_eq!(&res.shortened_url, "http://localhost/as9sud");
+
+        let details = app.link_details("as9sud").await.unwrap().unwrap();
+        assert_eq!(&details.url, &original_url);
+        assert_eq!(details.key.as_deref(), Some("key"));
+
+        let res = app
+            .create_link(
+                key,
+                LinkSource::Api,
+                CreateLinkRequest {
+                    url: original_url.clone(),
+                    ..Default::default()
+                },
+                false,
+                None,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(&res.id, "as9sud");
+        assert_eq!(&res.original_url, &original_url);
+        assert_eq!(&res.shortened_url, "http://localhost/as9sud");
+    }
+
+    #[tokio::test]
+    async fn test_permanent_link() {
+        init_crypto_provider();
+
+        let (_db_container, dburl) = get_postgres_testcontainer().await;
+
+        run_migrations(&dburl, None).unwrap();
+
+        let pool = DbPool::build(&dburl, DbPoolOptions::default())
+            .await
+            .unwrap();
+
+        let app = App::new(
+            AppConfig {
+                prefix: "http://localhost".to_string(),
+                cache_size: 10,
+                ..Default::default()
+            },
+            Arc::new(PostgresDb::new(pool)),
+            Arc::new(ClickCounter::new()),
+        );
+        let res = app
+            .create_link(
+                String::from("key"),
+                LinkSource::Api,
+                CreateLinkRequest {
+                    url: String::from("https://www.rustunit.com"),
+                    permanent: true,
+                    ..Default::default()
+                },
+                false,
+                None,
+            )
+            .await
+            .unwrap();
+
+        let link = app.redirect(&res.id, None).await.unwrap();
+        assert_eq!(link.redirect_status, PERMANENT_REDIRECT);
+    }
+
+    #[tokio::test]
+    async fn test_password_protected_link() {
+        init_crypto_provider();
+
+        let (_db_container, dburl) = get_postgres_testcontainer().await;
+
+        run_migrations(&dburl, None).unwrap();
+
+        let pool = DbPool::build(&dburl, DbPoolOptions::default())
+            .await
+            .unwrap();
+
+        let app = App::new(
+            AppConfig::default(),
+            Arc::new(PostgresDb::new(pool)),
+            Arc::new(ClickCounter::new()),
+        );
+        let protected = app
+            .create_link(
+                String::from("key"),
+                LinkSource::Api,
+                CreateLinkRequest {
+                    url: String::from("https://www.rustunit.com"),
+                    password: Some(String::from("secret")),
+                    ..Default::default()
+                },
+                false,
+                None,
+            )
+            .await
+            .unwrap();
+
+        let err = app.redirect(&protected.id, None).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LinkError>(),
+            Some(LinkError::PasswordRequired)
+        ));
+        let err = app
+            .redirect(&protected.id, Some("wrong"))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LinkError>(),
+            Some(LinkError::WrongPassword)
+        ));
+        let link = app.redirect(&protected.id, Some("secret")).await.unwrap();
+        assert_eq!(link.url, "https://www.rustunit.com");
+
+        // the same url without the password must not reuse the protected link
+        let open = app
+            .create_link(
+                String::from("key"),
+                LinkSource::Api,
+                CreateLinkRequest {
+                    url: String::from("https://www.rustunit.com"),
+                    ..Default::default()
+                },
+                false,
+                None,
+            )
+            .await
+            .unwrap();
+        assert_ne!(open.id, protected.id);
+        app.redirect(&open.id, None).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_summary_by_source() {
+        init_crypto_provider();
+
+        let (_db_container, dburl) = get_postgres_testcontainer().await;
+
+        run_migrations(&dburl, None).unwrap();
+
+        let pool = DbPool::build(&dburl, DbPoolOptions::default())
+            .await
+            .unwrap();
+
+        let app = App::new(
+            AppConfig::default(),
+            Arc::new(PostgresDb::new(pool)),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let links = [
+            ("key", LinkSource::Api, "https://www.rustunit.com/1"),
+            ("key", LinkSource::Api, "https://www.rustunit.com/2"),
+            ("public", LinkSource::Public, "https://www.rustunit.com/3"),
+            ("x402", LinkSource::X402, "https://www.rustunit.com/4"),
+        ];
+
+        for (key, source, url) in links {
+            app.create_link(
+                key.to_string(),
+                source,
+                CreateLinkRequest {
+                    url: url.to_string(),
+                    ..Default::default()
+                },
+                false,
+                None,
+            )
+            .await
+            .unwrap();
+        }
+
+        let summary = app.summary().await.unwrap();
+
+        assert_eq!(summary.total_links, 4);
+        assert_eq!(summary.by_source["api"], 2);
+        assert_eq!(summary.by_source["public"], 1);
+        assert_eq!(summary.by_source["x402"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_max_total_links() {
+        init_crypto_provider();
+
+        let (_db_container, dburl) = get_postgres_testcontainer().await;
+
+        run_migrations(&dburl, None).unwrap();
+
+        let pool = DbPool::build(&dburl, DbPoolOptions::default())
+            .await
+            .unwrap();
+
+        let app = App::new(
+            AppConfig {
+                max_total_links: Some(1),
+                ..Default::default()
+            },
+            Arc::new(PostgresDb::new(pool)),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let create = |url: &str| {
+            app.create_link(
+                String::from("key"),
+                LinkSource::Api,
+                CreateLinkRequest {
+                    url: url.to_string(),
+                    ..Default::default()
+                },
+                false,
+                None,
+            )
+        };
+
+        let first = create("https://www.rustunit.com/1").await.unwrap();
+
+        let err = create("https://www.rustunit.com/2").await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LinkError>(),
+            Some(LinkError::LimitReached)
+        ));
+
+        let again = create("https://www.rustunit.com/1").await.unwrap();
+        assert_eq!(again.id, first.id);
+    }
+
+    #[tokio::test]
+    async fn test_flush_counters() {
+        init_crypto_provider();
+
+        let (_db_container, dburl) = get_postgres_testcontainer().await;
+
+        run_migrations(&dburl, None).unwrap();
+
+        let pool = DbPool::build(&dburl, DbPoolOptions::default())
+            .await
+            .unwrap();
+
+        let app = App::new(
+            AppConfig::default(),
+            Arc::new(PostgresDb::new(pool)),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let res = app
+            .create_link(
+                String::from("key"),
+                LinkSource::Api,
+                CreateLinkRequest {
+                    url: String::from("https://www.rustunit.com"),
+                    ..Default::default()
+                },
+                false,
+                None,
+            )
+            .await
+            .unwrap();
+
+        app.redirect(&res.id, None).await.unwrap();
+        app.redirect(&res.id, None).await.unwrap();
+
+        assert_eq!(app.flush_counters().await.unwrap(), 1);
+        assert_eq!(app.flush_counters().await.unwrap(), 0);
+
+        // total, first/last use and the daily bucket all come from that flush
+        let details = app.link_details(&res.id).await.unwrap().unwrap();
+        assert_eq!(details.click_count, 2);
+        let first_used = details.first_used.unwrap();
+        assert!(first_used > details.created_at);
+        assert!(first_used <= details.last_used);
+        assert_eq!(
+            app.link_clicks_since("key", &res.id, details.last_used)
+                .await
+                .unwrap(),
+            2
+        );
+
+        app.redirect(&res.id, None).await.unwrap();
+        assert_eq!(app.flush_counters().await.unwrap(), 1);
+
+        let details = app.link_details(&res.id).await.unwrap().unwrap();
+        assert_eq!(details.click_count, 3);
+        assert_eq!(details.first_used, Some(first_used));
+        assert!(details.last_used > first_used);
+    }
+
+    #[tokio::test]
+    async fn test_update_keeps_stats() {
+        init_crypto_provider();
+
+        let (_db_container, dburl) = get_postgres_testcontainer().await;
+
+        run_migrations(&dburl, None).unwrap();
+
+        let pool = DbPool::build(&dburl, DbPoolOptions::default())
+            .await
+            .unwrap();
+
+        let app = App::new(
+            AppConfig::default(),
+            Arc::new(PostgresDb::new(pool)),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let res = app
+            .create_link(
+                String::from("key"),
+                LinkSource::Api,
+                CreateLinkRequest {
+                    url: String::from("https://www.rustunit.com"),
+                    ..Default::default()
+                },
+                false,
+                None,
+            )
+            .await
+            .unwrap();
+
+        app.redirect(&res.id, None).await.unwrap();
+        app.flush_counters().await.unwrap();
+
+        let err = app
+            .update_link(
+                "other",
+                &res.id,
+                UpdateLinkRequest {
+                    url: String::from("https://www.rustunit.com/other"),
+                },
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LinkError>(),
+            Some(LinkError::NotOwner)
+        ));
+
+        let updated = app
+            .update_link(
+                "key",
+                &res.id,
+                UpdateLinkRequest {
+                    url: String::from("https://www.rustunit.com/new"),
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(updated.id, res.id);
+
+        // the cached destination was dropped
+        let link = app.redirect(&res.id, None).await.unwrap();
+        assert_eq!(link.url, "https://www.rustunit.com/new");
+
+        app.flush_counters().await.unwrap();
+
+        let details = app.link_details(&res.id).await.unwrap().unwrap();
+        assert_eq!(details.url, "https://www.rustunit.com/new");
+        assert_eq!(details.click_count, 2);
+    }
+
+    #[tokio::test]
+    async fn test_sync_click_counts() {
+        init_crypto_provider();
+
+        let (_db_container, dburl) = get_postgres_testcontainer().await;
+
+        run_migrations(&dburl, None).unwrap();
+
+        let pool = DbPool::build(&dburl, DbPoolOptions::default())
+            .await
+            .unwrap();
+
+        let app = App::new(
+            AppConfig {
+                sync_click_counts: true,
+                ..Default::default()
+            },
+            Arc::new(PostgresDb::new(pool)),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let res = app
+            .create_link(
+                String::from("key"),
+                LinkSource::Api,
+                CreateLinkRequest {
+                    url: String::from("https://www.rustunit.com"),
+                    ..Default::default()
+                },
+                false,
+                None,
+            )
+            .await
+            .unwrap();
+
+        app.redirect(&res.id, None).await.unwrap();
+
+        // counted without a flush
+        let details = app.link_details(&res.id).await.unwrap().unwrap();
+        assert_eq!(details.click_count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_clicks_in_window() {
+        init_crypto_provider();
+
+        let (_db_container, dburl) = get_postgres_testcontainer().await;
+
+        run_migrations(&dburl, None).unwrap();
+
+        let pool = DbPool::build(&dburl, DbPoolOptions::default())
+            .await
+            .unwrap();
+
+        let app = App::new(
+            AppConfig::default(),
+            Arc::new(PostgresDb::new(pool)),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let res = app
+            .create_link(
+                String::from("key"),
+                LinkSource::Api,
+                CreateLinkRequest {
+                    url: String::from("https://www.rustunit.com"),
+                    ..Default::default()
+                },
+                false,
+                None,
+            )
+            .await
+            .unwrap();
+
+        let now = Utc::now();
+        let three_days_ago = now - chrono::Duration::days(3);
+
+        app.db
+            .batch_update_clicks(
+                vec![res.id.clone()],
+                vec![2],
+                vec![three_days_ago],
+                vec![three_days_ago],
+            )
+            .await
+            .unwrap();
+        app.db
+            .batch_update_clicks(vec![res.id.clone()], vec![5], vec![now], vec![now])
+            .await
+            .unwrap();
+        app.db
+            .batch_update_clicks(vec![res.id.clone()], vec![1], vec![now], vec![now])
+            .await
+            .unwrap();
+
+        let today = app
+            .link_clicks_since("key", &res.id, now - chrono::Duration::days(1))
+            .await
+            .unwrap();
+        assert_eq!(today, 6);
+
+        let week = app
+            .link_clicks_since("key", &res.id, now - chrono::Duration::days(7))
+            .await
+            .unwrap();
+        assert_eq!(week, 8);
+
+        let err = app
+            .link_clicks_since("other", &res.id, now)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LinkError>(),
+            Some(LinkError::NotOwner)
+        ));
+
+        let details = app.link_details(&res.id).await.unwrap().unwrap();
+        assert_eq!(details.click_count, 8);
+    }
+
+    #[tokio::test]
+    async fn test_pg_invalidations() {
+        use crate::invalidation::PgInvalidations;
+
+        init_crypto_provider();
+
+        let (_db_container, dburl) = get_postgres_testcontainer().await;
+
+        let pool = DbPool::build(&dburl, DbPoolOptions::default())
+            .await
+            .unwrap();
+
+        let publisher = PgInvalidations::start(dburl.clone(), pool.clone());
+        let listener = PgInvalidations::start(dburl, pool);
+        let mut received = listener.subscribe();
+
+        // notifications sent before the listener is up are lost
+        tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                publisher.publish("as9sud").await;
+
+                if let Ok(invalidation) =
+                    tokio::time::timeout(Duration::from_millis(100), received.recv()).await
+                {
+                    assert_eq!(
+                        invalidation.unwrap(),
+                        Invalidation::Link(String::from("as9sud"))
+                    );
+                    break;
+                }
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_link_timeseries() {
+        init_crypto_provider();
+
+        let (_db_container, dburl) = get_postgres_testcontainer().await;
+
+        run_migrations(&dburl, None).unwrap();
+
+        let pool = DbPool::build(&dburl, DbPoolOptions::default())
+            .await
+            .unwrap();
+
+        let app = App::new(
+            AppConfig::default(),
+            Arc::new(PostgresDb::new(pool)),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let res = app
+            .create_link(
+                String::from("key"),
+                LinkSource::Api,
+                CreateLinkRequest {
+                    url: String::from("https://www.rustunit.com"),
+                    ..Default::default()
+                },
+                false,
+                None,
+            )
+            .await
+            .unwrap();
+
+        let date = |day: u32| NaiveDate::from_ymd_opt(2025, 1, day).unwrap();
+        for (day, clicks) in [(1, 2), (3, 5), (3, 1), (6, 4)] {
+            let at = date(day).and_hms_opt(12, 0, 0).unwrap().and_utc();
+            app.db
+                .batch_update_clicks(vec![res.id.clone()], vec![clicks], vec![at], vec![at])
+                .await
+                .unwrap();
+        }
+
+        let series = app
+            .link_timeseries("key", &res.id, date(1), date(4))
+            .await
+            .unwrap();
+        assert_eq!(
+            series,
+            [(1, 2), (2, 0), (3, 6), (4, 0)].map(|(day, clicks)| DailyClicks {
+                date: date(day),
+                clicks,
+            })
+        );
+
+        let err = app
+            .link_timeseries("other", &res.id, date(1), date(4))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LinkError>(),
+            Some(LinkError::NotOwner)
+        ));
+
+        assert!(
+            app.link_timeseries("key", &res.id, date(4), date(1))
+                .await
+                .is_err()
+        );
+        assert!(
+            app.link_timeseries(
+                "key",
+                &res.id,
+                date(1),
+                date(1) + chrono::Days::new(MAX_TIMESERIES_DAYS as u64)
+            )
+            .await
+            .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_link_transactions() {
+        init_crypto_provider();
+
+        let (_db_container, dburl) = get_postgres_testcontainer().await;
+
+        run_migrations(&dburl, None).unwrap();
+
+        let pool = DbPool::build(&dburl, DbPoolOptions::default())
+            .await
+            .unwrap();
+
+        let app = App::new(
+            AppConfig::default(),
+            Arc::new(PostgresDb::new(pool)),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let res = app
+            .create_link(
+                String::from("x402"),
+                LinkSource::X402,
+                CreateLinkRequest {
+                    url: String::from("https://www.rustunit.com"),
+                    ..Default::default()
+                },
+                false,
+                None,
+            )
+            .await
+            .unwrap();
+
+        for (network, tx_hash) in [("base", "0x12"), ("base-sepolia", "0x34")] {
+            app.store_transaction(CreateTransaction {
+                link_id: res.id.clone(),
+                tx_hash: tx_hash.to_string(),
+                network: network.to_string(),
+                amount: Some(String::from("10000")),
+                payer: Some(String::from("0xabc")),
+            })
+            .await
+            .unwrap();
+        }
+
+        let transactions = app.link_transactions(&res.id).await.unwrap();
+        assert_eq!(
+            transactions,
+            vec![
+                Transaction {
+                    network: String::from("base"),
+                    tx_hash: String::from("0x12"),
+                    amount: Some(String::from("10000")),
+                    payer: Some(String::from("0xabc")),
+                },
+                Transaction {
+                    network: String::from("base-sepolia"),
+                    tx_hash: String::from("0x34"),
+                    amount: Some(String::from("10000")),
+                    payer: Some(String::from("0xabc")),
+                },
+            ]
+        );
+
+        let err = app.link_transactions("unknown").await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LinkError>(),
+            Some(LinkError::NotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_blocked_after_creation() {
+        init_crypto_provider();
+
+        let (_db_container, dburl) = get_postgres_testcontainer().await;
+
+        run_migrations(&dburl, None).unwrap();
+
+        let pool = DbPool::build(&dburl, DbPoolOptions::default())
+            .await
+            .unwrap();
+
+        let app = App::new(
+            AppConfig::default(),
+            Arc::new(PostgresDb::new(pool)),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let create = |url: &str| {
+            app.create_link(
+                String::from("key"),
+                LinkSource::Api,
+                CreateLinkRequest {
+                    url: url.to_string(),
+                    ..Default::default()
+                },
+                false,
+                None,
+            )
+        };
+
+        let res = create("https://www.rustunit.com").await.unwrap();
+        app.redirect(&res.id, None).await.unwrap();
+
+        app.block_host("rustunit.com").await.unwrap();
+
+        let err = app.redirect(&res.id, None).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LinkError>(),
+            Some(LinkError::RedirectBlocked(_))
+        ));
+
+        let err = create("https://blog.rustunit.com").await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LinkError>(),
+            Some(LinkError::HostBlocked)
+        ));
+
+        app.unblock_host("rustunit.com").await.unwrap();
+
+        app.redirect(&res.id, None).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_export() {
+        use crate::handler::handle_export;
+        use axum::{extract::State, response::IntoResponse};
+        use futures_util::TryStreamExt;
+
+        init_crypto_provider();
+
+        let (_db_container, dburl) = get_postgres_testcontainer().await;
+
+        run_migrations(&dburl, None).unwrap();
+
+        let pool = DbPool::build(&dburl, DbPoolOptions::default())
+            .await
+            .unwrap();
+
+        let db = Arc::new(PostgresDb::new(pool));
+        let app = App::new(
+            AppConfig::default(),
+            db.clone(),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let mut ids = Vec::new();
+        for i in 0..3 {
+            let res = app
+                .create_link(
+                    String::from("key"),
+                    LinkSource::Api,
+                    CreateLinkRequest {
+                        url: format!("https://www.rustunit.com/{i}"),
+                        ..Default::default()
+                    },
+                    false,
+                    None,
+                )
+                .await
+                .unwrap();
+            ids.push(res.id);
+        }
+        ids.sort();
+
+        // pages smaller than the table still yield every link once
+        let streamed: Vec<LinkDetails> = db.stream_all(2).try_collect().await.unwrap();
+        let streamed_ids: Vec<String> = streamed.into_iter().map(|link| link.id).collect();
+        assert_eq!(streamed_ids, ids);
+
+        let response = handle_export(State(app)).await.into_response();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let lines: Vec<serde_json::Value> = std::str::from_utf8(&body)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(lines.len(), 3);
+        for (line, id) in lines.iter().zip(&ids) {
+            assert_eq!(&line["id"], id);
+            assert_eq!(line["click_count"], 0);
+            assert!(
+                line["url"]
+                    .as_str()
+                    .unwrap()
+                    .starts_with("https://www.rustunit.com/")
+            );
+            assert!(line.get("created_at").is_some());
+            assert!(line.get("key").is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_export_pages_lazily() {
+        use futures_util::StreamExt;
+
+        init_crypto_provider();
+
+        let (_db_container, dburl) = get_postgres_testcontainer().await;
+
+        run_migrations(&dburl, None).unwrap();
+
+        // a single connection, which a stream holding on to it would starve
+        let pool = DbPool::build(&dburl, DbPoolOptions::default())
+            .await
+            .unwrap();
+
+        let db = Arc::new(PostgresDb::new(pool));
+        let app = App::new(
+            AppConfig::default(),
+            db.clone(),
+            Arc::new(ClickCounter::new()),
+        );
+
+        app.import_link(
+            "key",
+            "aaaa",
+            "https://www.rustunit.com/0",
+            OnConflict::Fail,
+        )
+        .await
+        .unwrap();
+
+        let mut stream = db.stream_all(1);
+        let first = stream.next().await.unwrap().unwrap();
+        assert_eq!(first.id, "aaaa");
+
+        // only pages not fetched yet see links created while streaming
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            app.import_link(
+                "key",
+                "zzzz",
+                "https://www.rustunit.com/1",
+                OnConflict::Fail,
+            ),
+        )
+        .await
+        .expect("export holds the only connection")
+        .unwrap();
+
+        let rest: Vec<String> = stream.map(|link| link.unwrap().id).collect().await;
+        assert_eq!(rest, vec![String::from("zzzz")]);
+    }
+
+    #[tokio::test]
+    async fn test_export_own() {
+        use crate::{auth::AuthenticatedKey, handler::handle_export_own};
+        use axum::{Extension, extract::State, response::IntoResponse};
+        use futures_util::TryStreamExt;
+
+        init_crypto_provider();
+
+        let (_db_container, dburl) = get_postgres_testcontainer().await;
+
+        run_migrations(&dburl, None).unwrap();
+
+        let pool = DbPool::build(&dburl, DbPoolOptions::default())
+            .await
+            .unwrap();
+
+        let db = Arc::new(PostgresDb::new(pool));
+        let app = App::new(
+            AppConfig::default(),
+            db.clone(),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let mut ids = Vec::new();
+        for i in 0..5 {
+            let key = if i % 2 == 0 { "mine" } else { "theirs" };
+            let res = app
+                .create_link(
+                    key.to_string(),
+                    LinkSource::Api,
+                    CreateLinkRequest {
+                        url: format!("https://www.rustunit.com/{i}"),
+                        ..Default::default()
+                    },
+                    false,
+                    None,
+                )
+                .await
+                .unwrap();
+            if key == "mine" {
+                ids.push(res.id);
+            }
+        }
+        ids.sort();
+
+        // pages smaller than the key's links still yield each of them once
+        let streamed: Vec<OwnedLink> = db.stream_owned("mine", 2).try_collect().await.unwrap();
+        let streamed_ids: Vec<String> = streamed.into_iter().map(|link| link.id).collect();
+        assert_eq!(streamed_ids, ids);
+
+        let (first, next) = app.owned_links_page("mine", None, Some(2)).await.unwrap();
+        let (rest, last) = app
+            .owned_links_page("mine", next.as_deref(), Some(2))
+            .await
+            .unwrap();
+        let paged_ids: Vec<String> = first.into_iter().chain(rest).map(|link| link.id).collect();
+        assert_eq!(paged_ids, ids);
+        assert_eq!(last, None);
+
+        let response = handle_export_own(
+            Extension(AuthenticatedKey(String::from("mine"))),
+            State(app),
+        )
+        .await
+        .into_response();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let lines: Vec<serde_json::Value> = std::str::from_utf8(&body)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(lines.len(), 3);
+        for (line, id) in lines.iter().zip(&ids) {
+            assert_eq!(&line["id"], id);
+            assert_eq!(line["source"], "api");
+            assert_eq!(line["click_count"], 0);
+            assert!(line.get("created_at").is_some());
+            assert!(line.get("last_used").is_some());
+            assert!(line.get("key").is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_top_links() {
+        init_crypto_provider();
+
+        let (_db_container, dburl) = get_postgres_testcontainer().await;
+
+        run_migrations(&dburl, None).unwrap();
+
+        let pool = DbPool::build(&dburl, DbPoolOptions::default())
+            .await
+            .unwrap();
+
+        let app = App::new(
+            AppConfig::default(),
+            Arc::new(PostgresDb::new(pool)),
+            Arc::new(ClickCounter::new()),
+        );
+        let now = chrono::Utc::now();
+
+        let links = [
+            ("few", 1, true, None),
+            ("many", 9, true, None),
+            ("some", 5, true, Some(now + chrono::Duration::hours(1))),
+            ("private", 20, false, None),
+            ("expired", 30, true, Some(now - chrono::Duration::hours(1))),
+            ("disabled", 40, true, None),
+            ("protected", 50, true, None),
+        ];
+
+        for (id, clicks, public, expires_at) in links {
+            app.db
+                .create(&CreateLink {
+                    id: id.to_string(),
+                    url: format!("https://www.rustunit.com/{id}"),
+                    key: String::from("key"),
+                    redirect_status: TEMPORARY_REDIRECT,
+                    source: LinkSource::Api.as_str().to_string(),
+                    expires_at,
+                    password_hash: (id == "protected").then(|| String::from("hash")),
+                    public,
+                    title: None,
+                    reserved: false,
+                    open_graph: OpenGraphTags::default(),
+                    creator_ip: None,
+                    max_clicks: None,
+                    note: None,
+                    content_hash: None,
+                })
+                .await
+                .unwrap();
+            app.db
+                .batch_update_clicks(vec![id.to_string()], vec![clicks], vec![now], vec![now])
+                .await
+                .unwrap();
+        }
+        app.db.set_disabled("disabled", true).await.unwrap();
+
+        let top = app.top_links(2).await.unwrap();
+        let ids: Vec<_> = top.iter().map(|link| link.id.as_str()).collect();
+        assert_eq!(ids, ["many", "some"]);
+        assert_eq!(top[0].click_count, 9);
+        assert_eq!(top[0].url, "https://www.rustunit.com/many");
+
+        let all = app.top_links(u32::MAX).await.unwrap();
+        let ids: Vec<_> = all.iter().map(|link| link.id.as_str()).collect();
+        assert_eq!(ids, ["many", "some", "few"]);
+    }
+
+    #[tokio::test]
+    async fn test_get_case_insensitive() {
+        init_crypto_provider();
+
+        let (_db_container, dburl) = get_postgres_testcontainer().await;
+
+        run_migrations(&dburl, None).unwrap();
+
+        let pool = DbPool::build(&dburl, DbPoolOptions::default())
+            .await
+            .unwrap();
+        let db = PostgresDb::new(pool);
+
+        db.create(&CreateLink {
+            id: String::from("AbC123"),
+            url: String::from("https://www.rustunit.com"),
+            key: String::from("key"),
+            redirect_status: TEMPORARY_REDIRECT,
+            source: String::from("api"),
+            expires_at: None,
+            password_hash: None,
+            public: false,
+            title: None,
+            reserved: false,
+            open_graph: OpenGraphTags::default(),
+            creator_ip: None,
+            max_clicks: None,
+            note: None,
+            content_hash: None,
+        })
+        .await
+        .unwrap();
+
+        let link = db.get_case_insensitive("abc123").await.unwrap().unwrap();
+        assert_eq!(link.id, "AbC123");
+        assert!(db.get("abc123").await.unwrap().is_none());
+        assert!(db.get_case_insensitive("abc12").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_next_sequence() {
+        init_crypto_provider();
+
+        let (_db_container, dburl) = get_postgres_testcontainer().await;
+
+        run_migrations(&dburl, None).unwrap();
+
+        let pool = DbPool::build(&dburl, DbPoolOptions::default())
+            .await
+            .unwrap();
+        let db = PostgresDb::new(pool);
+
+        assert_eq!(db.next_sequence().await.unwrap(), 1);
+        assert_eq!(db.next_sequence().await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_import_on_conflict_postgres() {
+        init_crypto_provider();
+
+        let (_db_container, dburl) = get_postgres_testcontainer().await;
+
+        run_migrations(&dburl, None).unwrap();
+
+        let pool = DbPool::build(&dburl, DbPoolOptions::default())
+            .await
+            .unwrap();
+
+        let app = App::new(
+            AppConfig::default(),
+            Arc::new(PostgresDb::new(pool)),
+            Arc::new(ClickCounter::new()),
+        );
+        let old = "https://www.rustunit.com/old";
+        let new = "https://www.rustunit.com/new";
+
+        app.import_link("key", "launch", old, OnConflict::Fail)
+            .await
+            .unwrap();
+        app.redirect("launch", None).await.unwrap();
+
+        let err = app
+            .import_link("key", "launch", new, OnConflict::Fail)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LinkError>(),
+            Some(LinkError::IdTaken)
+        ));
+
+        let outcome = app
+            .import_link("key", "launch", new, OnConflict::Skip)
+            .await
+            .unwrap();
+        assert_eq!(outcome, ImportOutcome::Skipped);
+        assert_eq!(app.db.get("launch").await.unwrap().unwrap().url, old);
+
+        let err = app
+            .import_link("other", "launch", new, OnConflict::Overwrite)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LinkError>(),
+            Some(LinkError::IdTaken)
+        ));
+
+        let outcome = app
+            .import_link("key", "launch", new, OnConflict::Overwrite)
+            .await
+            .unwrap();
+        assert_eq!(outcome, ImportOutcome::Overwritten);
+        assert_eq!(app.redirect("launch", None).await.unwrap().url, new);
+    }
+
+    #[tokio::test]
+    async fn test_delete_links() {
+        init_crypto_provider();
+
+        let (_db_container, dburl) = get_postgres_testcontainer().await;
+
+        run_migrations(&dburl, None).unwrap();
+
+        let pool = DbPool::build(&dburl, DbPoolOptions::default())
+            .await
+            .unwrap();
+
+        let app = App::new(
+            AppConfig::default(),
+            Arc::new(PostgresDb::new(pool)),
+            Arc::new(ClickCounter::new()),
+        );
+
+        for (id, key) in [("mine", "key"), ("theirs", "other")] {
+            app.import_link(key, id, "https://www.rustunit.com", OnConflict::Fail)
+                .await
+                .unwrap();
+        }
+        app.redirect("mine", None).await.unwrap();
+
+        let results = app
+            .delete_links(
+                "key",
+                vec![
+                    String::from("mine"),
+                    String::from("theirs"),
+                    String::from("unknown"),
+                ],
+            )
+            .await
+            .unwrap();
+
+        let results: Vec<_> = results
+            .iter()
+            .map(|link| (link.id.as_str(), link.deleted))
+            .collect();
+        assert_eq!(
+            results,
+            [("mine", true), ("theirs", false), ("unknown", false)]
+        );
+
+        // the cached link is gone too
+        assert!(app.redirect("mine", None).await.is_err());
+        assert!(app.db.get("theirs").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_disable_link() {
+        init_crypto_provider();
+
+        let (_db_container, dburl) = get_postgres_testcontainer().await;
+
+        run_migrations(&dburl, None).unwrap();
+
+        let pool = DbPool::build(&dburl, DbPoolOptions::default())
+            .await
+            .unwrap();
+
+        let app = App::new(
+            AppConfig::default(),
+            Arc::new(PostgresDb::new(pool)),
+            Arc::new(ClickCounter::new()),
+        );
+
+        app.import_link(
+            "key",
+            "mylink",
+            "https://www.rustunit.com",
+            OnConflict::Fail,
+        )
+        .await
+        .unwrap();
+        // cached before it gets disabled
+        app.redirect("mylink", None).await.unwrap();
+
+        let err = app
+            .set_disabled(Some("other"), "mylink", true)
+            .await
+            .unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(LinkError::NotOwner)));
+
+        app.set_disabled(Some("key"), "mylink", true).await.unwrap();
+        let err = app.redirect("mylink", None).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(LinkError::Disabled)));
+
+        // another replica learns about it from the DB
+        let replica = App::new(
+            AppConfig::default(),
+            Arc::clone(&app.db),
+            Arc::new(ClickCounter::new()),
+        );
+        let err = replica.redirect("mylink", None).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(LinkError::Disabled)));
+
+        // admins need not own the link
+        app.set_disabled(None, "mylink", false).await.unwrap();
+        let link = app.redirect("mylink", None).await.unwrap();
+        assert_eq!(link.url, "https://www.rustunit.com");
+        assert!(!link.disabled);
+
+        let err = app.set_disabled(None, "unknown", true).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(LinkError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_purge_expired() {
+        init_crypto_provider();
+
+        let (_db_container, dburl) = get_postgres_testcontainer().await;
+
+        run_migrations(&dburl, None).unwrap();
+
+        let pool = DbPool::build(&dburl, DbPoolOptions::default())
+            .await
+            .unwrap();
+
+        let db = PostgresDb::new(pool);
+        let now = chrono::Utc::now();
+
+        let links = [
+            ("expired", Some(now - chrono::Duration::hours(1))),
+            ("live", Some(now + chrono::Duration::hours(1))),
+            ("forever", None),
+        ];
+
+        for (id, expires_at) in links {
+            db.create(&CreateLink {
+                id: id.to_string(),
+                url: format!("https://www.rustunit.com/{id}"),
+                key: String::from("key"),
+                redirect_status: TEMPORARY_REDIRECT,
+                source: LinkSource::Api.as_str().to_string(),
+                expires_at,
+                password_hash: None,
+                public: false,
+                title: None,
+                reserved: false,
+                open_graph: OpenGraphTags::default(),
+                creator_ip: None,
+                max_clicks: None,
+                note: None,
+                content_hash: None,
+            })
+            .await
+            .unwrap();
+        }
+
+        db.create_transaction(&CreateTransaction {
+            link_id: String::from("expired"),
+            tx_hash: String::from("0x12"),
+            network: String::from("base"),
+            amount: None,
+            payer: None,
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(db.purge_expired(now).await.unwrap(), 1);
+
+        assert!(db.get("expired").await.unwrap().is_none());
+        assert!(db.get("live").await.unwrap().is_some());
+        assert!(db.get("forever").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_url() {
+        init_crypto_provider();
+
+        let (_db_container, dburl) = get_postgres_testcontainer().await;
+
+        run_migrations(&dburl, None).unwrap();
+
+        let pool = DbPool::build(&dburl, DbPoolOptions::default())
+            .await
+            .unwrap();
+
+        let app = App::new(
+            AppConfig {
+                prefix: "http://localhost".to_string(),
+                hash_length: 6,
+                cache_size: 10,
+                ..Default::default()
+            },
+            Arc::new(PostgresDb::new(pool)),
+            Arc::new(ClickCounter::new()),
+        );
+        let res = app
+            .create_link(
+                String::from("key"),
+                LinkSource::Api,
+                CreateLinkRequest {
+                    url: String::from("abcde.com"),
+                    ..Default::default()
+                },
+                false,
+                None,
+            )
+            .await;
+
+        assert!(res.is_err());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        counter::{ClickCounter, ClickData, MockClickStore},
+        db::MockLinksDB,
+    };
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_dedup_concurrent_creates() {
+        const REQUESTS: usize = 20;
+
+        let mut db = MockLinksDB::with_empty_blocklist();
+        db.expect_create().times(1).returning(|link| {
+            // keeps the insert in flight while the other requests arrive,
+            // handing the worker's queued tasks to another thread meanwhile
+            tokio::task::block_in_place(|| std::thread::sleep(Duration::from_millis(200)));
+            Ok(link.clone())
+        });
+
+        let app = App::new(
+            AppConfig {
+                dedup_concurrent_creates: true,
+                ..Default::default()
+            },
+            Arc::new(db),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let barrier = Arc::new(tokio::sync::Barrier::new(REQUESTS));
+        let requests: Vec<_> = (0..REQUESTS)
+            .map(|_| {
+                let app = Arc::clone(&app);
+                let barrier = Arc::clone(&barrier);
+                tokio::spawn(async move {
+                    barrier.wait().await;
+                    app.create_link(
+                        String::from("key"),
+                        LinkSource::Api,
+                        CreateLinkRequest {
+                            url: String::from("https://www.rustunit.com"),
+                            ..Default::default()
+                        },
+                        false,
+                        None,
+                    )
+                    .await
+                    .unwrap()
+                })
+            })
+            .collect();
+
+        for request in requests {
+            assert_eq!(request.await.unwrap().id, "as9sud");
+        }
+        assert!(app.inflight_creates.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_caching() {
+        let link = CreateLink {
+            id: String::from("id"),
+            url: String::from("url"),
+            key: String::from("key"),
+            redirect_status: TEMPORARY_REDIRECT,
+            source: LinkSource::Api.as_str().to_string(),
+            expires_at: None,
+            password_hash: None,
+            public: false,
+            title: None,
+            reserved: false,
+            open_graph: OpenGraphTags::default(),
+            creator_ip: None,
+            max_clicks: None,
+            note: None,
+            content_hash: None,
+        };
+
+        let mut db = MockLinksDB::new();
+        db.expect_get().times(1).returning(move |_| {
+            Ok(Some(FetchLink {
+                id: link.id.clone(),
+                url: link.url.clone(),
+                redirect_status: link.redirect_status,
+                password_hash: None,
+                disabled: false,
+                reserved: false,
+                click_count: 0,
+                max_clicks: None,
+                key: None,
+                expires_at: None,
+            }))
+        });
+
+        let app = App::new(
+            AppConfig {
+                prefix: "http://localhost".to_string(),
+                hash_length: 6,
+                cache_size: 10,
+                ..Default::default()
+            },
+            Arc::new(db),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let res = app.redirect("foo", None).await.unwrap();
+        assert_eq!(&res.url, "url");
+
+        let res = app.redirect("foo", None).await.unwrap();
+        assert_eq!(&res.url, "url");
+    }
+
+    fn rules() -> ValidationRules {
+        AppConfig::default().validation_rules
+    }
+
+    #[test]
+    fn test_hash_salt() {
+        let url = "https://www.rustunit.com";
+
+        assert_eq!(link_hash(url, "", 6, 0), "as9sud");
+        assert_ne!(link_hash(url, "one", 6, 0), link_hash(url, "two", 6, 0));
+        assert_ne!(link_hash(url, "one", 6, 0), link_hash(url, "", 6, 0));
+    }
+
+    #[test]
+    fn test_sequence_encoding() {
+        let alphabet = sequence_alphabet("");
+        assert_eq!(alphabet.len(), 36);
+        assert_ne!(alphabet, sequence_alphabet("salt"));
+
+        for n in [0, 1, 2, 35, 36, 1295, 1296, u64::MAX] {
+            let id = encode_sequence(n, &alphabet, 6);
+            assert!(id.len() >= 6, "{id}");
+            assert_eq!(decode_sequence(&id, &alphabet), Some(n), "{id}");
+        }
+        // consecutive numbers differ in more than their last character
+        let one = encode_sequence(1, &alphabet, 6);
+        let two = encode_sequence(2, &alphabet, 6);
+        assert_ne!(one[1..], two[1..]);
+
+        assert_eq!(decode_sequence("ab-", &alphabet), None);
+    }
+
+    #[test]
+    fn test_hash_padded_to_length() {
+        // a u64 never encodes to more than 11 base62 digits
+        let id = link_hash("https://www.rustunit.com", "", 16, 0);
+
+        assert_eq!(id.len(), 16);
+        assert!(id.starts_with("00000"));
+        assert!(id.ends_with(&link_hash("https://www.rustunit.com", "", 11, 0)));
+    }
+
+    #[test]
+    fn test_plausible_id() {
+        assert!(is_plausible_id("as9sud"));
+        assert!(!is_plausible_id(""));
+        assert!(!is_plausible_id("as9 sud"));
+        assert!(!is_plausible_id("wp-login.php"));
+    }
+
+    #[test]
+    fn test_validate_id() {
+        assert!(validate_id("as9sud", 1..=32).is_ok());
+        assert!(validate_id("", 1..=32).is_err());
+        assert!(validate_id(&"a".repeat(33), 1..=32).is_err());
+        assert!(validate_id("As9sud", 1..=32).is_err());
+        assert!(validate_id("as9-sud", 1..=32).is_err());
+        assert!(validate_id("health", 1..=32).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_import_alias_over_max_length() {
+        let mut db = MockLinksDB::with_empty_blocklist();
+        db.expect_create().never();
+        let app = App::new(
+            AppConfig {
+                alias_min_length: 3,
+                alias_max_length: 8,
+                ..Default::default()
+            },
+            Arc::new(db),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let err = app
+            .import_link(
+                "key",
+                "rustunit2",
+                "https://www.rustunit.com",
+                OnConflict::Fail,
+            )
+            .await
+            .unwrap_err();
+
+        assert_eq!(err.to_string(), "id must be 3 to 8 characters long");
+    }
+
+    #[test]
+    fn test_host_blocked() {
+        let blocked = HashSet::from([String::from("evil.com")]);
+
+        assert!(is_host_listed(&blocked, "evil.com"));
+        assert!(is_host_listed(&blocked, "login.evil.com."));
+        assert!(!is_host_listed(&blocked, "notevil.com"));
+        assert!(!is_host_listed(&blocked, "evil.com.example.org"));
+    }
+
+    #[test]
+    fn test_url_length() {
+        let rules = ValidationRules {
+            max_url_length: 30,
+            ..rules()
+        };
+
+        assert!(validate_url("https://www.rustunit.com", &rules, IdnPolicy::Allow).is_ok());
+        assert!(
+            validate_url(
+                "https://www.rustunit.com/too/long",
+                &rules,
+                IdnPolicy::Allow
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_denied_schemes() {
+        let permissive = ValidationRules {
+            allowed_schemes: DENIED_SCHEMES.map(String::from).to_vec(),
+            ..rules()
+        };
+
+        for url in [
+            "data:text/html;base64,PGgxPmhpPC9oMT4=",
+            "blob:https://www.rustunit.com/550e8400-e29b-41d4-a716-446655440000",
+            "file:///etc/passwd",
+            "javascript:alert(1)",
+        ] {
+            let violations = validate_url(url, &permissive, IdnPolicy::Allow).unwrap_err();
+            assert_eq!(violations, ["scheme_not_allowed"], "{url}");
+        }
+    }
+
+    #[test]
+    fn test_forward_query() {
+        assert_eq!(
+            forward_query("https://www.rustunit.com", "utm_source=x", 20, 1024),
+            "https://www.rustunit.com?utm_source=x"
+        );
+        assert_eq!(
+            forward_query("https://www.rustunit.com/?a=1#top", "b=2&c=3", 20, 1024),
+            "https://www.rustunit.com/?a=1&b=2&c=3#top"
+        );
+        assert_eq!(
+            forward_query("https://www.rustunit.com", "", 20, 1024),
+            "https://www.rustunit.com"
+        );
+
+        let query: Vec<String> = (0..100).map(|i| format!("p{i}=v")).collect();
+        assert_eq!(
+            forward_query("https://www.rustunit.com", &query.join("&"), 3, 1024),
+            "https://www.rustunit.com?p0=v&p1=v&p2=v"
+        );
+        assert_eq!(
+            forward_query("https://www.rustunit.com", &query.join("&"), 20, 14),
+            "https://www.rustunit.com?p0=v&p1=v&p2=v"
+        );
+    }
+
+    #[test]
+    fn test_private_hosts() {
+        let strict = ValidationRules {
+            block_private_hosts: true,
+            ..rules()
+        };
+
+        for url in [
+            "http://localhost:8080",
+            "http://127.0.0.1/admin",
+            "http://10.0.0.1",
+            "http://192.168.1.1",
+            "http://169.254.169.254/latest/meta-data",
+            "http://[::1]/",
+        ] {
+            assert!(
+                validate_url(url, &strict, IdnPolicy::Allow).is_err(),
+                "{url}"
+            );
+            assert!(
+                validate_url(url, &rules(), IdnPolicy::Allow).is_ok(),
+                "{url}"
+            );
+        }
+
+        assert!(validate_url("https://www.rustunit.com", &strict, IdnPolicy::Allow).is_ok());
+    }
+
+    #[test]
+    fn test_private_hosts_in_other_encodings() {
+        let strict = ValidationRules {
+            block_private_hosts: true,
+            ..rules()
+        };
+
+        for url in [
+            // decimal
+            "http://2130706433/",
+            // hex
+            "http://0x7f000001/",
+            "http://0x7f.1/",
+            // octal
+            "http://0177.0.0.1/",
+            "http://017700000001/",
+            // shortened
+            "http://127.1/",
+            // IPv4-mapped IPv6
+            "http://[::ffff:127.0.0.1]/",
+            "http://[::ffff:a00:1]/",
+        ] {
+            assert!(
+                validate_url(url, &strict, IdnPolicy::Allow).is_err(),
+                "{url}"
+            );
+        }
+
+        assert!(validate_url("http://[::ffff:8.8.8.8]/", &strict, IdnPolicy::Allow).is_ok());
+    }
+
+    // the first letter is a cyrillic `а`
+    const LOOKALIKE_URL: &str = "https://\u{0430}pple.com/login";
+
+    #[test]
+    fn test_idn_allow() {
+        assert_eq!(
+            validate_url(LOOKALIKE_URL, &rules(), IdnPolicy::Allow).unwrap(),
+            LOOKALIKE_URL
+        );
+    }
+
+    #[test]
+    fn test_idn_normalize() {
+        assert_eq!(
+            validate_url(LOOKALIKE_URL, &rules(), IdnPolicy::Normalize).unwrap(),
+            "https://xn--pple-43d.com/login"
+        );
+    }
+
+    #[test]
+    fn test_idn_reject() {
+        assert!(validate_url(LOOKALIKE_URL, &rules(), IdnPolicy::Reject).is_err());
+        assert!(validate_url("https://www.rustunit.com", &rules(), IdnPolicy::Reject).is_ok());
+    }
+
+    #[test]
+    fn test_mixed_script() {
+        assert!(is_mixed_script("\u{0430}pple.com"));
+        assert!(!is_mixed_script(
+            "\u{043f}\u{0440}\u{0438}\u{043c}\u{0435}\u{0440}.com"
+        ));
+        assert!(!is_mixed_script("rust-2024.com"));
+    }
+
+    #[tokio::test]
+    async fn test_redirect_does_not_wait_for_counter() {
+        let mut db = MockLinksDB::new();
+        db.expect_get().times(1).returning(|id| {
+            Ok(Some(FetchLink {
+                id: id.to_string(),
+                url: String::from("url"),
+                redirect_status: TEMPORARY_REDIRECT,
+                password_hash: None,
+                disabled: false,
+                reserved: false,
+                click_count: 0,
+                max_clicks: None,
+                key: None,
+                expires_at: None,
+            }))
+        });
+        db.expect_batch_update_clicks()
+            .times(1)
+            .returning(|_, increments, _, _| {
+                assert_eq!(increments, vec![1]);
+                Ok(1)
+            });
+
+        let db = Arc::new(db);
+        let counter = Arc::new(ClickCounter::new());
+        let app = App::new(AppConfig::default(), db.clone(), counter.clone());
+
+        let guard = counter.lock().await;
+
+        let res = tokio::time::timeout(Duration::from_secs(1), app.redirect("foo", None))
+            .await
+            .expect("redirect blocked on the counter lock")
+            .unwrap();
+        assert_eq!(&res.url, "url");
+
+        drop(guard);
+
+        // the detached increment still makes it into the final flush
+        assert_eq!(app.flush_counters().await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_create_with_ttl() {
+        let start = DateTime::parse_from_rfc3339("2026-01-01T00:00:00Z")
+            .unwrap()
+            .to_utc();
+        let clock = Arc::new(crate::clock::MockClock::new(start));
+
+        let expires = Arc::new(Mutex::new(Vec::new()));
+        let mut db = MockLinksDB::with_empty_blocklist();
+        db.expect_create().times(2).returning({
+            let expires = expires.clone();
+            move |link| {
+                expires.lock().unwrap().push(link.expires_at.unwrap());
+                Ok(link.clone())
+            }
+        });
+        let app = App::new(
+            AppConfig {
+                clock: clock.clone(),
+                ..Default::default()
+            },
+            Arc::new(db),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let create = |url: &str| {
+            app.create_link(
+                String::from("key"),
+                LinkSource::Api,
+                CreateLinkRequest {
+                    url: url.to_string(),
+                    ttl_seconds: Some(3600),
+                    ..Default::default()
+                },
+                false,
+                None,
+            )
+        };
+
+        create("https://www.rustunit.com/a").await.unwrap();
+        clock.advance(chrono::TimeDelta::minutes(10));
+        create("https://www.rustunit.com/b").await.unwrap();
+
+        assert_eq!(
+            *expires.lock().unwrap(),
+            [
+                start + chrono::TimeDelta::seconds(3600),
+                start + chrono::TimeDelta::seconds(4200),
+            ]
+        );
+
+        assert!(expires_after(start, u64::MAX).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_redirect_after_expiry() {
+        let clock = Arc::new(crate::clock::MockClock::new(Utc::now()));
+        let app = app(AppConfig {
+            clock: clock.clone(),
+            ..Default::default()
+        });
+
+        let ttl = app
+            .create_link(
+                String::from("key"),
+                LinkSource::Api,
+                CreateLinkRequest {
+                    url: String::from("https://www.rustunit.com"),
+                    ttl_seconds: Some(60),
+                    ..Default::default()
+                },
+                false,
+                None,
+            )
+            .await
+            .unwrap();
+        // expiring links are not handed out for permanent ones
+        let permanent = create(&app, "key", "https://www.rustunit.com").await;
+        assert_ne!(permanent.id, ttl.id);
+
+        // cached by the first redirect
+        app.redirect(&ttl.id, None).await.unwrap();
+
+        clock.advance(chrono::TimeDelta::seconds(60));
+        let err = app.redirect(&ttl.id, None).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(LinkError::NotFound)));
+        app.redirect(&permanent.id, None).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_upgrade_insecure() {
+        let mut db = MockLinksDB::with_empty_blocklist();
+        db.expect_create().times(1).returning(|link| {
+            assert_eq!(link.url, "https://www.rustunit.com/blog");
+            Ok(link.clone())
+        });
+        let app = App::new(
+            AppConfig {
+                upgrade_insecure: UpgradeInsecure::Listed,
+                upgrade_insecure_hosts: HashSet::from([String::from("rustunit.com")]),
+                ..Default::default()
+            },
+            Arc::new(db),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let created = app
+            .create_link(
+                String::from("key"),
+                LinkSource::Api,
+                CreateLinkRequest {
+                    url: String::from("http://www.rustunit.com/blog"),
+                    ..Default::default()
+                },
+                false,
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(created.original_url, "https://www.rustunit.com/blog");
+
+        let hosts = HashSet::from([String::from("rustunit.com")]);
+        let upgrade = |url| upgrade_insecure(url, UpgradeInsecure::Listed, &hosts);
+        assert_eq!(upgrade("http://example.com/"), "http://example.com/");
+        assert_eq!(
+            upgrade("http://rustunit.com:8080/"),
+            "http://rustunit.com:8080/"
+        );
+        assert_eq!(
+            upgrade_insecure("HTTP://example.com/", UpgradeInsecure::All, &hosts),
+            "https://example.com/"
+        );
+        assert_eq!(
+            upgrade_insecure("http://rustunit.com/", UpgradeInsecure::Off, &hosts),
+            "http://rustunit.com/"
+        );
+
+        // non-canonical spellings keep their host
+        for url in [
+            "http:example.com/a",
+            "http:/example.com/a",
+            " http://example.com/a",
+        ] {
+            assert_eq!(
+                upgrade_insecure(url, UpgradeInsecure::All, &hosts),
+                "https://example.com/a"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_redirect_from_cache_while_db_down() {
+        let db_down = Arc::new(AtomicBool::new(false));
+
+        let mut db = MockLinksDB::with_empty_blocklist();
+        db.expect_get().returning({
+            let db_down = Arc::clone(&db_down);
+            move |id| {
+                if db_down.load(Ordering::SeqCst) {
+                    return Err(DbError::General(String::from("connection refused")));
+                }
+                Ok(Some(FetchLink {
+                    id: id.to_string(),
+                    url: String::from("https://www.rustunit.com"),
+                    redirect_status: TEMPORARY_REDIRECT,
+                    password_hash: None,
+                    disabled: false,
+                    reserved: false,
+                    click_count: 0,
+                    max_clicks: None,
+                    key: None,
+                    expires_at: None,
+                }))
+            }
+        });
+
+        let app = App::new(
+            AppConfig {
+                unavailable_retry_after: 30,
+                ..Default::default()
+            },
+            Arc::new(db),
+            Arc::new(ClickCounter::new()),
+        );
+
+        app.redirect("foo", None).await.unwrap();
+
+        db_down.store(true, Ordering::SeqCst);
+
+        let res = app.redirect("foo", None).await.unwrap();
+        assert_eq!(res.url, "https://www.rustunit.com");
+
+        let err = app.redirect("bar", None).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LinkError>(),
+            Some(LinkError::Unavailable { retry_after: 30 })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_custom_click_store() {
+        let mut db = MockLinksDB::with_empty_blocklist();
+        db.expect_get().times(1).returning(|id| {
+            Ok(Some(FetchLink {
+                id: id.to_string(),
+                url: String::from("https://www.rustunit.com"),
+                redirect_status: TEMPORARY_REDIRECT,
+                password_hash: None,
+                disabled: false,
+                reserved: false,
+                click_count: 0,
+                max_clicks: None,
+                key: None,
+                expires_at: None,
+            }))
+        });
+        db.expect_batch_update_clicks()
+            .times(1)
+            .returning(|ids, increments, _, _| {
+                assert_eq!(ids, vec![String::from("foo")]);
+                assert_eq!(increments, vec![3]);
+                Ok(1)
+            });
+
+        let mut store = MockClickStore::new();
+        store
+            .expect_increment()
+            .withf(|id| id == "foo")
+            .times(1)
+            .returning(|_| ());
+        store.expect_drain().times(1).returning(|| {
+            std::collections::HashMap::from([(
+                String::from("foo"),
+                ClickData {
+                    count: 3,
+                    first_used: Utc::now(),
+                    last_used: Utc::now(),
+                },
+            )])
+        });
+
+        let app = App::new(AppConfig::default(), Arc::new(db), Arc::new(store));
+
+        app.redirect("foo", None).await.unwrap();
+        assert_eq!(app.flush_counters().await.unwrap(), 1);
+    }
+
+    #[test]
+    fn test_render_pool_metrics() {
+        let mut db = MockLinksDB::new();
+        db.expect_pool_status().returning(|| {
+            Some(deadpool::Status {
+                max_size: 10,
+                size: 4,
+                available: 1,
+                waiting: 0,
+            })
+        });
+
+        let app = App::new(
+            AppConfig::default(),
+            Arc::new(db),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let metrics = app.render_metrics().unwrap();
+
+        assert!(metrics.contains("db_pool_available 1\n"));
+        assert!(metrics.contains("db_pool_in_use 3\n"));
+        assert!(metrics.contains("db_pool_max 10\n"));
+    }
+
+    #[tokio::test]
+    async fn test_summary_reports_all_sources() {
+        let mut db = MockLinksDB::new();
+        db.expect_count_by_source()
+            .returning(|| Ok(vec![(String::from("api"), 3), (String::from("x402"), 2)]));
+
+        let app = App::new(
+            AppConfig::default(),
+            Arc::new(db),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let summary = app.summary().await.unwrap();
+
+        assert_eq!(summary.total_links, 5);
+        assert_eq!(
+            summary.by_source,
+            BTreeMap::from([("api", 3), ("public", 0), ("x402", 2)])
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_collisions {
+    use super::*;
+    use crate::{counter::ClickCounter, db::MemoryDb};
+    use tracing::{
+        Event, Level, Subscriber,
+        field::{Field, Visit},
+    };
+    use tracing_subscriber::{
+        Layer,
+        layer::{Context, SubscriberExt},
+    };
+
+    #[tokio::test]
+    async fn test_hash_collision() {
+        let link1 = "https://www.google.com/search?q=foobar";
+        let link2 = "https://www.google.com/search?q=foobar7";
+        assert_eq!(&link_hash(link1, "", 1, 0)[0..1], "d");
+        assert_eq!(
+            &link_hash(link1, "", 1, 0)[0..1],
+            &link_hash(link2, "", 1, 0)[0..1],
+        );
+
+        let db = MemoryDb::default();
+        let key = String::from("key");
+
+        let app = App::new(
+            AppConfig {
+                prefix: "http://localhost".to_string(),
+                hash_length: 1,
+                cache_size: 10,
+                ..Default::default()
+            },
+            Arc::new(db),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let res1 = app
+            .create_link(
+                key.clone(),
+                LinkSource::Api,
+                CreateLinkRequest {
+                    url: link1.to_string(),
+                    ..Default::default()
+                },
+                false,
+                None,
+            )
+            .await
+            .unwrap();
+        let res2 = app
+            .create_link(
+                key,
+                LinkSource::Api,
+                CreateLinkRequest {
+                    url: link2.to_string(),
+                    ..Default::default()
+                },
+                false,
+                None,
+            )
+            .await
+            .unwrap();
+
+        assert_ne!(res1.id, res2.id);
+    }
+
+    #[tokio::test]
+    async fn test_force_new() {
+        let app = App::new(
+            AppConfig::default(),
+            Arc::new(MemoryDb::default()),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let create = |force_new| {
+            app.create_link(
+                String::from("key"),
+                LinkSource::Api,
+                CreateLinkRequest {
+                    url: String::from("https://www.rustunit.com"),
+                    force_new,
+                    ..Default::default()
+                },
+                false,
+                None,
+            )
+        };
+
+        let first = create(true).await.unwrap();
+        let second = create(true).await.unwrap();
+        assert_ne!(first.id, second.id);
+
+        // without the flag the url dedups to the first link again
+        assert_eq!(create(false).await.unwrap().id, first.id);
+    }
+
+    #[tokio::test]
+    async fn test_blocked_id_terms() {
+        let url = "https://www.rustunit.com";
+        // block a term the first generated id is known to contain
+        let first = link_hash(url, "", 6, 0);
+        let term = first[1..4].to_string();
+
+        let app = App::new(
+            AppConfig {
+                blocked_id_terms: parse_blocked_id_terms(&format!("# offensive\n\n{term}\n")),
+                ..Default::default()
+            },
+            Arc::new(MemoryDb::default()),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let res = app
+            .create_link(
+                String::from("key"),
+                LinkSource::Api,
+                CreateLinkRequest {
+                    url: url.to_string(),
+                    ..Default::default()
+                },
+                false,
+                None,
+            )
+            .await
+            .unwrap();
+
+        assert_ne!(res.id, first);
+        assert!(!res.id.contains(&term));
+    }
+
+    #[tokio::test]
+    async fn test_strip_fragments() {
+        let app = App::new(
+            AppConfig {
+                strip_fragments: true,
+                ..Default::default()
+            },
+            Arc::new(MemoryDb::default()),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let mut ids = Vec::new();
+        for url in [
+            "https://www.rustunit.com/page#a",
+            "https://www.rustunit.com/page#b",
+        ] {
+            let res = app
+                .create_link(
+                    String::from("key"),
+                    LinkSource::Api,
+                    CreateLinkRequest {
+                        url: url.to_string(),
+                        ..Default::default()
+                    },
+                    false,
+                    None,
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(res.original_url, "https://www.rustunit.com/page");
+            ids.push(res.id);
+        }
+
+        assert_eq!(ids[0], ids[1]);
+    }
+
+    #[tokio::test]
+    async fn test_canonicalize_query() {
+        let app = App::new(
+            AppConfig {
+                canonicalize_query: true,
+                strip_query_params: vec![String::from("utm_*"), String::from("fbclid")],
+                ..Default::default()
+            },
+            Arc::new(MemoryDb::default()),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let mut ids = Vec::new();
+        for url in [
+            "https://www.rustunit.com/?a=1&b=2",
+            "https://www.rustunit.com/?b=2&a=1",
+            "https://www.rustunit.com/?utm_source=x&b=2&fbclid=y&a=1&UTM_Medium=z",
+        ] {
+            let res = app
+                .create_link(
+                    String::from("key"),
+                    LinkSource::Api,
+                    CreateLinkRequest {
+                        url: url.to_string(),
+                        ..Default::default()
+                    },
+                    false,
+                    None,
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(res.original_url, "https://www.rustunit.com/?a=1&b=2");
+            ids.push(res.id);
+        }
+
+        assert_eq!(ids[0], ids[1]);
+        assert_eq!(ids[0], ids[2]);
+    }
+
+    #[tokio::test]
+    async fn test_normalize_unicode() {
+        async fn ids(normalize_unicode: bool) -> Vec<String> {
+            let app = App::new(
+                AppConfig {
+                    normalize_unicode,
+                    ..Default::default()
+                },
+                Arc::new(MemoryDb::default()),
+                Arc::new(ClickCounter::new()),
+            );
+
+            let mut ids = Vec::new();
+            // "café" composed (NFC) and with a combining accent (NFD)
+            for url in [
+                "https://www.rustunit.com/caf\u{e9}?q=caf\u{e9}",
+                "https://www.rustunit.com/cafe\u{301}?q=cafe\u{301}",
+            ] {
+                let res = app
+                    .create_link(
+                        String::from("key"),
+                        LinkSource::Api,
+                        CreateLinkRequest {
+                            url: url.to_string(),
+                            ..Default::default()
+                        },
+                        false,
+                        None,
+                    )
+                    .await
+                    .unwrap();
+                ids.push(res.id);
+            }
+            ids
+        }
+
+        let normalized = ids(true).await;
+        assert_eq!(normalized[0], normalized[1]);
+
+        let kept = ids(false).await;
+        assert_ne!(kept[0], kept[1]);
+
+        assert_eq!(
+            normalize_unicode("https://x.com/cafe%CC%81%2Fa?b=%41#cafe%CC%81"),
+            "https://x.com/caf%C3%A9%2Fa?b=%41#cafe%CC%81"
+        );
+        assert_eq!(
+            normalize_unicode("https://x.com/caf\u{e9}"),
+            "https://x.com/caf%C3%A9"
+        );
+        assert!(matches!(
+            normalize_unicode("https://x.com/caf%C3%A9"),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn test_canonicalize_query_keeps_repeated_order() {
+        let strip = [String::from("gclid")];
+
+        assert_eq!(
+            canonicalize_query("https://x.com/?b=2&a=2&a=1#frag", &strip),
+            "https://x.com/?a=2&a=1&b=2#frag"
+        );
+        assert_eq!(
+            canonicalize_query("https://x.com/p?gclid=1", &strip),
+            "https://x.com/p"
+        );
+        assert!(matches!(
+            canonicalize_query("https://x.com/?a=1&b", &strip),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_namespace_by_key() {
+        async fn ids(namespace_by_key: bool) -> Vec<String> {
+            let app = App::new(
+                AppConfig {
+                    namespace_by_key,
+                    ..Default::default()
+                },
+                Arc::new(MemoryDb::default()),
+                Arc::new(ClickCounter::new()),
+            );
+
+            let mut ids = Vec::new();
+            for key in ["key1", "key2"] {
+                let res = app
+                    .create_link(
+                        key.to_string(),
+                        LinkSource::Api,
+                        CreateLinkRequest {
+                            url: String::from("https://www.rustunit.com"),
+                            ..Default::default()
+                        },
+                        false,
+                        None,
+                    )
+                    .await
+                    .unwrap();
+                ids.push(res.id);
+            }
+            ids
+        }
+
+        let shared = ids(false).await;
+        assert_eq!(shared[0], shared[1]);
+
+        let namespaced = ids(true).await;
+        assert_ne!(namespaced[0], namespaced[1]);
+    }
+
+    #[tokio::test]
+    async fn test_namespace_by_key_collision() {
+        let app = app(AppConfig {
+            namespace_by_key: true,
+            ..Default::default()
+        });
+        let url = "https://www.rustunit.com";
+
+        // another key holds the id the url hashes to for `key2`
+        let taken = link_hash(url, "key2:", 6, 0);
+        app.import_link("key1", &taken, url, OnConflict::Fail)
+            .await
+            .unwrap();
+
+        let created = create(&app, "key2", url).await;
+        assert_ne!(created.id, taken);
+        assert_eq!(created.id, link_hash(url, "key2:", 6, 1));
+    }
+
+    /// Records the level of every `hash collision` event.
+    #[derive(Clone, Default)]
+    struct CollisionLevels(Arc<std::sync::Mutex<Vec<Level>>>);
+
+    impl<S: Subscriber> Layer<S> for CollisionLevels {
+        fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+            struct Message(String);
+
+            impl Visit for Message {
+                fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+                    if field.name() == "message" {
+                        self.0 = format!("{value:?}");
+                    }
+                }
+            }
+
+            let mut message = Message(String::new());
+            event.record(&mut message);
+
+            if message.0 == "hash collision" {
+                self.0.lock().unwrap().push(*event.metadata().level());
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_collision_warn_limit() {
+        let levels = CollisionLevels::default();
+        let _guard =
+            tracing::subscriber::set_default(tracing_subscriber::registry().with(levels.clone()));
 
         let app = App::new(
-            "http://localhost".to_string(),
-            1,
-            Arc::new(db),
+            AppConfig {
+                hash_length: 1,
+                collision_warn_limit: 1,
+                ..Default::default()
+            },
+            Arc::new(MemoryDb::default()),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let mut quieted = 0;
+        for i in 0..20 {
+            levels.0.lock().unwrap().clear();
+
+            app.create_link(
+                String::from("key"),
+                LinkSource::Api,
+                CreateLinkRequest {
+                    url: format!("https://www.rustunit.com/{i}"),
+                    ..Default::default()
+                },
+                false,
+                None,
+            )
+            .await
+            .unwrap();
+
+            let levels = levels.0.lock().unwrap();
+            let warned = levels.iter().filter(|level| **level == Level::WARN).count();
+            assert!(
+                warned <= 1,
+                "{warned} warnings for {} collisions",
+                levels.len()
+            );
+            quieted += levels.len() - warned;
+        }
+
+        // with 20 links in a keyspace of 36 ids some need several attempts
+        assert!(quieted > 0);
+    }
+}
+
+#[cfg(test)]
+mod test_memory_db {
+    use super::*;
+    use crate::{counter::ClickCounter, db::MemoryDb};
+
+    fn app(config: AppConfig) -> Arc<App> {
+        App::new(
+            config,
+            Arc::new(MemoryDb::default()),
+            Arc::new(ClickCounter::new()),
+        )
+    }
+
+    async fn create(app: &App, key: &str, url: &str) -> CreatedLinkResponse {
+        app.create_link(
+            key.to_string(),
+            LinkSource::Api,
+            CreateLinkRequest {
+                url: url.to_string(),
+                ..Default::default()
+            },
+            false,
+            None,
+        )
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_create_redirect_flush() {
+        let app = app(AppConfig::default());
+
+        let created = create(&app, "key", "https://www.rustunit.com").await;
+
+        for _ in 0..3 {
+            let link = app.redirect(&created.id, None).await.unwrap();
+            assert_eq!(link.url, "https://www.rustunit.com");
+        }
+
+        assert_eq!(app.flush_counters().await.unwrap(), 1);
+
+        let details = app.link_details(&created.id).await.unwrap().unwrap();
+        assert_eq!(details.click_count, 3);
+        assert_eq!(details.key.as_deref(), Some("key"));
+        assert_eq!(
+            app.link_clicks_since("key", &created.id, Utc::now())
+                .await
+                .unwrap(),
+            3
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sync_click_counts() {
+        let app = app(AppConfig {
+            sync_click_counts: true,
+            ..Default::default()
+        });
+
+        let created = create(&app, "key", "https://www.rustunit.com").await;
+        app.redirect(&created.id, None).await.unwrap();
+
+        let details = app.link_details(&created.id).await.unwrap().unwrap();
+        assert_eq!(details.click_count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_case_insensitive_ids() {
+        let db = Arc::new(MemoryDb::default());
+        // created before ids were case-insensitive
+        db.create(&CreateLink {
+            id: String::from("LeGaCy"),
+            url: String::from("https://www.rustunit.com/legacy"),
+            key: String::from("key"),
+            redirect_status: TEMPORARY_REDIRECT,
+            source: String::from("api"),
+            expires_at: None,
+            password_hash: None,
+            public: false,
+            title: None,
+            reserved: false,
+            open_graph: OpenGraphTags::default(),
+            creator_ip: None,
+            max_clicks: None,
+            note: None,
+            content_hash: None,
+        })
+        .await
+        .unwrap();
+        let app = App::new(
+            AppConfig {
+                case_insensitive_ids: true,
+                ..Default::default()
+            },
+            db,
+            Arc::new(ClickCounter::new()),
+        );
+
+        let created = create(&app, "key", "https://www.rustunit.com").await;
+        assert_eq!(created.id, created.id.to_ascii_lowercase());
+
+        let link = app
+            .redirect(&created.id.to_ascii_uppercase(), None)
+            .await
+            .unwrap();
+        assert_eq!(link.id, created.id);
+
+        let link = app.redirect("legacy", None).await.unwrap();
+        assert_eq!(link.url, "https://www.rustunit.com/legacy");
+
+        app.flush_counters().await.unwrap();
+        let details = app.link_details(&created.id).await.unwrap().unwrap();
+        assert_eq!(details.click_count, 1);
+        let details = app.link_details("LeGaCy").await.unwrap().unwrap();
+        assert_eq!(details.click_count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_case_insensitive_ids_legacy_hash() {
+        let url = "https://www.rustunit.com";
+        let legacy = |id: &str, url: &str| CreateLink {
+            id: String::from(id),
+            url: String::from(url),
+            key: String::from("key"),
+            redirect_status: TEMPORARY_REDIRECT,
+            source: String::from("api"),
+            expires_at: None,
+            password_hash: None,
+            public: false,
+            title: None,
+            reserved: false,
+            open_graph: OpenGraphTags::default(),
+            creator_ip: None,
+            max_clicks: None,
+            note: None,
+            content_hash: None,
+        };
+        let app_with = |db: Arc<MemoryDb>| {
+            App::new(
+                AppConfig {
+                    case_insensitive_ids: true,
+                    ..Default::default()
+                },
+                db,
+                Arc::new(ClickCounter::new()),
+            )
+        };
+
+        // the same url shortened under a mixed-case id is reused
+        let db = Arc::new(MemoryDb::default());
+        db.create(&legacy("As9sUd", url)).await.unwrap();
+        let created = create(&app_with(Arc::clone(&db)), "key", url).await;
+        assert_eq!(created.id, "As9sUd");
+        assert!(db.get(&link_hash(url, "", 6, 0)).await.unwrap().is_none());
+
+        // another url keeps its id, the generated one would be shadowed
+        let db = Arc::new(MemoryDb::default());
+        db.create(&legacy("As9sUd", "https://www.rustunit.com/other"))
+            .await
+            .unwrap();
+        let created = create(&app_with(Arc::clone(&db)), "key", url).await;
+        assert_eq!(created.id, link_hash(url, "", 6, 1));
+
+        assert!(db.case_duplicate_ids().await.unwrap().is_empty());
+        db.create(&legacy("as9sud", url)).await.unwrap();
+        assert_eq!(db.case_duplicate_ids().await.unwrap(), ["as9sud"]);
+    }
+
+    #[tokio::test]
+    async fn test_link_note() {
+        let app = app(AppConfig::default());
+        let request = |note: String| CreateLinkRequest {
+            url: String::from("https://www.rustunit.com"),
+            note: Some(note),
+            force_new: true,
+            ..Default::default()
+        };
+
+        let created = app
+            .create_link(
+                String::from("key"),
+                LinkSource::Api,
+                request(String::from("Q3 launch tweet")),
+                false,
+                None,
+            )
+            .await
+            .unwrap();
+
+        let details = app.link_details(&created.id).await.unwrap().unwrap();
+        assert_eq!(details.note.as_deref(), Some("Q3 launch tweet"));
+        let (owned, _) = app.owned_links_page("key", None, None).await.unwrap();
+        assert_eq!(owned[0].note.as_deref(), Some("Q3 launch tweet"));
+
+        let err = app
+            .create_link(
+                String::from("key"),
+                LinkSource::Api,
+                request("a".repeat(MAX_NOTE_LENGTH + 1)),
+                false,
+                None,
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LinkError>(),
+            Some(LinkError::Validation(violations)) if violations == &["note_too_long"]
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_sequential_ids() {
+        let app = app(AppConfig {
+            id_strategy: IdStrategy::Sequential,
+            ..Default::default()
+        });
+        let alphabet = sequence_alphabet("");
+
+        let mut ids = Vec::new();
+        for i in 0..20 {
+            let created = create(&app, "key", &format!("https://www.rustunit.com/{i}")).await;
+            assert_eq!(created.id.len(), 6);
+            ids.push(created.id);
+        }
+
+        // every create took the next number, so none had to be retried
+        let numbers: Vec<u64> = ids
+            .iter()
+            .map(|id| decode_sequence(id, &alphabet).unwrap())
+            .collect();
+        assert_eq!(numbers, (1..=20).collect::<Vec<_>>());
+
+        // the same url of the same key is the same link
+        let again = create(&app, "key", "https://www.rustunit.com/3").await;
+        assert_eq!(again.id, ids[3]);
+        let other = create(&app, "other", "https://www.rustunit.com/3").await;
+        assert_eq!(decode_sequence(&other.id, &alphabet), Some(21));
+
+        let link = app.redirect(&ids[7], None).await.unwrap();
+        assert_eq!(link.url, "https://www.rustunit.com/7");
+    }
+
+    #[tokio::test]
+    async fn test_max_clicks() {
+        let app = app(AppConfig::default());
+
+        let request = || CreateLinkRequest {
+            url: String::from("https://www.rustunit.com"),
+            max_clicks: Some(1),
+            ..Default::default()
+        };
+        let once = app
+            .create_link(String::from("key"), LinkSource::Api, request(), false, None)
+            .await
+            .unwrap();
+        let unlimited = create(&app, "key", "https://www.rustunit.com").await;
+        assert_ne!(once.id, unlimited.id);
+
+        app.redirect(&once.id, None).await.unwrap();
+        app.flush_counters().await.unwrap();
+
+        let err = app.redirect(&once.id, None).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(LinkError::Exhausted)));
+        app.redirect(&unlimited.id, None).await.unwrap();
+
+        // a used up link is not handed out again
+        let again = app
+            .create_link(String::from("key"), LinkSource::Api, request(), false, None)
+            .await
+            .unwrap();
+        assert_ne!(again.id, once.id);
+        app.redirect(&again.id, None).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_redirect_unknown_and_deleted() {
+        let app = app(AppConfig::default());
+
+        let err = app.redirect("nope", None).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(LinkError::NotFound)));
+
+        let created = create(&app, "key", "https://www.rustunit.com").await;
+        let other = create(&app, "other", "https://www.rustunit.com/other").await;
+
+        let results = app
+            .delete_links("key", vec![created.id.clone(), other.id.clone()])
+            .await
+            .unwrap();
+        assert!(results[0].deleted);
+        assert!(!results[1].deleted);
+
+        let err = app.redirect(&created.id, None).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(LinkError::NotFound)));
+        app.redirect(&other.id, None).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_cache_max_bytes() {
+        let app = app(AppConfig {
+            cache_size: 10,
+            cache_max_bytes: Some(4096),
+            ..Default::default()
+        });
+
+        let long = format!("https://www.rustunit.com/{}", "x".repeat(1000));
+        let mut ids = Vec::new();
+        for i in 0..20 {
+            let created = create(&app, "key", &format!("https://www.rustunit.com/{i}")).await;
+            ids.push(created.id);
+            let created = create(&app, "key", &format!("{long}{i}")).await;
+            ids.push(created.id);
+        }
+
+        for id in &ids {
+            app.redirect(id, None).await.unwrap();
+        }
+
+        // 20 long urls alone would be about 20kb
+        assert!(app.cache.weight() <= 4096);
+        assert!(app.cache.len() < ids.len());
+        assert!(app.cache.len() > 4096 / 1100);
+    }
+
+    #[tokio::test]
+    async fn test_warm_cache() {
+        let db = Arc::new(MemoryDb::default());
+        let creator = App::new(
+            AppConfig::default(),
+            db.clone(),
             Arc::new(ClickCounter::new()),
-            10,
         );
 
-        let res1 = app
+        let mut ids = Vec::new();
+        for i in 0..3 {
+            let created = create(&creator, "key", &format!("https://www.rustunit.com/{i}")).await;
+            ids.push(created.id);
+        }
+
+        // a fresh instance, as after a deployment
+        let app = App::new(AppConfig::default(), db, Arc::new(ClickCounter::new()));
+        let warm = vec![ids[0].clone(), ids[1].clone(), String::from("unknown")];
+
+        assert_eq!(app.warm_cache(&warm).await.unwrap(), 2);
+        assert!(app.cache.get(&ids[0]).is_some());
+        assert!(app.cache.get(&ids[1]).is_some());
+        assert!(app.cache.get(&ids[2]).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_redirect_loop_guard() {
+        let app = app(AppConfig {
+            prefix: String::from("https://ezli.me"),
+            redirect_loop_guard: true,
+            redirect_loop_hosts: HashSet::from([String::from("short.example")]),
+            ..Default::default()
+        });
+
+        let target = create(&app, "key", "https://www.rustunit.com").await;
+        let looping = create(&app, "key", &format!("https://ezli.me/{}", target.id)).await;
+        let aliased = create(&app, "key", &format!("https://short.example/{}", target.id)).await;
+        let home = create(&app, "key", "https://ezli.me/").await;
+
+        for id in [&looping.id, &aliased.id] {
+            for _ in 0..2 {
+                let err = app.redirect(id, None).await.unwrap_err();
+                assert!(matches!(err.downcast_ref(), Some(LinkError::RedirectLoop)));
+            }
+        }
+
+        app.redirect(&target.id, None).await.unwrap();
+        app.redirect(&home.id, None).await.unwrap();
+    }
+
+    #[test]
+    fn test_points_to_short_link() {
+        let aliases = HashSet::new();
+        let prefix = "https://example.com/links";
+
+        assert!(points_to_short_link(
+            "https://EXAMPLE.com/links/as9sud",
+            prefix,
+            &aliases
+        ));
+        assert!(!points_to_short_link(
+            "https://example.com/as9sud",
+            prefix,
+            &aliases
+        ));
+        assert!(!points_to_short_link(
+            "https://example.com/links/as9sud/more",
+            prefix,
+            &aliases
+        ));
+        assert!(!points_to_short_link(
+            "https://example.com:8443/links/as9sud",
+            prefix,
+            &aliases
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_disabled_link() {
+        let app = app(AppConfig::default());
+
+        let created = create(&app, "key", "https://www.rustunit.com").await;
+        app.set_disabled(Some("key"), &created.id, true)
+            .await
+            .unwrap();
+
+        let err = app.redirect(&created.id, None).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(LinkError::Disabled)));
+
+        // a new create for the url gets a link that redirects
+        let again = create(&app, "key", "https://www.rustunit.com").await;
+        assert_ne!(again.id, created.id);
+        app.redirect(&again.id, None).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_owned_links_page() {
+        let app = app(AppConfig::default());
+
+        let mut ids = Vec::new();
+        for i in 0..4 {
+            ids.push(
+                create(&app, "mine", &format!("https://www.rustunit.com/{i}"))
+                    .await
+                    .id,
+            );
+            create(
+                &app,
+                "theirs",
+                &format!("https://www.rustunit.com/other/{i}"),
+            )
+            .await;
+        }
+        ids.sort();
+
+        let (page, next) = app.owned_links_page("mine", None, Some(3)).await.unwrap();
+        assert_eq!(page.len(), 3);
+        assert_eq!(next.as_ref(), Some(&ids[2]));
+
+        let (page, next) = app
+            .owned_links_page("mine", next.as_deref(), Some(3))
+            .await
+            .unwrap();
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].id, ids[3]);
+        assert_eq!(next, None);
+    }
+
+    #[tokio::test]
+    async fn test_dedupe_by_content() {
+        let destination = axum::Router::new()
+            .route("/a", axum::routing::get(|| async { "same" }))
+            .route("/b", axum::routing::get(|| async { "same" }))
+            .route("/c", axum::routing::get(|| async { "other" }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, destination).await });
+
+        let app = app(AppConfig {
+            dedupe_by_content: true,
+            ..Default::default()
+        });
+
+        let first = create(&app, "key", &format!("http://{addr}/a")).await;
+        let mirror = create(&app, "key", &format!("http://{addr}/b")).await;
+        assert_eq!(mirror.id, first.id);
+        assert_eq!(mirror.original_url, format!("http://{addr}/a"));
+
+        let other = create(&app, "key", &format!("http://{addr}/c")).await;
+        assert_ne!(other.id, first.id);
+
+        // unreachable destinations are created without a hash
+        create(&app, "key", "http://127.0.0.1:1/").await;
+    }
+
+    #[tokio::test]
+    async fn test_dedupe_by_content_cross_host_mirror() {
+        let destination =
+            axum::Router::new().route("/login", axum::routing::get(|| async { "login" }));
+        // reachable as 127.0.0.1 and as 127.0.0.2, two hosts serving the same body
+        let listener = tokio::net::TcpListener::bind("0.0.0.0:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move { axum::serve(listener, destination).await });
+
+        let app = app(AppConfig {
+            dedupe_by_content: true,
+            ..Default::default()
+        });
+
+        let mirror = create(&app, "attacker", &format!("http://127.0.0.2:{port}/login")).await;
+        let real = create(&app, "key", &format!("http://127.0.0.1:{port}/login")).await;
+        assert_ne!(real.id, mirror.id);
+        assert_eq!(real.original_url, format!("http://127.0.0.1:{port}/login"));
+    }
+
+    #[tokio::test]
+    async fn test_dedupe_by_content_namespace_by_key() {
+        let destination = axum::Router::new()
+            .route("/a", axum::routing::get(|| async { "same" }))
+            .route("/b", axum::routing::get(|| async { "same" }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, destination).await });
+
+        let app = app(AppConfig {
+            dedupe_by_content: true,
+            namespace_by_key: true,
+            ..Default::default()
+        });
+
+        let first = create(&app, "tenant-a", &format!("http://{addr}/a")).await;
+        let other = create(&app, "tenant-b", &format!("http://{addr}/b")).await;
+        assert_ne!(other.id, first.id);
+        assert_eq!(other.original_url, format!("http://{addr}/b"));
+
+        let mirror = create(&app, "tenant-a", &format!("http://{addr}/b")).await;
+        assert_eq!(mirror.id, first.id);
+    }
+
+    #[tokio::test]
+    async fn test_verify_destination() {
+        let destination = axum::Router::new()
+            .route("/ok", axum::routing::get(|| async { "ok" }))
+            .route(
+                "/moved",
+                axum::routing::get(|| async { axum::response::Redirect::temporary("/gone") }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, destination).await });
+
+        let app = app(AppConfig {
+            verify_destination: true,
+            ..Default::default()
+        });
+        let create = |url: String| {
+            app.create_link(
+                String::from("key"),
+                LinkSource::Api,
+                CreateLinkRequest {
+                    url,
+                    ..Default::default()
+                },
+                false,
+                None,
+            )
+        };
+
+        let created = create(format!("http://{addr}/ok")).await.unwrap();
+        create(format!("http://{addr}/moved")).await.unwrap();
+
+        let err = create(format!("http://{addr}/missing")).await.unwrap_err();
+        assert_eq!(err.to_string(), "Destination responded with 404 Not Found");
+
+        let err = app
+            .update_link(
+                "key",
+                &created.id,
+                UpdateLinkRequest {
+                    url: format!("http://{addr}/missing"),
+                },
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Destination responded with 404 Not Found");
+
+        // nothing listens on port 1
+        let err = create(String::from("http://127.0.0.1:1/"))
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Destination is unreachable");
+    }
+
+    #[tokio::test]
+    async fn test_title_round_trip() {
+        let app = app(AppConfig::default());
+
+        let created = app
+            .create_link(
+                String::from("key"),
+                LinkSource::Api,
+                CreateLinkRequest {
+                    url: String::from("https://www.rustunit.com"),
+                    title: Some(String::from("Rustunit")),
+                    ..Default::default()
+                },
+                false,
+                None,
+            )
+            .await
+            .unwrap();
+
+        let details = app.link_details(&created.id).await.unwrap().unwrap();
+        assert_eq!(details.title.as_deref(), Some("Rustunit"));
+
+        let (page, _) = app.owned_links_page("key", None, None).await.unwrap();
+        assert_eq!(page[0].title.as_deref(), Some("Rustunit"));
+
+        let err = app
+            .create_link(
+                String::from("key"),
+                LinkSource::Api,
+                CreateLinkRequest {
+                    url: String::from("https://www.rustunit.com/long"),
+                    title: Some("a".repeat(MAX_TITLE_LENGTH + 1)),
+                    ..Default::default()
+                },
+                false,
+                None,
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(LinkError::Validation(violations)) if violations == &["title_too_long"]
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_id_prefix() {
+        let app = app(AppConfig::default());
+        let request = |id_prefix| CreateLinkRequest {
+            url: String::from("https://www.rustunit.com/blog"),
+            id_prefix,
+            ..Default::default()
+        };
+
+        let plain = app
+            .create_link(
+                String::from("key"),
+                LinkSource::Api,
+                request(None),
+                false,
+                None,
+            )
+            .await
+            .unwrap();
+        let prefixed = app
+            .create_link(
+                String::from("key"),
+                LinkSource::Api,
+                request(Some('b')),
+                false,
+                None,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(prefixed.id, format!("b{}", plain.id));
+        assert_eq!(
+            app.resolve(&prefixed.id, None).await.unwrap().url,
+            "https://www.rustunit.com/blog"
+        );
+        let err = app
+            .resolve(&format!("d{}", plain.id), None)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LinkError>(),
+            Some(LinkError::NotFound)
+        ));
+
+        let err = app
             .create_link(
-                key.clone(),
+                String::from("key"),
+                LinkSource::Api,
+                request(Some('B')),
+                false,
+                None,
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(LinkError::Validation(violations)) if violations == &["invalid_id_prefix"]
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_create_with_alias() {
+        let app = app(AppConfig::default());
+        let request = |url: &str, alias: &str| CreateLinkRequest {
+            url: url.to_string(),
+            alias: Some(alias.to_string()),
+            ..Default::default()
+        };
+
+        let created = app
+            .create_link(
+                String::from("key"),
+                LinkSource::Api,
+                request("https://www.rustunit.com/launch", "launch"),
+                false,
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(created.id, "launch");
+        assert_eq!(
+            app.resolve("launch", None).await.unwrap().url,
+            "https://www.rustunit.com/launch"
+        );
+
+        let err = app
+            .create_link(
+                String::from("key"),
+                LinkSource::Api,
+                request("https://www.rustunit.com/other", "launch"),
+                false,
+                None,
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LinkError>(),
+            Some(LinkError::IdTaken)
+        ));
+
+        for alias in ["admin", "Launch"] {
+            assert!(
+                app.create_link(
+                    String::from("key"),
+                    LinkSource::Api,
+                    request("https://www.rustunit.com/other", alias),
+                    false,
+                    None,
+                )
+                .await
+                .is_err(),
+                "{alias}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_case_insensitive_aliases() {
+        let db = Arc::new(MemoryDb::default());
+        // created before aliases were case-insensitive
+        db.create(&CreateLink {
+            id: String::from("OldLink"),
+            url: String::from("https://www.rustunit.com/old"),
+            key: String::from("key"),
+            redirect_status: TEMPORARY_REDIRECT,
+            source: String::from("api"),
+            expires_at: None,
+            password_hash: None,
+            public: false,
+            title: None,
+            reserved: false,
+            open_graph: OpenGraphTags::default(),
+            creator_ip: None,
+            max_clicks: None,
+            note: None,
+            content_hash: None,
+        })
+        .await
+        .unwrap();
+        let app = App::new(
+            AppConfig {
+                case_insensitive_aliases: true,
+                ..Default::default()
+            },
+            db,
+            Arc::new(ClickCounter::new()),
+        );
+        let create = |url: &str, alias: &str| {
+            app.create_link(
+                String::from("key"),
+                LinkSource::Api,
                 CreateLinkRequest {
-                    url: link1.to_string(),
+                    url: url.to_string(),
+                    alias: Some(alias.to_string()),
+                    ..Default::default()
                 },
                 false,
+                None,
             )
+        };
+
+        let created = create("https://www.rustunit.com/launch", "mylink")
             .await
             .unwrap();
-        let res2 = app
-            .create_link(
-                key,
+        assert_eq!(created.id, "mylink");
+
+        for (url, alias) in [
+            ("https://www.rustunit.com/other", "MyLink"),
+            ("https://www.rustunit.com/other", "oldlink"),
+        ] {
+            let err = create(url, alias).await.unwrap_err();
+            assert!(
+                matches!(err.downcast_ref::<LinkError>(), Some(LinkError::IdTaken)),
+                "{alias}"
+            );
+        }
+
+        // the same link again is no conflict
+        let created = create("https://www.rustunit.com/launch", "MyLink")
+            .await
+            .unwrap();
+        assert_eq!(created.id, "mylink");
+        let created = create("https://www.rustunit.com/old", "oldlink")
+            .await
+            .unwrap();
+        assert_eq!(created.id, "OldLink");
+
+        let created = create("https://www.rustunit.com/new", "NewLink")
+            .await
+            .unwrap();
+        assert_eq!(created.id, "newlink");
+    }
+
+    #[tokio::test]
+    async fn test_shared_invalidations() {
+        let db = Arc::new(MemoryDb::default());
+        let invalidations: Arc<dyn Invalidations> =
+            Arc::new(crate::invalidation::LocalInvalidations::default());
+        let replica = || {
+            App::new(
+                AppConfig {
+                    invalidations: Some(Arc::clone(&invalidations)),
+                    ..Default::default()
+                },
+                db.clone(),
+                Arc::new(ClickCounter::new()),
+            )
+        };
+        let (first, second) = (replica(), replica());
+
+        let created = create(&first, "key", "https://www.rustunit.com/old").await;
+        // cached by the replica that did not change it
+        first.resolve(&created.id, None).await.unwrap();
+
+        second
+            .update_link(
+                "key",
+                &created.id,
+                UpdateLinkRequest {
+                    url: String::from("https://www.rustunit.com/new"),
+                },
+            )
+            .await
+            .unwrap();
+
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while first.cache.get(&created.id).is_some() {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(
+            first.resolve(&created.id, None).await.unwrap().url,
+            "https://www.rustunit.com/new"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_evict_cached() {
+        let db = Arc::new(MemoryDb::default());
+        let invalidations: Arc<dyn Invalidations> =
+            Arc::new(crate::invalidation::LocalInvalidations::default());
+        let replica = || {
+            App::new(
+                AppConfig {
+                    invalidations: Some(Arc::clone(&invalidations)),
+                    ..Default::default()
+                },
+                db.clone(),
+                Arc::new(ClickCounter::new()),
+            )
+        };
+        let (first, second) = (replica(), replica());
+
+        let created = create(&first, "key", "https://www.rustunit.com/old").await;
+        first.resolve(&created.id, None).await.unwrap();
+        second.resolve(&created.id, None).await.unwrap();
+
+        // changed behind the back of both replicas
+        db.update(&created.id, "https://www.rustunit.com/new")
+            .await
+            .unwrap();
+        assert_eq!(
+            second.resolve(&created.id, None).await.unwrap().url,
+            "https://www.rustunit.com/old"
+        );
+
+        assert!(second.evict_cached(&created.id).await);
+        assert!(!second.evict_cached(&created.id).await);
+        assert_eq!(
+            second.resolve(&created.id, None).await.unwrap().url,
+            "https://www.rustunit.com/new"
+        );
+
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while first.cache.get(&created.id).is_some() {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(
+            first.resolve(&created.id, None).await.unwrap().url,
+            "https://www.rustunit.com/new"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dedup_redirect_status() {
+        let app = app(AppConfig::default());
+        let create = |permanent| {
+            app.create_link(
+                String::from("key"),
+                LinkSource::Api,
                 CreateLinkRequest {
-                    url: link2.to_string(),
+                    url: String::from("https://www.rustunit.com"),
+                    permanent,
+                    ..Default::default()
                 },
                 false,
+                None,
             )
+        };
+
+        let temporary = create(false).await.unwrap();
+        let permanent = create(true).await.unwrap();
+        assert_ne!(permanent.id, temporary.id);
+        assert_eq!(
+            app.resolve(&permanent.id, None)
+                .await
+                .unwrap()
+                .redirect_status,
+            PERMANENT_REDIRECT
+        );
+
+        assert_eq!(create(true).await.unwrap().id, permanent.id);
+        assert_eq!(create(false).await.unwrap().id, temporary.id);
+    }
+
+    #[tokio::test]
+    async fn test_import_on_conflict() {
+        let app = app(AppConfig::default());
+        let old = "https://www.rustunit.com/old";
+        let new = "https://www.rustunit.com/new";
+
+        let outcome = app
+            .import_link("key", "launch", old, OnConflict::Fail)
             .await
             .unwrap();
+        assert_eq!(outcome, ImportOutcome::Imported);
+        // cached before the conflicting imports
+        app.resolve("launch", None).await.unwrap();
 
-        assert_ne!(res1.id, res2.id);
+        let err = app
+            .import_link("key", "launch", new, OnConflict::Fail)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LinkError>(),
+            Some(LinkError::IdTaken)
+        ));
+
+        let outcome = app
+            .import_link("key", "launch", new, OnConflict::Skip)
+            .await
+            .unwrap();
+        assert_eq!(outcome, ImportOutcome::Skipped);
+        assert_eq!(app.resolve("launch", None).await.unwrap().url, old);
+
+        // only the owner may overwrite
+        let err = app
+            .import_link("other", "launch", new, OnConflict::Overwrite)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LinkError>(),
+            Some(LinkError::IdTaken)
+        ));
+
+        let outcome = app
+            .import_link("key", "launch", new, OnConflict::Overwrite)
+            .await
+            .unwrap();
+        assert_eq!(outcome, ImportOutcome::Overwritten);
+        assert_eq!(app.resolve("launch", None).await.unwrap().url, new);
+
+        let outcome = app
+            .import_link("key", "launch", new, OnConflict::Overwrite)
+            .await
+            .unwrap();
+        assert_eq!(outcome, ImportOutcome::Skipped);
+        assert_eq!(app.db.count().await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_tenant() {
+        let app = app(AppConfig {
+            metrics_tenants: HashSet::from([String::from("acme"), String::from("globex")]),
+            ..Default::default()
+        });
+
+        let acme = create(&app, "acme_secret", "https://www.rustunit.com/acme").await;
+        create(&app, "globex_secret", "https://www.rustunit.com/globex").await;
+        create(&app, "initech_secret", "https://www.rustunit.com/initech").await;
+        app.redirect(&acme.id, None).await.unwrap();
+
+        let metrics = app.render_metrics().unwrap();
+        assert!(metrics.contains(r#"links_created_total{tenant="acme"} 1"#));
+        assert!(metrics.contains(r#"links_created_total{tenant="globex"} 1"#));
+        assert!(metrics.contains(r#"links_created_total{tenant="other"} 1"#));
+        assert!(!metrics.contains("initech"));
+        assert!(metrics.contains(r#"redirects_total{tenant="acme"} 1"#));
+        assert!(!metrics.contains(r#"redirects_total{tenant="globex"}"#));
+    }
+
+    #[tokio::test]
+    async fn test_reserve_link() {
+        let app = app(AppConfig::default());
+
+        app.reserve_link("key", "launch").await.unwrap();
+
+        let err = app.resolve("launch", None).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LinkError>(),
+            Some(LinkError::NotFound)
+        ));
+
+        let err = app.reserve_link("other", "launch").await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LinkError>(),
+            Some(LinkError::IdTaken)
+        ));
+
+        let update = || UpdateLinkRequest {
+            url: String::from("https://www.rustunit.com/launch"),
+        };
+        let err = app
+            .update_link("other", "launch", update())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LinkError>(),
+            Some(LinkError::NotOwner)
+        ));
+
+        app.update_link("key", "launch", update()).await.unwrap();
+        assert_eq!(
+            app.resolve("launch", None).await.unwrap().url,
+            "https://www.rustunit.com/launch"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dedup_concurrent_creates_expiring() {
+        const REQUESTS: usize = 10;
+
+        let app = app(AppConfig {
+            dedup_concurrent_creates: true,
+            ..Default::default()
+        });
+
+        let barrier = Arc::new(tokio::sync::Barrier::new(REQUESTS));
+        let requests: Vec<_> = (0..REQUESTS)
+            .map(|i| {
+                let app = Arc::clone(&app);
+                let barrier = Arc::clone(&barrier);
+                tokio::spawn(async move {
+                    barrier.wait().await;
+                    app.create_link(
+                        String::from("key"),
+                        LinkSource::Api,
+                        CreateLinkRequest {
+                            url: String::from("https://www.rustunit.com"),
+                            ttl_seconds: (i % 2 == 0).then_some(3600),
+                            max_clicks: (i % 2 == 1).then_some(1),
+                            ..Default::default()
+                        },
+                        false,
+                        None,
+                    )
+                    .await
+                    .unwrap()
+                })
+            })
+            .collect();
+
+        let mut ids = HashSet::new();
+        for request in requests {
+            assert!(ids.insert(request.await.unwrap().id));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_update_link_normalized() {
+        let app = app(AppConfig {
+            strip_fragments: true,
+            ..Default::default()
+        });
+        let created = create(&app, "key", "https://www.rustunit.com/old").await;
+
+        let updated = app
+            .update_link(
+                "key",
+                &created.id,
+                UpdateLinkRequest {
+                    url: String::from("https://www.rustunit.com/new#top"),
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(updated.original_url, "https://www.rustunit.com/new");
+        assert_eq!(
+            app.resolve(&created.id, None).await.unwrap().url,
+            "https://www.rustunit.com/new"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_link_blocked_host() {
+        let app = app(AppConfig::default());
+        let created = create(&app, "key", "https://www.rustunit.com").await;
+        app.block_host("evil.example").await.unwrap();
+
+        let err = app
+            .update_link(
+                "key",
+                &created.id,
+                UpdateLinkRequest {
+                    url: String::from("https://evil.example/phish"),
+                },
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LinkError>(),
+            Some(LinkError::HostBlocked)
+        ));
+        assert_eq!(
+            app.resolve(&created.id, None).await.unwrap().url,
+            "https://www.rustunit.com"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_store_creator_ip() {
+        let ip = IpAddr::from([203, 0, 113, 7]);
+        let create = |app: Arc<App>| async move {
+            let link = app
+                .create_link(
+                    String::from("key"),
+                    LinkSource::Api,
+                    CreateLinkRequest {
+                        url: String::from("https://www.rustunit.com"),
+                        ..Default::default()
+                    },
+                    false,
+                    Some(ip),
+                )
+                .await
+                .unwrap();
+            app.link_details(&link.id).await.unwrap().unwrap()
+        };
+
+        let stored = create(app(AppConfig {
+            store_creator_ip: true,
+            ..Default::default()
+        }))
+        .await;
+        assert_eq!(stored.creator_ip.as_deref(), Some("203.0.113.7"));
+
+        let stored = create(app(AppConfig::default())).await;
+        assert_eq!(stored.creator_ip, None);
+    }
+
+    #[tokio::test]
+    async fn test_create_links() {
+        let app = app(AppConfig::default());
+
+        let link = |url: &str| CreateLinkRequest {
+            url: url.to_string(),
+            ..Default::default()
+        };
+        let outcomes = app
+            .create_links(
+                String::from("key"),
+                vec![
+                    link("https://www.rustunit.com"),
+                    link("not a url"),
+                    link("https://www.rustunit.com/blog"),
+                ],
+                None,
+            )
+            .await
+            .unwrap();
+
+        let results: Vec<_> = outcomes
+            .iter()
+            .map(|outcome| {
+                (
+                    outcome.url.as_str(),
+                    outcome.link.is_some(),
+                    outcome.error.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            results,
+            [
+                ("https://www.rustunit.com", true, None),
+                ("not a url", false, Some("validation_failed")),
+                ("https://www.rustunit.com/blog", true, None),
+            ]
+        );
+
+        let too_many = (0..=MAX_CREATE_BATCH)
+            .map(|i| link(&format!("https://www.rustunit.com/{i}")))
+            .collect();
+        assert!(
+            app.create_links(String::from("key"), too_many, None)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_find_links() {
+        let app = app(AppConfig {
+            strip_fragments: true,
+            ..Default::default()
+        });
+
+        let mine = create(&app, "key", "https://www.rustunit.com/mine").await;
+        create(&app, "other", "https://www.rustunit.com/theirs").await;
+
+        let found = app
+            .find_links(
+                "key",
+                vec![
+                    String::from("https://www.rustunit.com/mine#top"),
+                    String::from("https://www.rustunit.com/theirs"),
+                    String::from("https://www.rustunit.com/unknown"),
+                    String::from("not a url"),
+                ],
+            )
+            .await
+            .unwrap();
+
+        let found: Vec<_> = found
+            .iter()
+            .map(|link| (link.url.as_str(), link.id.as_deref()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("https://www.rustunit.com/mine#top", Some(mine.id.as_str())),
+                ("https://www.rustunit.com/theirs", None),
+                ("https://www.rustunit.com/unknown", None),
+                ("not a url", None),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resolve_many() {
+        let app = app(AppConfig::default());
+
+        let first = create(&app, "key", "https://www.rustunit.com/first").await;
+        let second = create(&app, "other", "https://www.rustunit.com/second").await;
+
+        let resolved = app
+            .resolve_many(&[second.id.clone(), String::from("unknown"), first.id.clone()])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            resolved,
+            [
+                ResolvedLink {
+                    id: second.id,
+                    original_url: String::from("https://www.rustunit.com/second"),
+                },
+                ResolvedLink {
+                    id: first.id,
+                    original_url: String::from("https://www.rustunit.com/first"),
+                },
+            ]
+        );
+        assert_eq!(app.flush_counters().await.unwrap(), 0);
+
+        let too_many = vec![String::from("unknown"); MAX_RESOLVE_BATCH + 1];
+        assert!(app.resolve_many(&too_many).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_require_public_tld() {
+        let list = "// ===BEGIN ICANN DOMAINS===\ncom\nme\n"
+            .parse::<publicsuffix::List>()
+            .unwrap();
+        let app = app(AppConfig {
+            public_suffixes: Some(Arc::new(list)),
+            tld_exempt_hosts: HashSet::from([String::from("intranet")]),
+            ..Default::default()
+        });
+
+        for url in ["http://localhost", "http://wiki.corp", "http://printer"] {
+            let err = app
+                .create_link(
+                    String::from("key"),
+                    LinkSource::Api,
+                    CreateLinkRequest {
+                        url: url.to_string(),
+                        ..Default::default()
+                    },
+                    false,
+                    None,
+                )
+                .await;
+            assert!(err.is_err(), "{url}");
+        }
+
+        create(&app, "key", "https://example.com").await;
+        create(&app, "key", "https://ezli.me/").await;
+        create(&app, "key", "http://intranet/wiki").await;
+        create(&app, "key", "http://203.0.113.7/").await;
+    }
+
+    #[test]
+    fn test_public_tarpit() {
+        let app = app(AppConfig {
+            public_tarpit_threshold: Some(2),
+            public_tarpit_delay_ms: 500,
+            ..Default::default()
+        });
+        let ip = IpAddr::from([203, 0, 113, 7]);
+
+        assert_eq!(app.public_tarpit(ip), None);
+        assert_eq!(app.public_tarpit(ip), None);
+        assert_eq!(app.public_tarpit(ip), Some(Duration::from_millis(500)));
+        assert_eq!(app.public_tarpit(IpAddr::from([203, 0, 113, 8])), None);
+
+        // far more IPs than the link cache holds keep their counts
+        for other in 0..=255 {
+            app.public_tarpit(IpAddr::from([198, 51, 100, other]));
+        }
+        assert_eq!(app.public_tarpit(ip), Some(Duration::from_millis(500)));
+
+        let app = self::app(AppConfig::default());
+        for _ in 0..10 {
+            assert_eq!(app.public_tarpit(ip), None);
+        }
+    }
+
+    #[test]
+    fn test_public_rate_limit_concurrent() {
+        let app = app(AppConfig {
+            public_rate_limit: Some(5),
+            ..Default::default()
+        });
+        let ip = IpAddr::from([203, 0, 113, 7]);
+        let start = std::sync::Barrier::new(50);
+
+        let passed = std::thread::scope(|scope| {
+            let creates: Vec<_> = (0..50)
+                .map(|_| {
+                    scope.spawn(|| {
+                        start.wait();
+                        app.limit_public_rate(ip).is_ok()
+                    })
+                })
+                .collect();
+            creates
+                .into_iter()
+                .map(|create| create.join().unwrap())
+                .filter(|&passed| passed)
+                .count()
+        });
+        assert_eq!(passed, 5);
+
+        // more IPs than the link cache holds don't reset the window
+        for other in 0..=255 {
+            app.limit_public_rate(IpAddr::from([198, 51, 100, other]))
+                .unwrap();
+        }
+        assert!(app.limit_public_rate(ip).is_err());
+    }
+
+    #[test]
+    fn test_public_cooldown_concurrent() {
+        let app = app(AppConfig {
+            public_cooldown_secs: Some(30),
+            ..Default::default()
+        });
+        let ip = IpAddr::from([203, 0, 113, 7]);
+        let start = std::sync::Barrier::new(50);
+
+        let passed = std::thread::scope(|scope| {
+            let creates: Vec<_> = (0..50)
+                .map(|_| {
+                    scope.spawn(|| {
+                        start.wait();
+                        app.public_cooldown(ip).is_ok()
+                    })
+                })
+                .collect();
+            creates
+                .into_iter()
+                .map(|create| create.join().unwrap())
+                .filter(|&passed| passed)
+                .count()
+        });
+        assert_eq!(passed, 1);
+
+        // more IPs than the link cache holds don't end the cooldown
+        for other in 0..=255 {
+            app.public_cooldown(IpAddr::from([198, 51, 100, other]))
+                .unwrap();
+        }
+        assert!(app.public_cooldown(ip).is_err());
+    }
+
+    #[test]
+    fn test_redirect_rate_limit_concurrent() {
+        let app = app(AppConfig {
+            redirect_rate_limit: Some(20),
+            ..Default::default()
+        });
+        let start = std::sync::Barrier::new(50);
+
+        let passed = std::thread::scope(|scope| {
+            let resolves: Vec<_> = (0..50)
+                .map(|_| {
+                    scope.spawn(|| {
+                        start.wait();
+                        app.limit_redirect_rate("hammered").is_ok()
+                    })
+                })
+                .collect();
+            resolves
+                .into_iter()
+                .map(|resolve| resolve.join().unwrap())
+                .filter(|&passed| passed)
+                .count()
+        });
+        assert_eq!(passed, 20);
+
+        // more ids than the link cache holds don't reset the window
+        for other in 0..256 {
+            app.limit_redirect_rate(&other.to_string()).unwrap();
+        }
+        assert!(app.limit_redirect_rate("hammered").is_err());
     }
 }
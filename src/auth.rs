@@ -1,36 +1,285 @@
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
 
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier, password_hash::SaltString};
 use axum::{
     body::Body,
     extract::{Request, State},
-    http::StatusCode,
+    http::{HeaderName, StatusCode, header},
     middleware::Next,
-    response::Response,
+    response::{IntoResponse, Response},
 };
-use tracing::info;
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+const SHA256_PREFIX: &str = "sha256:";
+/// Suffix marking a configured key as exempt from rate limiting.
+const UNLIMITED_SUFFIX: char = '!';
+
+/// A configured key, either in plaintext or as `sha256:<salt>:<hex digest>`
+/// where the digest is `sha256(salt + key)`, so only the hash lives in memory.
+#[derive(Debug)]
+enum StoredKey {
+    Plain(String),
+    Sha256 { salt: String, hash: Vec<u8> },
+}
+
+impl StoredKey {
+    fn parse(entry: &str) -> Option<Self> {
+        let Some(hashed) = entry.strip_prefix(SHA256_PREFIX) else {
+            return Some(Self::Plain(entry.to_string()));
+        };
+
+        let (salt, hash) = hashed.split_once(':')?;
+        let hash = hex::decode(hash).ok().filter(|hash| hash.len() == 32)?;
+
+        Some(Self::Sha256 {
+            salt: salt.to_string(),
+            hash,
+        })
+    }
+
+    fn matches(&self, key: &str) -> bool {
+        match self {
+            Self::Plain(plain) => plain == key,
+            Self::Sha256 { salt, hash } => {
+                let digest = Sha256::new()
+                    .chain_update(salt.as_bytes())
+                    .chain_update(key.as_bytes())
+                    .finalize();
+
+                digest.as_slice() == hash.as_slice()
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ConfiguredKey {
+    key: StoredKey,
+    unlimited: bool,
+    /// Empty for configured keys, set via [`ApiKeys::update`].
+    scopes: Vec<String>,
+}
+
+/// Token bucket per key, refilling `per_minute` requests over a minute.
+/// Buckets are kept by the index of the [`ConfiguredKey`], so no plaintext
+/// of hashed keys is held on to.
+struct RateLimiter {
+    per_minute: u32,
+    buckets: Mutex<HashMap<usize, (f64, Instant)>>,
+}
+
+impl RateLimiter {
+    fn new(per_minute: u32) -> Self {
+        Self {
+            per_minute,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes a token from the bucket of the key at `index`. If it is empty,
+    /// returns the time until the next token is refilled instead.
+    fn try_acquire(&self, index: usize, now: Instant) -> Result<(), Duration> {
+        let capacity = f64::from(self.per_minute);
+        let refill_per_sec = capacity / Duration::from_secs(60).as_secs_f64();
+
+        let mut buckets = self.buckets.lock().unwrap();
+        let (tokens, last) = buckets.entry(index).or_insert((capacity, now));
+
+        let elapsed = now.saturating_duration_since(*last).as_secs_f64();
+        *tokens = (*tokens + elapsed * refill_per_sec).min(capacity);
+        *last = now;
+
+        if *tokens < 1.0 {
+            return Err(Duration::from_secs_f64((1.0 - *tokens) / refill_per_sec));
+        }
+
+        *tokens -= 1.0;
+        Ok(())
+    }
+}
+
+/// Argon2id hash of a link passphrase in the PHC string format, e.g.
+/// `$argon2id$v=19$...`, so the plaintext is never stored. Slow on purpose,
+/// a dumped hash of a short passphrase is costly to crack.
+pub fn hash_password(password: &str) -> Result<String, anyhow::Error> {
+    let mut salt = [0u8; 16];
+    getrandom::fill(&mut salt)?;
+    let salt = SaltString::encode_b64(&salt)?;
+
+    Ok(Argon2::default()
+        .hash_password(password.as_bytes(), &salt)?
+        .to_string())
+}
+
+/// Checks `password` against a hash created by [`hash_password`], or by
+/// earlier versions in the `sha256:<salt>:<hex>` format of hashed keys.
+pub fn verify_password(hash: &str, password: &str) -> bool {
+    if let Ok(parsed) = PasswordHash::new(hash) {
+        return Argon2::default()
+            .verify_password(password.as_bytes(), &parsed)
+            .is_ok();
+    }
+
+    matches!(
+        StoredKey::parse(hash),
+        Some(stored @ StoredKey::Sha256 { .. }) if stored.matches(password)
+    )
+}
+
+/// Why [`require_auth`] refused a request.
+#[derive(Debug, PartialEq)]
+pub enum AuthRejection {
+    Unauthorized,
+    /// Answered with a `Retry-After` header and a JSON body like the other
+    /// tagged errors, e.g. `{"error": "rate_limited", "retry_after_secs": 2}`.
+    RateLimited {
+        retry_after_secs: u64,
+    },
+}
+
+impl IntoResponse for AuthRejection {
+    fn into_response(self) -> Response {
+        match self {
+            Self::Unauthorized => StatusCode::UNAUTHORIZED.into_response(),
+            Self::RateLimited { retry_after_secs } => (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, retry_after_secs.to_string())],
+                axum::Json(serde_json::json!({
+                    "error": "rate_limited",
+                    "retry_after_secs": retry_after_secs,
+                })),
+            )
+                .into_response(),
+        }
+    }
+}
 
 // Store your API keys
 #[derive(Clone)]
 pub struct ApiKeys {
-    keys: Arc<Vec<String>>,
+    /// Shared by all clones, so updates reach the auth middleware.
+    keys: Arc<RwLock<Vec<ConfiguredKey>>>,
+    limiter: Option<Arc<RateLimiter>>,
+    header: HeaderName,
 }
 
 #[derive(Clone, Debug)]
 pub struct AuthenticatedKey(pub String);
 
+/// Scopes of the [`AuthenticatedKey`], reported by `/auth/check` but not
+/// required by any route yet.
+#[derive(Clone, Debug)]
+pub struct KeyScopes(pub Vec<String>);
+
 impl ApiKeys {
+    /// Parses a comma separated list of keys. Keys ending in `!` are exempt
+    /// from the rate limit.
     pub fn new(keys: &str) -> Self {
-        let keys: Vec<String> = keys.split(',').map(|s| s.trim().to_string()).collect();
+        let keys: Vec<ConfiguredKey> = keys
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .filter_map(|entry| {
+                let (entry, unlimited) = match entry.strip_suffix(UNLIMITED_SUFFIX) {
+                    Some(entry) => (entry, true),
+                    None => (entry, false),
+                };
+
+                let key = StoredKey::parse(entry);
+                if key.is_none() {
+                    warn!("ignoring malformed hashed key");
+                }
+                key.map(|key| ConfiguredKey {
+                    key,
+                    unlimited,
+                    scopes: Vec::new(),
+                })
+            })
+            .collect();
 
         info!("keys configured: {}", keys.len());
 
         Self {
-            keys: Arc::new(keys),
+            keys: Arc::new(RwLock::new(keys)),
+            limiter: None,
+            header: header::AUTHORIZATION,
         }
     }
 
-    pub fn is_valid(&self, key: &str) -> bool {
-        self.keys.contains(&key.to_string())
+    /// Reads the key from the `header` instead of `Authorization`, e.g. for
+    /// gateways consuming `Authorization` themselves.
+    pub fn with_header(mut self, header: HeaderName) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Limits every key not marked unlimited to `per_minute` requests.
+    pub fn with_rate_limit(mut self, per_minute: u32) -> Self {
+        self.limiter = Some(Arc::new(RateLimiter::new(per_minute)));
+        self
+    }
+
+    #[cfg(test)]
+    fn is_valid(&self, key: &str) -> bool {
+        self.keys
+            .read()
+            .unwrap()
+            .iter()
+            .any(|configured| configured.key.matches(key))
+    }
+
+    /// Changes the scopes and rate limit exemption of a configured key while
+    /// running, taking effect with its next request. Returns `false` if
+    /// `key` is not configured, keys are never added this way.
+    pub fn update(&self, key: &str, scopes: Option<Vec<String>>, unlimited: Option<bool>) -> bool {
+        let mut keys = self.keys.write().unwrap();
+        let Some(configured) = keys
+            .iter_mut()
+            .find(|configured| configured.key.matches(key))
+        else {
+            return false;
+        };
+
+        if let Some(scopes) = scopes {
+            configured.scopes = scopes;
+        }
+        if let Some(unlimited) = unlimited {
+            configured.unlimited = unlimited;
+        }
+
+        true
+    }
+
+    /// Takes a request of `key` from its rate limit, returning its scopes.
+    fn check(&self, key: &str, now: Instant) -> Result<Vec<String>, AuthRejection> {
+        // keys are never removed, so the index stays the key's
+        let (index, unlimited, scopes) = self
+            .keys
+            .read()
+            .unwrap()
+            .iter()
+            .enumerate()
+            .find(|(_, configured)| configured.key.matches(key))
+            .map(|(index, configured)| (index, configured.unlimited, configured.scopes.clone()))
+            .ok_or(AuthRejection::Unauthorized)?;
+
+        if let Some(limiter) = &self.limiter
+            && !unlimited
+        {
+            limiter
+                .try_acquire(index, now)
+                .map_err(|wait| AuthRejection::RateLimited {
+                    // a client retrying after rounded down seconds would be refused again
+                    retry_after_secs: wait.as_secs_f64().ceil() as u64,
+                })?;
+        }
+
+        Ok(scopes)
     }
 }
 
@@ -38,18 +287,163 @@ pub async fn require_auth(
     State(api_keys): State<ApiKeys>,
     mut request: Request<Body>,
     next: Next,
-) -> Result<Response, StatusCode> {
-    let auth_header = request
+) -> Result<Response, AuthRejection> {
+    let key = request
         .headers()
-        .get("Authorization")
-        .and_then(|v| v.to_str().ok());
+        .get(&api_keys.header)
+        .and_then(|v| v.to_str().ok())
+        .ok_or(AuthRejection::Unauthorized)?
+        .to_string();
+
+    let scopes = api_keys.check(&key, Instant::now())?;
+
+    request.extensions_mut().insert(AuthenticatedKey(key));
+    request.extensions_mut().insert(KeyScopes(scopes));
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // sha256("salt" + "secret-key")
+    const HASHED: &str =
+        "sha256:salt:c9637c250d376259b679afa2a30ab5a3cf90bced965129a2140a60f8f479ad9c";
+
+    #[test]
+    fn test_hashed_key() {
+        let keys = ApiKeys::new(HASHED);
+
+        assert!(keys.is_valid("secret-key"));
+        assert!(!keys.is_valid("wrong-key"));
+        assert!(!keys.is_valid(HASHED));
+    }
+
+    #[test]
+    fn test_plain_and_hashed_keys() {
+        let keys = ApiKeys::new(&format!("plain-key, {HASHED}"));
+
+        assert!(keys.is_valid("plain-key"));
+        assert!(keys.is_valid("secret-key"));
+    }
+
+    #[test]
+    fn test_malformed_hashed_key_is_ignored() {
+        let keys = ApiKeys::new("sha256:salt:not-hex,sha256:no-hash");
+
+        assert!(keys.keys.read().unwrap().is_empty());
+        assert!(!keys.is_valid("sha256:no-hash"));
+    }
+
+    #[test]
+    fn test_password_hash() {
+        let hash = hash_password("secret").unwrap();
+
+        assert!(!hash.contains("secret"));
+        assert_ne!(hash, hash_password("secret").unwrap());
+        assert!(verify_password(&hash, "secret"));
+        assert!(!verify_password(&hash, "wrong"));
+        assert!(!verify_password("secret", "secret"));
+        assert!(hash.starts_with("$argon2id$"));
+
+        // links protected before passphrases were hashed with Argon2
+        assert!(verify_password(HASHED, "secret-key"));
+        assert!(!verify_password(HASHED, "wrong-key"));
+    }
+
+    #[test]
+    fn test_unlimited_key_exceeds_bucket() {
+        let keys = ApiKeys::new(&format!("limited, unlimited!, {HASHED}!")).with_rate_limit(2);
+        let now = Instant::now();
+
+        assert!(keys.check("limited", now).is_ok());
+        assert!(keys.check("limited", now).is_ok());
+        assert_eq!(
+            keys.check("limited", now),
+            Err(AuthRejection::RateLimited {
+                retry_after_secs: 30
+            })
+        );
+
+        for _ in 0..10 {
+            assert!(keys.check("unlimited", now).is_ok());
+            assert!(keys.check("secret-key", now).is_ok());
+        }
+
+        assert_eq!(
+            keys.check("unlimited!", now),
+            Err(AuthRejection::Unauthorized)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_response() {
+        let response = AuthRejection::RateLimited {
+            retry_after_secs: 30,
+        }
+        .into_response();
+
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "30");
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({ "error": "rate_limited", "retry_after_secs": 30 })
+        );
+    }
+
+    #[test]
+    fn test_update_unlimited() {
+        let keys = ApiKeys::new("limited").with_rate_limit(1);
+        let now = Instant::now();
+
+        assert!(keys.check("limited", now).is_ok());
+        assert_eq!(
+            keys.check("limited", now),
+            Err(AuthRejection::RateLimited {
+                retry_after_secs: 60
+            })
+        );
 
-    match auth_header {
-        Some(key) if api_keys.is_valid(key) => {
-            let key = key.to_string();
-            request.extensions_mut().insert(AuthenticatedKey(key));
-            Ok(next.run(request).await)
+        assert!(keys.clone().update("limited", None, Some(true)));
+        assert!(keys.check("limited", now).is_ok());
+
+        assert!(keys.update("limited", Some(vec![String::from("links:write")]), None));
+        assert_eq!(
+            keys.check("limited", now),
+            Ok(vec![String::from("links:write")])
+        );
+
+        assert!(!keys.update("unknown", None, Some(true)));
+        assert!(!keys.is_valid("unknown"));
+    }
+
+    #[test]
+    fn test_bucket_refills() {
+        let keys = ApiKeys::new("limited").with_rate_limit(60);
+        let now = Instant::now();
+
+        for _ in 0..60 {
+            assert!(keys.check("limited", now).is_ok());
         }
-        _ => Err(StatusCode::UNAUTHORIZED),
+        assert_eq!(
+            keys.check("limited", now),
+            Err(AuthRejection::RateLimited {
+                retry_after_secs: 1
+            })
+        );
+
+        let later = now + Duration::from_secs(1);
+        assert!(keys.check("limited", later).is_ok());
+        assert_eq!(
+            keys.check("limited", later),
+            Err(AuthRejection::RateLimited {
+                retry_after_secs: 1
+            })
+        );
     }
 }
@@ -0,0 +1,46 @@
+use anyhow::Context;
+use clap::Command;
+use std::path::Path;
+use toml::Value;
+
+/// Uses the settings of the TOML file at `path` as defaults of `cmd`, so
+/// flags and env vars still take precedence over the file. Keys are the
+/// argument names, e.g. `db_url` or `db-url` for `--db-url`.
+pub fn apply(mut cmd: Command, path: &Path) -> anyhow::Result<Command> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Could not read config file {}", path.display()))?;
+    let table = content
+        .parse::<toml::Table>()
+        .with_context(|| format!("Could not parse config file {}", path.display()))?;
+
+    for (key, value) in table {
+        let id = key.replace('-', "_");
+
+        if id == "config" || !cmd.get_arguments().any(|arg| arg.get_id() == id.as_str()) {
+            anyhow::bail!("Unknown setting '{key}' in config file {}", path.display());
+        }
+
+        let values = match value {
+            Value::Array(items) => items
+                .into_iter()
+                .map(|item| scalar(&key, item))
+                .collect::<anyhow::Result<Vec<_>>>()?,
+            value => vec![scalar(&key, value)?],
+        };
+
+        // a required argument is satisfied by the file
+        cmd = cmd.mut_arg(id, |arg| arg.default_values(values).required(false));
+    }
+
+    Ok(cmd)
+}
+
+fn scalar(key: &str, value: Value) -> anyhow::Result<String> {
+    match value {
+        Value::String(s) => Ok(s),
+        Value::Integer(i) => Ok(i.to_string()),
+        Value::Float(f) => Ok(f.to_string()),
+        Value::Boolean(b) => Ok(b.to_string()),
+        _ => anyhow::bail!("Unsupported value for setting '{key}'"),
+    }
+}
@@ -1,50 +1,72 @@
+use async_trait::async_trait;
 use chrono::{DateTime, Utc};
-use diesel::deserialize::QueryableByName;
-use diesel::sql_types;
-use diesel_async::RunQueryDsl;
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{Mutex, RwLock};
+use tokio::task::JoinHandle;
 use tokio::time::{Duration, interval};
-
-use crate::db_pool::DbPool;
-
-#[derive(QueryableByName)]
-struct BatchUpdateResult {
-    #[diesel(sql_type = sql_types::Integer)]
-    batch_update_clicks: i32,
+use tokio_util::task::TaskTracker;
+
+use crate::{
+    clock::{Clock, SystemClock},
+    db::{DbError, LinksDB},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClickData {
+    pub count: i32,
+    pub first_used: DateTime<Utc>,
+    pub last_used: DateTime<Utc>,
 }
 
-#[derive(Debug, Clone)]
-struct ClickData {
-    count: i32,
-    last_used: DateTime<Utc>,
+/// Where clicks are counted until the flusher writes them to the DB.
+///
+/// The in-memory [`ClickCounter`] keeps counts per replica, a shared backend
+/// lets multiple instances count into the same buckets.
+#[cfg_attr(test, mockall::automock)]
+#[async_trait]
+pub trait ClickStore: Send + Sync {
+    async fn increment(&self, id: &str);
+    /// Takes all counted clicks, resetting the store.
+    async fn drain(&self) -> HashMap<String, ClickData>;
 }
 
 // Shared counter state with last used timestamps
 #[derive(Clone)]
 pub struct ClickCounter {
     counts: Arc<RwLock<HashMap<String, ClickData>>>,
+    clock: Arc<dyn Clock>,
 }
 
 impl ClickCounter {
     pub fn new() -> Self {
+        Self::with_clock(Arc::new(SystemClock))
+    }
+
+    /// Timestamps clicks with `clock` instead of the system time.
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
         Self {
             counts: Arc::new(RwLock::new(HashMap::new())),
+            clock,
         }
     }
+}
 
-    pub async fn increment(&self, id: &str) {
+#[async_trait]
+impl ClickStore for ClickCounter {
+    async fn increment(&self, id: &str) {
+        let now = self.clock.now();
         let mut counts = self.counts.write().await;
         counts
             .entry(id.to_string())
             .and_modify(|data| {
                 data.count += 1;
-                data.last_used = Utc::now();
+                data.last_used = now;
             })
-            .or_insert(ClickData {
+            .or_insert_with(|| ClickData {
                 count: 1,
-                last_used: Utc::now(),
+                first_used: now,
+                last_used: now,
             });
     }
 
@@ -54,49 +76,168 @@ impl ClickCounter {
     }
 }
 
+#[cfg(test)]
+impl ClickCounter {
+    /// Holds the counter lock until the returned guard is dropped.
+    pub async fn lock(&self) -> impl Sized + '_ {
+        self.counts.write().await
+    }
+}
+
+/// Increments running in the background, so callers never wait on the store.
+#[derive(Clone, Default)]
+pub struct PendingClicks {
+    tasks: TaskTracker,
+    settling: Arc<Mutex<()>>,
+}
+
+impl PendingClicks {
+    pub fn increment_detached(&self, store: &Arc<dyn ClickStore>, id: &str) {
+        let store = Arc::clone(store);
+        let id = id.to_string();
+
+        self.tasks.spawn(async move { store.increment(&id).await });
+    }
+
+    /// Waits for the increments still in flight, so none are lost on
+    /// shutdown or on a forced flush.
+    pub async fn settle(&self) {
+        // `reopen` must not race another caller waiting on the tracker
+        let _settling = self.settling.lock().await;
+        self.tasks.close();
+        self.tasks.wait().await;
+        self.tasks.reopen();
+    }
+}
+
+/// Writes all counted clicks to the DB, returning the number of links updated.
+pub async fn flush(store: &dyn ClickStore, db: &dyn LinksDB) -> Result<u64, DbError> {
+    let counts = store.drain().await;
+
+    if counts.is_empty() {
+        return Ok(0);
+    }
+
+    let ids: Vec<String> = counts.keys().cloned().collect();
+    let increments: Vec<i32> = counts.values().map(|d| d.count).collect();
+    let first_used: Vec<DateTime<Utc>> = counts.values().map(|d| d.first_used).collect();
+    let last_used: Vec<DateTime<Utc>> = counts.values().map(|d| d.last_used).collect();
+
+    // one call, so the total, the timestamps and the daily buckets stay in step
+    let rows_updated = db
+        .batch_update_clicks(ids, increments, first_used, last_used)
+        .await?;
+
+    tracing::info!(rows_updated, "flushed link counters");
+
+    Ok(rows_updated)
+}
+
+/// Flushes the `store` every `interval_duration`. A tick arriving while the
+/// previous flush is still running is skipped, so slow flushes never overlap.
 pub async fn start_counter_flusher(
-    counter: Arc<ClickCounter>,
-    db: DbPool,
+    store: Arc<dyn ClickStore>,
+    db: Arc<dyn LinksDB>,
     interval_duration: Duration,
 ) {
     let mut ticker = interval(interval_duration);
+    let mut in_flight: Option<JoinHandle<()>> = None;
 
     tracing::info!("counter flusher started");
 
     loop {
         ticker.tick().await;
 
-        let counts = counter.drain().await;
-
-        if counts.is_empty() {
+        if in_flight.as_ref().is_some_and(|flush| !flush.is_finished()) {
+            tracing::warn!("previous flush still running, skipping tick");
             continue;
         }
 
-        if let Err(e) = flush_counts_to_db(db.clone(), counts).await {
-            tracing::error!("failed to flush click counts: {e}");
-        }
+        let store = Arc::clone(&store);
+        let db = Arc::clone(&db);
+
+        in_flight = Some(tokio::spawn(async move {
+            if let Err(e) = flush(store.as_ref(), db.as_ref()).await {
+                tracing::error!("failed to flush click counts: {e}");
+            }
+        }));
     }
 }
 
-// Much cleaner - just call the stored function
-async fn flush_counts_to_db(
-    db: DbPool,
-    counts: HashMap<String, ClickData>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let ids: Vec<String> = counts.keys().cloned().collect();
-    let increments: Vec<i32> = counts.values().map(|d| d.count).collect();
-    let timestamps: Vec<DateTime<Utc>> = counts.values().map(|d| d.last_used).collect();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{clock::MockClock, db::MockLinksDB};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Store whose drain takes longer than the flush interval.
+    #[derive(Default)]
+    struct SlowStore {
+        running: AtomicUsize,
+        max_running: AtomicUsize,
+        drains: AtomicUsize,
+    }
 
-    let result: BatchUpdateResult = diesel::sql_query("SELECT batch_update_clicks($1, $2, $3)")
-        .bind::<sql_types::Array<sql_types::Text>, _>(ids)
-        .bind::<sql_types::Array<sql_types::Integer>, _>(increments)
-        .bind::<sql_types::Array<sql_types::Timestamptz>, _>(timestamps)
-        .get_result(&mut db.0.get().await?)
-        .await?;
+    #[async_trait]
+    impl ClickStore for SlowStore {
+        async fn increment(&self, _id: &str) {}
 
-    let rows_updated = result.batch_update_clicks;
+        async fn drain(&self) -> HashMap<String, ClickData> {
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_running.fetch_max(running, Ordering::SeqCst);
+            self.drains.fetch_add(1, Ordering::SeqCst);
 
-    tracing::info!(rows_updated, "flushed link counters");
+            tokio::time::sleep(Duration::from_millis(100)).await;
 
-    Ok(())
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            HashMap::new()
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_slow_flush_skips_ticks() {
+        let store = Arc::new(SlowStore::default());
+
+        let flusher = tokio::spawn(start_counter_flusher(
+            store.clone(),
+            Arc::new(MockLinksDB::new()),
+            Duration::from_millis(20),
+        ));
+
+        tokio::time::sleep(Duration::from_millis(350)).await;
+        flusher.abort();
+
+        // 17 ticks passed, but only every 5th or 6th found the previous
+        // flush done
+        assert_eq!(store.max_running.load(Ordering::SeqCst), 1);
+        assert!((3..=4).contains(&store.drains.load(Ordering::SeqCst)));
+    }
+
+    #[tokio::test]
+    async fn test_click_timestamps() {
+        let start = DateTime::parse_from_rfc3339("2026-01-01T00:00:00Z")
+            .unwrap()
+            .to_utc();
+        let clock = Arc::new(MockClock::new(start));
+        let counter = ClickCounter::with_clock(clock.clone());
+
+        counter.increment("a").await;
+        clock.advance(chrono::TimeDelta::minutes(5));
+        counter.increment("a").await;
+        counter.increment("b").await;
+
+        let counts = counter.drain().await;
+        assert_eq!(
+            counts["a"],
+            ClickData {
+                count: 2,
+                first_used: start,
+                last_used: start + chrono::TimeDelta::minutes(5),
+            }
+        );
+        assert_eq!(
+            counts["b"].first_used,
+            start + chrono::TimeDelta::minutes(5)
+        );
+    }
 }
@@ -0,0 +1,557 @@
+use super::{DbError, LinksDB};
+use crate::models::{
+    CreateAuditEntry, CreateDemoRequest, CreateLink, CreateTransaction, FetchLink, LinkDetails,
+    OpenGraphTags, OwnedLink, TopLink, Transaction,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use futures_util::stream::{self, BoxStream, StreamExt};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    ops::Bound,
+    sync::Mutex,
+};
+
+/// A [`LinksDB`] keeping everything in memory, to exercise
+/// [`crate::app::App`] without a Postgres instance. Mirrors the constraints of
+/// the schema, e.g. unique ids and deletes cascading to clicks and payments.
+#[derive(Debug, Default)]
+pub struct MemoryDb {
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    links: BTreeMap<String, StoredLink>,
+    transactions: Vec<(String, Transaction)>,
+    demo_requests: Vec<CreateDemoRequest>,
+    audit_log: Vec<CreateAuditEntry>,
+    daily_clicks: HashMap<(String, NaiveDate), u64>,
+    blocked_hosts: BTreeSet<String>,
+    sequence: i64,
+}
+
+#[derive(Debug)]
+struct StoredLink {
+    link: CreateLink,
+    created_at: DateTime<Utc>,
+    click_count: i32,
+    first_used: Option<DateTime<Utc>>,
+    last_used: DateTime<Utc>,
+    disabled: bool,
+}
+
+impl StoredLink {
+    fn fetch(&self) -> FetchLink {
+        FetchLink {
+            id: self.link.id.clone(),
+            url: self.link.url.clone(),
+            redirect_status: self.link.redirect_status,
+            password_hash: self.link.password_hash.clone(),
+            disabled: self.disabled,
+            reserved: self.link.reserved,
+            click_count: self.click_count,
+            max_clicks: self.link.max_clicks,
+            key: Some(self.link.key.clone()),
+            expires_at: self.link.expires_at,
+        }
+    }
+
+    fn details(&self) -> LinkDetails {
+        LinkDetails {
+            id: self.link.id.clone(),
+            url: self.link.url.clone(),
+            created_at: self.created_at,
+            key: Some(self.link.key.clone()),
+            click_count: self.click_count,
+            last_used: self.last_used,
+            title: self.link.title.clone(),
+            first_used: self.first_used,
+            creator_ip: self.link.creator_ip.clone(),
+            note: self.link.note.clone(),
+        }
+    }
+
+    fn owned(&self) -> OwnedLink {
+        OwnedLink {
+            id: self.link.id.clone(),
+            url: self.link.url.clone(),
+            created_at: self.created_at,
+            click_count: self.click_count,
+            last_used: self.last_used,
+            source: self.link.source.clone(),
+            title: self.link.title.clone(),
+            note: self.link.note.clone(),
+        }
+    }
+}
+
+impl State {
+    fn remove_links(&mut self, remove: impl Fn(&StoredLink) -> bool) -> Vec<String> {
+        let ids: Vec<String> = self
+            .links
+            .values()
+            .filter(|link| remove(link))
+            .map(|link| link.link.id.clone())
+            .collect();
+
+        for id in &ids {
+            self.links.remove(id);
+        }
+        self.transactions
+            .retain(|(link_id, _)| !ids.contains(link_id));
+        self.daily_clicks
+            .retain(|(link_id, _), _| !ids.contains(link_id));
+
+        ids
+    }
+}
+
+#[async_trait]
+impl LinksDB for MemoryDb {
+    async fn create_transaction(&self, tx: &CreateTransaction) -> Result<(), DbError> {
+        let mut state = self.state.lock().unwrap();
+
+        if !state.links.contains_key(&tx.link_id) {
+            return Err(DbError::General(format!("unknown link '{}'", tx.link_id)));
+        }
+
+        if state
+            .transactions
+            .iter()
+            .any(|(_, t)| t.network == tx.network && t.tx_hash == tx.tx_hash)
+        {
+            return Err(DbError::DuplicateId);
+        }
+
+        state.transactions.push((
+            tx.link_id.clone(),
+            Transaction {
+                network: tx.network.clone(),
+                tx_hash: tx.tx_hash.clone(),
+                amount: tx.amount.clone(),
+                payer: tx.payer.clone(),
+            },
+        ));
+
+        Ok(())
+    }
+
+    async fn transactions_for(&self, link_id: &str) -> Result<Vec<Transaction>, DbError> {
+        let state = self.state.lock().unwrap();
+
+        let mut transactions: Vec<Transaction> = state
+            .transactions
+            .iter()
+            .filter(|(id, _)| id == link_id)
+            .map(|(_, tx)| tx.clone())
+            .collect();
+        transactions.sort_by(|a, b| (&a.network, &a.tx_hash).cmp(&(&b.network, &b.tx_hash)));
+
+        Ok(transactions)
+    }
+
+    async fn create(&self, link: &CreateLink) -> Result<CreateLink, DbError> {
+        let mut state = self.state.lock().unwrap();
+
+        if state.links.contains_key(&link.id) {
+            return Err(DbError::DuplicateId);
+        }
+
+        let now = Utc::now();
+        state.links.insert(
+            link.id.clone(),
+            StoredLink {
+                link: link.clone(),
+                created_at: now,
+                click_count: 0,
+                first_used: None,
+                last_used: now,
+                disabled: false,
+            },
+        );
+
+        Ok(link.clone())
+    }
+
+    async fn create_or_skip(&self, link: &CreateLink) -> Result<bool, DbError> {
+        match self.create(link).await {
+            Ok(_) => Ok(true),
+            Err(DbError::DuplicateId) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn create_or_overwrite(&self, link: &CreateLink) -> Result<bool, DbError> {
+        if self.create_or_skip(link).await? {
+            return Ok(true);
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let Some(stored) = state
+            .links
+            .get_mut(&link.id)
+            .filter(|stored| stored.link.key == link.key && stored.link.url != link.url)
+        else {
+            return Ok(false);
+        };
+        stored.link.url = link.url.clone();
+        stored.link.reserved = false;
+        stored.link.content_hash = None;
+
+        Ok(true)
+    }
+
+    async fn create_demo_request(&self, request: &CreateDemoRequest) -> Result<(), DbError> {
+        self.state
+            .lock()
+            .unwrap()
+            .demo_requests
+            .push(request.clone());
+
+        Ok(())
+    }
+
+    async fn create_audit_entry(&self, entry: &CreateAuditEntry) -> Result<(), DbError> {
+        self.state.lock().unwrap().audit_log.push(entry.clone());
+
+        Ok(())
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<FetchLink>, DbError> {
+        let state = self.state.lock().unwrap();
+
+        Ok(state.links.get(id).map(StoredLink::fetch))
+    }
+
+    async fn get_case_insensitive(&self, id: &str) -> Result<Option<FetchLink>, DbError> {
+        let state = self.state.lock().unwrap();
+
+        Ok(state
+            .links
+            .values()
+            .filter(|stored| stored.link.id.to_ascii_lowercase() == id)
+            .min_by_key(|stored| stored.created_at)
+            .map(StoredLink::fetch))
+    }
+
+    async fn case_duplicate_ids(&self) -> Result<Vec<String>, DbError> {
+        let state = self.state.lock().unwrap();
+
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+        for id in state.links.keys() {
+            *counts.entry(id.to_ascii_lowercase()).or_default() += 1;
+        }
+
+        Ok(counts
+            .into_iter()
+            .filter(|(_, count)| *count > 1)
+            .map(|(id, _)| id)
+            .collect())
+    }
+
+    async fn get_by_content_hash(&self, content_hash: &str) -> Result<Vec<FetchLink>, DbError> {
+        let state = self.state.lock().unwrap();
+
+        let mut found: Vec<&StoredLink> = state
+            .links
+            .values()
+            .filter(|stored| stored.link.content_hash.as_deref() == Some(content_hash))
+            .collect();
+        found.sort_by_key(|stored| stored.created_at);
+
+        Ok(found.into_iter().map(StoredLink::fetch).collect())
+    }
+
+    async fn update(&self, id: &str, url: &str) -> Result<bool, DbError> {
+        let mut state = self.state.lock().unwrap();
+
+        Ok(state
+            .links
+            .get_mut(id)
+            .map(|stored| {
+                stored.link.url = url.to_string();
+                stored.link.reserved = false;
+                stored.link.content_hash = None;
+            })
+            .is_some())
+    }
+
+    async fn set_disabled(&self, id: &str, disabled: bool) -> Result<bool, DbError> {
+        let mut state = self.state.lock().unwrap();
+
+        Ok(state
+            .links
+            .get_mut(id)
+            .map(|stored| stored.disabled = disabled)
+            .is_some())
+    }
+
+    async fn set_title(&self, id: &str, title: &str) -> Result<bool, DbError> {
+        let mut state = self.state.lock().unwrap();
+
+        Ok(state
+            .links
+            .get_mut(id)
+            .map(|stored| stored.link.title = Some(title.to_string()))
+            .is_some())
+    }
+
+    async fn get_details(&self, id: &str) -> Result<Option<LinkDetails>, DbError> {
+        Ok(self
+            .state
+            .lock()
+            .unwrap()
+            .links
+            .get(id)
+            .map(StoredLink::details))
+    }
+
+    async fn open_graph(&self, id: &str) -> Result<Option<OpenGraphTags>, DbError> {
+        Ok(self
+            .state
+            .lock()
+            .unwrap()
+            .links
+            .get(id)
+            .map(|stored| stored.link.open_graph.clone()))
+    }
+
+    async fn count(&self) -> Result<i64, DbError> {
+        Ok(self.state.lock().unwrap().links.len() as i64)
+    }
+
+    async fn count_by_source(&self) -> Result<Vec<(String, i64)>, DbError> {
+        let state = self.state.lock().unwrap();
+
+        let mut counts: BTreeMap<String, i64> = BTreeMap::new();
+        for stored in state.links.values() {
+            *counts.entry(stored.link.source.clone()).or_default() += 1;
+        }
+
+        Ok(counts.into_iter().collect())
+    }
+
+    async fn batch_update_clicks(
+        &self,
+        ids: Vec<String>,
+        increments: Vec<i32>,
+        first_used: Vec<DateTime<Utc>>,
+        last_used: Vec<DateTime<Utc>>,
+    ) -> Result<u64, DbError> {
+        if ids.len() != increments.len()
+            || ids.len() != first_used.len()
+            || ids.len() != last_used.len()
+        {
+            return Err(DbError::General(String::from("Array length mismatch")));
+        }
+
+        if increments.iter().any(|inc| *inc < 0) {
+            return Err(DbError::General(String::from(
+                "Negative increments not allowed",
+            )));
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let mut updated = 0;
+
+        for (((id, inc), first), ts) in ids
+            .into_iter()
+            .zip(increments)
+            .zip(first_used)
+            .zip(last_used)
+        {
+            let Some(stored) = state.links.get_mut(&id) else {
+                continue;
+            };
+
+            stored.click_count += inc;
+            stored.first_used.get_or_insert(first);
+            stored.last_used = stored.last_used.max(ts);
+            updated += 1;
+
+            *state.daily_clicks.entry((id, ts.date_naive())).or_default() += inc as u64;
+        }
+
+        Ok(updated)
+    }
+
+    async fn increment_click(&self, id: &str) -> Result<(), DbError> {
+        let now = Utc::now();
+        self.batch_update_clicks(vec![id.to_string()], vec![1], vec![now], vec![now])
+            .await?;
+
+        Ok(())
+    }
+
+    async fn clicks_in_window(&self, id: &str, since: DateTime<Utc>) -> Result<u64, DbError> {
+        let state = self.state.lock().unwrap();
+
+        Ok(state
+            .daily_clicks
+            .iter()
+            .filter(|((link_id, day), _)| link_id == id && *day >= since.date_naive())
+            .map(|(_, clicks)| clicks)
+            .sum())
+    }
+
+    async fn daily_clicks(
+        &self,
+        id: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<(NaiveDate, u64)>, DbError> {
+        let state = self.state.lock().unwrap();
+
+        let mut days: Vec<(NaiveDate, u64)> = state
+            .daily_clicks
+            .iter()
+            .filter(|((link_id, day), _)| link_id == id && (from..=to).contains(day))
+            .map(|((_, day), clicks)| (*day, *clicks))
+            .collect();
+        days.sort();
+
+        Ok(days)
+    }
+
+    async fn top_links(&self, limit: i64, now: DateTime<Utc>) -> Result<Vec<TopLink>, DbError> {
+        let state = self.state.lock().unwrap();
+
+        let mut links: Vec<&StoredLink> = state
+            .links
+            .values()
+            .filter(|stored| stored.link.public && !stored.disabled)
+            .filter(|stored| stored.link.password_hash.is_none())
+            .filter(|stored| stored.link.expires_at.is_none_or(|at| at > now))
+            .collect();
+        // ties keep the id order of the map
+        links.sort_by_key(|stored| std::cmp::Reverse(stored.click_count));
+
+        Ok(links
+            .into_iter()
+            .take(usize::try_from(limit).unwrap_or_default())
+            .map(|stored| TopLink {
+                id: stored.link.id.clone(),
+                url: stored.link.url.clone(),
+                click_count: stored.click_count,
+            })
+            .collect())
+    }
+
+    async fn find_owned_by_urls(
+        &self,
+        urls: &[String],
+        key: &str,
+    ) -> Result<Vec<(String, String)>, DbError> {
+        let state = self.state.lock().unwrap();
+
+        let mut found: Vec<&StoredLink> = state
+            .links
+            .values()
+            .filter(|stored| {
+                urls.contains(&stored.link.url) && stored.link.key == key && !stored.link.reserved
+            })
+            .collect();
+        found.sort_by_key(|stored| (stored.created_at, &stored.link.id));
+
+        Ok(found
+            .into_iter()
+            .map(|stored| (stored.link.url.clone(), stored.link.id.clone()))
+            .collect())
+    }
+
+    async fn next_sequence(&self) -> Result<i64, DbError> {
+        let mut state = self.state.lock().unwrap();
+        state.sequence += 1;
+
+        Ok(state.sequence)
+    }
+
+    async fn delete_owned(&self, ids: &[String], key: &str) -> Result<Vec<String>, DbError> {
+        let mut state = self.state.lock().unwrap();
+
+        Ok(state.remove_links(|stored| ids.contains(&stored.link.id) && stored.link.key == key))
+    }
+
+    async fn purge_expired(&self, now: DateTime<Utc>) -> Result<u64, DbError> {
+        let mut state = self.state.lock().unwrap();
+
+        let purged = state.remove_links(|stored| stored.link.expires_at.is_some_and(|at| at < now));
+
+        Ok(purged.len() as u64)
+    }
+
+    fn stream_all(&self, _page_size: i64) -> BoxStream<'static, Result<LinkDetails, DbError>> {
+        let links: Vec<LinkDetails> = self
+            .state
+            .lock()
+            .unwrap()
+            .links
+            .values()
+            .map(StoredLink::details)
+            .collect();
+
+        stream::iter(links.into_iter().map(Ok)).boxed()
+    }
+
+    fn stream_owned(
+        &self,
+        key: &str,
+        _page_size: i64,
+    ) -> BoxStream<'static, Result<OwnedLink, DbError>> {
+        let links: Vec<OwnedLink> = self
+            .state
+            .lock()
+            .unwrap()
+            .links
+            .values()
+            .filter(|stored| stored.link.key == key)
+            .map(StoredLink::owned)
+            .collect();
+
+        stream::iter(links.into_iter().map(Ok)).boxed()
+    }
+
+    async fn owned_page(
+        &self,
+        key: &str,
+        after: &str,
+        limit: i64,
+    ) -> Result<Vec<OwnedLink>, DbError> {
+        let state = self.state.lock().unwrap();
+
+        Ok(state
+            .links
+            .range::<str, _>((Bound::Excluded(after), Bound::Unbounded))
+            .map(|(_, stored)| stored)
+            .filter(|stored| stored.link.key == key)
+            .take(usize::try_from(limit).unwrap_or_default())
+            .map(StoredLink::owned)
+            .collect())
+    }
+
+    async fn blocked_hosts(&self) -> Result<Vec<String>, DbError> {
+        Ok(self
+            .state
+            .lock()
+            .unwrap()
+            .blocked_hosts
+            .iter()
+            .cloned()
+            .collect())
+    }
+
+    async fn block_host(&self, host: &str) -> Result<(), DbError> {
+        self.state
+            .lock()
+            .unwrap()
+            .blocked_hosts
+            .insert(host.to_string());
+
+        Ok(())
+    }
+
+    async fn unblock_host(&self, host: &str) -> Result<bool, DbError> {
+        Ok(self.state.lock().unwrap().blocked_hosts.remove(host))
+    }
+}
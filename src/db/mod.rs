@@ -1,11 +1,22 @@
-use crate::models::{CreateLink, CreateTransaction, FetchLink};
+use crate::models::{
+    CreateAuditEntry, CreateDemoRequest, CreateLink, CreateTransaction, FetchLink, LinkDetails,
+    OpenGraphTags, OwnedLink, TopLink, Transaction,
+};
 use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
 use diesel::result::DatabaseErrorKind;
+use futures_util::stream::BoxStream;
 use thiserror::Error;
 
+#[cfg(test)]
+mod memory;
 mod postgres;
+mod slow_log;
 
+#[cfg(test)]
+pub use memory::MemoryDb;
 pub use postgres::PostgresDb;
+pub use slow_log::SlowQueryLog;
 
 #[derive(Error, Debug)]
 pub enum DbError {
@@ -36,6 +47,124 @@ impl From<deadpool::managed::PoolError<diesel_async::pooled_connection::PoolErro
 #[async_trait]
 pub trait LinksDB: Send + Sync {
     async fn create_transaction(&self, tx: &CreateTransaction) -> Result<(), DbError>;
+    /// x402 payments made for the link `link_id`.
+    async fn transactions_for(&self, link_id: &str) -> Result<Vec<Transaction>, DbError>;
     async fn create(&self, link: &CreateLink) -> Result<CreateLink, DbError>;
+    /// Stores `link` unless its id exists already, returning whether it was
+    /// stored.
+    async fn create_or_skip(&self, link: &CreateLink) -> Result<bool, DbError>;
+    /// Stores `link`, or points the link with its id to `link.url` if it
+    /// belongs to the same key and leads elsewhere, keeping its stats.
+    /// Returns whether a link was stored or changed.
+    async fn create_or_overwrite(&self, link: &CreateLink) -> Result<bool, DbError>;
+    async fn create_demo_request(&self, request: &CreateDemoRequest) -> Result<(), DbError>;
+    async fn create_audit_entry(&self, entry: &CreateAuditEntry) -> Result<(), DbError>;
     async fn get(&self, id: &str) -> Result<Option<FetchLink>, DbError>;
+    /// The oldest link whose id lowercased is `id`, which must be lowercase
+    /// already, see [`crate::app::AppConfig::case_insensitive_ids`].
+    async fn get_case_insensitive(&self, id: &str) -> Result<Option<FetchLink>, DbError>;
+    /// Ids lowercased that more than one link shares, of which
+    /// [`LinksDB::get_case_insensitive`] only finds the oldest.
+    async fn case_duplicate_ids(&self) -> Result<Vec<String>, DbError>;
+    /// The links whose destination served a body hashing to `content_hash`,
+    /// oldest first, see [`crate::app::AppConfig::dedupe_by_content`].
+    async fn get_by_content_hash(&self, content_hash: &str) -> Result<Vec<FetchLink>, DbError>;
+    /// Changes the url of the link in place, keeping its stats, which also
+    /// activates a reserved link and forgets its content hash. Returns `false` if no link with `id` exists.
+    async fn update(&self, id: &str, url: &str) -> Result<bool, DbError>;
+    /// Takes the link offline or brings it back. Returns `false` if no link
+    /// with `id` exists.
+    async fn set_disabled(&self, id: &str, disabled: bool) -> Result<bool, DbError>;
+    /// Returns `false` if no link with `id` exists.
+    async fn set_title(&self, id: &str, title: &str) -> Result<bool, DbError>;
+    async fn get_details(&self, id: &str) -> Result<Option<LinkDetails>, DbError>;
+    /// `None` if no link with `id` exists.
+    async fn open_graph(&self, id: &str) -> Result<Option<OpenGraphTags>, DbError>;
+    /// Total number of stored links.
+    async fn count(&self) -> Result<i64, DbError>;
+    /// Number of links per `source`.
+    async fn count_by_source(&self) -> Result<Vec<(String, i64)>, DbError>;
+    /// Adds the click `increments` to the links in `ids` and to the daily
+    /// buckets of `last_used`, sets `first_used` of links never clicked
+    /// before and bumps their `last_used`, all at once. Returns the number
+    /// of links updated.
+    async fn batch_update_clicks(
+        &self,
+        ids: Vec<String>,
+        increments: Vec<i32>,
+        first_used: Vec<DateTime<Utc>>,
+        last_used: Vec<DateTime<Utc>>,
+    ) -> Result<u64, DbError>;
+    /// Counts a single click on link `id` right away, like a
+    /// `batch_update_clicks` of one.
+    async fn increment_click(&self, id: &str) -> Result<(), DbError>;
+    /// Clicks on link `id` since the day of `since` (UTC), from the daily
+    /// buckets `batch_update_clicks` maintains.
+    async fn clicks_in_window(&self, id: &str, since: DateTime<Utc>) -> Result<u64, DbError>;
+    /// The daily buckets of link `id` from `from` to `to` (inclusive),
+    /// ordered by day. Days without clicks have no bucket.
+    async fn daily_clicks(
+        &self,
+        id: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<(NaiveDate, u64)>, DbError>;
+    /// The `limit` most clicked public links not expired at `now`, leaving
+    /// out disabled and password protected ones.
+    async fn top_links(&self, limit: i64, now: DateTime<Utc>) -> Result<Vec<TopLink>, DbError>;
+    /// `(url, id)` of the links created with `key` to one of `urls`, oldest
+    /// first. Reserved links have no destination yet and are left out.
+    async fn find_owned_by_urls(
+        &self,
+        urls: &[String],
+        key: &str,
+    ) -> Result<Vec<(String, String)>, DbError>;
+    /// Next number of the `links_id_seq` sequence, never handed out twice.
+    async fn next_sequence(&self) -> Result<i64, DbError>;
+    /// Deletes the links among `ids` created with `key`, returning the ids
+    /// that were actually deleted.
+    async fn delete_owned(&self, ids: &[String], key: &str) -> Result<Vec<String>, DbError>;
+    /// Deletes links that expired before `now`, returning how many were removed.
+    async fn purge_expired(&self, now: DateTime<Utc>) -> Result<u64, DbError>;
+    /// All links ordered by `id`, fetched lazily `page_size` rows at a time.
+    /// Each page is a query of its own, fetched once the previous one was
+    /// consumed, so exports never hold a connection while the client reads.
+    fn stream_all(&self, page_size: i64) -> BoxStream<'static, Result<LinkDetails, DbError>>;
+    /// Links created with `key` ordered by `id`, fetched like [`LinksDB::stream_all`].
+    fn stream_owned(
+        &self,
+        key: &str,
+        page_size: i64,
+    ) -> BoxStream<'static, Result<OwnedLink, DbError>>;
+    /// Up to `limit` links created with `key` whose id sorts after `after`,
+    /// ordered by `id`.
+    async fn owned_page(
+        &self,
+        key: &str,
+        after: &str,
+        limit: i64,
+    ) -> Result<Vec<OwnedLink>, DbError>;
+    /// All hosts links must not point to.
+    async fn blocked_hosts(&self) -> Result<Vec<String>, DbError>;
+    async fn block_host(&self, host: &str) -> Result<(), DbError>;
+    /// Returns `false` if `host` was not blocked.
+    async fn unblock_host(&self, host: &str) -> Result<bool, DbError>;
+    /// Fails if the backend can not be reached.
+    async fn ping(&self) -> Result<(), DbError> {
+        Ok(())
+    }
+    /// Connection pool usage, if the backend is pooled.
+    fn pool_status(&self) -> Option<deadpool::Status> {
+        None
+    }
+}
+
+#[cfg(test)]
+impl MockLinksDB {
+    /// Mock with an empty blocklist, which every create and redirect consults.
+    pub fn with_empty_blocklist() -> Self {
+        let mut db = Self::new();
+        db.expect_blocked_hosts().returning(|| Ok(Vec::new()));
+        db
+    }
 }
@@ -1,12 +1,41 @@
 use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use diesel::{deserialize::QueryableByName, sql_types};
+use futures_util::{
+    StreamExt, TryStreamExt,
+    stream::{self, BoxStream},
+};
 
 use crate::{
     db::LinksDB,
     db_pool::DbPool,
-    models::{CreateLink, CreateTransaction, FetchLink},
+    models::{
+        CreateAuditEntry, CreateDemoRequest, CreateLink, CreateTransaction, FetchLink, LinkDetails,
+        OpenGraphTags, OwnedLink, TopLink, Transaction,
+    },
     schema,
 };
 
+diesel::define_sql_function!(fn lower(x: sql_types::Text) -> sql_types::Text);
+
+#[derive(QueryableByName)]
+struct BatchUpdateResult {
+    #[diesel(sql_type = sql_types::Integer)]
+    batch_update_clicks: i32,
+}
+
+#[derive(QueryableByName)]
+struct LowerId {
+    #[diesel(sql_type = sql_types::Text)]
+    id: String,
+}
+
+#[derive(QueryableByName)]
+struct NextSequence {
+    #[diesel(sql_type = sql_types::BigInt)]
+    nextval: i64,
+}
+
 #[derive(Clone)]
 pub struct PostgresDb {
     db: DbPool,
@@ -35,6 +64,18 @@ impl LinksDB for PostgresDb {
         Ok(())
     }
 
+    async fn transactions_for(&self, link_id: &str) -> Result<Vec<Transaction>, super::DbError> {
+        use diesel::{ExpressionMethods, QueryDsl, SelectableHelper};
+        use diesel_async::RunQueryDsl;
+
+        Ok(schema::x402::table
+            .filter(schema::x402::link_id.eq(link_id))
+            .order((schema::x402::network.asc(), schema::x402::tx_hash.asc()))
+            .select(Transaction::as_select())
+            .load(&mut self.db.0.get().await?)
+            .await?)
+    }
+
     async fn create(&self, link: &CreateLink) -> Result<CreateLink, super::DbError> {
         use diesel_async::RunQueryDsl;
 
@@ -50,6 +91,66 @@ impl LinksDB for PostgresDb {
         Ok(link.clone())
     }
 
+    async fn create_or_skip(&self, link: &CreateLink) -> Result<bool, super::DbError> {
+        use diesel_async::RunQueryDsl;
+
+        let affected = diesel::insert_into(schema::links::table)
+            .values(link)
+            .on_conflict_do_nothing()
+            .execute(&mut self.db.0.get().await?)
+            .await?;
+
+        Ok(affected == 1)
+    }
+
+    async fn create_or_overwrite(&self, link: &CreateLink) -> Result<bool, super::DbError> {
+        use diesel::{ExpressionMethods, QueryDsl};
+        use diesel_async::RunQueryDsl;
+
+        if self.create_or_skip(link).await? {
+            return Ok(true);
+        }
+
+        // links of other keys and unchanged ones are left alone
+        let affected = diesel::update(
+            schema::links::table
+                .filter(schema::links::id.eq(&link.id))
+                .filter(schema::links::key.eq(&link.key))
+                .filter(schema::links::url.ne(&link.url)),
+        )
+        .set((
+            schema::links::url.eq(&link.url),
+            schema::links::reserved.eq(false),
+            schema::links::content_hash.eq(None::<String>),
+        ))
+        .execute(&mut self.db.0.get().await?)
+        .await?;
+
+        Ok(affected == 1)
+    }
+
+    async fn create_demo_request(&self, request: &CreateDemoRequest) -> Result<(), super::DbError> {
+        use diesel_async::RunQueryDsl;
+
+        diesel::insert_into(schema::demo_requests::table)
+            .values(request)
+            .execute(&mut self.db.0.get().await?)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn create_audit_entry(&self, entry: &CreateAuditEntry) -> Result<(), super::DbError> {
+        use diesel_async::RunQueryDsl;
+
+        diesel::insert_into(schema::audit_log::table)
+            .values(entry)
+            .execute(&mut self.db.0.get().await?)
+            .await?;
+
+        Ok(())
+    }
+
     async fn get(&self, id: &str) -> Result<Option<FetchLink>, super::DbError> {
         use diesel::{ExpressionMethods, OptionalExtension, QueryDsl, SelectableHelper};
         use diesel_async::RunQueryDsl;
@@ -61,4 +162,430 @@ impl LinksDB for PostgresDb {
             .await
             .optional()?)
     }
+
+    async fn get_case_insensitive(&self, id: &str) -> Result<Option<FetchLink>, super::DbError> {
+        use diesel::{ExpressionMethods, OptionalExtension, QueryDsl, SelectableHelper};
+        use diesel_async::RunQueryDsl;
+
+        // served by the `links_lower_id_idx` functional index
+        Ok(schema::links::table
+            .filter(lower(schema::links::id).eq(id))
+            .order(schema::links::created_at)
+            .select(FetchLink::as_select())
+            .first(&mut self.db.0.get().await?)
+            .await
+            .optional()?)
+    }
+
+    async fn case_duplicate_ids(&self) -> Result<Vec<String>, super::DbError> {
+        use diesel_async::RunQueryDsl;
+
+        let ids: Vec<LowerId> = diesel::sql_query(
+            "SELECT lower(id) AS id FROM links GROUP BY lower(id) HAVING count(*) > 1 ORDER BY 1",
+        )
+        .load(&mut self.db.0.get().await?)
+        .await?;
+
+        Ok(ids.into_iter().map(|row| row.id).collect())
+    }
+
+    async fn get_by_content_hash(
+        &self,
+        content_hash: &str,
+    ) -> Result<Vec<FetchLink>, super::DbError> {
+        use diesel::{ExpressionMethods, QueryDsl, SelectableHelper};
+        use diesel_async::RunQueryDsl;
+
+        Ok(schema::links::table
+            .filter(schema::links::content_hash.eq(content_hash))
+            .order(schema::links::created_at)
+            .select(FetchLink::as_select())
+            .load(&mut self.db.0.get().await?)
+            .await?)
+    }
+
+    async fn update(&self, id: &str, url: &str) -> Result<bool, super::DbError> {
+        use diesel::{ExpressionMethods, QueryDsl};
+        use diesel_async::RunQueryDsl;
+
+        let affected = diesel::update(schema::links::table.filter(schema::links::id.eq(id)))
+            .set((
+                schema::links::url.eq(url),
+                schema::links::reserved.eq(false),
+                // the hash was of the old destination
+                schema::links::content_hash.eq(None::<String>),
+            ))
+            .execute(&mut self.db.0.get().await?)
+            .await?;
+
+        Ok(affected == 1)
+    }
+
+    async fn set_disabled(&self, id: &str, disabled: bool) -> Result<bool, super::DbError> {
+        use diesel::{ExpressionMethods, QueryDsl};
+        use diesel_async::RunQueryDsl;
+
+        let affected = diesel::update(schema::links::table.filter(schema::links::id.eq(id)))
+            .set(schema::links::disabled.eq(disabled))
+            .execute(&mut self.db.0.get().await?)
+            .await?;
+
+        Ok(affected == 1)
+    }
+
+    async fn set_title(&self, id: &str, title: &str) -> Result<bool, super::DbError> {
+        use diesel::{ExpressionMethods, QueryDsl};
+        use diesel_async::RunQueryDsl;
+
+        let affected = diesel::update(schema::links::table.filter(schema::links::id.eq(id)))
+            .set(schema::links::title.eq(title))
+            .execute(&mut self.db.0.get().await?)
+            .await?;
+
+        Ok(affected == 1)
+    }
+
+    async fn get_details(&self, id: &str) -> Result<Option<LinkDetails>, super::DbError> {
+        use diesel::{ExpressionMethods, OptionalExtension, QueryDsl, SelectableHelper};
+        use diesel_async::RunQueryDsl;
+
+        Ok(schema::links::table
+            .filter(schema::links::id.eq(id))
+            .select(LinkDetails::as_select())
+            .first(&mut self.db.0.get().await?)
+            .await
+            .optional()?)
+    }
+
+    async fn open_graph(&self, id: &str) -> Result<Option<OpenGraphTags>, super::DbError> {
+        use diesel::{ExpressionMethods, OptionalExtension, QueryDsl, SelectableHelper};
+        use diesel_async::RunQueryDsl;
+
+        Ok(schema::links::table
+            .filter(schema::links::id.eq(id))
+            .select(OpenGraphTags::as_select())
+            .first(&mut self.db.0.get().await?)
+            .await
+            .optional()?)
+    }
+
+    async fn count(&self) -> Result<i64, super::DbError> {
+        use diesel::{QueryDsl, dsl::count_star};
+        use diesel_async::RunQueryDsl;
+
+        Ok(schema::links::table
+            .select(count_star())
+            .first(&mut self.db.0.get().await?)
+            .await?)
+    }
+
+    async fn count_by_source(&self) -> Result<Vec<(String, i64)>, super::DbError> {
+        use diesel::{QueryDsl, dsl::count_star};
+        use diesel_async::RunQueryDsl;
+
+        Ok(schema::links::table
+            .group_by(schema::links::source)
+            .select((schema::links::source, count_star()))
+            .load(&mut self.db.0.get().await?)
+            .await?)
+    }
+
+    async fn batch_update_clicks(
+        &self,
+        ids: Vec<String>,
+        increments: Vec<i32>,
+        first_used: Vec<DateTime<Utc>>,
+        last_used: Vec<DateTime<Utc>>,
+    ) -> Result<u64, super::DbError> {
+        use diesel_async::RunQueryDsl;
+
+        // the stored function updates all links in a single statement
+        let result: BatchUpdateResult =
+            diesel::sql_query("SELECT batch_update_clicks($1, $2, $3, $4)")
+                .bind::<sql_types::Array<sql_types::Text>, _>(ids)
+                .bind::<sql_types::Array<sql_types::Integer>, _>(increments)
+                .bind::<sql_types::Array<sql_types::Timestamptz>, _>(first_used)
+                .bind::<sql_types::Array<sql_types::Timestamptz>, _>(last_used)
+                .get_result(&mut self.db.0.get().await?)
+                .await?;
+
+        Ok(result.batch_update_clicks as u64)
+    }
+
+    async fn increment_click(&self, id: &str) -> Result<(), super::DbError> {
+        let now = Utc::now();
+        self.batch_update_clicks(vec![id.to_string()], vec![1], vec![now], vec![now])
+            .await?;
+
+        Ok(())
+    }
+
+    async fn clicks_in_window(
+        &self,
+        id: &str,
+        since: DateTime<Utc>,
+    ) -> Result<u64, super::DbError> {
+        use diesel::{ExpressionMethods, QueryDsl, dsl};
+        use diesel_async::RunQueryDsl;
+
+        let clicks: Option<i64> = schema::link_daily_clicks::table
+            .filter(schema::link_daily_clicks::link_id.eq(id))
+            .filter(schema::link_daily_clicks::day.ge(since.date_naive()))
+            .select(dsl::sum(schema::link_daily_clicks::clicks))
+            .first(&mut self.db.0.get().await?)
+            .await?;
+
+        Ok(clicks.unwrap_or_default() as u64)
+    }
+
+    async fn daily_clicks(
+        &self,
+        id: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<(NaiveDate, u64)>, super::DbError> {
+        use diesel::{ExpressionMethods, QueryDsl};
+        use diesel_async::RunQueryDsl;
+
+        let days: Vec<(NaiveDate, i32)> = schema::link_daily_clicks::table
+            .filter(schema::link_daily_clicks::link_id.eq(id))
+            .filter(schema::link_daily_clicks::day.between(from, to))
+            .order(schema::link_daily_clicks::day)
+            .select((
+                schema::link_daily_clicks::day,
+                schema::link_daily_clicks::clicks,
+            ))
+            .load(&mut self.db.0.get().await?)
+            .await?;
+
+        Ok(days
+            .into_iter()
+            .map(|(day, clicks)| (day, clicks as u64))
+            .collect())
+    }
+
+    async fn top_links(
+        &self,
+        limit: i64,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<TopLink>, super::DbError> {
+        use diesel::{BoolExpressionMethods, ExpressionMethods, QueryDsl, SelectableHelper};
+        use diesel_async::RunQueryDsl;
+
+        Ok(schema::links::table
+            .filter(schema::links::public)
+            .filter(schema::links::disabled.eq(false))
+            .filter(schema::links::password_hash.is_null())
+            .filter(
+                schema::links::expires_at
+                    .is_null()
+                    .or(schema::links::expires_at.gt(now)),
+            )
+            .order((schema::links::click_count.desc(), schema::links::id))
+            .limit(limit)
+            .select(TopLink::as_select())
+            .load(&mut self.db.0.get().await?)
+            .await?)
+    }
+
+    async fn find_owned_by_urls(
+        &self,
+        urls: &[String],
+        key: &str,
+    ) -> Result<Vec<(String, String)>, super::DbError> {
+        use diesel::{ExpressionMethods, QueryDsl};
+        use diesel_async::RunQueryDsl;
+
+        Ok(schema::links::table
+            .filter(schema::links::url.eq_any(urls))
+            .filter(schema::links::key.eq(key))
+            .filter(schema::links::reserved.eq(false))
+            .order((schema::links::created_at, schema::links::id))
+            .select((schema::links::url, schema::links::id))
+            .load(&mut self.db.0.get().await?)
+            .await?)
+    }
+
+    async fn next_sequence(&self) -> Result<i64, super::DbError> {
+        use diesel_async::RunQueryDsl;
+
+        let result: NextSequence = diesel::sql_query("SELECT nextval('links_id_seq')")
+            .get_result(&mut self.db.0.get().await?)
+            .await?;
+
+        Ok(result.nextval)
+    }
+
+    async fn delete_owned(&self, ids: &[String], key: &str) -> Result<Vec<String>, super::DbError> {
+        use diesel::{ExpressionMethods, QueryDsl};
+        use diesel_async::RunQueryDsl;
+
+        Ok(diesel::delete(
+            schema::links::table
+                .filter(schema::links::id.eq_any(ids))
+                .filter(schema::links::key.eq(key)),
+        )
+        .returning(schema::links::id)
+        .get_results(&mut self.db.0.get().await?)
+        .await?)
+    }
+
+    async fn purge_expired(&self, now: DateTime<Utc>) -> Result<u64, super::DbError> {
+        use diesel::{ExpressionMethods, QueryDsl};
+        use diesel_async::RunQueryDsl;
+
+        // x402 rows referencing the purged links are removed by `ON DELETE CASCADE`
+        let affected =
+            diesel::delete(schema::links::table.filter(schema::links::expires_at.lt(now)))
+                .execute(&mut self.db.0.get().await?)
+                .await?;
+
+        Ok(affected as u64)
+    }
+
+    async fn blocked_hosts(&self) -> Result<Vec<String>, super::DbError> {
+        use diesel::QueryDsl;
+        use diesel_async::RunQueryDsl;
+
+        Ok(schema::blocked_hosts::table
+            .select(schema::blocked_hosts::host)
+            .load(&mut self.db.0.get().await?)
+            .await?)
+    }
+
+    async fn block_host(&self, host: &str) -> Result<(), super::DbError> {
+        use diesel::ExpressionMethods;
+        use diesel_async::RunQueryDsl;
+
+        diesel::insert_into(schema::blocked_hosts::table)
+            .values(schema::blocked_hosts::host.eq(host))
+            .on_conflict_do_nothing()
+            .execute(&mut self.db.0.get().await?)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn unblock_host(&self, host: &str) -> Result<bool, super::DbError> {
+        use diesel::{ExpressionMethods, QueryDsl};
+        use diesel_async::RunQueryDsl;
+
+        let affected = diesel::delete(
+            schema::blocked_hosts::table.filter(schema::blocked_hosts::host.eq(host)),
+        )
+        .execute(&mut self.db.0.get().await?)
+        .await?;
+
+        Ok(affected == 1)
+    }
+
+    fn stream_all(
+        &self,
+        page_size: i64,
+    ) -> BoxStream<'static, Result<LinkDetails, super::DbError>> {
+        use diesel::{ExpressionMethods, QueryDsl, SelectableHelper};
+        use diesel_async::RunQueryDsl;
+
+        let db = self.db.clone();
+
+        // keyset pagination: every page starts after the last id of the previous one
+        stream::try_unfold(Some(String::new()), move |cursor| {
+            let db = db.clone();
+            async move {
+                let Some(cursor) = cursor else {
+                    return Ok(None);
+                };
+
+                let page: Vec<LinkDetails> = schema::links::table
+                    .filter(schema::links::id.gt(cursor))
+                    .order(schema::links::id.asc())
+                    .limit(page_size)
+                    .select(LinkDetails::as_select())
+                    .load(&mut db.0.get().await?)
+                    .await?;
+
+                let next = match page.last() {
+                    Some(last) if page.len() as i64 == page_size => Some(last.id.clone()),
+                    _ => None,
+                };
+
+                Ok::<_, super::DbError>(Some((stream::iter(page.into_iter().map(Ok)), next)))
+            }
+        })
+        .try_flatten()
+        .boxed()
+    }
+
+    async fn owned_page(
+        &self,
+        key: &str,
+        after: &str,
+        limit: i64,
+    ) -> Result<Vec<OwnedLink>, super::DbError> {
+        use diesel::{ExpressionMethods, QueryDsl, SelectableHelper};
+        use diesel_async::RunQueryDsl;
+
+        Ok(schema::links::table
+            .filter(schema::links::key.eq(key))
+            .filter(schema::links::id.gt(after))
+            .order(schema::links::id.asc())
+            .limit(limit)
+            .select(OwnedLink::as_select())
+            .load(&mut self.db.0.get().await?)
+            .await?)
+    }
+
+    fn stream_owned(
+        &self,
+        key: &str,
+        page_size: i64,
+    ) -> BoxStream<'static, Result<OwnedLink, super::DbError>> {
+        use diesel::{ExpressionMethods, QueryDsl, SelectableHelper};
+        use diesel_async::RunQueryDsl;
+
+        let db = self.db.clone();
+        let key = key.to_string();
+
+        stream::try_unfold(Some(String::new()), move |cursor| {
+            let db = db.clone();
+            let key = key.clone();
+            async move {
+                let Some(cursor) = cursor else {
+                    return Ok(None);
+                };
+
+                let page: Vec<OwnedLink> = schema::links::table
+                    .filter(schema::links::key.eq(key))
+                    .filter(schema::links::id.gt(cursor))
+                    .order(schema::links::id.asc())
+                    .limit(page_size)
+                    .select(OwnedLink::as_select())
+                    .load(&mut db.0.get().await?)
+                    .await?;
+
+                let next = match page.last() {
+                    Some(last) if page.len() as i64 == page_size => Some(last.id.clone()),
+                    _ => None,
+                };
+
+                Ok::<_, super::DbError>(Some((stream::iter(page.into_iter().map(Ok)), next)))
+            }
+        })
+        .try_flatten()
+        .boxed()
+    }
+
+    async fn ping(&self) -> Result<(), super::DbError> {
+        use diesel_async::RunQueryDsl;
+
+        diesel::sql_query("SELECT 1")
+            .execute(&mut self.db.0.get().await?)
+            .await?;
+
+        Ok(())
+    }
+
+    fn pool_status(&self) -> Option<deadpool::Status> {
+        Some(self.db.status())
+    }
 }
@@ -0,0 +1,320 @@
+use super::{DbError, LinksDB};
+use crate::models::{
+    CreateAuditEntry, CreateDemoRequest, CreateLink, CreateTransaction, FetchLink, LinkDetails,
+    OpenGraphTags, OwnedLink, TopLink, Transaction,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use futures_util::stream::BoxStream;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// A [`LinksDB`] passing every call on to `inner`, logging a warning for each
+/// operation that took longer than `threshold`. The streaming exports are
+/// passed on untimed, their duration is up to the reading client.
+pub struct SlowQueryLog {
+    inner: Arc<dyn LinksDB>,
+    threshold: Duration,
+}
+
+impl SlowQueryLog {
+    pub fn new(inner: Arc<dyn LinksDB>, threshold: Duration) -> Self {
+        Self { inner, threshold }
+    }
+
+    async fn timed<T>(&self, operation: &'static str, query: impl Future<Output = T>) -> T {
+        let start = Instant::now();
+        let result = query.await;
+
+        let elapsed = start.elapsed();
+        if elapsed > self.threshold {
+            tracing::warn!(
+                operation,
+                elapsed_ms = elapsed.as_millis() as u64,
+                "slow query"
+            );
+        }
+
+        result
+    }
+}
+
+#[async_trait]
+impl LinksDB for SlowQueryLog {
+    async fn create_transaction(&self, tx: &CreateTransaction) -> Result<(), DbError> {
+        self.timed("create_transaction", self.inner.create_transaction(tx))
+            .await
+    }
+
+    async fn transactions_for(&self, link_id: &str) -> Result<Vec<Transaction>, DbError> {
+        self.timed("transactions_for", self.inner.transactions_for(link_id))
+            .await
+    }
+
+    async fn create(&self, link: &CreateLink) -> Result<CreateLink, DbError> {
+        self.timed("create", self.inner.create(link)).await
+    }
+
+    async fn create_or_skip(&self, link: &CreateLink) -> Result<bool, DbError> {
+        self.timed("create_or_skip", self.inner.create_or_skip(link))
+            .await
+    }
+
+    async fn create_or_overwrite(&self, link: &CreateLink) -> Result<bool, DbError> {
+        self.timed("create_or_overwrite", self.inner.create_or_overwrite(link))
+            .await
+    }
+
+    async fn create_demo_request(&self, request: &CreateDemoRequest) -> Result<(), DbError> {
+        self.timed(
+            "create_demo_request",
+            self.inner.create_demo_request(request),
+        )
+        .await
+    }
+
+    async fn create_audit_entry(&self, entry: &CreateAuditEntry) -> Result<(), DbError> {
+        self.timed("create_audit_entry", self.inner.create_audit_entry(entry))
+            .await
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<FetchLink>, DbError> {
+        self.timed("get", self.inner.get(id)).await
+    }
+
+    async fn get_case_insensitive(&self, id: &str) -> Result<Option<FetchLink>, DbError> {
+        self.timed("get_case_insensitive", self.inner.get_case_insensitive(id))
+            .await
+    }
+
+    async fn case_duplicate_ids(&self) -> Result<Vec<String>, DbError> {
+        self.timed("case_duplicate_ids", self.inner.case_duplicate_ids())
+            .await
+    }
+
+    async fn get_by_content_hash(&self, content_hash: &str) -> Result<Vec<FetchLink>, DbError> {
+        self.timed(
+            "get_by_content_hash",
+            self.inner.get_by_content_hash(content_hash),
+        )
+        .await
+    }
+
+    async fn update(&self, id: &str, url: &str) -> Result<bool, DbError> {
+        self.timed("update", self.inner.update(id, url)).await
+    }
+
+    async fn set_disabled(&self, id: &str, disabled: bool) -> Result<bool, DbError> {
+        self.timed("set_disabled", self.inner.set_disabled(id, disabled))
+            .await
+    }
+
+    async fn set_title(&self, id: &str, title: &str) -> Result<bool, DbError> {
+        self.timed("set_title", self.inner.set_title(id, title))
+            .await
+    }
+
+    async fn get_details(&self, id: &str) -> Result<Option<LinkDetails>, DbError> {
+        self.timed("get_details", self.inner.get_details(id)).await
+    }
+
+    async fn open_graph(&self, id: &str) -> Result<Option<OpenGraphTags>, DbError> {
+        self.timed("open_graph", self.inner.open_graph(id)).await
+    }
+
+    async fn count(&self) -> Result<i64, DbError> {
+        self.timed("count", self.inner.count()).await
+    }
+
+    async fn count_by_source(&self) -> Result<Vec<(String, i64)>, DbError> {
+        self.timed("count_by_source", self.inner.count_by_source())
+            .await
+    }
+
+    async fn batch_update_clicks(
+        &self,
+        ids: Vec<String>,
+        increments: Vec<i32>,
+        first_used: Vec<DateTime<Utc>>,
+        last_used: Vec<DateTime<Utc>>,
+    ) -> Result<u64, DbError> {
+        self.timed(
+            "batch_update_clicks",
+            self.inner
+                .batch_update_clicks(ids, increments, first_used, last_used),
+        )
+        .await
+    }
+
+    async fn increment_click(&self, id: &str) -> Result<(), DbError> {
+        self.timed("increment_click", self.inner.increment_click(id))
+            .await
+    }
+
+    async fn clicks_in_window(&self, id: &str, since: DateTime<Utc>) -> Result<u64, DbError> {
+        self.timed("clicks_in_window", self.inner.clicks_in_window(id, since))
+            .await
+    }
+
+    async fn daily_clicks(
+        &self,
+        id: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<(NaiveDate, u64)>, DbError> {
+        self.timed("daily_clicks", self.inner.daily_clicks(id, from, to))
+            .await
+    }
+
+    async fn top_links(&self, limit: i64, now: DateTime<Utc>) -> Result<Vec<TopLink>, DbError> {
+        self.timed("top_links", self.inner.top_links(limit, now))
+            .await
+    }
+
+    async fn find_owned_by_urls(
+        &self,
+        urls: &[String],
+        key: &str,
+    ) -> Result<Vec<(String, String)>, DbError> {
+        self.timed(
+            "find_owned_by_urls",
+            self.inner.find_owned_by_urls(urls, key),
+        )
+        .await
+    }
+
+    async fn next_sequence(&self) -> Result<i64, DbError> {
+        self.timed("next_sequence", self.inner.next_sequence())
+            .await
+    }
+
+    async fn delete_owned(&self, ids: &[String], key: &str) -> Result<Vec<String>, DbError> {
+        self.timed("delete_owned", self.inner.delete_owned(ids, key))
+            .await
+    }
+
+    async fn purge_expired(&self, now: DateTime<Utc>) -> Result<u64, DbError> {
+        self.timed("purge_expired", self.inner.purge_expired(now))
+            .await
+    }
+
+    fn stream_all(&self, page_size: i64) -> BoxStream<'static, Result<LinkDetails, DbError>> {
+        self.inner.stream_all(page_size)
+    }
+
+    fn stream_owned(
+        &self,
+        key: &str,
+        page_size: i64,
+    ) -> BoxStream<'static, Result<OwnedLink, DbError>> {
+        self.inner.stream_owned(key, page_size)
+    }
+
+    async fn owned_page(
+        &self,
+        key: &str,
+        after: &str,
+        limit: i64,
+    ) -> Result<Vec<OwnedLink>, DbError> {
+        self.timed("owned_page", self.inner.owned_page(key, after, limit))
+            .await
+    }
+
+    async fn blocked_hosts(&self) -> Result<Vec<String>, DbError> {
+        self.timed("blocked_hosts", self.inner.blocked_hosts())
+            .await
+    }
+
+    async fn block_host(&self, host: &str) -> Result<(), DbError> {
+        self.timed("block_host", self.inner.block_host(host)).await
+    }
+
+    async fn unblock_host(&self, host: &str) -> Result<bool, DbError> {
+        self.timed("unblock_host", self.inner.unblock_host(host))
+            .await
+    }
+
+    async fn ping(&self) -> Result<(), DbError> {
+        self.timed("ping", self.inner.ping()).await
+    }
+
+    fn pool_status(&self) -> Option<deadpool::Status> {
+        self.inner.pool_status()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::MockLinksDB;
+    use std::sync::Mutex;
+    use tracing::{
+        Event, Subscriber,
+        field::{Field, Visit},
+    };
+    use tracing_subscriber::{
+        Layer,
+        layer::{Context, SubscriberExt},
+    };
+
+    /// Operations of the "slow query" warnings logged.
+    #[derive(Clone, Default)]
+    struct SlowOperations(Arc<Mutex<Vec<String>>>);
+
+    impl<S: Subscriber> Layer<S> for SlowOperations {
+        fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+            #[derive(Default)]
+            struct Fields {
+                message: String,
+                operation: String,
+            }
+
+            impl Visit for Fields {
+                fn record_str(&mut self, field: &Field, value: &str) {
+                    if field.name() == "operation" {
+                        self.operation = value.to_string();
+                    }
+                }
+
+                fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+                    if field.name() == "message" {
+                        self.message = format!("{value:?}");
+                    }
+                }
+            }
+
+            let mut fields = Fields::default();
+            event.record(&mut fields);
+
+            if fields.message == "slow query" {
+                self.0.lock().unwrap().push(fields.operation);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_slow_query_logged() {
+        let slow = SlowOperations::default();
+        let _guard =
+            tracing::subscriber::set_default(tracing_subscriber::registry().with(slow.clone()));
+
+        let mut inner = MockLinksDB::new();
+        inner.expect_get().returning(|id| {
+            if id == "slow" {
+                std::thread::sleep(Duration::from_millis(50));
+            }
+            Ok(None)
+        });
+        inner.expect_count().returning(|| Ok(0));
+        let db = SlowQueryLog::new(Arc::new(inner), Duration::from_millis(20));
+
+        db.get("fast").await.unwrap();
+        db.count().await.unwrap();
+        assert!(slow.0.lock().unwrap().is_empty());
+
+        db.get("slow").await.unwrap();
+        assert_eq!(*slow.0.lock().unwrap(), ["get"]);
+    }
+}
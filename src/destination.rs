@@ -0,0 +1,169 @@
+use crate::app::{MAX_TITLE_LENGTH, is_private_host};
+use anyhow::Context;
+use sha2::{Digest, Sha256};
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+use tokio::net::lookup_host;
+use url::{Host, Url};
+
+/// Bytes of a destination read while looking for its `<title>`.
+const FETCH_LIMIT: usize = 64 * 1024;
+const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+/// Bytes of a destination hashed at most, larger bodies are not hashed.
+const CONTENT_LIMIT: usize = 1024 * 1024;
+/// Kept short, as the create request waits for it.
+const VERIFY_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// A client for requests to `url` that can't be pointed at the server's own
+/// network: the host is resolved once, a private or loopback address is
+/// refused unless `allow_private`, and the request is pinned to the checked
+/// address. Redirects are not followed, as they could lead anywhere.
+async fn guarded_client(
+    url: &Url,
+    timeout: Duration,
+    allow_private: bool,
+) -> anyhow::Result<reqwest::Client> {
+    let port = url.port_or_known_default().context("URL without port")?;
+    let host = url.host().context("URL without host")?;
+
+    let addr = match host {
+        Host::Domain(domain) => lookup_host((domain, port))
+            .await?
+            .next()
+            .with_context(|| format!("{domain} did not resolve"))?,
+        Host::Ipv4(ip) => SocketAddr::new(ip.into(), port),
+        Host::Ipv6(ip) => SocketAddr::new(ip.into(), port),
+    };
+
+    let resolved = match addr.ip() {
+        IpAddr::V4(ip) => Host::Ipv4(ip),
+        IpAddr::V6(ip) => Host::Ipv6(ip),
+    };
+    if !allow_private && (is_private_host(&host) || is_private_host(&resolved)) {
+        anyhow::bail!("{host} resolves to a private address");
+    }
+
+    let mut client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .timeout(timeout);
+    if let Host::Domain(domain) = host {
+        client = client.resolve(domain, addr);
+    }
+
+    Ok(client.build()?)
+}
+
+/// Fetches the `<title>` of the page at `url`, `None` if it has none. Never
+/// requests private addresses, see [`guarded_client`].
+pub async fn fetch_title(url: &str) -> anyhow::Result<Option<String>> {
+    let url = Url::parse(url)?;
+    let client = guarded_client(&url, FETCH_TIMEOUT, false).await?;
+
+    let mut resp = client.get(url).send().await?.error_for_status()?;
+
+    let mut body = Vec::new();
+    while body.len() < FETCH_LIMIT
+        && let Some(chunk) = resp.chunk().await?
+    {
+        body.extend_from_slice(&chunk);
+    }
+
+    Ok(extract(&String::from_utf8_lossy(&body)))
+}
+
+/// Hex SHA-256 of the body served at `url`, `None` if it exceeds
+/// [`CONTENT_LIMIT`]. Private addresses are only requested if
+/// `allow_private`, see [`guarded_client`].
+pub async fn content_hash(url: &str, allow_private: bool) -> anyhow::Result<Option<String>> {
+    let url = Url::parse(url)?;
+    let client = guarded_client(&url, FETCH_TIMEOUT, allow_private).await?;
+
+    let mut resp = client.get(url).send().await?.error_for_status()?;
+
+    let mut hasher = Sha256::new();
+    let mut read = 0;
+    while let Some(chunk) = resp.chunk().await? {
+        read += chunk.len();
+        if read > CONTENT_LIMIT {
+            return Ok(None);
+        }
+        hasher.update(&chunk);
+    }
+
+    Ok(Some(hex::encode(hasher.finalize())))
+}
+
+/// Checks the destination `url` answers a `HEAD` request without an error
+/// status. Redirects count as answered, they are not followed. Private
+/// addresses are only requested if `allow_private`, see [`guarded_client`].
+pub async fn verify(url: &str, allow_private: bool) -> anyhow::Result<()> {
+    let url = Url::parse(url)?;
+    let client = guarded_client(&url, VERIFY_TIMEOUT, allow_private).await?;
+
+    let status = client
+        .head(url)
+        .send()
+        .await
+        .context("Destination is unreachable")?
+        .status();
+    if status.is_client_error() || status.is_server_error() {
+        anyhow::bail!("Destination responded with {status}");
+    }
+
+    Ok(())
+}
+
+/// The text of the first `<title>` element of `html`, whitespace collapsed
+/// and cut to [`MAX_TITLE_LENGTH`] characters.
+fn extract(html: &str) -> Option<String> {
+    // ascii lowercasing keeps the byte offsets of `html`
+    let lower = html.to_ascii_lowercase();
+    let open = lower.find("<title")?;
+    let start = open + lower[open..].find('>')? + 1;
+    let end = start + lower[start..].find("</title")?;
+
+    let title = html[start..end]
+        .replace("&amp;", "&")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'");
+    let title: String = title
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .take(MAX_TITLE_LENGTH)
+        .collect();
+
+    (!title.is_empty()).then_some(title)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract() {
+        assert_eq!(
+            extract("<html><head><TITLE lang=\"en\">\n  Rust &amp; Games\n</TITLE></head>"),
+            Some(String::from("Rust & Games"))
+        );
+        assert_eq!(extract("<title>  </title>"), None);
+        assert_eq!(extract("<title>unterminated"), None);
+        assert_eq!(extract("<h1>no title</h1>"), None);
+        assert_eq!(
+            extract(&format!("<title>{}</title>", "a".repeat(500))).map(|t| t.len()),
+            Some(MAX_TITLE_LENGTH)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch_title_refuses_private_hosts() {
+        assert!(fetch_title("http://127.0.0.1:1/").await.is_err());
+        assert!(fetch_title("http://localhost/").await.is_err());
+        assert!(fetch_title("http://[::1]/").await.is_err());
+    }
+}
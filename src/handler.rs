@@ -1,14 +1,44 @@
-use crate::{app::App, auth::AuthenticatedKey};
+use crate::{
+    app::{
+        App, HealthFormat, HealthStatus, ImportOutcome, LinkError, OnConflict, PublicStatsField,
+        SolanaPayments, Summary, forward_query, is_plausible_id,
+    },
+    auth::{ApiKeys, AuthenticatedKey, KeyScopes},
+    is_debug,
+    models::{
+        CreateTransaction, LinkDetails, LinkSource, OpenGraphTags, OwnedLink, TopLink,
+        network_to_db_string,
+    },
+    qr,
+    turnstile::{TOKEN_HEADER, TurnstileVerifier},
+};
 use axum::{
-    Extension, Json,
-    extract::{Path, State},
-    http::{HeaderMap, StatusCode},
-    response::{Html, IntoResponse, Redirect, Response},
+    BoxError, Extension, Form, Json,
+    body::Body,
+    extract::{ConnectInfo, FromRequestParts, Path, Query, RawQuery, Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header, request::Parts},
+    middleware::Next,
+    response::{
+        Html, IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use ezlime_rs::{
+    CreateLinkRequest, CreateLinksRequest, CreateLinksResponse, CreatedLinkResponse,
+    DeleteLinksRequest, DeleteLinksResponse, FindLinksRequest, FoundLink, KeyInfo,
+    ResolveLinksRequest, ResolvedLink, UpdateLinkRequest, ValidationRules,
 };
-use axum_turnstile::VerifiedTurnstile;
-use ezlime_rs::CreateLinkRequest;
-use std::{borrow::Cow, sync::Arc};
-use tracing::info;
+use futures_util::{Stream, StreamExt, TryStreamExt, stream};
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+use tokio::sync::{broadcast, mpsc};
+use tokio_util::io::{StreamReader, SyncIoBridge};
+use tracing::{debug, error, info, warn};
 use x402_rs::{
     network::Network,
     types::{PaymentPayload, SettleResponse},
@@ -21,11 +51,67 @@ pub struct AppError(anyhow::Error);
 // Tell axum how to convert `AppError` into a response.
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Something went wrong: {}", self.0),
-        )
-            .into_response()
+        let Some(e) = self.0.downcast_ref::<LinkError>() else {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Something went wrong: {}", self.0),
+            )
+                .into_response();
+        };
+
+        let status = match e {
+            LinkError::LimitReached => StatusCode::INSUFFICIENT_STORAGE,
+            LinkError::NotFound => StatusCode::NOT_FOUND,
+            LinkError::NotOwner | LinkError::HostBlocked => StatusCode::FORBIDDEN,
+            LinkError::RedirectBlocked(_) => StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS,
+            LinkError::IdTaken => StatusCode::CONFLICT,
+            LinkError::PasswordRequired => StatusCode::UNAUTHORIZED,
+            LinkError::WrongPassword | LinkError::Disabled => StatusCode::FORBIDDEN,
+            LinkError::Exhausted => StatusCode::GONE,
+            LinkError::UnsupportedPayment(_) => StatusCode::BAD_REQUEST,
+            LinkError::InsufficientPayment { .. } => StatusCode::PAYMENT_REQUIRED,
+            LinkError::RedirectLoop => StatusCode::LOOP_DETECTED,
+            LinkError::MalformedUrl => StatusCode::INTERNAL_SERVER_ERROR,
+            // tagged, so clients can tell it apart from the link limit
+            LinkError::RedirectRateLimited { retry_after }
+            | LinkError::PublicRateLimited { retry_after }
+            | LinkError::PublicCooldown { retry_after, .. }
+            | LinkError::PasswordAttempts { retry_after } => {
+                return (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    [(header::RETRY_AFTER, retry_after.to_string())],
+                    e.to_string(),
+                )
+                    .into_response();
+            }
+            LinkError::Validation(violations) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(serde_json::json!({
+                        "error": "validation_failed",
+                        "violations": violations,
+                    })),
+                )
+                    .into_response();
+            }
+            LinkError::IdSpaceExhausted => {
+                return (
+                    StatusCode::INSUFFICIENT_STORAGE,
+                    Json(serde_json::json!({ "error": "id_space_exhausted" })),
+                )
+                    .into_response();
+            }
+            LinkError::Unavailable { retry_after } | LinkError::ShuttingDown { retry_after } => {
+                return (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    [(header::RETRY_AFTER, retry_after.to_string())],
+                    e.to_string(),
+                )
+                    .into_response();
+            }
+        };
+
+        (status, e.to_string()).into_response()
     }
 }
 
@@ -40,226 +126,3440 @@ where
     }
 }
 
-pub async fn handle_health() -> Html<&'static str> {
-    Html("<h1>Hello, World!</h1>")
+/// Requires a verified Turnstile token, unless the check was disabled for
+/// automated tests (debug builds only).
+pub struct TurnstileCheck;
+
+impl FromRequestParts<Arc<App>> for TurnstileCheck {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        app: &Arc<App>,
+    ) -> Result<Self, Self::Rejection> {
+        if is_debug() && app.config().turnstile_disabled {
+            return Ok(Self);
+        }
+
+        // without a verifier, e.g. a release build with the check disabled,
+        // no token is accepted
+        let Some(verifier) = parts.extensions.get::<TurnstileVerifier>() else {
+            return Err(StatusCode::FORBIDDEN.into_response());
+        };
+
+        let token = parts
+            .headers
+            .get(TOKEN_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+
+        if token.is_empty() || !verifier.verify(token).await {
+            return Err(StatusCode::FORBIDDEN.into_response());
+        }
+
+        Ok(Self)
+    }
+}
+
+const FORWARDED_FOR_HEADER: &str = "x-forwarded-for";
+
+/// The IP of the client, the last `X-Forwarded-For` entry with
+/// [`crate::app::AppConfig::trust_forwarded_for`], as that one was appended
+/// by the proxy while earlier ones come from the client. Otherwise, or
+/// without the header, the peer address.
+pub struct ClientIp(pub IpAddr);
+
+impl FromRequestParts<Arc<App>> for ClientIp {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        app: &Arc<App>,
+    ) -> Result<Self, Self::Rejection> {
+        let forwarded = app
+            .config()
+            .trust_forwarded_for
+            .then(|| parts.headers.get(FORWARDED_FOR_HEADER))
+            .flatten()
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.rsplit(',').next())
+            .and_then(|ip| ip.trim().parse().ok());
+        if let Some(ip) = forwarded {
+            return Ok(Self(ip));
+        }
+
+        let ConnectInfo(peer) = ConnectInfo::<SocketAddr>::from_request_parts(parts, app)
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+        Ok(Self(peer.ip()))
+    }
+}
+
+const HEALTH_MEDIA_TYPE: &str = "application/health+json";
+
+/// Liveness probe, answers as long as the process serves requests at all.
+pub async fn handle_livez() -> &'static str {
+    "ok"
+}
+
+/// Readiness probe (also served at `/health`), `503` while the DB is down so
+/// no traffic is routed to this instance.
+pub async fn handle_health(State(app): State<Arc<App>>) -> Response {
+    let health = app.health().await;
+    let status = match health.status {
+        HealthStatus::Pass => StatusCode::OK,
+        HealthStatus::Fail => StatusCode::SERVICE_UNAVAILABLE,
+    };
+
+    if app.config().health_format == HealthFormat::Html {
+        return (status, Html(app.config().health_page.clone())).into_response();
+    }
+
+    (
+        status,
+        [(header::CONTENT_TYPE, HEALTH_MEDIA_TYPE)],
+        Json(health),
+    )
+        .into_response()
+}
+
+/// Only reached with a valid key, `require_auth` answers `401` otherwise.
+pub async fn handle_check_key(
+    Extension(AuthenticatedKey(api_key)): Extension<AuthenticatedKey>,
+    Extension(KeyScopes(scopes)): Extension<KeyScopes>,
+) -> Json<KeyInfo> {
+    info!(api_key, "handle_check_key");
+
+    Json(KeyInfo {
+        valid: true,
+        scopes,
+    })
+}
+
+/// Not `Debug`, so the key never ends up in a log.
+#[derive(Deserialize)]
+pub struct UpdateKeyRequest {
+    /// The key to change, in the body as paths end up in access logs.
+    pub key: String,
+    /// Replaces the scopes of the key if set. Scopes are only reported by
+    /// `/auth/check`, no route requires one.
+    pub scopes: Option<Vec<String>>,
+    /// Exempts the key from the rate limit if set to `true`.
+    pub unlimited: Option<bool>,
+}
+
+/// Changes a configured API key while running, only mounted with
+/// `--allow-key-updates`. Not persisted, a restart restores the configured
+/// keys.
+pub async fn handle_update_key(
+    Extension(api_keys): Extension<ApiKeys>,
+    Json(update): Json<UpdateKeyRequest>,
+) -> StatusCode {
+    warn!(scopes = ?update.scopes, unlimited = ?update.unlimited, "handle_update_key");
+
+    if api_keys.update(&update.key, update.scopes, update.unlimited) {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+/// The arguments the server was started with, after merging flags, env vars
+/// and the config file, with secrets redacted.
+#[derive(Clone)]
+pub struct EffectiveConfig(pub Arc<serde_json::Value>);
+
+pub async fn handle_admin_config(
+    Extension(EffectiveConfig(config)): Extension<EffectiveConfig>,
+) -> Json<serde_json::Value> {
+    info!("handle_admin_config");
+
+    Json(config.as_ref().clone())
+}
+
+pub async fn handle_rules(State(app): State<Arc<App>>) -> Json<ValidationRules> {
+    Json(app.config().validation_rules.clone())
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MetricsFormat {
+    #[default]
+    Prometheus,
+    Json,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct MetricsQuery {
+    #[serde(default)]
+    pub format: MetricsFormat,
+}
+
+/// Metrics in the Prometheus text format, or as JSON with `?format=json`.
+pub async fn handle_metrics(
+    Query(MetricsQuery { format }): Query<MetricsQuery>,
+    State(app): State<Arc<App>>,
+) -> Result<Response, AppError> {
+    Ok(match format {
+        MetricsFormat::Prometheus => (
+            [(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)],
+            app.render_metrics()?,
+        )
+            .into_response(),
+        MetricsFormat::Json => Json(app.render_metrics_json()).into_response(),
+    })
+}
+
+/// `/` is never a link id, so it either redirects to the configured landing
+/// page or shows a minimal default page.
+pub async fn handle_root(State(app): State<Arc<App>>) -> Result<Response, AppError> {
+    match &app.config().root_redirect {
+        Some(url) => redirect_response(307, url),
+        None => Ok(Html(ROOT_PAGE).into_response()),
+    }
 }
 
+const ROOT_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head><title>ezlime</title></head>
+<body>
+<h1>ezlime</h1>
+<p>A URL shortener, see <a href="https://github.com/rustunit/ezlime">github.com/rustunit/ezlime</a>.</p>
+</body>
+</html>"#;
+
 pub async fn handle_redirect(
     Path(id): Path<String>,
     State(app): State<Arc<App>>,
-) -> Result<impl IntoResponse, AppError> {
+    RawQuery(query): RawQuery,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
     info!("handle_redirect: {}", id);
 
-    let url = app.redirect(&id).await?;
+    let password = headers
+        .get(LINK_PASSWORD_HEADER)
+        .and_then(|value| value.to_str().ok());
 
-    Ok(Redirect::temporary(&url))
+    redirect(
+        &app,
+        &id,
+        password,
+        query.as_deref(),
+        true,
+        is_crawler(&app, &headers),
+    )
+    .await
 }
 
-pub async fn handle_create(
-    Extension(AuthenticatedKey(api_key)): Extension<AuthenticatedKey>,
+/// Answers like [`handle_redirect`] without a body, and without counting a
+/// click as crawlers and link checkers are no visitors.
+pub async fn handle_redirect_head(
+    Path(id): Path<String>,
     State(app): State<Arc<App>>,
-    Json(create): Json<CreateLinkRequest>,
-) -> Result<impl IntoResponse, AppError> {
-    info!(api_key, "handle_create: '{}'", create.url);
+    RawQuery(query): RawQuery,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    info!("handle_redirect_head: {}", id);
+
+    let password = headers
+        .get(LINK_PASSWORD_HEADER)
+        .and_then(|value| value.to_str().ok());
 
-    Ok(Json(app.create_link(api_key, create, false).await?).into_response())
+    redirect(
+        &app,
+        &id,
+        password,
+        query.as_deref(),
+        false,
+        is_crawler(&app, &headers),
+    )
+    .await
 }
 
-pub async fn handle_public_create(
-    _verified: VerifiedTurnstile,
+#[derive(Deserialize)]
+pub struct PasswordForm {
+    password: String,
+}
+
+/// Target of the password page, the passphrase is posted instead of being put
+/// in the query where it would end up in access logs.
+pub async fn handle_redirect_password(
+    Path(id): Path<String>,
     State(app): State<Arc<App>>,
-    Json(create): Json<CreateLinkRequest>,
-) -> Result<impl IntoResponse, AppError> {
-    info!("handle_public_create: '{}'", create.url);
+    Form(form): Form<PasswordForm>,
+) -> Result<Response, AppError> {
+    info!("handle_redirect_password: {}", id);
 
-    Ok(Json(app.create_link("public".to_string(), create, false).await?).into_response())
+    redirect(&app, &id, Some(&form.password), None, true, false).await
 }
 
-pub async fn handle_x402_create(
-    Extension(settlement): Extension<Option<SettleResponse>>,
-    State(app): State<Arc<App>>,
-    headers: HeaderMap,
-    Json(create): Json<CreateLinkRequest>,
-) -> Result<impl IntoResponse, AppError> {
-    info!(url = %create.url, "handle_x402_create");
+const LINK_PASSWORD_HEADER: &str = "x-link-password";
 
-    // Extract payment details from the X-Payment header
-    let payment = headers
-        .get("x-payment")
-        .and_then(|h| h.to_str().ok())
-        .and_then(|s| {
-            let base64_bytes = x402_rs::types::Base64Bytes(Cow::Borrowed(s.as_bytes()));
-            PaymentPayload::try_from(base64_bytes).ok()
-        })
-        .ok_or_else(|| anyhow::anyhow!("Missing or invalid X-Payment header"))?;
+fn is_crawler(app: &App, headers: &HeaderMap) -> bool {
+    headers
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|user_agent| app.is_crawler(user_agent))
+}
 
-    // Extract payment amount and addresses from EVM payload
-    let (amount, from, to) = match &payment.payload {
-        x402_rs::types::ExactPaymentPayload::Evm(evm_payload) => {
-            let amount = evm_payload.authorization.value.0.to_string();
-            let from = evm_payload.authorization.from.to_string();
-            let to = evm_payload.authorization.to.to_string();
-            (amount, from, to)
-        }
-        x402_rs::types::ExactPaymentPayload::Solana(_) => {
-            return Err(anyhow::anyhow!("Solana payments are not supported").into());
+async fn redirect(
+    app: &App,
+    id: &str,
+    password: Option<&str>,
+    query: Option<&str>,
+    count_click: bool,
+    crawler: bool,
+) -> Result<Response, AppError> {
+    let id = id.trim();
+    if !is_plausible_id(id) {
+        return Err(LinkError::NotFound.into());
+    }
+
+    let link = if count_click {
+        app.redirect(id, password).await
+    } else {
+        app.resolve(id, password).await
+    };
+    let link = match link {
+        Ok(link) => link,
+        Err(e) => {
+            return match e.downcast_ref::<LinkError>() {
+                Some(LinkError::PasswordRequired) => {
+                    Ok((StatusCode::UNAUTHORIZED, password_page(false)).into_response())
+                }
+                Some(LinkError::WrongPassword) => {
+                    Ok((StatusCode::FORBIDDEN, password_page(true)).into_response())
+                }
+                _ => Err(e.into()),
+            };
         }
     };
 
-    // Extract transaction hash from the settlement extension (error if missing)
-    let tx_hash = settlement
-        .and_then(|s| s.transaction)
-        .map(|tx| tx.to_string())
-        .ok_or_else(|| anyhow::anyhow!("No transaction hash in settlement"))?;
+    // links stored around the validation would make a broken `Location`
+    if let Err(e) = url::Url::parse(&link.url) {
+        error!(id = link.id, url = link.url, "stored url is malformed: {e}");
+        return Err(LinkError::MalformedUrl.into());
+    }
 
-    info!(
-        network = ?payment.network,
-        amount = %amount,
-        from = %from,
-        to = %to,
-        tx_hash = %tx_hash,
-        "x402 payment details"
-    );
+    // protected links keep their preview behind the password too
+    if crawler
+        && link.password_hash.is_none()
+        && let Some(tags) = app.open_graph(&link.id).await?
+    {
+        return Ok(preview_page(&tags, &link.url).into_response());
+    }
 
-    let is_testnet = payment.network == Network::BaseSepolia;
+    let config = app.config();
+    let url = match query {
+        Some(query) if config.forward_query => Cow::Owned(forward_query(
+            &link.url,
+            query,
+            config.forward_query_max_params,
+            config.forward_query_max_bytes,
+        )),
+        _ => Cow::Borrowed(link.url.as_str()),
+    };
 
-    let response = app
-        .create_link("x402".to_string(), create, is_testnet)
-        .await?;
+    let mut response = redirect_response(link.redirect_status, &url)?;
 
-    app.store_transaction(response.id.clone(), tx_hash, payment.network.to_string())
-        .await?;
+    if app.config().expose_original_url_header {
+        let location = response.headers()[header::LOCATION].clone();
+        response.headers_mut().insert(ORIGINAL_URL_HEADER, location);
+    }
 
-    Ok(Json(response).into_response())
+    // a cached redirect would skip the password check
+    if let Some(max_age) = app.config().redirect_cache_max_age
+        && link.password_hash.is_none()
+        && matches!(
+            response.status(),
+            StatusCode::MOVED_PERMANENTLY | StatusCode::PERMANENT_REDIRECT
+        )
+    {
+        response.headers_mut().insert(
+            header::CACHE_CONTROL,
+            HeaderValue::try_from(format!("public, max-age={max_age}"))?,
+        );
+    }
+
+    Ok(response)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::{counter::ClickCounter, db::MockLinksDB};
-    use axum::http::HeaderValue;
-    use ezlime_rs::CreatedLinkResponse;
-    use x402_rs::types::{ExactEvmPayload, ExactEvmPayloadAuthorization, ExactPaymentPayload};
+const ORIGINAL_URL_HEADER: &str = "x-original-url";
 
-    #[tokio::test]
-    async fn test_handle_x402_create_sepolia_returns_demo() {
-        // Create a mock app
-        let mut db = MockLinksDB::new();
-        db.expect_create_transaction().returning(|_| Ok(()));
+fn password_page(wrong: bool) -> Html<String> {
+    let hint = if wrong {
+        "<p>Wrong password, please try again.</p>"
+    } else {
+        ""
+    };
 
-        let app = App::new(
-            "http://localhost:8080".to_string(),
-            6,
-            Arc::new(db),
-            Arc::new(ClickCounter::new()),
-            10,
-        );
+    Html(format!(
+        r#"<!DOCTYPE html>
+<html>
+<head><title>Password required</title></head>
+<body>
+<h1>This link is password protected</h1>
+{hint}
+<form method="post">
+<input type="password" name="password" autofocus>
+<button type="submit">Continue</button>
+</form>
+</body>
+</html>"#
+    ))
+}
 
-        // Create a PaymentPayload for BaseSepolia
-        let payment = PaymentPayload {
-            x402_version: x402_rs::types::X402Version::V1,
-            scheme: x402_rs::types::Scheme::Exact,
-            network: Network::BaseSepolia,
-            payload: ExactPaymentPayload::Evm(ExactEvmPayload {
-                signature: x402_rs::types::EvmSignature(vec![0u8; 65]),
-                authorization: ExactEvmPayloadAuthorization {
-                    from: "0x0000000000000000000000000000000000000000"
-                        .parse()
-                        .unwrap(),
-                    to: "0x0000000000000000000000000000000000000000"
-                        .parse()
-                        .unwrap(),
-                    value: x402_rs::types::TokenAmount(
-                        x402_rs::__reexports::alloy::primitives::U256::from(1000000),
-                    ),
-                    valid_after: x402_rs::timestamp::UnixTimestamp(0),
-                    valid_before: x402_rs::timestamp::UnixTimestamp(u64::MAX),
-                    nonce: x402_rs::types::HexEncodedNonce([0u8; 32]),
-                },
-            }),
-        };
+/// Page with the Open Graph tags of a link for social crawlers, sending
+/// anything following it on to `url`.
+fn preview_page(tags: &OpenGraphTags, url: &str) -> Html<String> {
+    let meta = [
+        ("og:title", &tags.og_title),
+        ("og:description", &tags.og_description),
+        ("og:image", &tags.og_image),
+    ]
+    .into_iter()
+    .filter_map(|(property, content)| {
+        content.as_ref().map(|content| {
+            format!(
+                "<meta property=\"{property}\" content=\"{}\">\n",
+                escape_html(content)
+            )
+        })
+    })
+    .collect::<String>();
+    let url = escape_html(url);
 
-        // Encode the payment as base64 JSON
-        let payment_json = serde_json::to_string(&payment).unwrap();
-        let payment_base64 = base64::Engine::encode(
-            &base64::engine::general_purpose::STANDARD,
-            payment_json.as_bytes(),
-        );
+    Html(format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+{meta}<meta property="og:url" content="{url}">
+<meta http-equiv="refresh" content="0; url={url}">
+</head>
+<body>
+<a href="{url}">Continue</a>
+</body>
+</html>"#
+    ))
+}
 
-        // Create headers with the X-Payment header
-        let mut headers = HeaderMap::new();
-        headers.insert("x-payment", HeaderValue::from_str(&payment_base64).unwrap());
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
 
-        // Create the request
-        let test_url = "https://example.com/test".to_string();
-        let request = CreateLinkRequest {
-            url: test_url.clone(),
-        };
+/// Builds the redirect using the status stored with the link, falling back to
+/// a temporary redirect for anything that is not a redirect status.
+fn redirect_response(status: i16, url: &str) -> Result<Response, AppError> {
+    let status = u16::try_from(status)
+        .ok()
+        .and_then(|status| StatusCode::from_u16(status).ok())
+        .filter(|status| {
+            matches!(
+                *status,
+                StatusCode::MOVED_PERMANENTLY
+                    | StatusCode::FOUND
+                    | StatusCode::SEE_OTHER
+                    | StatusCode::TEMPORARY_REDIRECT
+                    | StatusCode::PERMANENT_REDIRECT
+            )
+        })
+        .unwrap_or(StatusCode::TEMPORARY_REDIRECT);
 
-        // Create a mock settlement response with a transaction hash
-        let mock_settlement = SettleResponse {
-            success: true,
-            error_reason: None,
-            payer: x402_rs::types::MixedAddress::from(
-                "0x0000000000000000000000000000000000000000"
-                    .parse::<x402_rs::types::EvmAddress>()
-                    .unwrap(),
-            ),
-            transaction: Some(x402_rs::types::TransactionHash::Evm([0x12; 32])),
-            network: Network::BaseSepolia,
-        };
+    Ok((status, [(header::LOCATION, HeaderValue::try_from(url)?)]).into_response())
+}
 
-        // Call the handler
-        let result = handle_x402_create(
-            Extension(Some(mock_settlement)),
-            State(app),
-            headers,
-            Json(request),
-        )
-        .await;
+const FORWARDED_PROTO_HEADER: &str = "x-forwarded-proto";
 
-        assert!(result.is_ok());
+/// Switches the scheme of the short url to the one the client used according
+/// to `X-Forwarded-Proto`, if the proxy in front is trusted to set it.
+fn apply_forwarded_proto(app: &App, headers: &HeaderMap, link: &mut CreatedLinkResponse) {
+    if !app.config().trust_forwarded_proto {
+        return;
+    }
 
-        // Extract the response
-        let response = result.unwrap().into_response();
-        let body_bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
-            .await
-            .unwrap();
-        let response_data: CreatedLinkResponse = serde_json::from_slice(&body_bytes).unwrap();
+    // proxies chaining the header append, the first entry is the client's
+    let Some(proto) = headers
+        .get(FORWARDED_PROTO_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(',').next())
+        .map(|v| v.trim().to_ascii_lowercase())
+        .filter(|proto| proto == "http" || proto == "https")
+    else {
+        return;
+    };
 
-        // Verify it's the demo response
-        assert_eq!(response_data.id, "rustunit");
-        assert_eq!(
-            response_data.shortened_url,
-            "http://localhost:8080/rustunit"
-        );
-        assert_eq!(response_data.original_url, test_url);
+    if let Some((_, rest)) = link.shortened_url.split_once("://") {
+        link.shortened_url = format!("{proto}://{rest}");
     }
+}
 
-    #[tokio::test]
-    async fn test_handle_x402_create_without_payment_header() {
-        // Create a mock app
-        let db = MockLinksDB::new();
+#[derive(Deserialize, Debug, Default)]
+pub struct CreateQuery {
+    /// `1` or `true` adds the QR code of the short url to the response.
+    pub include_qr: Option<String>,
+}
 
-        let app = App::new(
-            "http://localhost:8080".to_string(),
-            6,
-            Arc::new(db),
-            Arc::new(ClickCounter::new()),
-            10,
-        );
+impl CreateQuery {
+    fn include_qr(&self) -> bool {
+        matches!(self.include_qr.as_deref(), Some("1" | "true"))
+    }
+}
 
-        // Create headers without X-Payment header
-        let headers = HeaderMap::new();
+#[derive(Serialize, Debug)]
+pub struct CreatedLinkWithQr {
+    #[serde(flatten)]
+    pub link: CreatedLinkResponse,
+    /// Only present when asked for, to keep the default response small.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub qr_data_uri: Option<String>,
+}
 
-        // Create the request
-        let request = CreateLinkRequest {
-            url: "https://example.com/test".to_string(),
+pub async fn handle_create(
+    Extension(AuthenticatedKey(api_key)): Extension<AuthenticatedKey>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    State(app): State<Arc<App>>,
+    Query(query): Query<CreateQuery>,
+    headers: HeaderMap,
+    Json(create): Json<CreateLinkRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    info!(api_key, "handle_create: '{}'", create.url);
+
+    app.accept_creates()?;
+
+    let mut link = app
+        .create_link(api_key, LinkSource::Api, create, false, Some(peer.ip()))
+        .await?;
+    apply_forwarded_proto(&app, &headers, &mut link);
+
+    let qr_data_uri = if query.include_qr() {
+        Some(qr::png_data_uri(&link.shortened_url)?)
+    } else {
+        None
+    };
+
+    let id = link.id.clone();
+
+    Ok(with_link_id(
+        &app,
+        &id,
+        Json(CreatedLinkWithQr { link, qr_data_uri }).into_response(),
+    ))
+}
+
+const LINK_ID_HEADER: &str = "x-link-id";
+
+/// Adds the `X-Link-Id` header to a create response if configured, so
+/// scripts get the id without parsing the body.
+fn with_link_id(app: &App, id: &str, mut response: Response) -> Response {
+    if app.config().link_id_header
+        && let Ok(value) = HeaderValue::try_from(id)
+    {
+        response.headers_mut().insert(LINK_ID_HEADER, value);
+    }
+
+    response
+}
+
+/// Rows parsed ahead of the inserts, bounding memory for large imports.
+const IMPORT_BUFFER_ROWS: usize = 100;
+
+#[derive(Deserialize, Debug, Default)]
+pub struct ImportQuery {
+    /// `fail`, `skip` or `overwrite` for ids that already exist.
+    #[serde(default)]
+    pub on_conflict: OnConflict,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct ImportSummary {
+    pub imported: u64,
+    /// Ids that already exist.
+    pub conflicts: Vec<String>,
+    /// Existing ids left alone.
+    pub skipped: Vec<String>,
+    /// Existing ids now pointing to the imported url.
+    pub overwritten: Vec<String>,
+    pub errors: Vec<ImportRowError>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ImportRowError {
+    pub line: u64,
+    pub error: String,
+}
+
+type ImportRow = Result<(u64, String, String), ImportRowError>;
+
+/// Parses `id,url` rows, skipping an `id,url` header, and sends them to `rows`.
+fn parse_import_csv(input: impl std::io::Read, rows: mpsc::Sender<ImportRow>) {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(input);
+
+    for record in reader.records() {
+        let (row, read_failed) = match record {
+            Ok(record) => {
+                let line = record.position().map_or(0, |p| p.line());
+                let row = match (record.get(0), record.get(1), record.len()) {
+                    (Some("id"), Some("url"), 2) if line == 1 => continue,
+                    (Some(id), Some(url), 2) => Ok((line, id.to_string(), url.to_string())),
+                    _ => Err(ImportRowError {
+                        line,
+                        error: String::from("expected 2 fields: id,url"),
+                    }),
+                };
+                (row, false)
+            }
+            Err(e) => {
+                let read_failed = e.is_io_error();
+                let row = Err(ImportRowError {
+                    line: e.position().map_or(0, |p| p.line()),
+                    error: e.to_string(),
+                });
+                (row, read_failed)
+            }
         };
 
-        // Call the handler - should fail without X-Payment header
-        let result = handle_x402_create(Extension(None), State(app), headers, Json(request)).await;
+        // stop once the body can not be read anymore or nobody listens
+        if rows.blocking_send(row).is_err() || read_failed {
+            break;
+        }
+    }
+}
 
-        assert!(result.is_err());
+/// Imports links with their own ids from a `text/csv` body of `id,url` rows.
+/// The body is parsed while it streams in, so large files are not buffered.
+/// Existing ids are handled as `?on_conflict=` says, failing by default.
+pub async fn handle_import_csv(
+    Extension(AuthenticatedKey(api_key)): Extension<AuthenticatedKey>,
+    State(app): State<Arc<App>>,
+    Query(ImportQuery { on_conflict }): Query<ImportQuery>,
+    body: Body,
+) -> Json<ImportSummary> {
+    info!(api_key, "handle_import_csv");
+
+    let input = SyncIoBridge::new(StreamReader::new(
+        body.into_data_stream().map_err(std::io::Error::other),
+    ));
+    let (tx, mut rows) = mpsc::channel(IMPORT_BUFFER_ROWS);
+
+    // the csv parser is blocking, so it runs on the blocking pool
+    tokio::task::spawn_blocking(move || parse_import_csv(input, tx));
+
+    let mut summary = ImportSummary::default();
+
+    while let Some(row) = rows.recv().await {
+        let (line, id, url) = match row {
+            Ok(row) => row,
+            Err(e) => {
+                summary.errors.push(e);
+                continue;
+            }
+        };
+
+        match app.import_link(&api_key, &id, &url, on_conflict).await {
+            Ok(ImportOutcome::Imported) => summary.imported += 1,
+            Ok(ImportOutcome::Skipped) => summary.skipped.push(id),
+            Ok(ImportOutcome::Overwritten) => summary.overwritten.push(id),
+            Err(e) if matches!(e.downcast_ref::<LinkError>(), Some(LinkError::IdTaken)) => {
+                summary.conflicts.push(id);
+            }
+            Err(e) => summary.errors.push(ImportRowError {
+                line,
+                error: e.to_string(),
+            }),
+        }
+    }
+
+    info!(
+        imported = summary.imported,
+        conflicts = summary.conflicts.len(),
+        skipped = summary.skipped.len(),
+        overwritten = summary.overwritten.len(),
+        errors = summary.errors.len(),
+        "csv import done"
+    );
+
+    Json(summary)
+}
+
+#[derive(Serialize, Debug)]
+pub struct StoredLinkResponse {
+    pub id: String,
+    pub url: String,
+    pub created_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+pub async fn handle_get_link(
+    Extension(AuthenticatedKey(api_key)): Extension<AuthenticatedKey>,
+    Path(id): Path<String>,
+    State(app): State<Arc<App>>,
+) -> Result<Response, AppError> {
+    info!(api_key, "handle_get_link: {}", id);
+
+    let Some(link) = app.link_details(&id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    if link.key.as_deref() != Some(api_key.as_str()) {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+
+    Ok(Json(StoredLinkResponse {
+        id: link.id,
+        url: link.url,
+        created_at: link.created_at,
+        note: link.note,
+    })
+    .into_response())
+}
+
+#[derive(Deserialize, Debug)]
+pub struct LinkStatsQuery {
+    pub since: DateTime<Utc>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct LinkStatsResponse {
+    pub id: String,
+    pub since: DateTime<Utc>,
+    pub clicks: u64,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TimeseriesQuery {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+}
+
+pub async fn handle_link_timeseries(
+    Extension(AuthenticatedKey(api_key)): Extension<AuthenticatedKey>,
+    Path(id): Path<String>,
+    Query(TimeseriesQuery { from, to }): Query<TimeseriesQuery>,
+    State(app): State<Arc<App>>,
+) -> Result<impl IntoResponse, AppError> {
+    info!(
+        api_key,
+        "handle_link_timeseries: {} from {} to {}", id, from, to
+    );
+
+    Ok(Json(app.link_timeseries(&api_key, &id, from, to).await?))
+}
+
+pub async fn handle_link_stats(
+    Extension(AuthenticatedKey(api_key)): Extension<AuthenticatedKey>,
+    Path(id): Path<String>,
+    Query(LinkStatsQuery { since }): Query<LinkStatsQuery>,
+    State(app): State<Arc<App>>,
+) -> Result<impl IntoResponse, AppError> {
+    info!(api_key, "handle_link_stats: {} since {}", id, since);
+
+    let clicks = app.link_clicks_since(&api_key, &id, since).await?;
+
+    Ok(Json(LinkStatsResponse { id, since, clicks }))
+}
+
+pub async fn handle_update_link(
+    Extension(AuthenticatedKey(api_key)): Extension<AuthenticatedKey>,
+    Path(id): Path<String>,
+    State(app): State<Arc<App>>,
+    Json(update): Json<UpdateLinkRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    info!(api_key, "handle_update_link: {} -> '{}'", id, update.url);
+
+    Ok(Json(app.update_link(&api_key, &id, update).await?))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ReserveLinkRequest {
+    pub id: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ReservedLinkResponse {
+    pub id: String,
+    pub shortened_url: String,
+}
+
+pub async fn handle_reserve_link(
+    Extension(AuthenticatedKey(api_key)): Extension<AuthenticatedKey>,
+    State(app): State<Arc<App>>,
+    Json(request): Json<ReserveLinkRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    info!(api_key, "handle_reserve_link: {}", request.id);
+
+    app.reserve_link(&api_key, &request.id).await?;
+
+    let shortened_url = format!("{}/{}", app.config().prefix, request.id);
+
+    Ok((
+        StatusCode::CREATED,
+        Json(ReservedLinkResponse {
+            id: request.id,
+            shortened_url,
+        }),
+    ))
+}
+
+pub async fn handle_disable_link(
+    Extension(AuthenticatedKey(api_key)): Extension<AuthenticatedKey>,
+    Path(id): Path<String>,
+    State(app): State<Arc<App>>,
+) -> Result<StatusCode, AppError> {
+    info!(api_key, "handle_disable_link: {}", id);
+
+    app.set_disabled(Some(&api_key), &id, true).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn handle_enable_link(
+    Extension(AuthenticatedKey(api_key)): Extension<AuthenticatedKey>,
+    Path(id): Path<String>,
+    State(app): State<Arc<App>>,
+) -> Result<StatusCode, AppError> {
+    info!(api_key, "handle_enable_link: {}", id);
+
+    app.set_disabled(Some(&api_key), &id, false).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn handle_delete_links(
+    Extension(AuthenticatedKey(api_key)): Extension<AuthenticatedKey>,
+    State(app): State<Arc<App>>,
+    Json(request): Json<DeleteLinksRequest>,
+) -> Result<Json<DeleteLinksResponse>, AppError> {
+    info!(api_key, ids = request.ids.len(), "handle_delete_links");
+
+    let results = app.delete_links(&api_key, request.ids).await?;
+
+    Ok(Json(DeleteLinksResponse { results }))
+}
+
+pub async fn handle_create_links(
+    Extension(AuthenticatedKey(api_key)): Extension<AuthenticatedKey>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    State(app): State<Arc<App>>,
+    headers: HeaderMap,
+    Json(request): Json<CreateLinksRequest>,
+) -> Result<Json<CreateLinksResponse>, AppError> {
+    info!(api_key, links = request.links.len(), "handle_create_links");
+
+    app.accept_creates()?;
+
+    let mut results = app
+        .create_links(api_key, request.links, Some(peer.ip()))
+        .await?;
+    for link in results.iter_mut().filter_map(|result| result.link.as_mut()) {
+        apply_forwarded_proto(&app, &headers, link);
+    }
+
+    Ok(Json(CreateLinksResponse { results }))
+}
+
+pub async fn handle_find_links(
+    Extension(AuthenticatedKey(api_key)): Extension<AuthenticatedKey>,
+    State(app): State<Arc<App>>,
+    Json(request): Json<FindLinksRequest>,
+) -> Result<Json<Vec<FoundLink>>, AppError> {
+    info!(api_key, urls = request.urls.len(), "handle_find_links");
+
+    Ok(Json(app.find_links(&api_key, request.urls).await?))
+}
+
+pub async fn handle_resolve_links(
+    Extension(AuthenticatedKey(api_key)): Extension<AuthenticatedKey>,
+    State(app): State<Arc<App>>,
+    Json(request): Json<ResolveLinksRequest>,
+) -> Result<Json<Vec<ResolvedLink>>, AppError> {
+    info!(api_key, ids = request.ids.len(), "handle_resolve_links");
+
+    Ok(Json(app.resolve_many(&request.ids).await?))
+}
+
+pub async fn handle_summary(State(app): State<Arc<App>>) -> Result<Json<Summary>, AppError> {
+    Ok(Json(app.summary().await?))
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct LinkStats {
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub click_count: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_used: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_used: Option<DateTime<Utc>>,
+    /// x402 networks the link was paid on, only in the admin view.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub networks: Vec<String>,
+    /// Only in the admin view, see `--store-creator-ip`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub creator_ip: Option<String>,
+    /// Only in the admin view.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+impl LinkStats {
+    fn new(link: LinkDetails, fields: &[PublicStatsField]) -> Self {
+        let mut stats = Self {
+            id: link.id,
+            ..Default::default()
+        };
+
+        for field in fields {
+            match field {
+                PublicStatsField::ClickCount => stats.click_count = Some(link.click_count),
+                PublicStatsField::LastUsed => stats.last_used = Some(link.last_used),
+                PublicStatsField::CreatedAt => stats.created_at = Some(link.created_at),
+                PublicStatsField::Title => stats.title = link.title.clone(),
+                PublicStatsField::FirstUsed => stats.first_used = link.first_used,
+            }
+        }
+
+        stats
+    }
+}
+
+/// Stats of a link, limited to the configured `--public-stats-fields`.
+pub async fn handle_public_stats(
+    Path(id): Path<String>,
+    State(app): State<Arc<App>>,
+) -> Result<Json<LinkStats>, AppError> {
+    let link = app.link_details(&id).await?.ok_or(LinkError::NotFound)?;
+
+    Ok(Json(LinkStats::new(
+        link,
+        &app.config().public_stats_fields,
+    )))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TrendingQuery {
+    /// Capped at [`MAX_TOP_LINKS`].
+    #[serde(default = "default_trending_limit")]
+    pub limit: u32,
+}
+
+fn default_trending_limit() -> u32 {
+    10
+}
+
+/// The most clicked links whose creators opted into listing them.
+pub async fn handle_trending(
+    Query(TrendingQuery { limit }): Query<TrendingQuery>,
+    State(app): State<Arc<App>>,
+) -> Result<Json<Vec<TopLink>>, AppError> {
+    Ok(Json(app.top_links(limit).await?))
+}
+
+/// Stats of a link with all fields, regardless of `--public-stats-fields`,
+/// and the networks it was paid on, e.g. to tell mainnet from testnet.
+pub async fn handle_admin_stats(
+    Path(id): Path<String>,
+    State(app): State<Arc<App>>,
+) -> Result<Json<LinkStats>, AppError> {
+    let link = app.link_details(&id).await?.ok_or(LinkError::NotFound)?;
+    let networks = app.link_networks(&id).await?;
+
+    Ok(Json(LinkStats {
+        networks,
+        creator_ip: link.creator_ip.clone(),
+        note: link.note.clone(),
+        ..LinkStats::new(link, &PublicStatsField::ALL)
+    }))
+}
+
+#[derive(Serialize, Debug)]
+pub struct TransactionResponse {
+    pub network: String,
+    pub tx_hash: String,
+    pub amount: Option<String>,
+    pub payer: Option<String>,
+}
+
+pub async fn handle_link_transactions(
+    Path(id): Path<String>,
+    State(app): State<Arc<App>>,
+) -> Result<Json<Vec<TransactionResponse>>, AppError> {
+    info!("handle_link_transactions: {}", id);
+
+    let transactions = app
+        .link_transactions(&id)
+        .await?
+        .into_iter()
+        .map(|tx| TransactionResponse {
+            network: tx.network,
+            tx_hash: tx.tx_hash,
+            amount: tx.amount,
+            payer: tx.payer,
+        })
+        .collect();
+
+    Ok(Json(transactions))
+}
+
+#[derive(Serialize, Debug)]
+pub struct FlushCountersResponse {
+    pub rows_updated: u64,
+}
+
+pub async fn handle_flush_counters(
+    State(app): State<Arc<App>>,
+) -> Result<Json<FlushCountersResponse>, AppError> {
+    let rows_updated = app.flush_counters().await?;
+
+    info!(rows_updated, "handle_flush_counters");
+
+    Ok(Json(FlushCountersResponse { rows_updated }))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct WarmCacheRequest {
+    pub ids: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct WarmCacheResponse {
+    pub warmed: usize,
+}
+
+pub async fn handle_warm_cache(
+    State(app): State<Arc<App>>,
+    Json(request): Json<WarmCacheRequest>,
+) -> Result<Json<WarmCacheResponse>, AppError> {
+    info!(ids = request.ids.len(), "handle_warm_cache");
+
+    let warmed = app.warm_cache(&request.ids).await?;
+
+    Ok(Json(WarmCacheResponse { warmed }))
+}
+
+#[derive(Serialize, Debug)]
+pub struct EvictCacheResponse {
+    /// Whether the link was cached by this replica.
+    pub evicted: bool,
+}
+
+/// Drops a single link from the cache, e.g. after editing it in the DB.
+pub async fn handle_evict_cache(
+    Path(id): Path<String>,
+    State(app): State<Arc<App>>,
+) -> Json<EvictCacheResponse> {
+    info!("handle_evict_cache: {}", id);
+
+    Json(EvictCacheResponse {
+        evicted: app.evict_cached(&id).await,
+    })
+}
+
+/// Streams link creations and redirects as they happen. Subscribers that fall behind skip
+/// the events they missed instead of slowing down redirects.
+pub async fn handle_events(
+    State(app): State<Arc<App>>,
+) -> Sse<impl Stream<Item = Result<Event, axum::Error>>> {
+    let events = stream::unfold(app.subscribe_events(), |mut rx| async move {
+        loop {
+            match rx.recv().await {
+                Ok(event) => return Some((Event::default().json_data(&event), rx)),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!(skipped, "events subscriber lagged");
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+/// `Accept` media type asking `/shorten` for a [`MinimalLinkResponse`].
+pub const MINIMAL_RESPONSE_MEDIA_TYPE: &str = "application/vnd.ezlime.minimal+json";
+
+#[derive(Serialize, Debug)]
+pub struct MinimalLinkResponse {
+    pub short_url: String,
+}
+
+fn wants_minimal_response(app: &App, headers: &HeaderMap) -> bool {
+    app.config().public_minimal_response
+        || headers
+            .get_all(header::ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|media_type| {
+                media_type.split(';').next().map(str::trim) == Some(MINIMAL_RESPONSE_MEDIA_TYPE)
+            })
+}
+
+/// Whether `Accept` ranks `text/plain` above `application/json`, which
+/// wildcards don't count for, so JSON stays the default.
+fn prefers_plain_text(headers: &HeaderMap) -> bool {
+    let mut plain: f32 = 0.0;
+    let mut json: f32 = 0.0;
+
+    for media_range in headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+    {
+        let mut params = media_range.split(';');
+        let media_type = params.next().unwrap_or_default().trim();
+        let quality = params
+            .filter_map(|param| param.trim().strip_prefix("q="))
+            .find_map(|q| q.parse().ok())
+            .unwrap_or(1.0);
+
+        if media_type.eq_ignore_ascii_case("text/plain") {
+            plain = plain.max(quality);
+        } else if media_type.eq_ignore_ascii_case("application/json") {
+            json = json.max(quality);
+        }
+    }
+
+    plain > json
+}
+
+#[derive(Serialize, Debug)]
+pub struct ExportedLink {
+    pub id: String,
+    pub url: String,
+    pub created_at: DateTime<Utc>,
+    pub click_count: i32,
+}
+
+/// Streams all links as newline delimited JSON.
+pub async fn handle_export(State(app): State<Arc<App>>) -> impl IntoResponse {
+    info!("handle_export");
+
+    let lines = app.export_links().map(|link| {
+        let link = link?;
+        let mut line = serde_json::to_vec(&ExportedLink {
+            id: link.id,
+            url: link.url,
+            created_at: link.created_at,
+            click_count: link.click_count,
+        })?;
+        line.push(b'\n');
+        Ok::<_, BoxError>(line)
+    });
+
+    (
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(lines),
+    )
+}
+
+/// Streams the links of the caller's key as newline delimited JSON, the
+/// owner's counterpart to [`handle_export`].
+pub async fn handle_export_own(
+    Extension(AuthenticatedKey(api_key)): Extension<AuthenticatedKey>,
+    State(app): State<Arc<App>>,
+) -> impl IntoResponse {
+    info!(api_key, "handle_export_own");
+
+    let lines = app.export_owned_links(&api_key).map(|link| {
+        let mut line = serde_json::to_vec(&link?)?;
+        line.push(b'\n');
+        Ok::<_, BoxError>(line)
+    });
+
+    (
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(lines),
+    )
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ListLinksQuery {
+    /// Id of the last link of the previous page.
+    pub after: Option<String>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct LinksPageResponse {
+    pub links: Vec<OwnedLink>,
+    /// Passed as `after` for the next page, missing on the last one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next: Option<String>,
+}
+
+/// Lists the links of the caller's key a page at a time, see
+/// [`handle_export_own`] for all of them in one response.
+pub async fn handle_list_links(
+    Extension(AuthenticatedKey(api_key)): Extension<AuthenticatedKey>,
+    Query(query): Query<ListLinksQuery>,
+    State(app): State<Arc<App>>,
+) -> Result<Json<LinksPageResponse>, AppError> {
+    info!(api_key, after = ?query.after, "handle_list_links");
+
+    let (links, next) = app
+        .owned_links_page(&api_key, query.after.as_deref(), query.limit)
+        .await?;
+
+    Ok(Json(LinksPageResponse { links, next }))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct BlockHostRequest {
+    pub host: String,
+}
+
+pub async fn handle_block_host(
+    State(app): State<Arc<App>>,
+    Json(request): Json<BlockHostRequest>,
+) -> Result<StatusCode, AppError> {
+    info!("handle_block_host: {}", request.host);
+
+    app.block_host(&request.host).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn handle_unblock_host(
+    Path(host): Path<String>,
+    State(app): State<Arc<App>>,
+) -> Result<StatusCode, AppError> {
+    info!("handle_unblock_host: {}", host);
+
+    app.unblock_host(&host).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn handle_admin_disable_link(
+    Path(id): Path<String>,
+    State(app): State<Arc<App>>,
+) -> Result<StatusCode, AppError> {
+    info!("handle_admin_disable_link: {}", id);
+
+    app.set_disabled(None, &id, true).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn handle_admin_enable_link(
+    Path(id): Path<String>,
+    State(app): State<Arc<App>>,
+) -> Result<StatusCode, AppError> {
+    info!("handle_admin_enable_link: {}", id);
+
+    app.set_disabled(None, &id, false).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn handle_public_create(
+    _verified: TurnstileCheck,
+    ClientIp(ip): ClientIp,
+    State(app): State<Arc<App>>,
+    headers: HeaderMap,
+    Json(create): Json<CreateLinkRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    info!("handle_public_create: '{}'", create.url);
+
+    app.accept_creates()?;
+    app.limit_public_rate(ip)?;
+    app.public_cooldown(ip)?;
+
+    // slows down scripted use without refusing it
+    if let Some(delay) = app.public_tarpit(ip) {
+        info!(%ip, ?delay, "public create tarpitted");
+        tokio::time::sleep(delay).await;
+    }
+
+    let mut link = app
+        .create_link(
+            "public".to_string(),
+            LinkSource::Public,
+            create,
+            false,
+            Some(ip),
+        )
+        .await?;
+    apply_forwarded_proto(&app, &headers, &mut link);
+
+    let id = link.id.clone();
+
+    // e.g. `curl -H 'Accept: text/plain' ... | pbcopy`
+    if prefers_plain_text(&headers) {
+        let response = (
+            [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+            link.shortened_url,
+        )
+            .into_response();
+        return Ok(with_link_id(&app, &id, response));
+    }
+
+    if wants_minimal_response(&app, &headers) {
+        let response = Json(MinimalLinkResponse {
+            short_url: link.shortened_url,
+        })
+        .into_response();
+        return Ok(with_link_id(&app, &id, response));
+    }
+
+    Ok(with_link_id(&app, &id, Json(link).into_response()))
+}
+
+/// Bytes of a paid create request [`x402_host_price`] reads at most.
+const X402_BODY_LIMIT: usize = 64 * 1024;
+
+/// The payment of the `X-Payment` header, `None` if missing or invalid.
+fn x402_payment(headers: &HeaderMap) -> Option<PaymentPayload> {
+    headers
+        .get("x-payment")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|s| {
+            let base64_bytes = x402_rs::types::Base64Bytes(Cow::Borrowed(s.as_bytes()));
+            PaymentPayload::try_from(base64_bytes).ok()
+        })
+}
+
+/// Turns down paid creates authorizing less than the price of their
+/// destination host, see [`App::x402_price_for`]. Runs before the x402
+/// middleware settles the payment, as it only charges the flat price, so an
+/// underpaying client is never charged for a refused link. Requests without
+/// a payment or url are left to the middleware and [`handle_x402_create`].
+pub async fn x402_host_price(
+    State(app): State<Arc<App>>,
+    request: Request<Body>,
+    next: Next,
+) -> Result<Response, AppError> {
+    let (parts, body) = request.into_parts();
+    let body = axum::body::to_bytes(body, X402_BODY_LIMIT)
+        .await
+        .map_err(|e| anyhow::anyhow!("failed to read request body: {e}"))?;
+
+    if let Some(payment) = x402_payment(&parts.headers)
+        && let x402_rs::types::ExactPaymentPayload::Evm(evm_payload) = &payment.payload
+        && let Ok(create) = serde_json::from_slice::<CreateLinkRequest>(&body)
+        && let Some(required) = app.x402_price_for(&create.url)
+    {
+        let amount = evm_payload.authorization.value.0.to_string();
+        // amounts beyond u128 certainly cover any price
+        let paid = amount.parse::<u128>().unwrap_or(u128::MAX);
+        if paid < u128::from(required) {
+            warn!(amount, required, url = %create.url, "x402 payment below host price");
+            app.count_x402_payment(&network_to_db_string(payment.network), "rejected");
+            return Err(LinkError::InsufficientPayment { required }.into());
+        }
+    }
+
+    Ok(next.run(Request::from_parts(parts, Body::from(body))).await)
+}
+
+pub async fn handle_x402_create(
+    Extension(settlement): Extension<Option<SettleResponse>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    State(app): State<Arc<App>>,
+    headers: HeaderMap,
+    Json(create): Json<CreateLinkRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    info!(url = %create.url, "handle_x402_create");
+
+    // Extract payment details from the X-Payment header
+    let payment = x402_payment(&headers)
+        .ok_or_else(|| anyhow::anyhow!("Missing or invalid X-Payment header"))?;
+
+    // Testnet payments only get the demo response unless the operator opted
+    // into persisting them via `--x402-persist-testnet`.
+    let is_testnet = payment.network == Network::BaseSepolia;
+    let demo_mode = is_testnet && !app.config().x402_persist_testnet;
+    let network = network_to_db_string(payment.network);
+
+    let outcome = async {
+        // Extract payment amount and addresses from EVM payload
+        let (amount, from, to) = match &payment.payload {
+            x402_rs::types::ExactPaymentPayload::Evm(evm_payload) => {
+                let amount = evm_payload.authorization.value.0.to_string();
+                let from = evm_payload.authorization.from.to_string();
+                let to = evm_payload.authorization.to.to_string();
+                (amount, from, to)
+            }
+            x402_rs::types::ExactPaymentPayload::Solana(_) => {
+                return Err(match app.config().x402_solana {
+                    SolanaPayments::RejectLoud => {
+                        error!("rejected x402 payment on Solana");
+                        anyhow::anyhow!("Solana payments are not supported")
+                    }
+                    SolanaPayments::RejectQuiet => {
+                        debug!("rejected x402 payment on Solana");
+                        LinkError::UnsupportedPayment("Solana").into()
+                    }
+                });
+            }
+        };
+
+        // Extract transaction hash from the settlement extension (error if missing)
+        let tx_hash = settlement
+            .and_then(|s| s.transaction)
+            .map(|tx| tx.to_string())
+            .ok_or_else(|| anyhow::anyhow!("No transaction hash in settlement"))?;
+
+        info!(
+            network = ?payment.network,
+            amount = %amount,
+            from = %from,
+            to = %to,
+            tx_hash = %tx_hash,
+            "x402 payment details"
+        );
+
+        if demo_mode {
+            app.record_demo_request(&create.url, Some(peer.ip().to_string()))
+                .await;
+        }
+
+        let mut response = app
+            .create_link(
+                "x402".to_string(),
+                LinkSource::X402,
+                create,
+                demo_mode,
+                Some(peer.ip()),
+            )
+            .await?;
+        apply_forwarded_proto(&app, &headers, &mut response);
+
+        app.store_transaction(CreateTransaction {
+            link_id: response.id.clone(),
+            tx_hash,
+            network: network.clone(),
+            amount: Some(amount),
+            payer: Some(from),
+        })
+        .await?;
+
+        Ok(response)
+    }
+    .await;
+
+    let result = match (&outcome, demo_mode) {
+        (Err(_), _) => "rejected",
+        (Ok(_), true) => "demo",
+        (Ok(_), false) => "created",
+    };
+    app.count_x402_payment(&network, result);
+
+    let link = outcome?;
+    let id = link.id.clone();
+
+    Ok(with_link_id(&app, &id, Json(link).into_response()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        app::AppConfig,
+        counter::ClickCounter,
+        db::{DbError, MockLinksDB},
+        models::{CreateLink, FetchLink, LinkDetails, Transaction},
+    };
+    use std::collections::HashMap;
+    use x402_rs::types::{
+        ExactEvmPayload, ExactEvmPayloadAuthorization, ExactPaymentPayload, ExactSolanaPayload,
+    };
+
+    fn test_app(db: MockLinksDB, x402_persist_testnet: bool) -> Arc<App> {
+        App::new(
+            AppConfig {
+                prefix: "http://localhost:8080".to_string(),
+                cache_size: 10,
+                x402_persist_testnet,
+                ..Default::default()
+            },
+            Arc::new(db),
+            Arc::new(ClickCounter::new()),
+        )
+    }
+
+    fn payment_headers(network: Network) -> HeaderMap {
+        encode_payment(
+            network,
+            ExactPaymentPayload::Evm(ExactEvmPayload {
+                signature: x402_rs::types::EvmSignature(vec![0u8; 65]),
+                authorization: ExactEvmPayloadAuthorization {
+                    from: "0x0000000000000000000000000000000000000000"
+                        .parse()
+                        .unwrap(),
+                    to: "0x0000000000000000000000000000000000000000"
+                        .parse()
+                        .unwrap(),
+                    value: x402_rs::types::TokenAmount(
+                        x402_rs::__reexports::alloy::primitives::U256::from(1000000),
+                    ),
+                    valid_after: x402_rs::timestamp::UnixTimestamp(0),
+                    valid_before: x402_rs::timestamp::UnixTimestamp(u64::MAX),
+                    nonce: x402_rs::types::HexEncodedNonce([0u8; 32]),
+                },
+            }),
+        )
+    }
+
+    fn encode_payment(network: Network, payload: ExactPaymentPayload) -> HeaderMap {
+        let payment = PaymentPayload {
+            x402_version: x402_rs::types::X402Version::V1,
+            scheme: x402_rs::types::Scheme::Exact,
+            network,
+            payload,
+        };
+
+        // Encode the payment as base64 JSON
+        let payment_json = serde_json::to_string(&payment).unwrap();
+        let payment_base64 = base64::Engine::encode(
+            &base64::engine::general_purpose::STANDARD,
+            payment_json.as_bytes(),
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-payment", HeaderValue::from_str(&payment_base64).unwrap());
+        headers
+    }
+
+    fn mock_settlement(network: Network) -> SettleResponse {
+        SettleResponse {
+            success: true,
+            error_reason: None,
+            payer: x402_rs::types::MixedAddress::from(
+                "0x0000000000000000000000000000000000000000"
+                    .parse::<x402_rs::types::EvmAddress>()
+                    .unwrap(),
+            ),
+            transaction: Some(x402_rs::types::TransactionHash::Evm([0x12; 32])),
+            network,
+        }
+    }
+
+    async fn json_body<T: serde::de::DeserializeOwned>(response: impl IntoResponse) -> T {
+        let body = axum::body::to_bytes(response.into_response().into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    fn health_app(db: MockLinksDB) -> Arc<App> {
+        App::new(
+            AppConfig {
+                health_format: HealthFormat::Json,
+                ..Default::default()
+            },
+            Arc::new(db),
+            Arc::new(ClickCounter::new()),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_health_json_pass() {
+        let mut db = MockLinksDB::new();
+        db.expect_ping().returning(|| Ok(()));
+        db.expect_pool_status().returning(|| None);
+
+        let response = handle_health(State(health_app(db))).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], HEALTH_MEDIA_TYPE);
+
+        let json: serde_json::Value = json_body(response).await;
+        assert_eq!(json["status"], "pass");
+        assert_eq!(json["checks"]["database:connectivity"][0]["status"], "pass");
+    }
+
+    #[tokio::test]
+    async fn test_health_json_failing_check() {
+        let mut db = MockLinksDB::new();
+        db.expect_ping()
+            .returning(|| Err(DbError::General(String::from("connection refused"))));
+        db.expect_pool_status().returning(|| None);
+
+        let response = handle_health(State(health_app(db))).await;
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let json: serde_json::Value = json_body(response).await;
+        assert_eq!(json["status"], "fail");
+        let check = &json["checks"]["database:connectivity"][0];
+        assert_eq!(check["status"], "fail");
+        assert_eq!(check["componentType"], "datastore");
+        assert!(
+            check["output"]
+                .as_str()
+                .unwrap()
+                .contains("connection refused")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_health_page() {
+        let mut db = MockLinksDB::new();
+        db.expect_ping().returning(|| Ok(()));
+        db.expect_pool_status().returning(|| None);
+        let app = App::new(
+            AppConfig {
+                health_page: String::from("<p>ezlime is up</p>"),
+                ..Default::default()
+            },
+            Arc::new(db),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let response = handle_health(State(app)).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, "<p>ezlime is up</p>");
+    }
+
+    #[tokio::test]
+    async fn test_probes() {
+        for healthy in [true, false] {
+            let mut db = MockLinksDB::new();
+            db.expect_ping().returning(move || {
+                if healthy {
+                    Ok(())
+                } else {
+                    Err(DbError::General(String::from("connection refused")))
+                }
+            });
+            db.expect_pool_status().returning(|| None);
+            let app = App::new(
+                AppConfig::default(),
+                Arc::new(db),
+                Arc::new(ClickCounter::new()),
+            );
+
+            let live = handle_livez().await.into_response();
+            assert_eq!(live.status(), StatusCode::OK);
+
+            let ready = handle_health(State(app)).await;
+            let expected = if healthy {
+                StatusCode::OK
+            } else {
+                StatusCode::SERVICE_UNAVAILABLE
+            };
+            assert_eq!(ready.status(), expected);
+        }
+    }
+
+    fn peer() -> SocketAddr {
+        SocketAddr::from(([203, 0, 113, 7], 40000))
+    }
+
+    #[tokio::test]
+    async fn test_handle_x402_create_records_demo_request() {
+        let mut db = MockLinksDB::with_empty_blocklist();
+        db.expect_create().never();
+        db.expect_create_transaction().returning(|_| Ok(()));
+        db.expect_create_demo_request()
+            .times(1)
+            .returning(|request| {
+                assert_eq!(request.url, "https://example.com/test");
+                assert_eq!(request.source_ip.as_deref(), Some("203.0.113.7"));
+                Ok(())
+            });
+
+        let app = App::new(
+            AppConfig {
+                record_demo_requests: true,
+                ..Default::default()
+            },
+            Arc::new(db),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let response = handle_x402_create(
+            Extension(Some(mock_settlement(Network::BaseSepolia))),
+            ConnectInfo(peer()),
+            State(app),
+            payment_headers(Network::BaseSepolia),
+            Json(CreateLinkRequest {
+                url: "https://example.com/test".to_string(),
+                ..Default::default()
+            }),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            json_body::<CreatedLinkResponse>(response).await.id,
+            "rustunit"
+        );
+    }
+
+    #[test]
+    fn test_network_db_strings() {
+        assert_eq!(network_to_db_string(Network::Base), "base");
+        assert_eq!(network_to_db_string(Network::BaseSepolia), "base-sepolia");
+    }
+
+    #[tokio::test]
+    async fn test_handle_x402_create_sepolia_returns_demo() {
+        let mut db = MockLinksDB::with_empty_blocklist();
+        db.expect_create().never();
+        db.expect_create_transaction().returning(|_| Ok(()));
+
+        let app = test_app(db, false);
+
+        let test_url = "https://example.com/test".to_string();
+        let request = CreateLinkRequest {
+            url: test_url.clone(),
+            ..Default::default()
+        };
+
+        let result = handle_x402_create(
+            Extension(Some(mock_settlement(Network::BaseSepolia))),
+            ConnectInfo(peer()),
+            State(app),
+            payment_headers(Network::BaseSepolia),
+            Json(request),
+        )
+        .await;
+
+        let response_data = json_body::<CreatedLinkResponse>(result.unwrap()).await;
+
+        // Verify it's the demo response
+        assert_eq!(response_data.id, "rustunit");
+        assert_eq!(
+            response_data.shortened_url,
+            "http://localhost:8080/rustunit"
+        );
+        assert_eq!(response_data.original_url, test_url);
+    }
+
+    #[tokio::test]
+    async fn test_x402_payment_metrics() {
+        let mut db = MockLinksDB::with_empty_blocklist();
+        db.expect_create_transaction().returning(|_| Ok(()));
+        db.expect_pool_status().returning(|| None);
+
+        let app = test_app(db, false);
+
+        handle_x402_create(
+            Extension(Some(mock_settlement(Network::BaseSepolia))),
+            ConnectInfo(peer()),
+            State(app.clone()),
+            payment_headers(Network::BaseSepolia),
+            Json(CreateLinkRequest {
+                url: "https://example.com/test".to_string(),
+                ..Default::default()
+            }),
+        )
+        .await
+        .unwrap();
+
+        let metrics = app.render_metrics().unwrap();
+        assert!(metrics.contains(r#"x402_payments_total{network="base-sepolia",result="demo"} 1"#));
+
+        let response = handle_metrics(
+            Query(MetricsQuery {
+                format: MetricsFormat::Json,
+            }),
+            State(app),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+        let metrics: serde_json::Value = json_body(response).await;
+        for key in ["db_pool_available", "db_pool_in_use", "db_pool_max"] {
+            assert_eq!(metrics[key]["type"], "gauge");
+        }
+        assert_eq!(
+            metrics["x402_payments_total"],
+            serde_json::json!({
+                "type": "counter",
+                "help": "Paid link requests by outcome",
+                "samples": [{
+                    "labels": { "network": "base-sepolia", "result": "demo" },
+                    "value": 1.0,
+                }],
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_x402_premium_host_price() {
+        use tower::ServiceExt;
+
+        // the test payment authorizes 1 USDC
+        let app = App::new(
+            AppConfig {
+                x402_host_prices: HashMap::from([
+                    (String::from("premium.com"), 2_000_000),
+                    (String::from("cheap.com"), 500_000),
+                ]),
+                ..Default::default()
+            },
+            Arc::new(MockLinksDB::new()),
+            Arc::new(ClickCounter::new()),
+        );
+
+        // stands in for the x402 middleware, which would settle the payment
+        let router = axum::Router::new()
+            .route("/x402/shorten", axum::routing::post(|| async { "settled" }))
+            .layer(axum::middleware::from_fn_with_state(
+                app.clone(),
+                x402_host_price,
+            ));
+
+        let create = |url: &str| {
+            let mut request = Request::post("/x402/shorten")
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(serde_json::json!({ "url": url }).to_string()))
+                .unwrap();
+            request
+                .headers_mut()
+                .extend(payment_headers(Network::BaseSepolia));
+            router.clone().oneshot(request)
+        };
+
+        // turned down before it reaches the settlement
+        let response = create("https://www.premium.com/page").await.unwrap();
+        assert_eq!(response.status(), StatusCode::PAYMENT_REQUIRED);
+
+        let response = create("https://cheap.com/page").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // hosts without a price of their own pay the flat price
+        let response = create("https://example.com").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_handle_x402_create_sepolia_persisted() {
+        let mut db = MockLinksDB::with_empty_blocklist();
+        db.expect_create()
+            .times(1)
+            .returning(|link: &CreateLink| Ok(link.clone()));
+        db.expect_create_transaction().times(1).returning(|tx| {
+            assert_eq!(tx.network, "base-sepolia");
+            assert_eq!(tx.amount.as_deref(), Some("1000000"));
+            Ok(())
+        });
+
+        let app = test_app(db, true);
+
+        let test_url = "https://example.com/test".to_string();
+        let request = CreateLinkRequest {
+            url: test_url.clone(),
+            ..Default::default()
+        };
+
+        let result = handle_x402_create(
+            Extension(Some(mock_settlement(Network::BaseSepolia))),
+            ConnectInfo(peer()),
+            State(app),
+            payment_headers(Network::BaseSepolia),
+            Json(request),
+        )
+        .await;
+
+        let response_data = json_body::<CreatedLinkResponse>(result.unwrap()).await;
+
+        // A real link was created instead of the demo response
+        assert_ne!(response_data.id, "rustunit");
+        assert_eq!(
+            response_data.shortened_url,
+            format!("http://localhost:8080/{}", response_data.id)
+        );
+        assert_eq!(response_data.original_url, test_url);
+    }
+
+    #[tokio::test]
+    async fn test_handle_x402_create_without_payment_header() {
+        let app = test_app(MockLinksDB::new(), false);
+
+        // Create headers without X-Payment header
+        let headers = HeaderMap::new();
+
+        // Create the request
+        let request = CreateLinkRequest {
+            url: "https://example.com/test".to_string(),
+            ..Default::default()
+        };
+
+        // Call the handler - should fail without X-Payment header
+        let result = handle_x402_create(
+            Extension(None),
+            ConnectInfo(peer()),
+            State(app),
+            headers,
+            Json(request),
+        )
+        .await;
+
+        assert!(result.is_err());
+    }
+
+    /// Records the level of every event.
+    #[derive(Clone, Default)]
+    struct Levels(Arc<std::sync::Mutex<Vec<tracing::Level>>>);
+
+    impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for Levels {
+        fn on_event(
+            &self,
+            event: &tracing::Event<'_>,
+            _ctx: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            self.0.lock().unwrap().push(*event.metadata().level());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handle_x402_create_solana() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        for (mode, status, level) in [
+            (
+                SolanaPayments::RejectLoud,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                tracing::Level::ERROR,
+            ),
+            (
+                SolanaPayments::RejectQuiet,
+                StatusCode::BAD_REQUEST,
+                tracing::Level::DEBUG,
+            ),
+        ] {
+            let levels = Levels::default();
+            let _guard = tracing::subscriber::set_default(
+                tracing_subscriber::registry().with(levels.clone()),
+            );
+
+            let mut db = MockLinksDB::new();
+            db.expect_create().never();
+            let app = App::new(
+                AppConfig {
+                    x402_solana: mode,
+                    ..Default::default()
+                },
+                Arc::new(db),
+                Arc::new(ClickCounter::new()),
+            );
+
+            let response = handle_x402_create(
+                Extension(None),
+                ConnectInfo(peer()),
+                State(app),
+                encode_payment(
+                    Network::Solana,
+                    ExactPaymentPayload::Solana(ExactSolanaPayload {
+                        transaction: String::from("AQ=="),
+                    }),
+                ),
+                Json(CreateLinkRequest {
+                    url: "https://example.com/test".to_string(),
+                    ..Default::default()
+                }),
+            )
+            .await
+            .into_response();
+
+            assert_eq!(response.status(), status, "{mode:?}");
+            let levels = levels.0.lock().unwrap();
+            assert!(levels.contains(&level), "{mode:?}: {levels:?}");
+            assert!(
+                level == tracing::Level::ERROR || !levels.contains(&tracing::Level::ERROR),
+                "{mode:?}: {levels:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handle_create_at_link_limit() {
+        let mut db = MockLinksDB::with_empty_blocklist();
+        db.expect_count().times(1).returning(|| Ok(1));
+        db.expect_get().returning(|_| Ok(None));
+        db.expect_create().never();
+
+        let app = App::new(
+            AppConfig {
+                max_total_links: Some(1),
+                ..Default::default()
+            },
+            Arc::new(db),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let response = handle_create(
+            Extension(AuthenticatedKey("key".to_string())),
+            ConnectInfo(peer()),
+            State(app),
+            Query(CreateQuery::default()),
+            HeaderMap::new(),
+            Json(CreateLinkRequest {
+                url: "https://www.rustunit.com".to_string(),
+                ..Default::default()
+            }),
+        )
+        .await
+        .into_response();
+
+        assert_eq!(response.status(), StatusCode::INSUFFICIENT_STORAGE);
+    }
+
+    #[tokio::test]
+    async fn test_handle_create_validation_failed() {
+        let mut db = MockLinksDB::with_empty_blocklist();
+        db.expect_create().never();
+        let app = App::new(
+            AppConfig {
+                validation_rules: ValidationRules {
+                    max_url_length: 30,
+                    ..AppConfig::default().validation_rules
+                },
+                ..Default::default()
+            },
+            Arc::new(db),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let response = handle_create(
+            Extension(AuthenticatedKey("key".to_string())),
+            ConnectInfo(peer()),
+            State(app),
+            Query(CreateQuery::default()),
+            HeaderMap::new(),
+            Json(CreateLinkRequest {
+                url: "ftp://www.rustunit.com/far/too/long".to_string(),
+                ..Default::default()
+            }),
+        )
+        .await
+        .into_response();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = json_body(response).await;
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "error": "validation_failed",
+                "violations": ["url_too_long", "scheme_not_allowed"],
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_handle_create_writes_audit_log() {
+        let mut db = create_db();
+        db.expect_create_audit_entry().times(1).returning(|entry| {
+            assert_eq!(entry.action, "create");
+            assert_eq!(entry.link_id, "as9sud");
+            assert_eq!(entry.url, "https://www.rustunit.com");
+            assert_eq!(entry.key.as_deref(), Some("key"));
+            assert_eq!(entry.source, "api");
+            assert_eq!(entry.source_ip.as_deref(), Some("203.0.113.7"));
+            // not fatal, the link was created already
+            Err(DbError::General(String::from("audit_log unavailable")))
+        });
+        let app = App::new(
+            AppConfig {
+                audit_log: true,
+                ..Default::default()
+            },
+            Arc::new(db),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let response = handle_create(
+            Extension(AuthenticatedKey("key".to_string())),
+            ConnectInfo(peer()),
+            State(app),
+            Query(CreateQuery::default()),
+            HeaderMap::new(),
+            Json(CreateLinkRequest {
+                url: "https://www.rustunit.com".to_string(),
+                ..Default::default()
+            }),
+        )
+        .await
+        .into_response();
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_handle_create_link_id_header() {
+        let app = App::new(
+            AppConfig {
+                link_id_header: true,
+                ..Default::default()
+            },
+            Arc::new(create_db()),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let response = handle_create(
+            Extension(AuthenticatedKey("key".to_string())),
+            ConnectInfo(peer()),
+            State(app),
+            Query(CreateQuery::default()),
+            HeaderMap::new(),
+            Json(CreateLinkRequest {
+                url: "https://www.rustunit.com".to_string(),
+                ..Default::default()
+            }),
+        )
+        .await
+        .into_response();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[LINK_ID_HEADER], "as9sud");
+        let link: serde_json::Value = json_body(response).await;
+        assert_eq!(link["id"], "as9sud");
+    }
+
+    #[tokio::test]
+    async fn test_handle_events_streams_created_link() {
+        let app = test_app(create_db(), false);
+
+        let mut events = handle_events(State(app.clone()))
+            .await
+            .into_response()
+            .into_body()
+            .into_data_stream();
+
+        handle_create(
+            Extension(AuthenticatedKey("key".to_string())),
+            ConnectInfo(peer()),
+            State(app),
+            Query(CreateQuery::default()),
+            HeaderMap::new(),
+            Json(CreateLinkRequest {
+                url: "https://www.rustunit.com".to_string(),
+                ..Default::default()
+            }),
+        )
+        .await
+        .unwrap();
+
+        let frame = events.next().await.unwrap().unwrap();
+        let frame = std::str::from_utf8(&frame).unwrap();
+        let data = frame.strip_prefix("data: ").unwrap().trim_end();
+        let event: serde_json::Value = serde_json::from_str(data).unwrap();
+
+        assert_eq!(event["type"], "created");
+        assert_eq!(event["url"], "https://www.rustunit.com");
+        assert_eq!(event["source"], "api");
+    }
+
+    async fn public_create(app: Arc<App>, headers: HeaderMap) -> serde_json::Value {
+        let response = handle_public_create(
+            TurnstileCheck,
+            ClientIp(peer().ip()),
+            State(app),
+            headers,
+            Json(CreateLinkRequest {
+                url: "https://www.rustunit.com".to_string(),
+                ..Default::default()
+            }),
+        )
+        .await
+        .unwrap()
+        .into_response();
+
+        json_body(response).await
+    }
+
+    fn create_db() -> MockLinksDB {
+        let mut db = MockLinksDB::with_empty_blocklist();
+        db.expect_create().returning(|link| Ok(link.clone()));
+        db
+    }
+
+    #[tokio::test]
+    async fn test_handle_public_create_minimal_by_accept_header() {
+        let app = test_app(create_db(), false);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::ACCEPT,
+            HeaderValue::from_static(MINIMAL_RESPONSE_MEDIA_TYPE),
+        );
+
+        let body = public_create(app, headers).await;
+
+        assert_eq!(
+            body,
+            serde_json::json!({ "short_url": "http://localhost:8080/as9sud" })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_handle_public_create_plain_text() {
+        for (accept, plain) in [
+            ("text/plain", true),
+            ("text/plain, */*;q=0.8", true),
+            ("application/json;q=0.5, text/plain", true),
+            ("application/json, text/plain", false),
+            ("text/plain;q=0.2, application/json", false),
+            ("*/*", false),
+        ] {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::ACCEPT, HeaderValue::from_static(accept));
+
+            let response = handle_public_create(
+                TurnstileCheck,
+                ClientIp(peer().ip()),
+                State(test_app(create_db(), false)),
+                headers,
+                Json(CreateLinkRequest {
+                    url: "https://www.rustunit.com".to_string(),
+                    ..Default::default()
+                }),
+            )
+            .await
+            .unwrap()
+            .into_response();
+
+            let content_type = response.headers()[header::CONTENT_TYPE].clone();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            if plain {
+                assert_eq!(content_type, "text/plain; charset=utf-8", "{accept}");
+                assert_eq!(body, "http://localhost:8080/as9sud", "{accept}");
+            } else {
+                assert_eq!(content_type, "application/json", "{accept}");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handle_public_create_minimal_by_config() {
+        let app = App::new(
+            AppConfig {
+                public_minimal_response: true,
+                ..Default::default()
+            },
+            Arc::new(create_db()),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let body = public_create(app, HeaderMap::new()).await;
+
+        assert_eq!(
+            body,
+            serde_json::json!({ "short_url": "http://localhost:8080/as9sud" })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_handle_create_forwarded_proto() {
+        let create = |app: Arc<App>| async move {
+            let mut headers = HeaderMap::new();
+            headers.insert(FORWARDED_PROTO_HEADER, HeaderValue::from_static("https"));
+
+            let response = handle_create(
+                Extension(AuthenticatedKey("key".to_string())),
+                ConnectInfo(peer()),
+                State(app),
+                Query(CreateQuery::default()),
+                headers,
+                Json(CreateLinkRequest {
+                    url: "https://www.rustunit.com".to_string(),
+                    ..Default::default()
+                }),
+            )
+            .await
+            .unwrap();
+
+            json_body::<CreatedLinkResponse>(response)
+                .await
+                .shortened_url
+        };
+
+        let trusting = App::new(
+            AppConfig {
+                trust_forwarded_proto: true,
+                ..Default::default()
+            },
+            Arc::new(create_db()),
+            Arc::new(ClickCounter::new()),
+        );
+        assert_eq!(create(trusting).await, "https://localhost:8080/as9sud");
+
+        let untrusting = test_app(create_db(), false);
+        assert_eq!(create(untrusting).await, "http://localhost:8080/as9sud");
+    }
+
+    #[tokio::test]
+    async fn test_handle_create_include_qr() {
+        let create = |app: Arc<App>, include_qr: Option<&'static str>| async move {
+            let response = handle_create(
+                Extension(AuthenticatedKey("key".to_string())),
+                ConnectInfo(peer()),
+                State(app),
+                Query(CreateQuery {
+                    include_qr: include_qr.map(str::to_string),
+                }),
+                HeaderMap::new(),
+                Json(CreateLinkRequest {
+                    url: "https://www.rustunit.com".to_string(),
+                    ..Default::default()
+                }),
+            )
+            .await
+            .unwrap()
+            .into_response();
+
+            json_body::<serde_json::Value>(response).await
+        };
+
+        let json = create(test_app(create_db(), false), Some("1")).await;
+        let uri = json["qr_data_uri"].as_str().unwrap();
+        let png = uri.strip_prefix("data:image/png;base64,").unwrap();
+        let png = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, png).unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+        assert_eq!(json["id"], "as9sud");
+
+        let json = create(test_app(create_db(), false), None).await;
+        assert!(json.get("qr_data_uri").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_handle_import_csv() {
+        let mut db = MockLinksDB::with_empty_blocklist();
+        db.expect_create().times(2).returning(|link| {
+            if link.id == "dup" {
+                return Err(DbError::DuplicateId);
+            }
+            assert_eq!(link.key, "key");
+            Ok(link.clone())
+        });
+
+        let csv = "id,url\n\
+            abc,https://www.rustunit.com\n\
+            dup,https://www.rustunit.com/dup\n\
+            Not Valid,https://www.rustunit.com/invalid\n\
+            missing-url\n";
+
+        let Json(summary) = handle_import_csv(
+            Extension(AuthenticatedKey("key".to_string())),
+            State(test_app(db, false)),
+            Query(ImportQuery::default()),
+            Body::from(csv),
+        )
+        .await;
+
+        assert_eq!(summary.imported, 1);
+        assert_eq!(summary.conflicts, vec![String::from("dup")]);
+
+        let failed_lines: Vec<u64> = summary.errors.iter().map(|e| e.line).collect();
+        assert_eq!(failed_lines, vec![4, 5]);
+    }
+
+    #[tokio::test]
+    async fn test_handle_public_create_tarpit() {
+        let mut db = MockLinksDB::with_empty_blocklist();
+        db.expect_create().returning(|link| Ok(link.clone()));
+        let app = App::new(
+            AppConfig {
+                public_tarpit_threshold: Some(2),
+                public_tarpit_delay_ms: 200,
+                ..Default::default()
+            },
+            Arc::new(db),
+            Arc::new(ClickCounter::new()),
+        );
+
+        for _ in 0..2 {
+            let started = std::time::Instant::now();
+            public_create(Arc::clone(&app), HeaderMap::new()).await;
+            assert!(started.elapsed() < std::time::Duration::from_millis(200));
+        }
+
+        let started = std::time::Instant::now();
+        let body = public_create(app, HeaderMap::new()).await;
+        assert!(started.elapsed() >= std::time::Duration::from_millis(200));
+        assert_eq!(body["id"], "as9sud");
+    }
+
+    #[tokio::test]
+    async fn test_handle_public_create_rate_limit() {
+        let app = App::new(
+            AppConfig {
+                public_rate_limit: Some(2),
+                ..Default::default()
+            },
+            Arc::new(create_db()),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let create = |peer| {
+            handle_public_create(
+                TurnstileCheck,
+                ClientIp(peer.ip()),
+                State(Arc::clone(&app)),
+                HeaderMap::new(),
+                Json(CreateLinkRequest {
+                    url: "https://www.rustunit.com".to_string(),
+                    ..Default::default()
+                }),
+            )
+        };
+
+        for _ in 0..2 {
+            let response = create(peer()).await.unwrap().into_response();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        let response = create(peer()).await.into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(response.headers().contains_key(header::RETRY_AFTER));
+
+        let other = SocketAddr::from(([203, 0, 113, 8], 40000));
+        let response = create(other).await.unwrap().into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_client_ip() {
+        let client_ip = |trust_forwarded_for, forwarded: Option<&'static str>| {
+            let app = App::new(
+                AppConfig {
+                    trust_forwarded_for,
+                    ..Default::default()
+                },
+                Arc::new(MockLinksDB::new()),
+                Arc::new(ClickCounter::new()),
+            );
+            let mut request = axum::http::Request::new(());
+            request.extensions_mut().insert(ConnectInfo(peer()));
+            if let Some(forwarded) = forwarded {
+                request
+                    .headers_mut()
+                    .insert(FORWARDED_FOR_HEADER, HeaderValue::from_static(forwarded));
+            }
+            let (mut parts, _) = request.into_parts();
+            async move {
+                let Ok(ClientIp(ip)) = ClientIp::from_request_parts(&mut parts, &app).await else {
+                    panic!("no client ip");
+                };
+                ip
+            }
+        };
+
+        let proxy = peer().ip();
+        let client: IpAddr = "198.51.100.4".parse().unwrap();
+        // the first entry is whatever the client sent
+        let forwarded = Some("10.0.0.1, 198.51.100.4");
+
+        assert_eq!(client_ip(false, forwarded).await, proxy);
+        assert_eq!(client_ip(true, forwarded).await, client);
+        assert_eq!(client_ip(true, None).await, proxy);
+        assert_eq!(client_ip(true, Some("garbage")).await, proxy);
+    }
+
+    #[tokio::test]
+    async fn test_handle_public_create_cooldown() {
+        let app = App::new(
+            AppConfig {
+                public_cooldown_secs: Some(30),
+                ..Default::default()
+            },
+            Arc::new(create_db()),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let create = |peer| {
+            handle_public_create(
+                TurnstileCheck,
+                ClientIp(peer.ip()),
+                State(Arc::clone(&app)),
+                HeaderMap::new(),
+                Json(CreateLinkRequest {
+                    url: "https://www.rustunit.com".to_string(),
+                    ..Default::default()
+                }),
+            )
+        };
+
+        let response = create(peer()).await.unwrap().into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = create(peer()).await.into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let retry_after: u64 = response.headers()[header::RETRY_AFTER]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!((29..=30).contains(&retry_after), "{retry_after}");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(
+            String::from_utf8_lossy(&body).starts_with("one link per 30s, retry in "),
+            "{body:?}"
+        );
+
+        let other = SocketAddr::from(([203, 0, 113, 8], 40000));
+        let response = create(other).await.unwrap().into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_handle_public_create_full_by_default() {
+        let app = test_app(create_db(), false);
+
+        let body = public_create(app, HeaderMap::new()).await;
+
+        assert_eq!(body["id"], "as9sud");
+        assert_eq!(body["original_url"], "https://www.rustunit.com");
+    }
+
+    fn owned_link_db() -> MockLinksDB {
+        paid_link_db(Vec::new())
+    }
+
+    /// [`owned_link_db`] with x402 payments on `networks`.
+    fn paid_link_db(networks: Vec<&'static str>) -> MockLinksDB {
+        let mut db = MockLinksDB::new();
+        db.expect_transactions_for().returning(move |_| {
+            Ok(networks
+                .iter()
+                .enumerate()
+                .map(|(i, network)| Transaction {
+                    network: network.to_string(),
+                    tx_hash: format!("0x{i}"),
+                    amount: None,
+                    payer: None,
+                })
+                .collect())
+        });
+        db.expect_get_details().returning(|id| {
+            Ok(Some(LinkDetails {
+                id: id.to_string(),
+                url: "https://www.rustunit.com".to_string(),
+                created_at: chrono::DateTime::UNIX_EPOCH,
+                key: Some("owner".to_string()),
+                click_count: 0,
+                last_used: chrono::DateTime::UNIX_EPOCH,
+                title: None,
+                first_used: None,
+                creator_ip: None,
+                note: Some("Q3 launch tweet".to_string()),
+            }))
+        });
+        db
+    }
+
+    #[tokio::test]
+    async fn test_public_stats_fields() {
+        let app = App::new(
+            AppConfig {
+                public_stats_fields: vec![PublicStatsField::ClickCount],
+                ..Default::default()
+            },
+            Arc::new(owned_link_db()),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let public = handle_public_stats(Path("as9sud".to_string()), State(app.clone()))
+            .await
+            .unwrap();
+        let public = json_body::<serde_json::Value>(public).await;
+        assert_eq!(public["id"], "as9sud");
+        assert_eq!(public["click_count"], 0);
+        assert!(public.get("last_used").is_none());
+        assert!(public.get("created_at").is_none());
+
+        let admin = handle_admin_stats(Path("as9sud".to_string()), State(app))
+            .await
+            .unwrap();
+        let admin = json_body::<serde_json::Value>(admin).await;
+        assert_eq!(admin["click_count"], 0);
+        assert!(admin.get("last_used").is_some());
+        assert!(admin.get("created_at").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_admin_stats_networks() {
+        let app = test_app(paid_link_db(vec!["base", "base", "base-sepolia"]), false);
+
+        let admin = handle_admin_stats(Path("as9sud".to_string()), State(app.clone()))
+            .await
+            .unwrap();
+        let admin = json_body::<serde_json::Value>(admin).await;
+        assert_eq!(
+            admin["networks"],
+            serde_json::json!(["base", "base-sepolia"])
+        );
+
+        let public = handle_public_stats(Path("as9sud".to_string()), State(app))
+            .await
+            .unwrap();
+        assert!(
+            json_body::<serde_json::Value>(public)
+                .await
+                .get("networks")
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_note_not_public() {
+        let app = test_app(owned_link_db(), false);
+
+        let response = handle_get_link(
+            Extension(AuthenticatedKey("owner".to_string())),
+            Path("as9sud".to_string()),
+            State(app.clone()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            json_body::<serde_json::Value>(response).await["note"],
+            "Q3 launch tweet"
+        );
+
+        let admin = handle_admin_stats(Path("as9sud".to_string()), State(app.clone()))
+            .await
+            .unwrap();
+        assert_eq!(
+            json_body::<serde_json::Value>(admin).await["note"],
+            "Q3 launch tweet"
+        );
+
+        let public = handle_public_stats(Path("as9sud".to_string()), State(app))
+            .await
+            .unwrap();
+        assert!(
+            json_body::<serde_json::Value>(public)
+                .await
+                .get("note")
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stats_timestamps_utc() {
+        let app = test_app(owned_link_db(), false);
+
+        let admin = handle_admin_stats(Path("as9sud".to_string()), State(app))
+            .await
+            .unwrap();
+        let admin = json_body::<serde_json::Value>(admin).await;
+        assert_eq!(admin["created_at"], "1970-01-01T00:00:00Z");
+        assert_eq!(admin["last_used"], "1970-01-01T00:00:00Z");
+    }
+
+    #[tokio::test]
+    async fn test_handle_get_link_as_owner() {
+        let app = test_app(owned_link_db(), false);
+
+        let response = handle_get_link(
+            Extension(AuthenticatedKey("owner".to_string())),
+            Path("as9sud".to_string()),
+            State(app),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body: serde_json::Value = json_body(response).await;
+
+        assert_eq!(body["id"], "as9sud");
+        assert_eq!(body["url"], "https://www.rustunit.com");
+        assert!(body.get("created_at").is_some());
+        assert!(body.get("key").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_handle_get_link_as_non_owner() {
+        let app = test_app(owned_link_db(), false);
+
+        let response = handle_get_link(
+            Extension(AuthenticatedKey("other".to_string())),
+            Path("as9sud".to_string()),
+            State(app),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_handle_get_link_unknown() {
+        let mut db = MockLinksDB::new();
+        db.expect_get_details().returning(|_| Ok(None));
+        let app = test_app(db, false);
+
+        let response = handle_get_link(
+            Extension(AuthenticatedKey("owner".to_string())),
+            Path("unknown".to_string()),
+            State(app),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_handle_flush_counters() {
+        let mut db = redirect_db(307);
+        db.expect_batch_update_clicks()
+            .times(1)
+            .returning(|ids, increments, _, _| {
+                assert_eq!(ids, vec!["as9sud".to_string()]);
+                assert_eq!(increments, vec![2]);
+                Ok(1)
+            });
+        let app = test_app(db, false);
+
+        for _ in 0..2 {
+            handle_redirect(
+                Path("as9sud".to_string()),
+                State(Arc::clone(&app)),
+                RawQuery(None),
+                HeaderMap::new(),
+            )
+            .await
+            .unwrap();
+        }
+
+        let Json(response) = handle_flush_counters(State(app)).await.unwrap();
+
+        assert_eq!(response.rows_updated, 1);
+    }
+
+    fn redirect_db(redirect_status: i16) -> MockLinksDB {
+        let mut db = MockLinksDB::with_empty_blocklist();
+        db.expect_get().returning(move |id| {
+            Ok(Some(FetchLink {
+                id: id.to_string(),
+                url: "https://www.rustunit.com".to_string(),
+                redirect_status,
+                password_hash: None,
+                disabled: false,
+                reserved: false,
+                click_count: 0,
+                max_clicks: None,
+                key: None,
+                expires_at: None,
+            }))
+        });
+        db
+    }
+
+    #[tokio::test]
+    async fn test_handle_redirect_permanent() {
+        let app = test_app(redirect_db(301), false);
+
+        let response = handle_redirect(
+            Path("as9sud".to_string()),
+            State(app),
+            RawQuery(None),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap()
+        .into_response();
+
+        assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(
+            response.headers()[header::LOCATION],
+            "https://www.rustunit.com"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_handle_redirect_head() {
+        let app = test_app(redirect_db(301), false);
+
+        let response = handle_redirect_head(
+            Path("as9sud".to_string()),
+            State(Arc::clone(&app)),
+            RawQuery(None),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(
+            response.headers()[header::LOCATION],
+            "https://www.rustunit.com"
+        );
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(body.is_empty());
+
+        // nothing was counted, so there is nothing to flush
+        assert_eq!(app.flush_counters().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_handle_redirect_blocked_destination() {
+        let mut db = MockLinksDB::new();
+        db.expect_blocked_hosts()
+            .returning(|| Ok(vec!["rustunit.com".to_string()]));
+        db.expect_get().returning(|id| {
+            Ok(Some(FetchLink {
+                id: id.to_string(),
+                url: "https://www.rustunit.com".to_string(),
+                redirect_status: 307,
+                password_hash: None,
+                disabled: false,
+                reserved: false,
+                click_count: 0,
+                max_clicks: None,
+                key: None,
+                expires_at: None,
+            }))
+        });
+        let app = test_app(db, false);
+
+        let response = handle_redirect(
+            Path("as9sud".to_string()),
+            State(app),
+            RawQuery(None),
+            HeaderMap::new(),
+        )
+        .await
+        .into_response();
+
+        assert_eq!(response.status(), StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS);
+        assert!(response.headers().get(header::LOCATION).is_none());
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, AppConfig::default().blocked_redirect_message);
+    }
+
+    #[tokio::test]
+    async fn test_handle_redirect_db_down() {
+        let mut db = MockLinksDB::new();
+        db.expect_get()
+            .returning(|_| Err(DbError::General(String::from("connection refused"))));
+        let app = test_app(db, false);
+
+        let response = handle_redirect(
+            Path("as9sud".to_string()),
+            State(app),
+            RawQuery(None),
+            HeaderMap::new(),
+        )
+        .await
+        .into_response();
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            response.headers()[header::RETRY_AFTER],
+            AppConfig::default().unavailable_retry_after.to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_creates_refused_during_shutdown() {
+        let mut db = create_db();
+        db.expect_get().returning(|id| {
+            Ok(Some(FetchLink {
+                id: id.to_string(),
+                url: "https://www.rustunit.com".to_string(),
+                redirect_status: 307,
+                password_hash: None,
+                disabled: false,
+                reserved: false,
+                click_count: 0,
+                max_clicks: None,
+                key: None,
+                expires_at: None,
+            }))
+        });
+        let app = test_app(db, false);
+
+        let create = || {
+            handle_create(
+                Extension(AuthenticatedKey("key".to_string())),
+                ConnectInfo(peer()),
+                State(app.clone()),
+                Query(CreateQuery::default()),
+                HeaderMap::new(),
+                Json(CreateLinkRequest {
+                    url: "https://www.rustunit.com".to_string(),
+                    ..Default::default()
+                }),
+            )
+        };
+
+        assert_eq!(create().await.into_response().status(), StatusCode::OK);
+
+        app.begin_shutdown();
+
+        let response = create().await.into_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            response.headers()[header::RETRY_AFTER],
+            AppConfig::default().unavailable_retry_after.to_string()
+        );
+
+        let response = handle_redirect(
+            Path("as9sud".to_string()),
+            State(app),
+            RawQuery(None),
+            HeaderMap::new(),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+    }
+
+    #[tokio::test]
+    async fn test_redirect_rate_limit() {
+        let mut db = MockLinksDB::with_empty_blocklist();
+        db.expect_get().returning(|id| {
+            Ok(Some(FetchLink {
+                id: id.to_string(),
+                url: "https://www.rustunit.com".to_string(),
+                redirect_status: 307,
+                password_hash: None,
+                disabled: false,
+                reserved: false,
+                click_count: 0,
+                max_clicks: None,
+                key: None,
+                expires_at: None,
+            }))
+        });
+        let app = App::new(
+            AppConfig {
+                redirect_rate_limit: Some(3),
+                ..Default::default()
+            },
+            Arc::new(db),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let redirect = |id: &str| {
+            handle_redirect(
+                Path(id.to_string()),
+                State(app.clone()),
+                RawQuery(None),
+                HeaderMap::new(),
+            )
+        };
+
+        for _ in 0..3 {
+            let response = redirect("hammered").await.into_response();
+            assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+        }
+
+        let response = redirect("hammered").await.into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "60");
+
+        let response = redirect("as9sud").await.into_response();
+        assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+    }
+
+    #[tokio::test]
+    async fn test_redirect_malformed_url() {
+        let mut db = MockLinksDB::with_empty_blocklist();
+        db.expect_get().returning(|id| {
+            Ok(Some(FetchLink {
+                id: id.to_string(),
+                url: "www.rustunit.com/\r\nSet-Cookie: x".to_string(),
+                redirect_status: 307,
+                password_hash: None,
+                disabled: false,
+                reserved: false,
+                click_count: 0,
+                max_clicks: None,
+                key: None,
+                expires_at: None,
+            }))
+        });
+        let app = test_app(db, false);
+
+        let response = handle_redirect(
+            Path("as9sud".to_string()),
+            State(app),
+            RawQuery(None),
+            HeaderMap::new(),
+        )
+        .await
+        .into_response();
+
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(!response.headers().contains_key(header::LOCATION));
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, "the destination of this link is malformed");
+    }
+
+    #[tokio::test]
+    async fn test_redirect_cache_max_age() {
+        let mut db = MockLinksDB::with_empty_blocklist();
+        db.expect_get().returning(|id| {
+            Ok(Some(FetchLink {
+                id: id.to_string(),
+                url: "https://www.rustunit.com".to_string(),
+                redirect_status: if id == "permanent" { 301 } else { 307 },
+                password_hash: None,
+                disabled: false,
+                reserved: false,
+                click_count: 0,
+                max_clicks: None,
+                key: None,
+                expires_at: None,
+            }))
+        });
+        let app = App::new(
+            AppConfig {
+                redirect_cache_max_age: Some(3600),
+                ..Default::default()
+            },
+            Arc::new(db),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let response = handle_redirect(
+            Path("permanent".to_string()),
+            State(app.clone()),
+            RawQuery(None),
+            HeaderMap::new(),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(
+            response.headers()[header::CACHE_CONTROL],
+            "public, max-age=3600"
+        );
+
+        let response = handle_redirect(
+            Path("temporary".to_string()),
+            State(app),
+            RawQuery(None),
+            HeaderMap::new(),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+        assert!(!response.headers().contains_key(header::CACHE_CONTROL));
+    }
+
+    #[tokio::test]
+    async fn test_redirect_crawler_preview() {
+        let mut db = MockLinksDB::with_empty_blocklist();
+        db.expect_get().returning(|id| {
+            Ok(Some(FetchLink {
+                id: id.to_string(),
+                url: "https://www.rustunit.com".to_string(),
+                redirect_status: 307,
+                password_hash: None,
+                disabled: false,
+                reserved: false,
+                click_count: 0,
+                max_clicks: None,
+                key: None,
+                expires_at: None,
+            }))
+        });
+        db.expect_open_graph().returning(|_| {
+            Ok(Some(OpenGraphTags {
+                og_title: Some("Rustunit & friends".to_string()),
+                og_description: Some("Games in Rust".to_string()),
+                og_image: None,
+            }))
+        });
+        let app = test_app(db, false);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::USER_AGENT,
+            HeaderValue::from_static("Twitterbot/1.0"),
+        );
+        let response = handle_redirect(
+            Path("as9sud".to_string()),
+            State(app.clone()),
+            RawQuery(None),
+            headers,
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains(r#"<meta property="og:title" content="Rustunit &amp; friends">"#));
+        assert!(body.contains(r#"<meta property="og:description" content="Games in Rust">"#));
+        assert!(!body.contains("og:image"));
+        assert!(body.contains(r#"content="0; url=https://www.rustunit.com""#));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::USER_AGENT,
+            HeaderValue::from_static("Mozilla/5.0 (X11; Linux x86_64) Firefox/130.0"),
+        );
+        let response = handle_redirect(
+            Path("as9sud".to_string()),
+            State(app),
+            RawQuery(None),
+            headers,
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+        assert_eq!(
+            response.headers()[header::LOCATION],
+            "https://www.rustunit.com"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_redirect_forward_query() {
+        let mut db = MockLinksDB::with_empty_blocklist();
+        db.expect_get().returning(|id| {
+            Ok(Some(FetchLink {
+                id: id.to_string(),
+                url: "https://www.rustunit.com/?ref=ezlime".to_string(),
+                redirect_status: 307,
+                password_hash: None,
+                disabled: false,
+                reserved: false,
+                click_count: 0,
+                max_clicks: None,
+                key: None,
+                expires_at: None,
+            }))
+        });
+        let app = App::new(
+            AppConfig {
+                forward_query: true,
+                forward_query_max_params: 2,
+                ..Default::default()
+            },
+            Arc::new(db),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let query: Vec<String> = (0..50).map(|i| format!("p{i}={i}")).collect();
+        let response = handle_redirect(
+            Path("as9sud".to_string()),
+            State(app),
+            RawQuery(Some(query.join("&"))),
+            HeaderMap::new(),
+        )
+        .await
+        .into_response();
+
+        assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+        assert_eq!(
+            response.headers()[header::LOCATION],
+            "https://www.rustunit.com/?ref=ezlime&p0=0&p1=1"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_handle_redirect_unknown() {
+        let mut db = MockLinksDB::new();
+        db.expect_get().returning(|_| Ok(None));
+        let app = test_app(db, false);
+
+        let response = handle_redirect(
+            Path("as9sud".to_string()),
+            State(app),
+            RawQuery(None),
+            HeaderMap::new(),
+        )
+        .await
+        .into_response();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_handle_redirect_disabled() {
+        let mut db = MockLinksDB::new();
+        // the disabled state is remembered for the second redirect
+        db.expect_get().times(1).returning(|id| {
+            Ok(Some(FetchLink {
+                id: id.to_string(),
+                url: "https://www.rustunit.com".to_string(),
+                redirect_status: 307,
+                password_hash: None,
+                disabled: true,
+                reserved: false,
+                click_count: 0,
+                max_clicks: None,
+                key: None,
+                expires_at: None,
+            }))
+        });
+        let app = test_app(db, false);
+
+        for _ in 0..2 {
+            let response = handle_redirect(
+                Path("as9sud".to_string()),
+                State(app.clone()),
+                RawQuery(None),
+                HeaderMap::new(),
+            )
+            .await
+            .into_response();
+
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handle_redirect_implausible_id() {
+        let mut db = MockLinksDB::new();
+        db.expect_get().never();
+        let app = test_app(db, false);
+
+        for id in [" ", "wp-login.php", "as9sud%20"] {
+            let response = handle_redirect(
+                Path(id.to_string()),
+                State(app.clone()),
+                RawQuery(None),
+                HeaderMap::new(),
+            )
+            .await
+            .into_response();
+
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handle_redirect_original_url_header() {
+        let app = App::new(
+            AppConfig {
+                expose_original_url_header: true,
+                ..Default::default()
+            },
+            Arc::new(redirect_db(307)),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let response = handle_redirect(
+            Path("as9sud".to_string()),
+            State(app),
+            RawQuery(None),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap()
+        .into_response();
+
+        assert_eq!(
+            response.headers()[ORIGINAL_URL_HEADER],
+            "https://www.rustunit.com"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_handle_redirect_original_url_header_off_by_default() {
+        let app = test_app(redirect_db(307), false);
+
+        let response = handle_redirect(
+            Path("as9sud".to_string()),
+            State(app),
+            RawQuery(None),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap()
+        .into_response();
+
+        assert!(response.headers().get(ORIGINAL_URL_HEADER).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_handle_redirect_temporary() {
+        let app = test_app(redirect_db(307), false);
+
+        let response = handle_redirect(
+            Path("as9sud".to_string()),
+            State(app),
+            RawQuery(None),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap()
+        .into_response();
+
+        assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+    }
+
+    #[tokio::test]
+    async fn test_handle_redirect_invalid_status_falls_back_to_temporary() {
+        let app = test_app(redirect_db(200), false);
+
+        let response = handle_redirect(
+            Path("as9sud".to_string()),
+            State(app),
+            RawQuery(None),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap()
+        .into_response();
+
+        assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+    }
+
+    #[tokio::test]
+    async fn test_handle_redirect_password() {
+        let hash = crate::auth::hash_password("secret").unwrap();
+        let mut db = MockLinksDB::with_empty_blocklist();
+        db.expect_get().returning(move |id| {
+            Ok(Some(FetchLink {
+                id: id.to_string(),
+                url: "https://www.rustunit.com".to_string(),
+                redirect_status: 307,
+                password_hash: Some(hash.clone()),
+                disabled: false,
+                reserved: false,
+                click_count: 0,
+                max_clicks: None,
+                key: None,
+                expires_at: None,
+            }))
+        });
+        let app = test_app(db, false);
+
+        let response = handle_redirect(
+            Path("as9sud".to_string()),
+            State(app.clone()),
+            RawQuery(None),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(response.headers().get(header::LOCATION).is_none());
+
+        let response = handle_redirect_password(
+            Path("as9sud".to_string()),
+            State(app.clone()),
+            Form(PasswordForm {
+                password: "wrong".to_string(),
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(response.headers().get(header::LOCATION).is_none());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(LINK_PASSWORD_HEADER, HeaderValue::from_static("secret"));
+        let response = handle_redirect(
+            Path("as9sud".to_string()),
+            State(app),
+            RawQuery(None),
+            headers,
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+        assert_eq!(
+            response.headers()[header::LOCATION],
+            "https://www.rustunit.com"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_handle_redirect_password_attempts() {
+        let hash = crate::auth::hash_password("secret").unwrap();
+        let mut db = MockLinksDB::with_empty_blocklist();
+        db.expect_get().returning(move |id| {
+            Ok(Some(FetchLink {
+                id: id.to_string(),
+                url: "https://www.rustunit.com".to_string(),
+                redirect_status: 307,
+                password_hash: Some(hash.clone()),
+                disabled: false,
+                reserved: false,
+                click_count: 0,
+                max_clicks: None,
+                key: None,
+                expires_at: None,
+            }))
+        });
+        let app = test_app(db, false);
+        let attempt = |password: &str| {
+            handle_redirect_password(
+                Path("as9sud".to_string()),
+                State(app.clone()),
+                Form(PasswordForm {
+                    password: password.to_string(),
+                }),
+            )
+        };
+
+        // a right passphrase doesn't count
+        let response = attempt("secret").await.unwrap();
+        assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+
+        for _ in 0..AppConfig::default().password_attempts {
+            let response = attempt("wrong").await.unwrap();
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        }
+
+        let response = attempt("secret").await.into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "900");
+    }
+
+    #[tokio::test]
+    async fn test_handle_root() {
+        let app = App::new(
+            AppConfig {
+                root_redirect: Some("https://rustunit.com/".to_string()),
+                ..Default::default()
+            },
+            Arc::new(MockLinksDB::new()),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let response = handle_root(State(app)).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+        assert_eq!(
+            response.headers()[header::LOCATION],
+            "https://rustunit.com/"
+        );
+
+        let response = handle_root(State(test_app(MockLinksDB::new(), false)))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().get(header::LOCATION).is_none());
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, ROOT_PAGE);
     }
 }
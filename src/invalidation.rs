@@ -0,0 +1,160 @@
+use crate::db_pool::{DbPool, tls_connector};
+use async_trait::async_trait;
+use diesel::sql_types::Text;
+use diesel_async::RunQueryDsl;
+use futures_util::{StreamExt, stream};
+use std::{fmt::Debug, time::Duration};
+use tokio::sync::{broadcast, mpsc};
+use tokio_postgres::AsyncMessage;
+use tracing::{error, info, warn};
+
+/// Postgres channel the invalidations are sent on.
+const CHANNEL: &str = "ezlime_invalidations";
+
+/// Invalidations buffered per subscriber before it lags.
+const CAPACITY: usize = 1024;
+
+/// Wait before listening again after the connection was lost.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// A cached link that became stale.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Invalidation {
+    Link(String),
+    /// Invalidations may have been missed, e.g. while reconnecting, so
+    /// nothing cached can be trusted anymore.
+    All,
+}
+
+/// Carries cache invalidations between the replicas of a deployment, so a
+/// link changed on one of them is not redirected from the stale caches of
+/// the others until they expire.
+#[async_trait]
+pub trait Invalidations: Send + Sync + Debug {
+    /// Tells every replica, including this one, that link `id` changed.
+    /// Failures are only logged, the caches still expire eventually.
+    async fn publish(&self, id: &str);
+    fn subscribe(&self) -> broadcast::Receiver<Invalidation>;
+}
+
+/// [`Invalidations`] within one process, e.g. for several apps in tests.
+#[cfg(test)]
+#[derive(Debug)]
+pub struct LocalInvalidations(broadcast::Sender<Invalidation>);
+
+#[cfg(test)]
+impl Default for LocalInvalidations {
+    fn default() -> Self {
+        Self(broadcast::channel(CAPACITY).0)
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl Invalidations for LocalInvalidations {
+    async fn publish(&self, id: &str) {
+        // only fails when nobody is subscribed
+        let _ = self.0.send(Invalidation::Link(id.to_string()));
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<Invalidation> {
+        self.0.subscribe()
+    }
+}
+
+/// [`Invalidations`] sent with Postgres `NOTIFY` and received by `LISTEN`ing
+/// on a connection of its own, outside the pool.
+pub struct PgInvalidations {
+    pool: DbPool,
+    received: broadcast::Sender<Invalidation>,
+}
+
+impl Debug for PgInvalidations {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PgInvalidations").finish_non_exhaustive()
+    }
+}
+
+impl PgInvalidations {
+    /// Publishes through `pool` and starts listening on `db_url`, reconnecting
+    /// whenever the connection is lost.
+    pub fn start(db_url: String, pool: DbPool) -> Self {
+        let received = broadcast::channel(CAPACITY).0;
+
+        tokio::spawn(listen(db_url, received.clone()));
+
+        Self { pool, received }
+    }
+}
+
+#[async_trait]
+impl Invalidations for PgInvalidations {
+    async fn publish(&self, id: &str) {
+        let notified = match self.pool.0.get().await {
+            Ok(mut conn) => diesel::sql_query("SELECT pg_notify($1, $2)")
+                .bind::<Text, _>(CHANNEL)
+                .bind::<Text, _>(id)
+                .execute(&mut conn)
+                .await
+                .map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+
+        if let Err(e) = notified {
+            error!(id, "publishing cache invalidation failed: {e}");
+        }
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<Invalidation> {
+        self.received.subscribe()
+    }
+}
+
+async fn listen(db_url: String, received: broadcast::Sender<Invalidation>) {
+    let mut reconnect = false;
+
+    loop {
+        match listen_once(&db_url, &received, reconnect).await {
+            Ok(()) => warn!("cache invalidation connection closed"),
+            Err(e) => error!("listening for cache invalidations failed: {e}"),
+        }
+
+        tokio::time::sleep(RECONNECT_DELAY).await;
+        reconnect = true;
+    }
+}
+
+async fn listen_once(
+    db_url: &str,
+    received: &broadcast::Sender<Invalidation>,
+    reconnect: bool,
+) -> Result<(), tokio_postgres::Error> {
+    let (client, mut connection) = tokio_postgres::connect(db_url, tls_connector()).await?;
+
+    // the connection has to be polled for the client to make progress
+    let (tx, mut ids) = mpsc::unbounded_channel();
+    let mut messages = stream::poll_fn(move |cx| connection.poll_message(cx));
+    let driver = tokio::spawn(async move {
+        while let Some(message) = messages.next().await {
+            if let AsyncMessage::Notification(notification) = message? {
+                let _ = tx.send(notification.payload().to_string());
+            }
+        }
+        Ok::<_, tokio_postgres::Error>(())
+    });
+
+    client.batch_execute(&format!("LISTEN {CHANNEL}")).await?;
+    info!("listening for cache invalidations");
+
+    // changes made while not listening were missed
+    if reconnect {
+        let _ = received.send(Invalidation::All);
+    }
+
+    while let Some(id) = ids.recv().await {
+        let _ = received.send(Invalidation::Link(id));
+    }
+
+    drop(client);
+    driver.await.unwrap_or(Ok(()))
+}
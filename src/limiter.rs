@@ -0,0 +1,109 @@
+use std::{
+    borrow::Borrow,
+    collections::HashMap,
+    hash::{BuildHasher, Hash, RandomState},
+    sync::Mutex,
+};
+
+/// Locks the state of a key is split across, so keys of different shards
+/// don't wait for each other.
+const SHARDS: usize = 16;
+
+/// State of a limit per key, e.g. a client IP, changed under a lock so
+/// concurrent requests of one key can't all pass the check.
+///
+/// Holds at most about `capacity` keys. A full shard sweeps the entries no
+/// longer live, live ones are never evicted, as that would reset their limit.
+pub struct Limiter<K, V> {
+    shards: Box<[Mutex<HashMap<K, V>>]>,
+    shard_capacity: usize,
+    hasher: RandomState,
+}
+
+impl<K: Hash + Eq, V> Limiter<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            shards: (0..SHARDS).map(|_| Mutex::default()).collect(),
+            shard_capacity: capacity.div_ceil(SHARDS),
+            hasher: RandomState::new(),
+        }
+    }
+
+    /// Runs `update` on the state of `key`, starting from `init()` if `key`
+    /// isn't tracked yet. `None` if there is no room for `key`, as all
+    /// entries of its shard are still `live`.
+    pub fn update<Q, R>(
+        &self,
+        key: &Q,
+        init: impl FnOnce() -> V,
+        live: impl Fn(&V) -> bool,
+        update: impl FnOnce(&mut V) -> R,
+    ) -> Option<R>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let shard = self.hasher.hash_one(key) as usize % SHARDS;
+        let mut entries = self.shards[shard].lock().unwrap();
+
+        // a known key is updated in place, without allocating its key
+        if let Some(state) = entries.get_mut(key) {
+            return Some(update(state));
+        }
+
+        if entries.len() >= self.shard_capacity {
+            entries.retain(|_, state| live(state));
+
+            if entries.len() >= self.shard_capacity {
+                return None;
+            }
+        }
+
+        Some(update(entries.entry(key.to_owned()).or_insert_with(init)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limiter_sweeps_when_full() {
+        // one entry per shard
+        let limiter: Limiter<u32, bool> = Limiter::new(SHARDS);
+        let insert = |key: u32, live: bool| limiter.update(&key, || live, |live| *live, |_| ());
+
+        // keys beyond the capacity land in an occupied shard
+        let refused = (0..=SHARDS as u32)
+            .filter(|&key| insert(key, true).is_none())
+            .count();
+        assert!(refused > 0);
+
+        for shard in limiter.shards.iter() {
+            shard
+                .lock()
+                .unwrap()
+                .values_mut()
+                .for_each(|live| *live = false);
+        }
+        assert!((0..=SHARDS as u32).all(|key| insert(key, false).is_some()));
+    }
+
+    #[test]
+    fn test_limiter_updates_in_place() {
+        let limiter: Limiter<String, u32> = Limiter::new(10);
+
+        for expected in 1..=3 {
+            let count = limiter.update(
+                "id",
+                || 0,
+                |_| true,
+                |count| {
+                    *count += 1;
+                    *count
+                },
+            );
+            assert_eq!(count, Some(expected));
+        }
+    }
+}
@@ -1,37 +1,87 @@
 use crate::{
-    app::App,
+    access_log::AccessLogLayer,
+    app::{
+        App, AppConfig, HealthFormat, IdStrategy, IdnPolicy, PublicStatsField, SolanaPayments,
+        UpgradeInsecure, parse_blocked_id_terms,
+    },
     auth::{ApiKeys, require_auth},
-    counter::{ClickCounter, start_counter_flusher},
-    db::PostgresDb,
-    db_pool::DbPool,
+    clock::SystemClock,
+    counter::{ClickCounter, ClickStore, start_counter_flusher},
+    db::{LinksDB, PostgresDb, SlowQueryLog},
+    db_pool::{ConnectRetry, DbPool, DbPoolOptions, DbRecycle, DbSchema, init_crypto_provider},
     handler::{
-        handle_create, handle_health, handle_public_create, handle_redirect, handle_x402_create,
+        EffectiveConfig, handle_admin_config, handle_admin_disable_link, handle_admin_enable_link,
+        handle_admin_stats, handle_block_host, handle_check_key, handle_create,
+        handle_create_links, handle_delete_links, handle_disable_link, handle_enable_link,
+        handle_events, handle_evict_cache, handle_export, handle_export_own, handle_find_links,
+        handle_flush_counters, handle_get_link, handle_health, handle_import_csv,
+        handle_link_stats, handle_link_timeseries, handle_link_transactions, handle_list_links,
+        handle_livez, handle_metrics, handle_public_create, handle_public_stats, handle_redirect,
+        handle_redirect_head, handle_redirect_password, handle_reserve_link, handle_resolve_links,
+        handle_root, handle_rules, handle_summary, handle_trending, handle_unblock_host,
+        handle_update_key, handle_update_link, handle_warm_cache, handle_x402_create,
+        x402_host_price,
     },
-    migrations::run_migrations,
+    invalidation::{Invalidations, PgInvalidations},
+    migrations::{MigrateMode, migrate},
+    models::network_to_db_string,
+    purge::start_expired_purger,
+    turnstile::{TurnstileClaims, TurnstileVerifier, VerifyPolicy},
 };
 use axum::{
-    Router, middleware,
-    routing::{get, post},
+    Extension, Router,
+    http::{HeaderName, HeaderValue, header},
+    middleware,
+    response::Response,
+    routing::{delete, get, post, put},
+};
+use clap::{CommandFactory, FromArgMatches, Parser, ValueEnum, error::ErrorKind};
+use ezlime_rs::ValidationRules;
+use serde::{Serialize, Serializer};
+use std::{
+    collections::HashMap, ffi::OsString, io::IsTerminal, net::SocketAddr, ops::RangeInclusive,
+    path::PathBuf, sync::Arc, time::Duration,
 };
-use axum_turnstile::TurnstileLayer;
-use clap::Parser;
-use std::{net::SocketAddr, sync::Arc, time::Duration};
 use tokio::net::TcpListener;
-use tower_http::{cors::CorsLayer, trace::TraceLayer};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use tower_http::{
+    compression::{
+        CompressionLayer, Predicate,
+        predicate::{DefaultPredicate, NotForContentType},
+    },
+    cors::CorsLayer,
+    set_header::SetResponseHeaderLayer,
+    trace::TraceLayer,
+};
+use tracing_subscriber::{
+    Layer,
+    filter::{FilterExt, filter_fn},
+    layer::SubscriberExt,
+    util::SubscriberInitExt,
+};
+use url::Url;
 use x402_axum::{PriceTag, X402Middleware};
 use x402_rs::network::{Network, USDCDeployment};
 
+mod access_log;
 mod app;
 mod auth;
+mod clock;
+mod config_file;
 mod counter;
 mod db;
 mod db_pool;
+mod destination;
 mod handler;
+mod invalidation;
+mod limiter;
+mod metrics;
 mod migrations;
 mod models;
+mod purge;
+mod qr;
 mod schema;
 mod signals;
+mod turnstile;
 
 pub const GIT_HASH: &str = env!("VERGEN_GIT_SHA");
 
@@ -47,8 +97,70 @@ pub const fn is_debug() -> bool {
     true
 }
 
-#[derive(Default, Parser, Debug)]
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum LogColor {
+    /// Colored output only when logging to a terminal
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl LogColor {
+    fn use_ansi(self, is_terminal: bool) -> bool {
+        match self {
+            LogColor::Auto => is_terminal,
+            LogColor::Always => true,
+            LogColor::Never => false,
+        }
+    }
+}
+
+/// Accepted `--hash-length`s. Shorter ids collide all the time, and base62
+/// of the 64 bit hash has at most 11 digits, longer ids only add padding.
+const HASH_LENGTHS: RangeInclusive<usize> = 2..=11;
+
+fn parse_hash_length(value: &str) -> Result<usize, String> {
+    let length: usize = value.parse().map_err(|e| format!("{e}"))?;
+
+    if !HASH_LENGTHS.contains(&length) {
+        return Err(format!(
+            "must be between {} and {}",
+            HASH_LENGTHS.start(),
+            HASH_LENGTHS.end()
+        ));
+    }
+
+    Ok(length)
+}
+
+/// Stands in for secrets in the configuration served at `/admin/config`.
+const REDACTED: &str = "<redacted>";
+
+fn redact<T, S: Serializer>(_secret: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(REDACTED)
+}
+
+/// Like [`redact`], but still tells whether the secret is set at all.
+fn redact_optional<T, S: Serializer>(secret: &Option<T>, serializer: S) -> Result<S::Ok, S::Error> {
+    match secret {
+        Some(_) => serializer.serialize_str(REDACTED),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Serialized with secrets redacted for `GET /admin/config`, so fields
+/// holding credentials need `#[serde(serialize_with = "redact")]`.
+#[derive(Default, Parser, Debug, Serialize)]
 struct Arguments {
+    #[arg(
+        long,
+        help = "TOML file with settings keyed by argument name, flags and env vars take precedence",
+        env = "CONFIG"
+    )]
+    config: Option<PathBuf>,
+
     #[arg(long, default_value_t = true, help = "Relax CORS", env = "RELAX_CORS")]
     cors_relaxed: bool,
 
@@ -58,9 +170,64 @@ struct Arguments {
     #[arg(long, default_value_t = 100, help = "Cache size", env = "CACHE_SIZE")]
     cache_size: usize,
 
-    #[arg(long, default_value_t = 6, help = "Hash length", env = "HASH_LENGTH")]
+    #[arg(
+        long,
+        help = "Bound the link cache by the bytes of its urls, --cache-size then only estimates the entries",
+        env = "CACHE_MAX_BYTES"
+    )]
+    cache_max_bytes: Option<u64>,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Share cache invalidations between replicas via Postgres LISTEN/NOTIFY",
+        env = "SHARED_CACHE_INVALIDATION"
+    )]
+    shared_cache_invalidation: bool,
+
+    #[arg(
+        long,
+        default_value_t = 6,
+        value_parser = parse_hash_length,
+        help = "Hash length",
+        env = "HASH_LENGTH"
+    )]
     hash_length: usize,
 
+    #[arg(
+        long,
+        default_value_t = AppConfig::default().alias_min_length,
+        help = "Shortest id clients may choose for their links, e.g. on import",
+        env = "ALIAS_MIN_LENGTH"
+    )]
+    alias_min_length: usize,
+
+    #[arg(
+        long,
+        default_value_t = AppConfig::default().alias_max_length,
+        help = "Longest id clients may choose for their links, e.g. on import",
+        env = "ALIAS_MAX_LENGTH"
+    )]
+    alias_max_length: usize,
+
+    #[arg(
+        long,
+        default_value_t = String::new(),
+        help = "Salt for link ids, changes all generated ids when set",
+        env = "HASH_SALT"
+    )]
+    #[serde(serialize_with = "redact")]
+    hash_salt: String,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = IdStrategy::Hash,
+        help = "Generate ids by hashing the URL, or by encoding a DB sequence which never collides",
+        env = "ID_STRATEGY"
+    )]
+    id_strategy: IdStrategy,
+
     #[arg(
         long,
         default_value_t = 3,
@@ -69,11 +236,52 @@ struct Arguments {
     )]
     stats_flush_interval_secs: u64,
 
+    #[arg(
+        long,
+        help = "Interval to purge expired links, disabled if unset",
+        env = "PURGE_EXPIRED_INTERVAL_SECS"
+    )]
+    purge_expired_interval_secs: Option<u64>,
+
+    #[arg(
+        long,
+        help = "URL POSTed to when the server begins to shut down",
+        env = "SHUTDOWN_WEBHOOK"
+    )]
+    #[serde(serialize_with = "redact_optional")]
+    shutdown_webhook: Option<Url>,
+
     #[arg(long, help = "Logging level of the Rust log", env = "RUST_LOG")]
     #[clap(default_value_t = String::from("info,tower_http=debug"))]
     rust_log_level: String,
 
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = LogColor::Auto,
+        help = "Use ANSI colors in the log output",
+        env = "LOG_COLOR"
+    )]
+    log_color: LogColor,
+
+    #[arg(
+        long,
+        help = "Append HTTP access logs as JSON lines to this file instead of the app log",
+        env = "ACCESS_LOG_FILE"
+    )]
+    access_log_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = MigrateMode::Auto,
+        help = "Apply pending migrations at startup, only check for them, or skip them",
+        env = "MIGRATE"
+    )]
+    migrate: MigrateMode,
+
     #[arg(long, env = "DATABASE_URL")]
+    #[serde(serialize_with = "redact")]
     db_url: String,
 
     #[arg(
@@ -84,169 +292,1971 @@ struct Arguments {
     )]
     db_pool_size: usize,
 
+    #[arg(
+        long,
+        help = "Postgres schema of the tables, instead of public",
+        env = "DB_SCHEMA"
+    )]
+    db_schema: Option<DbSchema>,
+
+    #[arg(
+        long,
+        default_value_t = 5,
+        help = "Attempts to reach the DB at startup before giving up",
+        env = "DB_CONNECT_ATTEMPTS"
+    )]
+    db_connect_attempts: u32,
+
+    #[arg(
+        long,
+        default_value_t = 2000,
+        help = "Delay between DB connection attempts at startup",
+        env = "DB_CONNECT_RETRY_DELAY_MS"
+    )]
+    db_connect_retry_delay_ms: u64,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = DbRecycle::Verified,
+        help = "How pooled DB connections are checked before reuse",
+        env = "DB_RECYCLE"
+    )]
+    db_recycle: DbRecycle,
+
+    #[arg(
+        long,
+        help = "Log DB operations taking longer than this many milliseconds, disabled if unset",
+        env = "SLOW_QUERY_MS"
+    )]
+    slow_query_ms: Option<u64>,
+
+    #[arg(
+        long,
+        help = "Maximum number of links to store, unlimited if unset",
+        env = "MAX_TOTAL_LINKS"
+    )]
+    max_total_links: Option<u64>,
+
     #[arg(long, default_value_t = String::from("http://localhost:8080"), env = "URL_PREFIX")]
     url_prefix: String,
 
-    #[arg(long, default_value_t = String::new(), env = "KEYS")]
-    keys: String,
+    #[arg(
+        long,
+        help = "Path all routes are served under, e.g. /s for short links like https://example.com/s/{id}; URL_PREFIX must end with it",
+        env = "ROUTE_PREFIX"
+    )]
+    route_prefix: Option<String>,
 
-    #[arg(long, default_value_t = String::from("1x0000000000000000000000000000000AA"), env = "TURNSTILE_SECRET")]
-    turnstile_secret: String,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = IdnPolicy::Allow,
+        help = "How to handle internationalized (non-ASCII) hosts",
+        env = "IDN_POLICY"
+    )]
+    idn_policy: IdnPolicy,
 
-    #[arg(long, default_value_t = String::from("http://localhost:8081"), env = "X402_FACILITATOR_URL")]
-    x402_facilitator_url: String,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = UpgradeInsecure::Off,
+        help = "Store http urls as https, for the listed hosts or all of them",
+        env = "UPGRADE_INSECURE"
+    )]
+    upgrade_insecure: UpgradeInsecure,
 
-    #[arg(long, default_value_t = String::from("0.01"), env = "X402_PRICE_PER_LINK")]
-    x402_price_per_link: String,
+    #[arg(
+        long,
+        value_delimiter = ',',
+        help = "Comma separated hosts (and their subdomains) upgraded to https by --upgrade-insecure listed",
+        env = "UPGRADE_INSECURE_HOSTS"
+    )]
+    upgrade_insecure_hosts: Vec<String>,
 
-    #[arg(long, env = "X402_MERCHANT_WALLET")]
-    x402_merchant_wallet: Option<String>,
-}
+    #[arg(
+        long,
+        default_value_t = 4096,
+        help = "Maximum length of URLs to shorten",
+        env = "MAX_URL_LENGTH"
+    )]
+    max_url_length: usize,
 
-fn setup_cors(relaxed: bool) -> CorsLayer {
-    if relaxed {
-        tracing::info!("cors setup: very_permissive");
-        CorsLayer::very_permissive().allow_credentials(true)
-    } else {
-        tracing::info!("cors setup: default");
-        CorsLayer::new()
-    }
-}
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Reject URLs pointing at loopback or private network hosts",
+        env = "BLOCK_PRIVATE_HOSTS"
+    )]
+    block_private_hosts: bool,
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let args = Arguments::parse();
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Only shorten https URLs, rejecting http ones",
+        env = "HTTPS_ONLY_DESTINATIONS"
+    )]
+    https_only_destinations: bool,
 
-    let log_level = args.rust_log_level;
+    #[arg(
+        long,
+        default_value_t = AppConfig::default().blocked_redirect_message,
+        help = "Message shown for links to hosts blocked after creation",
+        env = "BLOCKED_REDIRECT_MESSAGE"
+    )]
+    blocked_redirect_message: String,
 
-    let cors_relaxed = args.cors_relaxed;
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Add an X-Original-Url header with the destination to redirects",
+        env = "EXPOSE_ORIGINAL_URL_HEADER"
+    )]
+    expose_original_url_header: bool,
 
-    tracing_subscriber::registry()
-        .with(tracing_subscriber::EnvFilter::new(log_level.clone()))
-        .with(tracing_subscriber::fmt::layer().with_ansi(is_debug()))
-        .init();
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Add an X-Link-Id header with the id to create responses",
+        env = "LINK_ID_HEADER"
+    )]
+    link_id_header: bool,
 
-    rustls::crypto::aws_lc_rs::default_provider()
-        .install_default()
-        .expect("Could not install rustls default crypto provider.");
+    #[arg(
+        long,
+        help = "Cache-Control max-age of permanent redirects in seconds, uncached if unset",
+        env = "REDIRECT_CACHE_MAX_AGE"
+    )]
+    redirect_cache_max_age: Option<u64>,
 
-    tracing::info!(git = %GIT_HASH, log = log_level, cors_relaxed, cache_size = args.cache_size, "server starting");
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Append the query of redirect requests to the destination URL",
+        env = "FORWARD_QUERY"
+    )]
+    forward_query: bool,
 
-    run_migrations(&args.db_url)?;
+    #[arg(
+        long,
+        default_value_t = AppConfig::default().forward_query_max_params,
+        help = "Most query parameters appended by --forward-query, further ones are dropped",
+        env = "FORWARD_QUERY_MAX_PARAMS"
+    )]
+    forward_query_max_params: usize,
 
-    let dbpool = DbPool::build(&args.db_url, args.db_pool_size).await?;
+    #[arg(
+        long,
+        default_value_t = AppConfig::default().forward_query_max_bytes,
+        help = "Most bytes of query parameters appended by --forward-query, further ones are dropped",
+        env = "FORWARD_QUERY_MAX_BYTES"
+    )]
+    forward_query_max_bytes: usize,
 
-    let counter = Arc::new(ClickCounter::new());
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Strip #fragments from URLs before shortening",
+        env = "STRIP_FRAGMENTS"
+    )]
+    strip_fragments: bool,
 
-    tokio::spawn(start_counter_flusher(
-        Arc::clone(&counter),
-        dbpool.clone(),
-        Duration::from_secs(args.stats_flush_interval_secs),
-    ));
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Answer redirects to short links of this service with 508 Loop Detected",
+        env = "REDIRECT_LOOP_GUARD"
+    )]
+    redirect_loop_guard: bool,
 
-    let api_keys = ApiKeys::new(&args.keys);
+    #[arg(
+        long,
+        value_delimiter = ',',
+        help = "Comma separated hosts also serving this service, checked by --redirect-loop-guard",
+        env = "REDIRECT_LOOP_HOSTS"
+    )]
+    redirect_loop_hosts: Vec<String>,
 
-    let app = App::new(
-        args.url_prefix,
-        args.hash_length,
-        Arc::new(PostgresDb::new(dbpool)),
-        Arc::clone(&counter),
-        args.cache_size,
-    );
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Append every created link with key, source and client IP to the audit_log table",
+        env = "AUDIT_LOG"
+    )]
+    audit_log: bool,
 
-    let pub_api = Router::new()
-        .route("/shorten", post(handle_public_create))
-        .layer(TurnstileLayer::from_secret(args.turnstile_secret));
-
-    // x402 payment endpoint (optional - only if merchant wallet is configured)
-    let x402_router = if let Some(merchant_wallet) = args.x402_merchant_wallet {
-        tracing::info!(
-            facilitator = %args.x402_facilitator_url,
-            price = %args.x402_price_per_link,
-            merchant = %merchant_wallet,
-            "x402 payment endpoint enabled"
-        );
-
-        let x402 = X402Middleware::try_from(args.x402_facilitator_url.as_str())
-            .expect("Failed to create x402 middleware");
-
-        // Parse merchant wallet address
-        let merchant_address: x402_rs::types::EvmAddress = merchant_wallet
-            .parse()
-            .expect("Invalid merchant wallet address");
-
-        // Parse price as float, then convert to token units (USDC has 6 decimals)
-        let price_usdc: f64 = args
-            .x402_price_per_link
-            .parse()
-            .expect("Invalid price format");
-
-        // Convert to base units (multiply by 10^6 for USDC)
-        let price_base_units = (price_usdc * 1_000_000.0) as u64;
-
-        // Create price tags for both Base mainnet and Base Sepolia
-        let usdc_base = USDCDeployment::by_network(Network::Base);
-        let price_tag_base = PriceTag::new(merchant_address, price_base_units, usdc_base);
-
-        let usdc_sepolia = USDCDeployment::by_network(Network::BaseSepolia);
-        let price_tag_sepolia = PriceTag::new(merchant_address, price_base_units, usdc_sepolia);
-
-        tracing::info!(
-            merchant = ?merchant_address,
-            amount = price_base_units,
-            networks = "base-mainnet, base-sepolia",
-            "x402 price tags configured"
-        );
-
-        Router::new()
-            .route("/x402/shorten", post(handle_x402_create))
-            .layer(
-                x402.with_description("Link shortening service")
-                    .settle_before_execution()
-                    .with_price_tag(price_tag_base) // Base mainnet (first one)
-                    .or_price_tag(price_tag_sepolia), // Base Sepolia testnet (add to list)
-            )
-    } else {
-        tracing::info!("x402 payment endpoint disabled (no merchant wallet configured)");
-        Router::new()
-    };
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Store the client IP creating a link with it, shown by /admin/stats/{id}",
+        env = "STORE_CREATOR_IP"
+    )]
+    store_creator_ip: bool,
 
-    let router = Router::new()
-        //authenticated routes
-        .route("/link/create", post(handle_create))
-        .route_layer(middleware::from_fn_with_state(api_keys, require_auth))
-        //public routes
-        .route("/{id}", get(handle_redirect))
-        .merge(pub_api)
-        .merge(x402_router)
-        .route("/health", get(handle_health))
-        .layer(TraceLayer::new_for_http())
-        .layer(setup_cors(cors_relaxed))
-        .with_state(Arc::clone(&app));
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Sort query parameters of URLs before shortening",
+        env = "CANONICALIZE_QUERY"
+    )]
+    canonicalize_query: bool,
 
-    let addr = SocketAddr::from(([0, 0, 0, 0], args.port));
+    #[arg(
+        long,
+        value_delimiter = ',',
+        help = "Comma separated query parameters dropped by --canonicalize-query, a trailing * matches any suffix (e.g. utm_*,fbclid,gclid)",
+        env = "STRIP_QUERY_PARAMS"
+    )]
+    strip_query_params: Vec<String>,
 
-    tracing::info!("listening on https://{}", addr);
-    tracing::info!("listening on http://{}", addr);
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = HealthFormat::Html,
+        help = "Format of /readyz and /health, json lists the individual checks",
+        env = "HEALTH_FORMAT"
+    )]
+    health_format: HealthFormat,
 
-    let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+    #[arg(
+        long,
+        help = "File with the html served by /health in the html format",
+        env = "HEALTH_PAGE"
+    )]
+    health_page: Option<PathBuf>,
 
-    signals::create_term_signal_handler(tx);
+    #[arg(
+        long,
+        help = "File with terms (one per line) generated ids must not contain",
+        env = "BLOCKED_ID_TERMS"
+    )]
+    blocked_id_terms: Option<PathBuf>,
 
-    let listener = TcpListener::bind(addr).await?;
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Reject URLs whose host has no suffix of --public-suffix-list, like http://localhost",
+        env = "REQUIRE_PUBLIC_TLD"
+    )]
+    require_public_tld: bool,
 
-    let server = axum::serve(
-        listener,
-        router.into_make_service_with_connect_info::<SocketAddr>(),
-    );
+    #[arg(
+        long,
+        help = "Public suffix list (https://publicsuffix.org/list/public_suffix_list.dat) checked by --require-public-tld",
+        env = "PUBLIC_SUFFIX_LIST"
+    )]
+    public_suffix_list: Option<PathBuf>,
 
-    let graceful = server.with_graceful_shutdown(async {
-        rx.await.ok();
-    });
+    #[arg(
+        long,
+        value_delimiter = ',',
+        help = "Comma separated hosts accepted by --require-public-tld without a public suffix, e.g. intranet hosts",
+        env = "TLD_EXEMPT_HOSTS"
+    )]
+    tld_exempt_hosts: Vec<String>,
 
-    if let Err(e) = graceful.await {
-        tracing::error!("server error: {}", e);
-    }
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Let concurrent creates of the same URL share one insert and its response",
+        env = "DEDUP_CONCURRENT_CREATES"
+    )]
+    dedup_concurrent_creates: bool,
 
-    Ok(())
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Fetch the <title> of new links created without one in the background",
+        env = "FETCH_TITLES"
+    )]
+    fetch_titles: bool,
+
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_values = app::DEFAULT_CRAWLER_USER_AGENTS,
+        help = "Comma separated User-Agent parts of social crawlers served the Open Graph tags of links instead of the redirect, empty disables",
+        env = "CRAWLER_USER_AGENTS"
+    )]
+    crawler_user_agents: Vec<String>,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Reject URLs whose destination is unreachable or answers HEAD with an error status",
+        env = "VERIFY_DESTINATION"
+    )]
+    verify_destination: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Fetch destinations and reuse the link of another URL of the same site serving the same content, adds latency to creates",
+        env = "DEDUPE_BY_CONTENT"
+    )]
+    dedupe_by_content: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "NFC normalize the path and query of URLs before shortening",
+        env = "NORMALIZE_UNICODE"
+    )]
+    normalize_unicode: bool,
+
+    #[arg(
+        long,
+        help = "Redirects of a single link per minute answered before further ones get 429, unlimited if unset",
+        env = "REDIRECT_RATE_LIMIT"
+    )]
+    redirect_rate_limit: Option<u32>,
+
+    #[arg(
+        long,
+        default_value_t = AppConfig::default().redirect_limiter_capacity,
+        help = "Links --redirect-rate-limit tracks, links beyond are not limited until others' windows run out",
+        env = "REDIRECT_LIMITER_CAPACITY"
+    )]
+    redirect_limiter_capacity: usize,
+
+    #[arg(
+        long,
+        default_value_t = AppConfig::default().password_attempts,
+        help = "Wrong passphrases of a protected link within 15 minutes before further attempts get 429",
+        env = "PASSWORD_ATTEMPTS"
+    )]
+    password_attempts: u32,
+
+    #[arg(
+        long,
+        value_delimiter = ',',
+        help = "Comma separated API key namespaces (<tenant>_<secret>) labelled in the create and redirect metrics, others are counted as 'other'",
+        env = "METRICS_TENANTS"
+    )]
+    metrics_tenants: Vec<String>,
+
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        default_values_t = PublicStatsField::DEFAULT,
+        help = "Fields returned by the public /stats/{id} endpoint",
+        env = "PUBLIC_STATS_FIELDS"
+    )]
+    public_stats_fields: Vec<PublicStatsField>,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Use the scheme of X-Forwarded-Proto for short URLs, only behind a trusted proxy",
+        env = "TRUST_FORWARDED_PROTO"
+    )]
+    trust_forwarded_proto: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Count public creates by the last X-Forwarded-For entry instead of the peer address, only behind a trusted proxy",
+        env = "TRUST_FORWARDED_FOR"
+    )]
+    trust_forwarded_for: bool,
+
+    #[arg(
+        long,
+        help = "URL to redirect the root path to, a default page is shown if unset",
+        env = "ROOT_REDIRECT"
+    )]
+    root_redirect: Option<Url>,
+
+    #[arg(
+        long,
+        default_value_t = AppConfig::default().collision_warn_limit,
+        help = "Hash collisions per created link logged as warnings, further ones at debug",
+        env = "COLLISION_WARN_LIMIT"
+    )]
+    collision_warn_limit: u64,
+
+    #[arg(
+        long,
+        default_value_t = AppConfig::default().max_collision_retries,
+        help = "Hash collisions per created link before giving up with 507 id_space_exhausted",
+        env = "MAX_COLLISION_RETRIES"
+    )]
+    max_collision_retries: u64,
+
+    #[arg(
+        long,
+        help = "Public creates per IP and minute answered without delay, further ones are delayed by --public-tarpit-delay-ms. Behind a proxy needs --trust-forwarded-for",
+        env = "PUBLIC_TARPIT_THRESHOLD"
+    )]
+    public_tarpit_threshold: Option<u32>,
+
+    #[arg(
+        long,
+        default_value_t = AppConfig::default().public_tarpit_delay_ms,
+        help = "Delay in milliseconds of public creates past --public-tarpit-threshold",
+        env = "PUBLIC_TARPIT_DELAY_MS"
+    )]
+    public_tarpit_delay_ms: u64,
+
+    #[arg(
+        long,
+        default_value_t = AppConfig::default().public_limiter_capacity,
+        help = "Client IPs the public tarpit, rate limit and cooldown each track, IPs beyond are held back until others' windows run out",
+        env = "PUBLIC_LIMITER_CAPACITY"
+    )]
+    public_limiter_capacity: usize,
+
+    #[arg(
+        long,
+        help = "Public creates per IP and minute answered before further ones get 429, unlimited if unset. Behind a proxy needs --trust-forwarded-for",
+        env = "PUBLIC_RATE_LIMIT"
+    )]
+    public_rate_limit: Option<u32>,
+
+    #[arg(
+        long,
+        help = "Seconds an IP has to wait after a public create before the next one, answered 429 until then. Behind a proxy needs --trust-forwarded-for",
+        env = "PUBLIC_COOLDOWN_SECS"
+    )]
+    public_cooldown_secs: Option<u64>,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Give each API key its own ids for the same URL, changes all generated ids",
+        env = "NAMESPACE_BY_KEY"
+    )]
+    namespace_by_key: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Resolve ids regardless of case, e.g. of links created with mixed-case ids",
+        env = "CASE_INSENSITIVE_IDS"
+    )]
+    case_insensitive_ids: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Accept aliases in any case, stored lowercase, refusing ones that differ from an existing id in case only",
+        env = "CASE_INSENSITIVE_ALIASES"
+    )]
+    case_insensitive_aliases: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Add Referrer-Policy, X-Content-Type-Options and CSP headers to responses",
+        env = "SECURITY_HEADERS"
+    )]
+    security_headers: bool,
+
+    #[arg(
+        long,
+        default_value_t = AppConfig::default().unavailable_retry_after,
+        help = "Retry-After seconds of the 503 for uncached links while the DB is down",
+        env = "UNAVAILABLE_RETRY_AFTER"
+    )]
+    unavailable_retry_after: u64,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Count clicks in the DB on every redirect instead of flushing them periodically",
+        env = "SYNC_CLICK_COUNTS"
+    )]
+    sync_click_counts: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Log and store the URLs of requests answered with the demo response",
+        env = "RECORD_DEMO_REQUESTS"
+    )]
+    record_demo_requests: bool,
+
+    #[arg(long, default_value_t = String::new(), env = "KEYS")]
+    #[serde(serialize_with = "redact")]
+    keys: String,
+
+    #[arg(
+        long,
+        help = "Requests per minute allowed per API key, keys ending in `!` are exempt",
+        env = "KEY_RATE_LIMIT"
+    )]
+    key_rate_limit: Option<u32>,
+
+    #[arg(
+        long,
+        default_value = "Authorization",
+        help = "Header API keys are read from, e.g. X-Api-Key behind a gateway using Authorization",
+        env = "AUTH_HEADER"
+    )]
+    auth_header: String,
+
+    #[arg(
+        long,
+        default_value_t = String::new(),
+        help = "Comma separated API keys allowed to use the /admin endpoints",
+        env = "ADMIN_KEYS"
+    )]
+    #[serde(serialize_with = "redact")]
+    admin_keys: String,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Let admin keys change the scopes (only reported by /auth/check, not enforced) and rate limit exemption of API keys via PUT /admin/keys, until the next restart",
+        env = "ALLOW_KEY_UPDATES"
+    )]
+    allow_key_updates: bool,
+
+    #[arg(long, default_value_t = String::from("1x0000000000000000000000000000000AA"), env = "TURNSTILE_SECRET")]
+    #[serde(serialize_with = "redact")]
+    turnstile_secret: String,
+
+    #[arg(
+        long,
+        help = "Reject Turnstile tokens not issued for this action",
+        env = "TURNSTILE_ACTION"
+    )]
+    turnstile_action: Option<String>,
+
+    #[arg(
+        long,
+        help = "Reject Turnstile tokens not carrying this cdata",
+        env = "TURNSTILE_CDATA"
+    )]
+    turnstile_cdata: Option<String>,
+
+    #[arg(
+        long,
+        value_delimiter = ',',
+        help = "Reject Turnstile tokens not issued on one of these hostnames",
+        env = "TURNSTILE_HOSTNAMES"
+    )]
+    turnstile_hostnames: Vec<String>,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Reject Turnstile tokens issued without an interactive challenge",
+        env = "TURNSTILE_REQUIRE_INTERACTIVE"
+    )]
+    turnstile_require_interactive: bool,
+
+    #[arg(
+        long,
+        help = "Milliseconds to wait for Cloudflare to verify a Turnstile token, 10s if unset",
+        env = "TURNSTILE_TIMEOUT_MS"
+    )]
+    turnstile_timeout_ms: Option<u64>,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Accept Turnstile tokens unverified while Cloudflare is unreachable, letting bots through during outages",
+        env = "TURNSTILE_FAIL_OPEN"
+    )]
+    turnstile_fail_open: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Disable the Turnstile check on /shorten (debug builds only)",
+        env = "TURNSTILE_DISABLED"
+    )]
+    turnstile_disabled: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Only return the short url from /shorten",
+        env = "PUBLIC_MINIMAL_RESPONSE"
+    )]
+    public_minimal_response: bool,
+
+    #[arg(long, default_value_t = String::from("http://localhost:8081"), env = "X402_FACILITATOR_URL")]
+    x402_facilitator_url: String,
+
+    #[arg(long, default_value_t = String::from("0.01"), env = "X402_PRICE_PER_LINK")]
+    x402_price_per_link: String,
+
+    #[arg(
+        long,
+        default_value_t = 0.001,
+        help = "Lowest X402_PRICE_PER_LINK (in USDC) the server starts with",
+        env = "X402_MIN_PRICE_PER_LINK"
+    )]
+    x402_min_price_per_link: f64,
+
+    #[arg(long, env = "X402_MERCHANT_WALLET")]
+    #[serde(serialize_with = "redact_optional")]
+    x402_merchant_wallet: Option<String>,
+
+    #[arg(
+        long,
+        default_value_t = true,
+        action = clap::ArgAction::Set,
+        help = "Serve /x402/shorten if a merchant wallet is configured, false pauses paid links",
+        env = "X402_ENABLED"
+    )]
+    x402_enabled: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Persist links paid on a testnet (Base Sepolia) instead of returning the demo response",
+        env = "X402_PERSIST_TESTNET"
+    )]
+    x402_persist_testnet: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = SolanaPayments::RejectLoud,
+        help = "Reject x402 payments on Solana with an error log and 500, or quietly with 400",
+        env = "X402_SOLANA"
+    )]
+    x402_solana: SolanaPayments,
+
+    #[arg(
+        long,
+        value_delimiter = ',',
+        help = "Comma separated host=price (in USDC) pairs for paid links to hosts costing more than X402_PRICE_PER_LINK",
+        env = "X402_HOST_PRICES"
+    )]
+    x402_host_prices: Vec<String>,
+}
+
+fn public_api(
+    turnstile_secret: String,
+    turnstile_disabled: bool,
+    turnstile_claims: TurnstileClaims,
+    turnstile_policy: VerifyPolicy,
+) -> anyhow::Result<Router<Arc<App>>> {
+    let router = Router::new().route("/shorten", post(handle_public_create));
+
+    if turnstile_disabled {
+        tracing::warn!("turnstile check disabled for the public api");
+        Ok(router)
+    } else {
+        tracing::info!(claims = ?turnstile_claims, policy = ?turnstile_policy, "turnstile verification configured");
+        Ok(router.layer(Extension(
+            TurnstileVerifier::new(turnstile_secret, turnstile_claims)?
+                .with_policy(turnstile_policy)?,
+        )))
+    }
+}
+
+/// Normalizes `route_prefix` to `/path`, `None` for the root. Fails unless
+/// the path of `url_prefix` ends with it, as short urls built from
+/// `url_prefix` would not reach the routes otherwise.
+fn route_prefix(url_prefix: &str, route_prefix: Option<&str>) -> anyhow::Result<Option<String>> {
+    let Some(route_prefix) = route_prefix
+        .map(|prefix| prefix.trim_matches('/'))
+        .filter(|prefix| !prefix.is_empty())
+    else {
+        return Ok(None);
+    };
+    let route_prefix = format!("/{route_prefix}");
+
+    let url = url::Url::parse(url_prefix)
+        .map_err(|e| anyhow::anyhow!("invalid URL_PREFIX '{url_prefix}': {e}"))?;
+    if !url.path().trim_end_matches('/').ends_with(&route_prefix) {
+        anyhow::bail!("URL_PREFIX '{url_prefix}' does not end with ROUTE_PREFIX '{route_prefix}'");
+    }
+
+    Ok(Some(route_prefix))
+}
+
+/// Parses the price per link in USDC into token base units, refusing prices
+/// that are below `min_price` or round to no base unit at all, so paid links
+/// are never given away by a misconfiguration.
+fn x402_price_base_units(price: &str, min_price: f64) -> anyhow::Result<u64> {
+    let price_usdc: f64 = price
+        .parse()
+        .map_err(|e| anyhow::anyhow!("invalid X402_PRICE_PER_LINK '{price}': {e}"))?;
+
+    // USDC has 6 decimals
+    let base_units = (price_usdc * 1_000_000.0).round();
+
+    if !(price_usdc >= min_price && base_units >= 1.0 && base_units < u64::MAX as f64) {
+        anyhow::bail!(
+            "X402_PRICE_PER_LINK must be at least {min_price} USDC and one base unit (0.000001), got '{price}'"
+        );
+    }
+
+    Ok(base_units as u64)
+}
+
+fn setup_cors(relaxed: bool) -> CorsLayer {
+    if relaxed {
+        tracing::info!("cors setup: very_permissive");
+        CorsLayer::very_permissive().allow_credentials(true)
+    } else {
+        tracing::info!("cors setup: default");
+        CorsLayer::new()
+    }
+}
+
+/// Policy for the html pages served, which need neither scripts nor to be
+/// framed.
+const HTML_CSP: &str = "default-src 'none'; style-src 'unsafe-inline'; frame-ancestors 'none'";
+
+/// Adds `Referrer-Policy: no-referrer`, so redirects don't leak the short url
+/// to the destination, `nosniff` and a restrictive CSP on html pages.
+fn security_headers(router: Router<Arc<App>>) -> Router<Arc<App>> {
+    router
+        .layer(SetResponseHeaderLayer::if_not_present(
+            header::REFERRER_POLICY,
+            HeaderValue::from_static("no-referrer"),
+        ))
+        .layer(SetResponseHeaderLayer::if_not_present(
+            header::X_CONTENT_TYPE_OPTIONS,
+            HeaderValue::from_static("nosniff"),
+        ))
+        .layer(SetResponseHeaderLayer::if_not_present(
+            header::CONTENT_SECURITY_POLICY,
+            |response: &Response| {
+                response
+                    .headers()
+                    .get(header::CONTENT_TYPE)
+                    .and_then(|value| value.to_str().ok())
+                    .is_some_and(|value| value.starts_with("text/html"))
+                    .then(|| HeaderValue::from_static(HTML_CSP))
+            },
+        ))
+}
+
+/// Compresses responses as the client accepts, e.g. the JSON of exports and
+/// link lists. Images like QR code PNGs are compressed already, and event
+/// streams would be held back until enough of them are buffered.
+fn compression() -> CompressionLayer<impl Predicate> {
+    CompressionLayer::new().compress_when(
+        DefaultPredicate::new()
+            .and(NotForContentType::IMAGES)
+            .and(NotForContentType::SSE),
+    )
+}
+
+/// The URL checks of creates, also served on `/rules`.
+fn validation_rules(args: &Arguments) -> ValidationRules {
+    let mut rules = ValidationRules {
+        max_url_length: args.max_url_length,
+        block_private_hosts: args.block_private_hosts,
+        ..AppConfig::default().validation_rules
+    };
+
+    if args.https_only_destinations {
+        rules.allowed_schemes.retain(|scheme| scheme == "https");
+    }
+
+    rules
+}
+
+/// Resolves once the term signal was received, after refusing further creates
+/// and notifying the `--shutdown-webhook`.
+async fn shutdown_signal(
+    signal: tokio::sync::oneshot::Receiver<()>,
+    app: Arc<App>,
+    webhook: Option<Url>,
+) {
+    signal.await.ok();
+    // creates arriving while connections drain get a 503 instead of a link
+    app.begin_shutdown();
+
+    if let Some(url) = webhook {
+        signals::notify_shutdown(&url).await;
+    }
+}
+
+/// Parses `host=price` pairs, prices in USDC like `X402_PRICE_PER_LINK`.
+fn x402_host_prices(pairs: &[String], min_price: f64) -> anyhow::Result<HashMap<String, u64>> {
+    pairs
+        .iter()
+        .map(|pair| {
+            let (host, price) = pair
+                .split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("invalid X402_HOST_PRICES entry '{pair}'"))?;
+            let host = host.trim().trim_end_matches('.').to_ascii_lowercase();
+
+            Ok((host, x402_price_base_units(price.trim(), min_price)?))
+        })
+        .collect()
+}
+
+/// The x402 payment endpoint, only served if a merchant wallet is configured
+/// and it is not switched off.
+fn x402_router(args: &Arguments) -> anyhow::Result<Router<Arc<App>>> {
+    let Some(merchant_wallet) = &args.x402_merchant_wallet else {
+        tracing::info!("x402 payment endpoint disabled (no merchant wallet configured)");
+        return Ok(Router::new());
+    };
+
+    if !args.x402_enabled {
+        tracing::warn!("x402 payment endpoint disabled (X402_ENABLED=false)");
+        return Ok(Router::new());
+    }
+
+    tracing::info!(
+        facilitator = %args.x402_facilitator_url,
+        price = %args.x402_price_per_link,
+        merchant = %merchant_wallet,
+        persist_testnet = args.x402_persist_testnet,
+        "x402 payment endpoint enabled"
+    );
+
+    let x402 = X402Middleware::try_from(args.x402_facilitator_url.as_str())
+        .expect("Failed to create x402 middleware");
+
+    // Parse merchant wallet address
+    let merchant_address: x402_rs::types::EvmAddress = merchant_wallet
+        .parse()
+        .expect("Invalid merchant wallet address");
+
+    let price_base_units =
+        x402_price_base_units(&args.x402_price_per_link, args.x402_min_price_per_link)?;
+
+    // Create price tags for both Base mainnet and Base Sepolia
+    let usdc_base = USDCDeployment::by_network(Network::Base);
+    let price_tag_base = PriceTag::new(merchant_address, price_base_units, usdc_base);
+
+    let usdc_sepolia = USDCDeployment::by_network(Network::BaseSepolia);
+    let price_tag_sepolia = PriceTag::new(merchant_address, price_base_units, usdc_sepolia);
+
+    tracing::info!(
+        merchant = ?merchant_address,
+        amount = price_base_units,
+        networks = %format!(
+            "{}, {}",
+            network_to_db_string(Network::Base),
+            network_to_db_string(Network::BaseSepolia)
+        ),
+        "x402 price tags configured"
+    );
+
+    Ok(Router::new()
+        .route("/x402/shorten", post(handle_x402_create))
+        .layer(
+            x402.with_description("Link shortening service")
+                .settle_before_execution()
+                .with_price_tag(price_tag_base) // Base mainnet (first one)
+                .or_price_tag(price_tag_sepolia), // Base Sepolia testnet (add to list)
+        ))
+}
+
+/// Parses the arguments on top of the settings of the `--config` file.
+fn parse_arguments<I, T>(itr: I) -> Result<Arguments, clap::Error>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    let itr: Vec<OsString> = itr.into_iter().map(Into::into).collect();
+
+    // settings only the file provides would fail a complete parse
+    let config = Arguments::command()
+        .ignore_errors(true)
+        .try_get_matches_from(&itr)?
+        .get_one::<PathBuf>("config")
+        .cloned();
+
+    let mut cmd = Arguments::command();
+    if let Some(path) = config {
+        cmd = config_file::apply(cmd, &path)
+            .map_err(|e| Arguments::command().error(ErrorKind::InvalidValue, format!("{e:#}")))?;
+    }
+
+    Arguments::from_arg_matches(&cmd.try_get_matches_from(itr)?)
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = parse_arguments(std::env::args_os()).unwrap_or_else(|e| e.exit());
+
+    let effective_config = EffectiveConfig(Arc::new(serde_json::to_value(&args)?));
+
+    let log_level = args.rust_log_level.clone();
+
+    let cors_relaxed = args.cors_relaxed;
+
+    let access_log = args
+        .access_log_file
+        .as_deref()
+        .map(AccessLogLayer::open)
+        .transpose()?;
+    let access_logged = access_log.is_some();
+
+    // the access log has a filter of its own, independent of the log level
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_ansi(args.log_color.use_ansi(std::io::stdout().is_terminal()))
+                .with_filter(
+                    tracing_subscriber::EnvFilter::new(log_level.clone())
+                        .and(filter_fn(|metadata| !access_log::is_access_log(metadata))),
+                ),
+        )
+        .with(access_log.map(|layer| layer.with_filter(filter_fn(access_log::is_access_log))))
+        .init();
+
+    init_crypto_provider();
+
+    tracing::info!(git = %GIT_HASH, log = log_level, cors_relaxed, cache_size = args.cache_size, cache_max_bytes = ?args.cache_max_bytes, "server starting");
+
+    if args.turnstile_disabled && !is_debug() {
+        anyhow::bail!("turnstile can only be disabled in debug builds");
+    }
+
+    if args.alias_min_length == 0 || args.alias_min_length > args.alias_max_length {
+        anyhow::bail!(
+            "alias lengths must satisfy 1 <= min ({}) <= max ({})",
+            args.alias_min_length,
+            args.alias_max_length
+        );
+    }
+
+    // wait for the DB to come up before running migrations against it
+    let dbpool = DbPool::build(
+        &args.db_url,
+        DbPoolOptions {
+            size: args.db_pool_size,
+            retry: ConnectRetry {
+                attempts: args.db_connect_attempts,
+                delay: Duration::from_millis(args.db_connect_retry_delay_ms),
+            },
+            recycle: args.db_recycle,
+            schema: args.db_schema.clone(),
+        },
+    )
+    .await?;
+
+    migrate(&args.db_url, args.migrate, args.db_schema.as_ref())?;
+
+    let invalidations: Option<Arc<dyn Invalidations>> = args
+        .shared_cache_invalidation
+        .then(|| Arc::new(PgInvalidations::start(args.db_url.clone(), dbpool.clone())) as _);
+
+    let mut db: Arc<dyn LinksDB> = Arc::new(PostgresDb::new(dbpool));
+    if let Some(ms) = args.slow_query_ms {
+        db = Arc::new(SlowQueryLog::new(db, Duration::from_millis(ms)));
+    }
+
+    // only the oldest of those is found by a redirect
+    if args.case_insensitive_ids {
+        let shadowed = db.case_duplicate_ids().await?;
+        if !shadowed.is_empty() {
+            tracing::warn!(
+                count = shadowed.len(),
+                ids = ?&shadowed[..shadowed.len().min(10)],
+                "ids differing only in case, all but the oldest link are unreachable"
+            );
+        }
+    }
+
+    let counter: Arc<dyn ClickStore> = Arc::new(ClickCounter::new());
+
+    tokio::spawn(start_counter_flusher(
+        Arc::clone(&counter),
+        Arc::clone(&db),
+        Duration::from_secs(args.stats_flush_interval_secs),
+    ));
+
+    if let Some(secs) = args.purge_expired_interval_secs {
+        tokio::spawn(start_expired_purger(
+            Arc::clone(&db),
+            Duration::from_secs(secs),
+        ));
+    }
+
+    let auth_header = HeaderName::try_from(&args.auth_header)
+        .map_err(|e| anyhow::anyhow!("invalid AUTH_HEADER '{}': {e}", args.auth_header))?;
+    let mut api_keys = ApiKeys::new(&args.keys).with_header(auth_header.clone());
+    if let Some(per_minute) = args.key_rate_limit {
+        api_keys = api_keys.with_rate_limit(per_minute);
+    }
+    let admin_keys = ApiKeys::new(&args.admin_keys).with_header(auth_header);
+
+    let x402_router = x402_router(&args)?;
+
+    let health_page = match &args.health_page {
+        Some(path) => std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("failed to read HEALTH_PAGE '{}': {e}", path.display()))?,
+        None => AppConfig::default().health_page,
+    };
+
+    let blocked_id_terms = match &args.blocked_id_terms {
+        Some(path) => parse_blocked_id_terms(&std::fs::read_to_string(path).map_err(|e| {
+            anyhow::anyhow!("failed to read BLOCKED_ID_TERMS '{}': {e}", path.display())
+        })?),
+        None => Vec::new(),
+    };
+
+    let public_suffixes = match (args.require_public_tld, &args.public_suffix_list) {
+        (false, _) => None,
+        (true, None) => anyhow::bail!("--require-public-tld needs a --public-suffix-list"),
+        (true, Some(path)) => {
+            let list = std::fs::read_to_string(path).map_err(|e| {
+                anyhow::anyhow!(
+                    "failed to read PUBLIC_SUFFIX_LIST '{}': {e}",
+                    path.display()
+                )
+            })?;
+            let list = list.parse::<publicsuffix::List>().map_err(|e| {
+                anyhow::anyhow!("invalid PUBLIC_SUFFIX_LIST '{}': {e}", path.display())
+            })?;
+            Some(Arc::new(list))
+        }
+    };
+
+    let route_prefix = route_prefix(&args.url_prefix, args.route_prefix.as_deref())?;
+    let validation_rules = validation_rules(&args);
+
+    let app = App::new(
+        AppConfig {
+            prefix: args.url_prefix,
+            hash_length: args.hash_length,
+            alias_min_length: args.alias_min_length,
+            alias_max_length: args.alias_max_length,
+            hash_salt: args.hash_salt,
+            id_strategy: args.id_strategy,
+            cache_size: args.cache_size,
+            cache_max_bytes: args.cache_max_bytes,
+            invalidations,
+            clock: Arc::new(SystemClock),
+            x402_persist_testnet: args.x402_persist_testnet,
+            x402_solana: args.x402_solana,
+            x402_host_prices: x402_host_prices(
+                &args.x402_host_prices,
+                args.x402_min_price_per_link,
+            )?,
+            turnstile_disabled: args.turnstile_disabled,
+            max_total_links: args.max_total_links,
+            public_minimal_response: args.public_minimal_response,
+            idn_policy: args.idn_policy,
+            upgrade_insecure: args.upgrade_insecure,
+            upgrade_insecure_hosts: args
+                .upgrade_insecure_hosts
+                .iter()
+                .map(|host| host.trim().trim_end_matches('.').to_ascii_lowercase())
+                .collect(),
+            validation_rules,
+            blocked_redirect_message: args.blocked_redirect_message,
+            expose_original_url_header: args.expose_original_url_header,
+            link_id_header: args.link_id_header,
+            redirect_cache_max_age: args.redirect_cache_max_age,
+            forward_query: args.forward_query,
+            forward_query_max_params: args.forward_query_max_params,
+            forward_query_max_bytes: args.forward_query_max_bytes,
+            strip_fragments: args.strip_fragments,
+            canonicalize_query: args.canonicalize_query,
+            strip_query_params: args
+                .strip_query_params
+                .iter()
+                .map(|param| param.trim().to_ascii_lowercase())
+                .filter(|param| !param.is_empty())
+                .collect(),
+            health_format: args.health_format,
+            health_page,
+            blocked_id_terms,
+            redirect_loop_guard: args.redirect_loop_guard,
+            redirect_loop_hosts: args
+                .redirect_loop_hosts
+                .iter()
+                .map(|host| host.trim().trim_end_matches('.').to_ascii_lowercase())
+                .collect(),
+            public_stats_fields: args.public_stats_fields,
+            trust_forwarded_proto: args.trust_forwarded_proto,
+            trust_forwarded_for: args.trust_forwarded_for,
+            root_redirect: args.root_redirect.map(String::from),
+            collision_warn_limit: args.collision_warn_limit,
+            max_collision_retries: args.max_collision_retries,
+            public_tarpit_threshold: args.public_tarpit_threshold,
+            public_tarpit_delay_ms: args.public_tarpit_delay_ms,
+            public_limiter_capacity: args.public_limiter_capacity,
+            public_rate_limit: args.public_rate_limit,
+            public_cooldown_secs: args.public_cooldown_secs,
+            audit_log: args.audit_log,
+            store_creator_ip: args.store_creator_ip,
+            public_suffixes,
+            dedup_concurrent_creates: args.dedup_concurrent_creates,
+            fetch_titles: args.fetch_titles,
+            crawler_user_agents: args.crawler_user_agents,
+            verify_destination: args.verify_destination,
+            dedupe_by_content: args.dedupe_by_content,
+            normalize_unicode: args.normalize_unicode,
+            redirect_rate_limit: args.redirect_rate_limit,
+            redirect_limiter_capacity: args.redirect_limiter_capacity,
+            password_attempts: args.password_attempts,
+            metrics_tenants: args
+                .metrics_tenants
+                .iter()
+                .map(|tenant| tenant.trim().to_string())
+                .collect(),
+            tld_exempt_hosts: args
+                .tld_exempt_hosts
+                .iter()
+                .map(|host| host.trim().trim_end_matches('.').to_ascii_lowercase())
+                .collect(),
+            namespace_by_key: args.namespace_by_key,
+            case_insensitive_ids: args.case_insensitive_ids,
+            case_insensitive_aliases: args.case_insensitive_aliases,
+            unavailable_retry_after: args.unavailable_retry_after,
+            sync_click_counts: args.sync_click_counts,
+            record_demo_requests: args.record_demo_requests,
+        },
+        Arc::clone(&db),
+        Arc::clone(&counter),
+    );
+
+    let pub_api = public_api(
+        args.turnstile_secret,
+        args.turnstile_disabled,
+        TurnstileClaims {
+            action: args.turnstile_action,
+            cdata: args.turnstile_cdata,
+            hostnames: args.turnstile_hostnames,
+            interactive: args.turnstile_require_interactive,
+        },
+        VerifyPolicy {
+            timeout: args.turnstile_timeout_ms.map(Duration::from_millis),
+            fail_open: args.turnstile_fail_open,
+        },
+    )?;
+
+    let mut admin_api = Router::new()
+        .route("/admin/summary", get(handle_summary))
+        .route(
+            "/admin/config",
+            get(handle_admin_config).layer(Extension(effective_config)),
+        )
+        .route("/admin/stats/{id}", get(handle_admin_stats))
+        .route("/link/{id}/transactions", get(handle_link_transactions))
+        .route("/admin/flush-counters", post(handle_flush_counters))
+        .route("/admin/cache/warm", post(handle_warm_cache))
+        .route("/admin/cache/{id}", delete(handle_evict_cache))
+        .route("/admin/export", get(handle_export))
+        .route("/admin/events", get(handle_events))
+        .route("/admin/blocklist", post(handle_block_host))
+        .route("/admin/blocklist/{host}", delete(handle_unblock_host))
+        .route("/admin/link/{id}/disable", post(handle_admin_disable_link))
+        .route("/admin/link/{id}/enable", post(handle_admin_enable_link));
+    if args.allow_key_updates {
+        tracing::warn!("api keys can be changed via /admin/keys");
+        admin_api = admin_api.route(
+            "/admin/keys",
+            put(handle_update_key).layer(Extension(api_keys.clone())),
+        );
+    }
+    let admin_api = admin_api.route_layer(middleware::from_fn_with_state(admin_keys, require_auth));
+
+    let router = Router::new()
+        //authenticated routes
+        .route("/link/create", post(handle_create))
+        .route("/link/import/csv", post(handle_import_csv))
+        .route("/link/reserve", post(handle_reserve_link))
+        .route("/link/create/batch", post(handle_create_links))
+        .route("/link/exists/batch", post(handle_find_links))
+        .route("/link/resolve/batch", post(handle_resolve_links))
+        .route("/link/delete", post(handle_delete_links))
+        .route("/link/export/me", get(handle_export_own))
+        .route("/link/list", get(handle_list_links))
+        .route("/link/{id}", get(handle_get_link).put(handle_update_link))
+        .route("/link/{id}/stats", get(handle_link_stats))
+        .route("/link/{id}/timeseries", get(handle_link_timeseries))
+        .route("/link/{id}/disable", post(handle_disable_link))
+        .route("/link/{id}/enable", post(handle_enable_link))
+        .route("/auth/check", get(handle_check_key))
+        .route_layer(middleware::from_fn_with_state(api_keys, require_auth))
+        //admin routes
+        .merge(admin_api)
+        //public routes
+        .route(
+            "/{id}",
+            get(handle_redirect)
+                .head(handle_redirect_head)
+                .post(handle_redirect_password),
+        )
+        .route("/", get(handle_root))
+        .route("/stats/{id}", get(handle_public_stats))
+        .route("/trending", get(handle_trending))
+        .merge(pub_api)
+        // host prices are checked before the x402 middleware settles
+        .merge(x402_router.layer(middleware::from_fn_with_state(
+            Arc::clone(&app),
+            x402_host_price,
+        )))
+        .route("/livez", get(handle_livez))
+        .route("/readyz", get(handle_health))
+        .route("/health", get(handle_health))
+        .route("/metrics", get(handle_metrics))
+        .route("/rules", get(handle_rules));
+
+    let router = if args.security_headers {
+        security_headers(router)
+    } else {
+        router
+    };
+
+    let router = match &route_prefix {
+        Some(prefix) => {
+            tracing::info!(prefix, "routes served under prefix");
+            Router::new().nest(prefix, router)
+        }
+        None => router,
+    };
+
+    let router = if access_logged {
+        router.layer(middleware::from_fn(access_log::record))
+    } else {
+        router
+    };
+
+    let router = router
+        .layer(compression())
+        .layer(TraceLayer::new_for_http())
+        .layer(setup_cors(cors_relaxed))
+        .with_state(Arc::clone(&app));
+
+    let addr = SocketAddr::from(([0, 0, 0, 0], args.port));
+
+    tracing::info!("listening on https://{}", addr);
+    tracing::info!("listening on http://{}", addr);
+
+    let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+
+    signals::create_term_signal_handler(tx);
+
+    let listener = TcpListener::bind(addr).await?;
+
+    let server = axum::serve(
+        listener,
+        router.into_make_service_with_connect_info::<SocketAddr>(),
+    );
+
+    let graceful =
+        server.with_graceful_shutdown(shutdown_signal(rx, Arc::clone(&app), args.shutdown_webhook));
+
+    if let Err(e) = graceful.await {
+        tracing::error!("server error: {}", e);
+    }
+
+    if let Err(e) = app.flush_counters().await {
+        tracing::error!("failed to flush click counts on shutdown: {e}");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{db::MockLinksDB, models::FetchLink};
+    use axum::{
+        body::Body,
+        extract::connect_info::MockConnectInfo,
+        http::{Request, StatusCode, header::CONTENT_TYPE},
+    };
+    use tower::ServiceExt;
+
+    fn shorten_request() -> Request<Body> {
+        Request::post("/shorten")
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"url":"https://www.rustunit.com"}"#))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_log_color() {
+        assert!(LogColor::Auto.use_ansi(true));
+        assert!(!LogColor::Auto.use_ansi(false));
+        assert!(LogColor::Always.use_ansi(false));
+        assert!(!LogColor::Never.use_ansi(true));
+
+        let args =
+            Arguments::try_parse_from(["ezlime", "--db-url=x", "--log-color=never"]).unwrap();
+        assert_eq!(args.log_color, LogColor::Never);
+    }
+
+    #[tokio::test]
+    async fn test_https_only_destinations() {
+        let args = Arguments::try_parse_from(["ezlime", "--db-url=x", "--https-only-destinations"])
+            .unwrap();
+        let app = App::new(
+            AppConfig {
+                validation_rules: validation_rules(&args),
+                ..Default::default()
+            },
+            Arc::new(db::MemoryDb::default()),
+            Arc::new(ClickCounter::new()),
+        );
+        let create = |url: &str| {
+            app.create_link(
+                String::from("key"),
+                models::LinkSource::Api,
+                ezlime_rs::CreateLinkRequest {
+                    url: url.to_string(),
+                    ..Default::default()
+                },
+                false,
+                None,
+            )
+        };
+
+        let err = create("http://www.rustunit.com").await.unwrap_err();
+        assert_eq!(err.to_string(), "validation failed: scheme_not_allowed");
+        create("https://www.rustunit.com").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_webhook() {
+        let (hooked_tx, mut hooked) = tokio::sync::mpsc::unbounded_channel();
+        let hook = Router::new().route(
+            "/hook",
+            post(move |body: String| async move {
+                hooked_tx.send(body).unwrap();
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let webhook =
+            Url::parse(&format!("http://{}/hook", listener.local_addr().unwrap())).unwrap();
+        tokio::spawn(async move { axum::serve(listener, hook).await.unwrap() });
+
+        let app = App::new(
+            AppConfig::default(),
+            Arc::new(MockLinksDB::new()),
+            Arc::new(ClickCounter::new()),
+        );
+        let (signal, rx) = tokio::sync::oneshot::channel();
+        let shutdown = tokio::spawn(shutdown_signal(rx, Arc::clone(&app), Some(webhook)));
+
+        tokio::task::yield_now().await;
+        assert!(hooked.try_recv().is_err());
+
+        signal.send(()).unwrap();
+        shutdown.await.unwrap();
+
+        assert_eq!(hooked.recv().await.unwrap(), r#"{"event":"shutdown"}"#);
+        assert!(app.accept_creates().is_err());
+    }
+
+    #[test]
+    fn test_effective_config_redacts_secrets() {
+        let args = Arguments::try_parse_from([
+            "ezlime",
+            "--db-url=postgres://user:hunter2@db/ezlime",
+            "--keys=secret-key",
+            "--admin-keys=admin-key",
+            "--turnstile-secret=turnstile-token",
+            "--hash-salt=pepper",
+            "--hash-length=8",
+            "--url-prefix=https://ezli.me",
+            "--public-stats-fields=click-count,last-used",
+        ])
+        .unwrap();
+
+        let config = serde_json::to_value(&args).unwrap();
+        for secret in [
+            "db_url",
+            "keys",
+            "admin_keys",
+            "turnstile_secret",
+            "hash_salt",
+        ] {
+            assert_eq!(config[secret], REDACTED, "{secret}");
+        }
+        assert_eq!(config["x402_merchant_wallet"], serde_json::Value::Null);
+
+        assert_eq!(config["hash_length"], 8);
+        assert_eq!(config["cache_size"], 100);
+        assert_eq!(config["url_prefix"], "https://ezli.me");
+        assert_eq!(config["stats_flush_interval_secs"], 3);
+        assert_eq!(config["migrate"], "auto");
+        assert_eq!(
+            config["public_stats_fields"],
+            serde_json::json!(["click-count", "last-used"])
+        );
+
+        let rendered = config.to_string();
+        for secret in [
+            "hunter2",
+            "secret-key",
+            "admin-key",
+            "turnstile-token",
+            "pepper",
+        ] {
+            assert!(!rendered.contains(secret), "{secret} leaked");
+        }
+    }
+
+    #[test]
+    fn test_hash_length_range() {
+        for length in ["0", "1", "12", "-1"] {
+            let err = Arguments::try_parse_from([
+                String::from("ezlime"),
+                String::from("--db-url=x"),
+                format!("--hash-length={length}"),
+            ])
+            .unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::ValueValidation);
+        }
+
+        let args = Arguments::try_parse_from(["ezlime", "--db-url=x", "--hash-length=11"]).unwrap();
+        assert_eq!(args.hash_length, 11);
+    }
+
+    #[test]
+    fn test_config_file() {
+        let path = std::env::temp_dir().join(format!("ezlime-test-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"
+db_url = "postgres://localhost/ezlime"
+port = 9000
+hash-length = 8
+namespace_by_key = true
+turnstile_hostnames = ["ezli.me", "www.ezli.me"]
+log_color = "never"
+"#,
+        )
+        .unwrap();
+
+        let args = parse_arguments([
+            "ezlime".into(),
+            OsString::from("--config"),
+            path.clone().into(),
+            "--port=9001".into(),
+        ])
+        .unwrap();
+
+        assert_eq!(args.db_url, "postgres://localhost/ezlime");
+        // flags win over the file
+        assert_eq!(args.port, 9001);
+        assert_eq!(args.hash_length, 8);
+        assert!(args.namespace_by_key);
+        assert_eq!(args.turnstile_hostnames, ["ezli.me", "www.ezli.me"]);
+        assert_eq!(args.log_color, LogColor::Never);
+        // untouched settings keep their defaults
+        assert_eq!(args.cache_size, 100);
+
+        std::fs::write(&path, "db_url = \"x\"\nno_such_setting = 1\n").unwrap();
+        let err = parse_arguments([
+            "ezlime".into(),
+            OsString::from("--config"),
+            path.clone().into(),
+        ])
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("Unknown setting 'no_such_setting'")
+        );
+
+        std::fs::write(&path, "db_url = ").unwrap();
+        let err = parse_arguments([
+            "ezlime".into(),
+            OsString::from("--config"),
+            path.clone().into(),
+        ])
+        .unwrap_err();
+        assert!(err.to_string().contains("Could not parse config file"));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_x402_price() {
+        assert_eq!(x402_price_base_units("0.01", 0.001).unwrap(), 10_000);
+
+        assert!(x402_price_base_units("0", 0.0).is_err());
+        assert!(x402_price_base_units("-0.01", 0.001).is_err());
+        assert!(x402_price_base_units("0.0001", 0.001).is_err());
+        assert!(x402_price_base_units("NaN", 0.001).is_err());
+        assert!(x402_price_base_units("free", 0.001).is_err());
+        // no floor configured, the conversion itself must not end up free
+        assert!(x402_price_base_units("0.0000001", 0.0).is_err());
+        assert!(x402_price_base_units("inf", 0.0).is_err());
+        assert!(x402_price_base_units("1e300", 0.0).is_err());
+        assert_eq!(x402_price_base_units("0.000001", 0.0).unwrap(), 1);
+        assert_eq!(x402_price_base_units("0.57", 0.0).unwrap(), 570_000);
+    }
+
+    #[test]
+    fn test_x402_host_prices() {
+        let prices = x402_host_prices(
+            &[
+                String::from("GitHub.com=0.05"),
+                String::from(" ezli.me = 0.02"),
+            ],
+            0.001,
+        )
+        .unwrap();
+        assert_eq!(prices["github.com"], 50_000);
+        assert_eq!(prices["ezli.me"], 20_000);
+
+        assert!(x402_host_prices(&[String::from("github.com")], 0.001).is_err());
+        assert!(x402_host_prices(&[String::from("github.com=0")], 0.001).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_public_create_with_turnstile_disabled() {
+        let mut db = MockLinksDB::with_empty_blocklist();
+        db.expect_create()
+            .times(1)
+            .returning(|link| Ok(link.clone()));
+
+        let app = App::new(
+            AppConfig {
+                turnstile_disabled: true,
+                ..Default::default()
+            },
+            Arc::new(db),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let response = public_api(
+            String::new(),
+            true,
+            TurnstileClaims::default(),
+            VerifyPolicy::default(),
+        )
+        .unwrap()
+        .with_state(app)
+        .layer(MockConnectInfo(SocketAddr::from(([127, 0, 0, 1], 40000))))
+        .oneshot(shorten_request())
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_public_create_requires_turnstile_by_default() {
+        let mut db = MockLinksDB::new();
+        db.expect_create().never();
+
+        let app = App::new(
+            AppConfig::default(),
+            Arc::new(db),
+            Arc::new(ClickCounter::new()),
+        );
+
+        // even without the layer the handler insists on a verified token
+        let response = public_api(
+            String::new(),
+            true,
+            TurnstileClaims::default(),
+            VerifyPolicy::default(),
+        )
+        .unwrap()
+        .with_state(app)
+        .oneshot(shorten_request())
+        .await
+        .unwrap();
+
+        assert_ne!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_check_key() {
+        let router: Router = Router::new()
+            .route("/auth/check", get(handle_check_key))
+            .route_layer(middleware::from_fn_with_state(
+                ApiKeys::new("valid-key"),
+                require_auth,
+            ));
+
+        let check = |key: &'static str| {
+            Request::get("/auth/check")
+                .header("Authorization", key)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = router.clone().oneshot(check("valid-key")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["valid"], true);
+        assert_eq!(json["scopes"], serde_json::json!([]));
+
+        let response = router.oneshot(check("invalid-key")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_security_headers() {
+        let mut db = MockLinksDB::with_empty_blocklist();
+        db.expect_get().returning(|id| {
+            Ok(Some(FetchLink {
+                id: id.to_string(),
+                url: "https://www.rustunit.com".to_string(),
+                redirect_status: 307,
+                password_hash: None,
+                disabled: false,
+                reserved: false,
+                click_count: 0,
+                max_clicks: None,
+                key: None,
+                expires_at: None,
+            }))
+        });
+        let app = App::new(
+            AppConfig::default(),
+            Arc::new(db),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let router = security_headers(
+            Router::new()
+                .route("/", get(handle_root))
+                .route("/{id}", get(handle_redirect)),
+        )
+        .with_state(app);
+
+        let get = |uri: &'static str| Request::get(uri).body(Body::empty()).unwrap();
+
+        let response = router.clone().oneshot(get("/as9sud")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+        assert_eq!(response.headers()[header::REFERRER_POLICY], "no-referrer");
+        assert_eq!(
+            response.headers()[header::X_CONTENT_TYPE_OPTIONS],
+            "nosniff"
+        );
+        assert!(
+            response
+                .headers()
+                .get(header::CONTENT_SECURITY_POLICY)
+                .is_none()
+        );
+
+        let response = router.oneshot(get("/")).await.unwrap();
+        assert_eq!(
+            response.headers()[header::CONTENT_SECURITY_POLICY],
+            HTML_CSP
+        );
+    }
+
+    #[tokio::test]
+    async fn test_compression() {
+        use crate::models::LinkDetails;
+        use futures_util::{StreamExt, stream};
+
+        let mut db = MockLinksDB::new();
+        db.expect_stream_all().returning(|_| {
+            let links = (0..100).map(|i| {
+                Ok(LinkDetails {
+                    id: format!("id{i}"),
+                    url: format!("https://www.rustunit.com/{i}"),
+                    created_at: chrono::DateTime::UNIX_EPOCH,
+                    key: None,
+                    click_count: 0,
+                    last_used: chrono::DateTime::UNIX_EPOCH,
+                    title: None,
+                    first_used: None,
+                    creator_ip: None,
+                    note: None,
+                })
+            });
+            stream::iter(links.collect::<Vec<_>>()).boxed()
+        });
+        let app = App::new(
+            AppConfig::default(),
+            Arc::new(db),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let router = Router::new()
+            .route("/admin/export", get(handle_export))
+            .route(
+                "/qr.png",
+                get(|| async {
+                    (
+                        [(header::CONTENT_TYPE, "image/png")],
+                        qr::png("https://ezli.me/as9sud").unwrap(),
+                    )
+                }),
+            )
+            .layer(compression())
+            .with_state(app);
+
+        let get = |uri: &'static str| {
+            Request::get(uri)
+                .header(header::ACCEPT_ENCODING, "gzip")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = router.clone().oneshot(get("/admin/export")).await.unwrap();
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "application/x-ndjson"
+        );
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        // the gzip magic number
+        assert!(body.starts_with(&[0x1f, 0x8b]));
+
+        let response = router.oneshot(get("/qr.png")).await.unwrap();
+        assert!(response.headers().get(header::CONTENT_ENCODING).is_none());
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(body.starts_with(b"\x89PNG"));
+    }
+
+    #[tokio::test]
+    async fn test_x402_disabled_with_wallet() {
+        let args = Arguments::try_parse_from([
+            "ezlime",
+            "--db-url=x",
+            "--x402-merchant-wallet=0x0000000000000000000000000000000000000001",
+            "--x402-enabled=false",
+        ])
+        .unwrap();
+
+        let app = App::new(
+            AppConfig::default(),
+            Arc::new(MockLinksDB::new()),
+            Arc::new(ClickCounter::new()),
+        );
+
+        let response = x402_router(&args)
+            .unwrap()
+            .with_state(app)
+            .oneshot(
+                Request::post("/x402/shorten")
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(Body::from(r#"{"url":"https://www.rustunit.com"}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_update_key() {
+        let api_keys = ApiKeys::new("valid-key");
+        let router: Router = Router::new()
+            .route("/auth/check", get(handle_check_key))
+            .route_layer(middleware::from_fn_with_state(
+                api_keys.clone(),
+                require_auth,
+            ))
+            .merge(
+                Router::new().route(
+                    "/admin/keys",
+                    put(handle_update_key)
+                        .layer(Extension(api_keys))
+                        .route_layer(middleware::from_fn_with_state(
+                            ApiKeys::new("admin-key"),
+                            require_auth,
+                        )),
+                ),
+            );
+
+        let update = |admin: &'static str, key: &'static str| {
+            Request::put("/admin/keys")
+                .header("Authorization", admin)
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from(
+                    serde_json::json!({ "key": key, "scopes": ["links:write"] }).to_string(),
+                ))
+                .unwrap()
+        };
+        let scopes = |router: Router| async move {
+            let response = router
+                .oneshot(
+                    Request::get("/auth/check")
+                        .header("Authorization", "valid-key")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()["scopes"].clone()
+        };
+
+        assert_eq!(scopes(router.clone()).await, serde_json::json!([]));
+
+        let response = router
+            .clone()
+            .oneshot(update("valid-key", "valid-key"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = router
+            .clone()
+            .oneshot(update("admin-key", "unknown-key"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = router
+            .clone()
+            .oneshot(update("admin-key", "valid-key"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        assert_eq!(scopes(router).await, serde_json::json!(["links:write"]));
+    }
+
+    #[test]
+    fn test_route_prefix() {
+        let prefix = |url: &str, route: Option<&str>| route_prefix(url, route).unwrap();
+
+        assert_eq!(prefix("https://ezli.me", None), None);
+        assert_eq!(prefix("https://ezli.me", Some("/")), None);
+        assert_eq!(
+            prefix("https://ezli.me/s", Some("s/")),
+            Some(String::from("/s"))
+        );
+        assert_eq!(
+            prefix("https://example.com/apps/s/", Some("/s")),
+            Some(String::from("/s"))
+        );
+
+        assert!(route_prefix("https://ezli.me", Some("/s")).is_err());
+        assert!(route_prefix("https://ezli.me/links", Some("/s")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_short_url_under_route_prefix() {
+        let app = App::new(
+            AppConfig {
+                prefix: String::from("https://example.com/s"),
+                ..Default::default()
+            },
+            Arc::new(db::MemoryDb::default()),
+            Arc::new(ClickCounter::new()),
+        );
+        let router = Router::new()
+            .route("/link/create", post(handle_create))
+            .route_layer(middleware::from_fn_with_state(
+                ApiKeys::new("valid-key"),
+                require_auth,
+            ))
+            .route("/{id}", get(handle_redirect));
+        let prefix = route_prefix(&app.config().prefix, Some("/s"))
+            .unwrap()
+            .unwrap();
+        let router = Router::new()
+            .nest(&prefix, router)
+            .with_state(app)
+            .layer(MockConnectInfo(SocketAddr::from(([127, 0, 0, 1], 40000))));
+
+        let response = router
+            .clone()
+            .oneshot(
+                Request::post("/s/link/create")
+                    .header("Authorization", "valid-key")
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(Body::from(r#"{"url":"https://www.rustunit.com"}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let created: ezlime_rs::CreatedLinkResponse = serde_json::from_slice(&body).unwrap();
+
+        let short_url = url::Url::parse(&created.shortened_url).unwrap();
+        let response = router
+            .oneshot(Request::get(short_url.path()).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+        assert_eq!(
+            response.headers()[header::LOCATION],
+            "https://www.rustunit.com"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_custom_auth_header() {
+        let router: Router = Router::new()
+            .route("/auth/check", get(handle_check_key))
+            .route_layer(middleware::from_fn_with_state(
+                ApiKeys::new("valid-key").with_header(HeaderName::from_static("x-api-key")),
+                require_auth,
+            ));
+
+        let check = |header: &'static str| {
+            Request::get("/auth/check")
+                .header(header, "valid-key")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = router.clone().oneshot(check("X-Api-Key")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = router.oneshot(check("Authorization")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_id_space_exhausted() {
+        let app = App::new(
+            AppConfig {
+                hash_length: 1,
+                max_collision_retries: 2,
+                ..Default::default()
+            },
+            Arc::new(db::MemoryDb::default()),
+            Arc::new(ClickCounter::new()),
+        );
+        let router = Router::new()
+            .route("/link/create", post(handle_create))
+            .route_layer(middleware::from_fn_with_state(
+                ApiKeys::new("valid-key"),
+                require_auth,
+            ))
+            .with_state(app);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(
+                listener,
+                router.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .await
+        });
+
+        let api = ezlime_rs::EzlimeApi::new("valid-key".to_string())
+            .with_url(&format!("http://{addr}"))
+            .with_retries(3);
+
+        // 36 single character ids fill up long before 100 links
+        let mut err = None;
+        for i in 0..100 {
+            if let Err(e) = api
+                .create_short_url(&format!("https://www.rustunit.com/{i}"))
+                .await
+            {
+                err = Some(e);
+                break;
+            }
+        }
+
+        let err = err.expect("id space never exhausted");
+        assert!(matches!(err, ezlime_rs::EzlimeApiError::IdSpaceExhausted));
+        assert_eq!(err.code(), "id_space_exhausted");
+    }
 }
@@ -0,0 +1,168 @@
+use prometheus::{
+    Encoder, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+    proto::{Metric, MetricType},
+};
+use serde_json::{Map, Value, json};
+
+/// Prometheus metrics rendered by the `/metrics` endpoint.
+pub struct Metrics {
+    registry: Registry,
+    db_pool_available: IntGauge,
+    db_pool_in_use: IntGauge,
+    db_pool_max: IntGauge,
+    x402_payments: IntCounterVec,
+    links_created: IntCounterVec,
+    redirects: IntCounterVec,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new();
+
+        let gauge = |name: &str, help: &str| {
+            let gauge = IntGauge::new(name, help).expect("valid gauge");
+            registry
+                .register(Box::new(gauge.clone()))
+                .expect("unique metric name");
+            gauge
+        };
+
+        let counter = |name: &str, help: &str, labels: &[&str]| {
+            let counter = IntCounterVec::new(Opts::new(name, help), labels).expect("valid counter");
+            registry
+                .register(Box::new(counter.clone()))
+                .expect("unique metric name");
+            counter
+        };
+
+        Self {
+            db_pool_available: gauge("db_pool_available", "Idle connections in the DB pool"),
+            db_pool_in_use: gauge("db_pool_in_use", "Connections currently checked out"),
+            db_pool_max: gauge("db_pool_max", "Maximum size of the DB pool"),
+            x402_payments: counter(
+                "x402_payments_total",
+                "Paid link requests by outcome",
+                &["network", "result"],
+            ),
+            links_created: counter(
+                "links_created_total",
+                "Links created by tenant",
+                &["tenant"],
+            ),
+            redirects: counter("redirects_total", "Redirects served by tenant", &["tenant"]),
+            registry,
+        }
+    }
+
+    pub fn set_pool_status(&self, status: deadpool::Status) {
+        self.db_pool_available.set(status.available as i64);
+        self.db_pool_in_use
+            .set(status.size.saturating_sub(status.available) as i64);
+        self.db_pool_max.set(status.max_size as i64);
+    }
+
+    /// Counts a paid request, `result` being `created`, `demo` or `rejected`.
+    pub fn count_x402_payment(&self, network: &str, result: &str) {
+        self.x402_payments
+            .with_label_values(&[network, result])
+            .inc();
+    }
+
+    pub fn count_link_created(&self, tenant: &str) {
+        self.links_created.with_label_values(&[tenant]).inc();
+    }
+
+    pub fn count_redirect(&self, tenant: &str) {
+        self.redirects.with_label_values(&[tenant]).inc();
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> Result<String, anyhow::Error> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+
+        Ok(String::from_utf8(buffer)?)
+    }
+
+    /// Renders all metrics as a JSON object keyed by metric name, for
+    /// consumers without a Prometheus parser. Every metric lists its `type`,
+    /// `help` and one sample per label set.
+    pub fn render_json(&self) -> Value {
+        let families = self.registry.gather().into_iter().map(|family| {
+            let kind = family.get_field_type();
+            let samples: Vec<Value> = family
+                .get_metric()
+                .iter()
+                .map(|metric| sample_json(metric, kind))
+                .collect();
+
+            (
+                family.name().to_string(),
+                json!({
+                    "type": type_name(kind),
+                    "help": family.help(),
+                    "samples": samples,
+                }),
+            )
+        });
+
+        Value::Object(families.collect())
+    }
+}
+
+fn type_name(kind: MetricType) -> &'static str {
+    match kind {
+        MetricType::COUNTER => "counter",
+        MetricType::GAUGE => "gauge",
+        MetricType::SUMMARY => "summary",
+        MetricType::UNTYPED => "untyped",
+        MetricType::HISTOGRAM => "histogram",
+    }
+}
+
+fn sample_json(metric: &Metric, kind: MetricType) -> Value {
+    let labels: Map<String, Value> = metric
+        .get_label()
+        .iter()
+        .map(|label| (label.name().to_string(), label.value().into()))
+        .collect();
+
+    match kind {
+        MetricType::COUNTER => {
+            json!({ "labels": labels, "value": metric.get_counter().get_value() })
+        }
+        MetricType::GAUGE => json!({ "labels": labels, "value": metric.get_gauge().get_value() }),
+        // not produced by the typed metrics of the registry
+        MetricType::UNTYPED => json!({ "labels": labels }),
+        MetricType::HISTOGRAM => {
+            let histogram = metric.get_histogram();
+            let buckets: Vec<Value> = histogram
+                .get_bucket()
+                .iter()
+                .map(|b| json!({ "le": b.upper_bound(), "count": b.cumulative_count() }))
+                .collect();
+
+            json!({
+                "labels": labels,
+                "count": histogram.get_sample_count(),
+                "sum": histogram.get_sample_sum(),
+                "buckets": buckets,
+            })
+        }
+        MetricType::SUMMARY => {
+            let summary = metric.get_summary();
+            let quantiles: Vec<Value> = summary
+                .get_quantile()
+                .iter()
+                .map(|q| json!({ "quantile": q.quantile(), "value": q.value() }))
+                .collect();
+
+            json!({
+                "labels": labels,
+                "count": summary.sample_count(),
+                "sum": summary.sample_sum(),
+                "quantiles": quantiles,
+            })
+        }
+    }
+}
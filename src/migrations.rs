@@ -1,11 +1,48 @@
-use diesel::{Connection, PgConnection};
+use crate::db_pool::DbSchema;
+use diesel::{Connection, PgConnection, connection::SimpleConnection};
 use diesel_migrations::{EmbeddedMigrations, MigrationHarness, embed_migrations};
-use tracing::info;
+use serde::Serialize;
+use tracing::{info, warn};
 
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("./migrations");
 
-pub fn run_migrations(url: &str) -> Result<(), anyhow::Error> {
+/// What to do about pending migrations at startup.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, clap::ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MigrateMode {
+    /// Apply pending migrations
+    #[default]
+    Auto,
+    /// Refuse to start if migrations are pending, without applying them
+    Check,
+    /// Don't look at migrations at all
+    Skip,
+}
+
+pub fn migrate(
+    url: &str,
+    mode: MigrateMode,
+    schema: Option<&DbSchema>,
+) -> Result<(), anyhow::Error> {
+    match mode {
+        MigrateMode::Auto => run_migrations(url, schema),
+        MigrateMode::Check => check_migrations(url, schema),
+        MigrateMode::Skip => {
+            warn!("skipping migrations");
+            Ok(())
+        }
+    }
+}
+
+/// Applies pending migrations to the tables of `schema` (creating it if
+/// needed) or of `public`.
+pub fn run_migrations(url: &str, schema: Option<&DbSchema>) -> Result<(), anyhow::Error> {
     let mut db = PgConnection::establish(url)?;
+    if let Some(schema) = schema {
+        db.batch_execute(&format!("CREATE SCHEMA IF NOT EXISTS {schema}"))?;
+        db.batch_execute(&schema.set_search_path())?;
+    }
+
     let migrations = db
         .run_pending_migrations(MIGRATIONS)
         .map_err(|e| anyhow::anyhow!(e.to_string()))?
@@ -15,3 +52,54 @@ pub fn run_migrations(url: &str) -> Result<(), anyhow::Error> {
 
     Ok(())
 }
+
+/// Fails if any migration is pending, for deployments that migrate manually.
+pub fn check_migrations(url: &str, schema: Option<&DbSchema>) -> Result<(), anyhow::Error> {
+    let mut db = PgConnection::establish(url)?;
+    if let Some(schema) = schema {
+        db.batch_execute(&schema.set_search_path())?;
+    }
+
+    let pending = db
+        .has_pending_migration(MIGRATIONS)
+        .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+
+    if pending {
+        anyhow::bail!("database has pending migrations, apply them or use --migrate=auto");
+    }
+
+    info!("no pending migrations");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod e2e_tests {
+    use super::*;
+    use testcontainers::{ContainerAsync, runners::AsyncRunner};
+    use testcontainers_modules::postgres::Postgres;
+
+    async fn get_postgres_testcontainer() -> (ContainerAsync<Postgres>, String) {
+        let c = Postgres::default().start().await.unwrap();
+
+        let host_port = c.get_host_port_ipv4(5432).await.unwrap();
+        let host = c.get_host().await.unwrap();
+
+        let db_url = format!("postgres://postgres:postgres@{host}:{host_port}/postgres",);
+
+        (c, db_url)
+    }
+
+    #[tokio::test]
+    async fn test_check_pending_migrations() {
+        let (_db_container, dburl) = get_postgres_testcontainer().await;
+
+        assert!(migrate(&dburl, MigrateMode::Check, None).is_err());
+        // skip neither applies nor checks
+        migrate(&dburl, MigrateMode::Skip, None).unwrap();
+        assert!(check_migrations(&dburl, None).is_err());
+
+        migrate(&dburl, MigrateMode::Auto, None).unwrap();
+        migrate(&dburl, MigrateMode::Check, None).unwrap();
+    }
+}
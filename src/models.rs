@@ -1,4 +1,27 @@
+use chrono::{DateTime, Utc};
 use diesel::prelude::*;
+use serde::Serialize;
+use x402_rs::network::Network;
+
+/// Channel a link was created through, stored in `links.source`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LinkSource {
+    Api,
+    Public,
+    X402,
+}
+
+impl LinkSource {
+    pub const ALL: [LinkSource; 3] = [LinkSource::Api, LinkSource::Public, LinkSource::X402];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LinkSource::Api => "api",
+            LinkSource::Public => "public",
+            LinkSource::X402 => "x402",
+        }
+    }
+}
 
 #[derive(Insertable, Clone, Debug)]
 #[diesel(table_name = crate::schema::links)]
@@ -7,6 +30,55 @@ pub struct CreateLink {
     pub id: String,
     pub url: String,
     pub key: String,
+    pub redirect_status: i16,
+    pub source: String,
+    pub expires_at: Option<DateTime<Utc>>,
+    /// See [`crate::auth::hash_password`].
+    pub password_hash: Option<String>,
+    /// Listed on `/trending`.
+    pub public: bool,
+    /// See [`crate::app::MAX_TITLE_LENGTH`].
+    pub title: Option<String>,
+    /// Holds the id without a destination yet, see `POST /link/reserve`.
+    pub reserved: bool,
+    #[diesel(embed)]
+    pub open_graph: OpenGraphTags,
+    /// See [`crate::app::AppConfig::store_creator_ip`].
+    pub creator_ip: Option<String>,
+    /// Clicks after which the link stops redirecting.
+    pub max_clicks: Option<i32>,
+    /// Free text for the owner and admins, see [`crate::app::MAX_NOTE_LENGTH`].
+    pub note: Option<String>,
+    /// See [`crate::app::AppConfig::dedupe_by_content`].
+    pub content_hash: Option<String>,
+}
+
+/// Open Graph tags of a link, served to social crawlers on a preview page,
+/// see [`crate::app::AppConfig::crawler_user_agents`].
+#[derive(Insertable, Queryable, Selectable, Clone, Default, PartialEq, Eq, Debug)]
+#[diesel(table_name = crate::schema::links)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct OpenGraphTags {
+    pub og_title: Option<String>,
+    pub og_description: Option<String>,
+    pub og_image: Option<String>,
+}
+
+impl OpenGraphTags {
+    pub fn is_empty(&self) -> bool {
+        self.og_title.is_none() && self.og_description.is_none() && self.og_image.is_none()
+    }
+}
+
+/// Canonical value of `x402.network`, also used to describe the configured
+/// price tags: `base` for Base mainnet and `base-sepolia` for the Base Sepolia
+/// testnet. Other networks keep x402's own naming.
+pub fn network_to_db_string(network: Network) -> String {
+    match network {
+        Network::Base => String::from("base"),
+        Network::BaseSepolia => String::from("base-sepolia"),
+        other => other.to_string(),
+    }
 }
 
 #[derive(Insertable, Clone, Debug)]
@@ -16,6 +88,41 @@ pub struct CreateTransaction {
     pub network: String,
     pub tx_hash: String,
     pub link_id: String,
+    pub amount: Option<String>,
+    pub payer: Option<String>,
+}
+
+/// A request answered with the demo response, see
+/// [`crate::app::AppConfig::record_demo_requests`].
+#[derive(Insertable, Clone, PartialEq, Eq, Debug)]
+#[diesel(table_name = crate::schema::demo_requests)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct CreateDemoRequest {
+    pub url: String,
+    pub source_ip: Option<String>,
+}
+
+/// An entry of the audit trail, see [`crate::app::AppConfig::audit_log`].
+#[derive(Insertable, Clone, PartialEq, Eq, Debug)]
+#[diesel(table_name = crate::schema::audit_log)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct CreateAuditEntry {
+    pub action: String,
+    pub link_id: String,
+    pub url: String,
+    pub key: Option<String>,
+    pub source: String,
+    pub source_ip: Option<String>,
+}
+
+#[derive(Queryable, Selectable, Clone, PartialEq, Eq, Debug)]
+#[diesel(table_name = crate::schema::x402)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Transaction {
+    pub network: String,
+    pub tx_hash: String,
+    pub amount: Option<String>,
+    pub payer: Option<String>,
 }
 
 #[derive(Queryable, Selectable, Clone, PartialEq, Eq, Debug)]
@@ -24,4 +131,56 @@ pub struct CreateTransaction {
 pub struct FetchLink {
     pub id: String,
     pub url: String,
+    pub redirect_status: i16,
+    pub password_hash: Option<String>,
+    pub disabled: bool,
+    pub reserved: bool,
+    /// Only flushed clicks, so [`Self::max_clicks`] is enforced best-effort.
+    pub click_count: i32,
+    pub max_clicks: Option<i32>,
+    pub key: Option<String>,
+    /// After which the link stops redirecting, even before it is purged.
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Queryable, Selectable, Clone, PartialEq, Eq, Debug)]
+#[diesel(table_name = crate::schema::links)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct LinkDetails {
+    pub id: String,
+    pub url: String,
+    pub created_at: DateTime<Utc>,
+    pub key: Option<String>,
+    pub click_count: i32,
+    pub last_used: DateTime<Utc>,
+    pub title: Option<String>,
+    /// Unset until the first click is flushed.
+    pub first_used: Option<DateTime<Utc>>,
+    pub creator_ip: Option<String>,
+    pub note: Option<String>,
+}
+
+/// Line of an owner's export, see `GET /link/export/me`.
+#[derive(Queryable, Selectable, Serialize, Clone, PartialEq, Eq, Debug)]
+#[diesel(table_name = crate::schema::links)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct OwnedLink {
+    pub id: String,
+    pub url: String,
+    pub created_at: DateTime<Utc>,
+    pub click_count: i32,
+    pub last_used: DateTime<Utc>,
+    pub source: String,
+    pub title: Option<String>,
+    pub note: Option<String>,
+}
+
+/// Entry of `/trending`.
+#[derive(Queryable, Selectable, Serialize, Clone, PartialEq, Eq, Debug)]
+#[diesel(table_name = crate::schema::links)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct TopLink {
+    pub id: String,
+    pub url: String,
+    pub click_count: i32,
 }
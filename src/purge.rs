@@ -0,0 +1,21 @@
+use chrono::Utc;
+use std::sync::Arc;
+use tokio::time::{Duration, interval};
+
+use crate::db::LinksDB;
+
+pub async fn start_expired_purger(db: Arc<dyn LinksDB>, interval_duration: Duration) {
+    let mut ticker = interval(interval_duration);
+
+    tracing::info!("expired link purger started");
+
+    loop {
+        ticker.tick().await;
+
+        match db.purge_expired(Utc::now()).await {
+            Ok(0) => {}
+            Ok(purged) => tracing::info!(purged, "purged expired links"),
+            Err(e) => tracing::error!("failed to purge expired links: {e}"),
+        }
+    }
+}
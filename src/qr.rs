@@ -0,0 +1,26 @@
+use base64::{Engine, engine::general_purpose::STANDARD};
+use image::{ImageFormat, Luma};
+use qrcode::QrCode;
+use std::io::Cursor;
+
+/// Renders `data` as a QR code PNG.
+pub fn png(data: &str) -> Result<Vec<u8>, anyhow::Error> {
+    let image = QrCode::new(data.as_bytes())?
+        .render::<Luma<u8>>()
+        .min_dimensions(200, 200)
+        .build();
+
+    let mut png = Vec::new();
+    image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+
+    Ok(png)
+}
+
+/// The QR code of `data` as a `data:image/png;base64,` URI, ready for an
+/// `<img src>`.
+pub fn png_data_uri(data: &str) -> Result<String, anyhow::Error> {
+    Ok(format!(
+        "data:image/png;base64,{}",
+        STANDARD.encode(png(data)?)
+    ))
+}
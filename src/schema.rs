@@ -1,14 +1,68 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    audit_log (id) {
+        id -> Int8,
+        action -> Varchar,
+        link_id -> Varchar,
+        url -> Text,
+        #[max_length = 32]
+        key -> Nullable<Varchar>,
+        source -> Varchar,
+        source_ip -> Nullable<Varchar>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    blocked_hosts (host) {
+        host -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    demo_requests (id) {
+        id -> Int8,
+        url -> Text,
+        source_ip -> Nullable<Varchar>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    link_daily_clicks (link_id, day) {
+        link_id -> Varchar,
+        day -> Date,
+        clicks -> Int4,
+    }
+}
+
 diesel::table! {
     links (id) {
         id -> Varchar,
         url -> Text,
-        created_at -> Timestamp,
+        created_at -> Timestamptz,
         #[max_length = 32]
         key -> Nullable<Varchar>,
         click_count -> Int4,
         last_used -> Timestamptz,
+        redirect_status -> Int2,
+        source -> Varchar,
+        expires_at -> Nullable<Timestamptz>,
+        password_hash -> Nullable<Varchar>,
+        public -> Bool,
+        disabled -> Bool,
+        title -> Nullable<Varchar>,
+        first_used -> Nullable<Timestamptz>,
+        reserved -> Bool,
+        og_title -> Nullable<Varchar>,
+        og_description -> Nullable<Varchar>,
+        og_image -> Nullable<Text>,
+        creator_ip -> Nullable<Varchar>,
+        max_clicks -> Nullable<Int4>,
+        note -> Nullable<Varchar>,
+        content_hash -> Nullable<Varchar>,
     }
 }
 
@@ -17,9 +71,19 @@ diesel::table! {
         network -> Varchar,
         tx_hash -> Varchar,
         link_id -> Varchar,
+        amount -> Nullable<Varchar>,
+        payer -> Nullable<Varchar>,
     }
 }
 
+diesel::joinable!(link_daily_clicks -> links (link_id));
 diesel::joinable!(x402 -> links (link_id));
 
-diesel::allow_tables_to_appear_in_same_query!(links, x402,);
+diesel::allow_tables_to_appear_in_same_query!(
+    audit_log,
+    blocked_hosts,
+    demo_requests,
+    link_daily_clicks,
+    links,
+    x402,
+);
@@ -1,5 +1,10 @@
+use std::time::Duration;
 #[cfg(not(target_os = "windows"))]
 use tokio::signal::unix::{SignalKind, signal};
+use url::Url;
+
+/// How long the shutdown webhook may take, shutting down continues regardless.
+const SHUTDOWN_WEBHOOK_TIMEOUT: Duration = Duration::from_secs(2);
 
 #[cfg(not(target_os = "windows"))]
 pub fn create_term_signal_handler(sender: tokio::sync::oneshot::Sender<()>) {
@@ -23,3 +28,26 @@ pub fn create_term_signal_handler(sender: tokio::sync::oneshot::Sender<()>) {
 
 #[cfg(target_os = "windows")]
 pub fn create_term_signal_handler(_sender: tokio::sync::oneshot::Sender<()>) {}
+
+/// POSTs `{"event":"shutdown"}` to `url`, e.g. to deregister from a service
+/// registry. The outcome is only logged, it never holds up the shutdown
+/// longer than [`SHUTDOWN_WEBHOOK_TIMEOUT`].
+pub async fn notify_shutdown(url: &Url) {
+    let sent = match reqwest::Client::builder()
+        .timeout(SHUTDOWN_WEBHOOK_TIMEOUT)
+        .build()
+    {
+        Ok(client) => client
+            .post(url.clone())
+            .json(&serde_json::json!({ "event": "shutdown" }))
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status),
+        Err(e) => Err(e),
+    };
+
+    match sent {
+        Ok(response) => tracing::info!(status = %response.status(), "shutdown webhook notified"),
+        Err(e) => tracing::warn!("shutdown webhook failed: {e}"),
+    }
+}
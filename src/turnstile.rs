@@ -0,0 +1,311 @@
+use serde::Deserialize;
+use std::time::Duration;
+use tracing::warn;
+
+const SITEVERIFY_URL: &str = "https://challenges.cloudflare.com/turnstile/v0/siteverify";
+
+/// How long siteverify may take unless [`VerifyPolicy::timeout`] says
+/// otherwise, so a slow Cloudflare can't hang public creates.
+const DEFAULT_VERIFY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Header the widget token is sent in.
+pub const TOKEN_HEADER: &str = "cf-turnstile-response";
+
+/// Claims a token has to carry, to prevent reusing tokens issued for other
+/// forms or sites.
+#[derive(Clone, Debug, Default)]
+pub struct TurnstileClaims {
+    pub action: Option<String>,
+    pub cdata: Option<String>,
+    /// Hostnames the widget may be served on, any if empty.
+    pub hostnames: Vec<String>,
+    /// Only accept tokens of solved interactive challenges, rejecting those
+    /// Cloudflare issued without any interaction as less certain.
+    pub interactive: bool,
+}
+
+/// How tokens are treated while Cloudflare's siteverify API cannot answer.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct VerifyPolicy {
+    /// Gives up on siteverify after this long, [`DEFAULT_VERIFY_TIMEOUT`]
+    /// if unset.
+    pub timeout: Option<Duration>,
+    /// Accept tokens unverified when siteverify times out, is unreachable or
+    /// answers `5xx`, keeping public creates available during Cloudflare
+    /// outages. Anyone can then create links with made up tokens for as long
+    /// as the outage lasts, so only enable this if the other limits of the
+    /// public endpoint are tight. Tokens Cloudflare rejects stay rejected.
+    pub fail_open: bool,
+}
+
+#[derive(Deserialize, Debug)]
+struct SiteVerifyResponse {
+    success: bool,
+    action: Option<String>,
+    cdata: Option<String>,
+    hostname: Option<String>,
+    #[serde(rename = "error-codes", default)]
+    error_codes: Vec<String>,
+    #[serde(default)]
+    metadata: SiteVerifyMetadata,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct SiteVerifyMetadata {
+    /// Whether the visitor had to interact with the widget.
+    #[serde(default)]
+    interactive: bool,
+}
+
+/// Verifies tokens against Cloudflare's siteverify API, including the
+/// `action`, `cdata` and `hostname` claims when configured.
+#[derive(Clone, Debug)]
+pub struct TurnstileVerifier {
+    secret: String,
+    claims: TurnstileClaims,
+    fail_open: bool,
+    verify_url: String,
+    client: reqwest::Client,
+}
+
+impl TurnstileVerifier {
+    pub fn new(secret: String, claims: TurnstileClaims) -> Result<Self, reqwest::Error> {
+        Ok(Self {
+            secret,
+            claims,
+            fail_open: false,
+            verify_url: SITEVERIFY_URL.to_string(),
+            client: reqwest::Client::builder()
+                .timeout(DEFAULT_VERIFY_TIMEOUT)
+                .build()?,
+        })
+    }
+
+    pub fn with_policy(mut self, policy: VerifyPolicy) -> Result<Self, reqwest::Error> {
+        if let Some(timeout) = policy.timeout {
+            self.client = reqwest::Client::builder().timeout(timeout).build()?;
+        }
+        self.fail_open = policy.fail_open;
+        Ok(self)
+    }
+
+    #[cfg(test)]
+    pub fn with_verify_url(mut self, verify_url: String) -> Self {
+        self.verify_url = verify_url;
+        self
+    }
+
+    pub async fn verify(&self, token: &str) -> bool {
+        let response = self
+            .client
+            .post(&self.verify_url)
+            .form(&[("secret", self.secret.as_str()), ("response", token)])
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status);
+
+        let response: SiteVerifyResponse = match response {
+            Ok(response) => match response.json().await {
+                Ok(response) => response,
+                Err(e) => {
+                    warn!("invalid turnstile response: {e}");
+                    return false;
+                }
+            },
+            Err(e) => {
+                let unavailable = e.is_timeout()
+                    || e.is_connect()
+                    || e.status().is_some_and(|status| status.is_server_error());
+                if unavailable && self.fail_open {
+                    warn!("turnstile verification failed, accepting token: {e}");
+                    return true;
+                }
+
+                warn!("turnstile verification failed: {e}");
+                return false;
+            }
+        };
+
+        if !response.success {
+            warn!(errors = ?response.error_codes, "turnstile token rejected");
+            return false;
+        }
+
+        self.matches_claims(&response)
+    }
+
+    fn matches_claims(&self, response: &SiteVerifyResponse) -> bool {
+        let matches = |expected: &Option<String>, actual: &Option<String>| {
+            expected.is_none() || expected == actual
+        };
+
+        let hostname_allowed = self.claims.hostnames.is_empty()
+            || response.hostname.as_ref().is_some_and(|hostname| {
+                self.claims
+                    .hostnames
+                    .iter()
+                    .any(|allowed| allowed.eq_ignore_ascii_case(hostname))
+            });
+
+        let valid = matches(&self.claims.action, &response.action)
+            && matches(&self.claims.cdata, &response.cdata)
+            && hostname_allowed
+            && (!self.claims.interactive || response.metadata.interactive);
+
+        if !valid {
+            warn!(
+                action = ?response.action,
+                cdata = ?response.cdata,
+                hostname = ?response.hostname,
+                interactive = response.metadata.interactive,
+                "turnstile token claims mismatch"
+            );
+        }
+
+        valid
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Json, Router, routing::post};
+    use tokio::net::TcpListener;
+
+    /// Serves a siteverify endpoint accepting every token as issued for `action`
+    /// on `hostname`.
+    async fn siteverify_server(action: &'static str, hostname: &'static str) -> String {
+        siteverify_server_with(serde_json::json!({
+            "success": true,
+            "action": action,
+            "hostname": hostname,
+        }))
+        .await
+    }
+
+    /// Serves a siteverify endpoint answering every token with `response`.
+    async fn siteverify_server_with(response: serde_json::Value) -> String {
+        let router =
+            Router::new().route("/siteverify", post(move || async move { Json(response) }));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+        format!("http://{addr}/siteverify")
+    }
+
+    fn verifier(verify_url: String) -> TurnstileVerifier {
+        TurnstileVerifier::new(
+            String::from("secret"),
+            TurnstileClaims {
+                action: Some(String::from("shorten")),
+                cdata: None,
+                hostnames: vec![String::from("ezli.me")],
+                interactive: false,
+            },
+        )
+        .unwrap()
+        .with_verify_url(verify_url)
+    }
+
+    #[tokio::test]
+    async fn test_matching_action() {
+        let verifier = verifier(siteverify_server("shorten", "ezli.me").await);
+
+        assert!(verifier.verify("token").await);
+    }
+
+    #[tokio::test]
+    async fn test_mismatched_action() {
+        let verifier = verifier(siteverify_server("login", "ezli.me").await);
+
+        assert!(!verifier.verify("token").await);
+    }
+
+    #[tokio::test]
+    async fn test_unexpected_hostname() {
+        let verifier = verifier(siteverify_server("shorten", "evil.example").await);
+
+        assert!(!verifier.verify("token").await);
+    }
+
+    #[tokio::test]
+    async fn test_verification_timeout() {
+        let router = Router::new().route(
+            "/siteverify",
+            post(|| async {
+                tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+                Json(serde_json::json!({ "success": true }))
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+        let verifier = |fail_open| {
+            TurnstileVerifier::new(String::from("secret"), TurnstileClaims::default())
+                .unwrap()
+                .with_verify_url(format!("http://{addr}/siteverify"))
+                .with_policy(VerifyPolicy {
+                    timeout: Some(Duration::from_millis(100)),
+                    fail_open,
+                })
+                .unwrap()
+        };
+
+        assert!(!verifier(false).verify("token").await);
+        assert!(verifier(true).verify("token").await);
+    }
+
+    #[tokio::test]
+    async fn test_fail_open_keeps_rejections() {
+        let rejecting = siteverify_server_with(serde_json::json!({
+            "success": false,
+            "error-codes": ["invalid-input-response"],
+        }))
+        .await;
+        let verifier = TurnstileVerifier::new(String::from("secret"), TurnstileClaims::default())
+            .unwrap()
+            .with_verify_url(rejecting)
+            .with_policy(VerifyPolicy {
+                timeout: None,
+                fail_open: true,
+            })
+            .unwrap();
+
+        assert!(!verifier.verify("token").await);
+    }
+
+    #[tokio::test]
+    async fn test_require_interactive() {
+        let interactive_verifier = |verify_url| {
+            TurnstileVerifier::new(
+                String::from("secret"),
+                TurnstileClaims {
+                    interactive: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+            .with_verify_url(verify_url)
+        };
+
+        let passive = siteverify_server_with(serde_json::json!({
+            "success": true,
+            "metadata": { "interactive": false },
+        }))
+        .await;
+        assert!(!interactive_verifier(passive).verify("token").await);
+
+        let unknown = siteverify_server_with(serde_json::json!({ "success": true })).await;
+        assert!(!interactive_verifier(unknown).verify("token").await);
+
+        let solved = siteverify_server_with(serde_json::json!({
+            "success": true,
+            "metadata": { "interactive": true },
+        }))
+        .await;
+        assert!(interactive_verifier(solved).verify("token").await);
+    }
+}